@@ -15,18 +15,209 @@
  *
  *   0. You just DO WHAT THE FUCK YOU WANT TO.
 */
+use crate::crypto::{self, DataKey, MasterKey};
+use crate::versions::{ObjectVersion, VersionsPage};
+
+use s3::command::Command;
 use s3::creds::Credentials;
-use s3::serde_types::Object as S3Object;
+use s3::request::blocking::AttoRequest;
+use s3::request::{Request, ResponseData};
+use s3::serde_types::{Object as S3Object, Part};
 use s3::{Bucket, Region};
-use std::fs::File;
-use std::path::{Path, PathBuf};
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::fs::{self, File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Component, Path, PathBuf};
 use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
 
 pub type BucketResult<T> = Result<T, String>;
 
+/// Minimum size of a multipart chunk accepted by S3 (except for the last one)
+const MIN_PART_SIZE: usize = 5 * 1024 * 1024;
+/// Maximum amount of parts in a multipart upload
+const MAX_PARTS: usize = 10000;
+/// Maximum size of an object copied with a single request (5GB)
+const MAX_COPY_SIZE: usize = 5 * 1024 * 1024 * 1024;
+/// Size of each part of a multipart copy
+const COPY_PART_SIZE: usize = 512 * 1024 * 1024;
+/// Maximum validity of a presigned URL (7 days)
+const MAX_PRESIGN_EXPIRY: u32 = 604800;
+
+/// ## ConnectOptions
+///
+/// Describes where the bucket is and how to authenticate
+#[derive(Debug, Clone)]
+pub struct ConnectOptions {
+    /// AWS region, or the region name to sign requests with when using a custom endpoint
+    pub region: String,
+    /// Custom endpoint URL (e.g. MinIO, Ceph or LocalStack)
+    pub endpoint: Option<String>,
+    /// Address bucket as `endpoint/bucket` instead of `bucket.endpoint`
+    pub path_style: bool,
+    pub credentials: CredentialsSource,
+}
+
+impl ConnectOptions {
+    pub fn new(region: &str) -> Self {
+        Self {
+            region: region.to_string(),
+            endpoint: None,
+            path_style: false,
+            credentials: CredentialsSource::Profile(None),
+        }
+    }
+}
+
+/// ## CredentialsSource
+///
+/// Describes where credentials are taken from
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum CredentialsSource {
+    /// `AWS_ACCESS_KEY_ID`, `AWS_SECRET_ACCESS_KEY` and `AWS_SESSION_TOKEN` environment variables
+    Environment,
+    /// Profile in `~/.aws/credentials`; `None` is the default profile
+    Profile(Option<String>),
+    /// Static access key and secret, with optional session token
+    Static {
+        access_key: String,
+        secret_key: String,
+        session_token: Option<String>,
+    },
+    /// Assume `role_arn` with the web identity token stored at `token_file`
+    WebIdentity {
+        role_arn: String,
+        token_file: PathBuf,
+        session_name: String,
+    },
+}
+
+impl CredentialsSource {
+    /// ### resolve
+    ///
+    /// Get credentials from source
+    fn resolve(&self) -> BucketResult<Credentials> {
+        match self {
+            Self::Environment => Credentials::from_env().map_err(|e| e.to_string()),
+            Self::Profile(profile) => {
+                Credentials::from_profile(profile.as_deref()).map_err(|e| e.to_string())
+            }
+            Self::Static {
+                access_key,
+                secret_key,
+                session_token,
+            } => Credentials::new(
+                Some(access_key.as_str()),
+                Some(secret_key.as_str()),
+                None,
+                session_token.as_deref(),
+                None,
+            )
+            .map_err(|e| e.to_string()),
+            Self::WebIdentity {
+                role_arn,
+                token_file,
+                session_name,
+            } => {
+                let token: String = fs::read_to_string(token_file.as_path())
+                    .map_err(|e| format!("Could not read web identity token: {}", e))?;
+                Credentials::from_sts(role_arn.as_str(), session_name.as_str(), token.trim())
+                    .map_err(|e| e.to_string())
+            }
+        }
+    }
+}
+
 pub struct S3Bucket {
     bucket: Bucket,
     wrkdir: PathBuf,
+    transfer: TransferOptions,
+    encryption: EncryptionOptions,
+}
+
+/// ## TransferOptions
+///
+/// Describes how large objects are transferred
+#[derive(Debug, Clone, Copy)]
+pub struct TransferOptions {
+    /// Size of each part of multipart uploads and ranged downloads.
+    /// Objects smaller than this are transferred with a single request
+    pub part_size: usize,
+    /// Amount of parts transferred at the same time
+    pub parallelism: usize,
+}
+
+impl Default for TransferOptions {
+    fn default() -> Self {
+        Self {
+            part_size: 8 * 1024 * 1024,
+            parallelism: 4,
+        }
+    }
+}
+
+/// ## EncryptionOptions
+///
+/// Describes how uploaded objects are encrypted
+#[derive(Debug, Clone, Default)]
+pub struct EncryptionOptions {
+    /// Master key used to wrap the data key of objects encrypted on client side.
+    /// When set, uploads are encrypted with AES-256-GCM before leaving the host
+    pub client: Option<MasterKey>,
+    /// Server-side encryption requested on upload
+    pub server: Option<ServerSideEncryption>,
+}
+
+/// ## ServerSideEncryption
+///
+/// Server-side encryption mode
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum ServerSideEncryption {
+    /// Keys managed by S3 (SSE-S3)
+    S3,
+    /// Keys managed by KMS (SSE-KMS); uses the default key of the account if no key id is provided
+    Kms(Option<String>),
+}
+
+impl ServerSideEncryption {
+    /// ### from_header
+    ///
+    /// Get encryption mode from the `x-amz-server-side-encryption` header of an object and its KMS key id
+    fn from_header(algorithm: &str, key_id: Option<String>) -> Option<Self> {
+        match algorithm {
+            "AES256" => Some(Self::S3),
+            "aws:kms" => Some(Self::Kms(key_id)),
+            _ => None,
+        }
+    }
+
+    /// ### headers
+    ///
+    /// Get headers to request server-side encryption
+    fn headers(&self) -> Vec<(String, String)> {
+        match self {
+            Self::S3 => vec![(
+                String::from("x-amz-server-side-encryption"),
+                String::from("AES256"),
+            )],
+            Self::Kms(key_id) => {
+                let mut headers: Vec<(String, String)> = vec![(
+                    String::from("x-amz-server-side-encryption"),
+                    String::from("aws:kms"),
+                )];
+                if let Some(key_id) = key_id {
+                    headers.push((
+                        String::from("x-amz-server-side-encryption-aws-kms-key-id"),
+                        key_id.clone(),
+                    ));
+                }
+                headers
+            }
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -35,6 +226,7 @@ pub struct S3File {
     pub path: PathBuf,
     pub size: usize,
     pub last_modified: String,
+    pub e_tag: String,
     pub is_dir: bool,
 }
 
@@ -46,30 +238,146 @@ impl S3File {
             path: PathBuf::from(obj.key.as_str()),
             size: obj.size as usize,
             last_modified: obj.last_modified.clone(),
+            e_tag: obj
+                .e_tag
+                .as_deref()
+                .unwrap_or_default()
+                .trim_matches('"')
+                .to_string(),
             is_dir,
         }
     }
-}
 
-impl S3Bucket {
-    /// ### list_object_should_be_kept
-    ///
-    /// Returns whether object should be kept after list command.
-    /// The object won't be kept if:
+    /// ### directory
     ///
-    /// 1. is not a direct child of provided dir
-    fn list_object_should_be_kept(obj: &S3Object, dir: &str) -> bool {
-        Self::is_direct_child(obj.key.as_str(), dir)
+    /// Make a directory entry out of a common prefix
+    pub fn directory(prefix: &str) -> Self {
+        Self {
+            name: S3Bucket::object_name(prefix),
+            path: PathBuf::from(prefix),
+            size: 0,
+            last_modified: String::new(),
+            e_tag: String::new(),
+            is_dir: true,
+        }
     }
+}
+
+/// ## ObjectMetadata
+///
+/// Object properties which are not returned by list
+#[derive(Debug, Default)]
+pub struct ObjectMetadata {
+    pub content_type: Option<String>,
+    pub e_tag: Option<String>,
+    pub storage_class: String,
+    pub server_side_encryption: Option<ServerSideEncryption>,
+    /// User defined metadata (`x-amz-meta-*`)
+    pub metadata: HashMap<String, String>,
+    pub tags: Vec<(String, String)>,
+}
+
+/// ## CopyObjectResult
+///
+/// `CopyObject` response body
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct CopyObjectResult {
+    #[serde(rename = "ETag")]
+    e_tag: String,
+}
+
+/// ## ErrorResponse
+///
+/// Error response body; `CopyObject` may return it along with a 200 status code
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct ErrorResponse {
+    code: String,
+    message: String,
+}
 
-    /// ### is_direct_child
+/// ## CopyPartResult
+///
+/// `UploadPartCopy` response body
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct CopyPartResult {
+    #[serde(rename = "ETag")]
+    e_tag: String,
+}
+
+/// ## ObjectIter
+///
+/// Lazy iterator over the objects under a prefix.
+/// Pages are fetched with continuation tokens as the iterator is consumed
+pub struct ObjectIter<'a> {
+    bucket: &'a Bucket,
+    prefix: String,
+    delimiter: Option<String>,
+    continuation_token: Option<String>,
+    buffer: VecDeque<S3File>,
+    done: bool,
+}
+
+impl<'a> ObjectIter<'a> {
+    fn new(bucket: &'a Bucket, prefix: String, recursive: bool) -> Self {
+        Self {
+            bucket,
+            prefix,
+            delimiter: match recursive {
+                true => None,
+                false => Some(String::from("/")),
+            },
+            continuation_token: None,
+            buffer: VecDeque::new(),
+            done: false,
+        }
+    }
+
+    /// ### fetch_page
     ///
-    /// Checks whether Object's key is direct child of `parent` path.
-    fn is_direct_child(key: &str, parent: &str) -> bool {
-        key == format!("{}{}", parent, Self::object_name(key))
-            || key == format!("{}{}/", parent, Self::object_name(key))
+    /// Fetch next page of results into buffer
+    fn fetch_page(&mut self) -> BucketResult<()> {
+        let (page, _) = self
+            .bucket
+            .list_page(
+                self.prefix.clone(),
+                self.delimiter.clone(),
+                self.continuation_token.take(),
+                None,
+                None,
+            )
+            .map_err(|e| format!("Could not list dir: {}", e))?;
+        if let Some(prefixes) = page.common_prefixes.as_ref() {
+            prefixes
+                .iter()
+                .for_each(|x| self.buffer.push_back(S3File::directory(x.prefix.as_str())));
+        }
+        page.contents
+            .iter()
+            .for_each(|x| self.buffer.push_back(S3File::new(x)));
+        self.continuation_token = page.next_continuation_token;
+        self.done = !page.is_truncated || self.continuation_token.is_none();
+        Ok(())
     }
+}
 
+impl<'a> Iterator for ObjectIter<'a> {
+    type Item = BucketResult<S3File>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.buffer.is_empty() && !self.done {
+            if let Err(err) = self.fetch_page() {
+                self.done = true;
+                return Some(Err(err));
+            }
+        }
+        self.buffer.pop_front().map(Ok)
+    }
+}
+
+impl S3Bucket {
     /// ### object_name
     ///
     /// Get object name from key
@@ -80,7 +388,7 @@ impl S3Bucket {
             true => tokens.nth(count - 2).unwrap().to_string(),
             false => String::new(),
         };
-        if let Some(last) = tokens.last() {
+        if let Some(last) = tokens.next_back() {
             // If last is not empty, return last one
             if !last.is_empty() {
                 return last.to_string();
@@ -92,67 +400,412 @@ impl S3Bucket {
 }
 
 impl S3Bucket {
-    pub fn connect(name: &str, region: &str, profile: Option<&str>) -> BucketResult<Self> {
+    pub fn connect(name: &str, options: &ConnectOptions) -> BucketResult<Self> {
         // Get region
-        let region: Region =
-            Region::from_str(region).map_err(|e| format!("Invalid region: {}", e))?;
+        let region: Region = match options.endpoint.as_ref() {
+            Some(endpoint) => Region::Custom {
+                region: options.region.clone(),
+                endpoint: endpoint.clone(),
+            },
+            None => Region::from_str(options.region.as_str())
+                .map_err(|e| format!("Invalid region: {}", e))?,
+        };
         // Get credentials
-        let credentials: Credentials = Credentials::new(None, None, None, None, profile.as_deref())
+        let credentials: Credentials = options
+            .credentials
+            .resolve()
             .map_err(|e| format!("Could not get credentials: {}", e))?;
         // Connect to bucket
-        let bucket: Bucket = Bucket::new(name, region, credentials)
+        let mut bucket: Bucket = *Bucket::new(name, region, credentials)
             .map_err(|e| format!("Could not connect to bucket: {}", e))?;
+        if options.path_style {
+            bucket.set_path_style();
+        }
         Ok(Self {
             bucket,
             wrkdir: PathBuf::from("/"),
+            transfer: TransferOptions::default(),
+            encryption: EncryptionOptions::default(),
         })
     }
 
+    /// ### set_encryption
+    ///
+    /// Set how uploaded objects are encrypted
+    pub fn set_encryption(&mut self, encryption: EncryptionOptions) {
+        self.encryption = encryption;
+    }
+
+    /// ### set_part_size
+    ///
+    /// Set the part size used for multipart transfers
+    pub fn set_part_size(&mut self, part_size: usize) -> BucketResult<()> {
+        if part_size < MIN_PART_SIZE {
+            return Err(format!(
+                "Part size must be at least {} bytes",
+                MIN_PART_SIZE
+            ));
+        }
+        self.transfer.part_size = part_size;
+        Ok(())
+    }
+
+    /// ### transfer_options
+    ///
+    /// Get current transfer options
+    pub fn transfer_options(&self) -> TransferOptions {
+        self.transfer
+    }
+
+    /// ### encryption
+    ///
+    /// Get how uploaded objects are encrypted
+    pub fn encryption(&self) -> &EncryptionOptions {
+        &self.encryption
+    }
+
+    /// ### set_parallelism
+    ///
+    /// Set the amount of parts transferred concurrently
+    pub fn set_parallelism(&mut self, parallelism: usize) -> BucketResult<()> {
+        if parallelism == 0 {
+            return Err(String::from("Parallelism must be greater than 0"));
+        }
+        self.transfer.parallelism = parallelism;
+        Ok(())
+    }
+
     pub fn pwd(&self) -> BucketResult<&Path> {
         Ok(self.wrkdir.as_path())
     }
 
     pub fn list(&self, dir: Option<&str>) -> BucketResult<Vec<S3File>> {
+        self.list_iter(dir, false).collect()
+    }
+
+    /// ### list_iter
+    ///
+    /// Lazily list entries in `dir` (or working directory).
+    /// If `recursive` is false, only direct children are returned, with sub directories collapsed into a single entry
+    pub fn list_iter(
+        &self,
+        dir: Option<&str>,
+        recursive: bool,
+    ) -> impl Iterator<Item = BucketResult<S3File>> + '_ {
         let dir: String = match dir {
             Some(d) => d.to_string(),
             None => self.wrkdir.to_string_lossy().to_string(),
         };
-        // If root, convert to empty string
-        let dir: String = match dir.as_str() == "/" {
-            true => String::default(),
-            false => dir,
+        let dir: String = Self::dir_key(dir.as_str());
+        let marker: PathBuf = PathBuf::from(dir.as_str());
+        // Filter out directory marker
+        ObjectIter::new(&self.bucket, dir, recursive).filter(move |x| match x {
+            Ok(file) => file.path != marker,
+            Err(_) => true,
+        })
+    }
+
+    /// ### list_recursive
+    ///
+    /// List every object whose key starts with `prefix`
+    pub fn list_recursive(&self, prefix: &str) -> BucketResult<Vec<S3File>> {
+        ObjectIter::new(&self.bucket, prefix.to_string(), true).collect()
+    }
+
+    /// ### du
+    ///
+    /// Compute disk usage of `dir` (or working directory).
+    /// Returns the summed size of each direct sub directory, followed by the total size of `dir`
+    pub fn du(&self, dir: Option<&str>) -> BucketResult<Vec<(String, usize)>> {
+        let dir: String = match dir {
+            Some(d) => d.to_string(),
+            None => self.wrkdir.to_string_lossy().to_string(),
         };
-        let results = self.bucket.list(dir.clone(), None);
-        match results {
-            Ok(entries) => {
-                let mut objects: Vec<S3File> = Vec::new();
-                entries.iter().for_each(|x| {
-                    x.contents
-                        .iter()
-                        .filter(|x| Self::list_object_should_be_kept(x, dir.as_str()))
-                        .for_each(|x| objects.push(S3File::new(x)))
-                });
-                Ok(objects)
+        let dir: String = Self::dir_key(dir.as_str());
+        let mut usage: BTreeMap<String, usize> = BTreeMap::new();
+        let mut total: usize = 0;
+        for file in ObjectIter::new(&self.bucket, dir.clone(), true) {
+            let file: S3File = file?;
+            let key: String = file.path.to_string_lossy().to_string();
+            let relative: &str = key.strip_prefix(dir.as_str()).unwrap_or(key.as_str());
+            if let Some((child, _)) = relative.split_once('/') {
+                *usage.entry(format!("{}{}/", dir, child)).or_insert(0) += file.size;
             }
-            Err(e) => Err(format!("Could not list dir: {}", e)),
+            total += file.size;
         }
+        let mut usage: Vec<(String, usize)> = usage.into_iter().collect();
+        usage.push((dir, total));
+        Ok(usage)
+    }
+
+    /// ### find
+    ///
+    /// Lazily search objects under working directory whose name matches `pattern`.
+    /// `pattern` supports `*` and `?` wildcards; if it contains a `/`, it's matched against the entire key
+    pub fn find<'a>(&'a self, pattern: &'a str) -> impl Iterator<Item = BucketResult<S3File>> + 'a {
+        self.list_iter(None, true).filter(move |x| match x {
+            Ok(file) if pattern.contains('/') => {
+                Self::wildmatch(pattern, file.path.to_string_lossy().as_ref())
+            }
+            Ok(file) => Self::wildmatch(pattern, file.name.as_str()),
+            Err(_) => true,
+        })
     }
 
     pub fn stat(&self, p: &str) -> BucketResult<S3File> {
-        let results = self.bucket.list(p.to_string(), None);
-        match results {
-            Ok(entries) => {
-                let mut objects: Vec<S3File> = Vec::new();
-                entries
-                    .iter()
-                    .for_each(|x| x.contents.iter().for_each(|x| objects.push(S3File::new(x))));
-                match objects.into_iter().find(|x| x.path == PathBuf::from(p)) {
-                    Some(file) => Ok(file),
-                    None => Err(format!("{}: No such file or directory", p)),
-                }
+        let path: PathBuf = PathBuf::from(p);
+        for file in ObjectIter::new(&self.bucket, p.trim_start_matches('/').to_string(), false) {
+            let file: S3File = file?;
+            if file.path == path {
+                return Ok(file);
+            }
+        }
+        Err(format!("{}: No such file or directory", p))
+    }
+
+    /// ### metadata
+    ///
+    /// Get content type, ETag, storage class, user metadata and tags of object at `p`
+    pub fn metadata(&self, p: &str) -> BucketResult<ObjectMetadata> {
+        Self::object_metadata(&self.bucket, p)
+    }
+
+    /// ### object_metadata
+    ///
+    /// Get metadata of object at `p` in `bucket`; see `metadata`
+    fn object_metadata(bucket: &Bucket, p: &str) -> BucketResult<ObjectMetadata> {
+        let (head, _) = bucket
+            .head_object(p)
+            .map_err(|e| format!("Could not stat object: {}", e))?;
+        let (tagging, _) = bucket
+            .get_object_tagging(p)
+            .map_err(|e| format!("Could not get object tags: {}", e))?;
+        let server_side_encryption: Option<ServerSideEncryption> = head
+            .server_side_encryption
+            .as_deref()
+            .and_then(|x| ServerSideEncryption::from_header(x, head.ssekms_key_id.clone()));
+        let content_type: Option<String> = match head.content_type {
+            Some(content_type) => Some(content_type),
+            None => Self::content_type(bucket, p, head.content_length.unwrap_or_default()),
+        };
+        Ok(ObjectMetadata {
+            content_type,
+            e_tag: head.e_tag.map(|x| x.trim_matches('"').to_string()),
+            storage_class: head
+                .storage_class
+                .unwrap_or_else(|| String::from("STANDARD")),
+            server_side_encryption,
+            metadata: head.metadata.unwrap_or_default(),
+            tags: tagging
+                .into_iter()
+                .map(|tag| (tag.key(), tag.value()))
+                .collect(),
+        })
+    }
+
+    /// ### is_client_side_encrypted
+    ///
+    /// Returns whether object at `p` has been encrypted on client side
+    pub fn is_client_side_encrypted(&self, p: &str) -> BucketResult<bool> {
+        let (head, _) = self
+            .bucket
+            .head_object(p)
+            .map_err(|e| format!("Could not stat object: {}", e))?;
+        Ok(crypto::is_encrypted(&head.metadata.unwrap_or_default()))
+    }
+
+    /// ### content_type
+    ///
+    /// Get content type of object at `p` in `bucket`, `size` bytes long, from the response to a GET of its first byte.
+    /// The HEAD response of the sync client never reports it
+    fn content_type(bucket: &Bucket, p: &str, size: i64) -> Option<String> {
+        match size {
+            0 => bucket.get_object(p),
+            _ => bucket.get_object_range(p, 0, Some(0)),
+        }
+        .ok()
+        .and_then(|response| response.headers().get("content-type").cloned())
+    }
+
+    /// ### set_metadata
+    ///
+    /// Replace user metadata of object at `p`. Content type, storage class and encryption are preserved.
+    /// Keys starting with `cse-` are reserved for the data key of client-side encrypted objects
+    pub fn set_metadata(&self, p: &str, metadata: &[(String, String)]) -> BucketResult<()> {
+        if let Some((k, _)) = metadata
+            .iter()
+            .find(|(k, _)| k.to_ascii_lowercase().starts_with(crypto::METADATA_PREFIX))
+        {
+            return Err(format!(
+                "Metadata key `{}` is reserved for client-side encryption",
+                k
+            ));
+        }
+        let current: ObjectMetadata = self.metadata(p)?;
+        let mut headers: Vec<(String, String)> = vec![(
+            String::from("x-amz-metadata-directive"),
+            String::from("REPLACE"),
+        )];
+        if let Some(content_type) = current.content_type {
+            headers.push((String::from("content-type"), content_type));
+        }
+        headers.push((String::from("x-amz-storage-class"), current.storage_class));
+        headers.extend(
+            current
+                .server_side_encryption
+                .map(|x| x.headers())
+                .unwrap_or_default(),
+        );
+        current
+            .metadata
+            .iter()
+            .filter(|(k, _)| k.starts_with(crypto::METADATA_PREFIX))
+            .chain(metadata.iter().map(|(k, v)| (k, v)))
+            .for_each(|(k, v)| headers.push((format!("x-amz-meta-{}", k), v.clone())));
+        self.server_copy(&self.bucket, p, None, &self.bucket, p, headers.as_slice())
+            .map_err(|e| format!("Could not set metadata: {}", e))
+    }
+
+    /// ### set_tags
+    ///
+    /// Replace tags of object at `p`
+    pub fn set_tags(&self, p: &str, tags: &[(String, String)]) -> BucketResult<()> {
+        let tags: Vec<(&str, &str)> = tags.iter().map(|(k, v)| (k.as_str(), v.as_str())).collect();
+        self.bucket
+            .put_object_tagging(p, tags.as_slice())
+            .map(|_| ())
+            .map_err(|e| format!("Could not set tags: {}", e))
+    }
+
+    /// ### set_storage_class
+    ///
+    /// Change storage class of object at `p` (e.g. `STANDARD_IA`, `GLACIER`). Server-side encryption is preserved
+    pub fn set_storage_class(&self, p: &str, storage_class: &str) -> BucketResult<()> {
+        let current: ObjectMetadata = self.metadata(p)?;
+        let mut headers: Vec<(String, String)> = vec![
+            (
+                String::from("x-amz-metadata-directive"),
+                String::from("COPY"),
+            ),
+            (
+                String::from("x-amz-storage-class"),
+                storage_class.to_ascii_uppercase(),
+            ),
+        ];
+        headers.extend(
+            current
+                .server_side_encryption
+                .map(|x| x.headers())
+                .unwrap_or_default(),
+        );
+        self.server_copy(&self.bucket, p, None, &self.bucket, p, headers.as_slice())
+            .map_err(|e| format!("Could not change storage class: {}", e))
+    }
+
+    /// ### presign
+    ///
+    /// Generate a presigned URL to download (`put` is false) or upload object at `p`, valid for `expiry_secs`
+    pub fn presign(&self, p: &str, put: bool, expiry_secs: u32) -> BucketResult<String> {
+        if expiry_secs == 0 || expiry_secs > MAX_PRESIGN_EXPIRY {
+            return Err(format!(
+                "Expiry must be between 1 and {} seconds",
+                MAX_PRESIGN_EXPIRY
+            ));
+        }
+        match put {
+            true => self.bucket.presign_put(p, expiry_secs, None, None),
+            false => self.bucket.presign_get(p, expiry_secs, None),
+        }
+        .map_err(|e| format!("Could not presign URL: {}", e))
+    }
+
+    /// ### versions
+    ///
+    /// List every version and delete marker of object at `p`, latest first
+    pub fn versions(&self, p: &str) -> BucketResult<Vec<ObjectVersion>> {
+        let p: &str = p.trim_start_matches('/');
+        let mut versions: Vec<ObjectVersion> = Vec::new();
+        let mut key_marker: Option<String> = None;
+        let mut version_id_marker: Option<String> = None;
+        loop {
+            let mut bucket: Bucket = self.bucket.clone();
+            bucket.add_query("versions", "");
+            bucket.add_query("prefix", p);
+            if let Some(marker) = key_marker.as_deref() {
+                bucket.add_query("key-marker", marker);
+            }
+            if let Some(marker) = version_id_marker.as_deref() {
+                bucket.add_query("version-id-marker", marker);
+            }
+            let response = bucket
+                .get_object("/")
+                .map_err(|e| format!("Could not list versions: {}", e))?;
+            if response.status_code() != 200 {
+                return Err(format!(
+                    "Could not list versions: status code {}",
+                    response.status_code()
+                ));
+            }
+            let page: VersionsPage = VersionsPage::parse(response.as_slice())?;
+            // Prefix may match other keys too
+            versions.extend(page.versions.into_iter().filter(|x| x.key == p));
+            if !page.is_truncated {
+                break;
             }
-            Err(e) => Err(format!("Could not list dir: {}", e)),
+            key_marker = page.next_key_marker;
+            version_id_marker = page.next_version_id_marker;
         }
+        versions.sort_by(|a, b| b.last_modified.cmp(&a.last_modified));
+        Ok(versions)
+    }
+
+    /// ### get_version
+    ///
+    /// Download version `version_id` of `src` to `dest`. Client-side encrypted versions are decrypted
+    pub fn get_version(&self, src: &str, dest: &Path, version_id: &str) -> BucketResult<()> {
+        let mut bucket: Bucket = self.bucket.clone();
+        bucket.add_query("versionId", version_id);
+        let (head, _) = bucket
+            .head_object(src)
+            .map_err(|e| format!("Could not stat object: {}", e))?;
+        let size: usize = head.content_length.unwrap_or_default() as usize;
+        self.download(&bucket, src, dest, size)
+    }
+
+    /// ### copy
+    ///
+    /// Server-side copy `src` to `dest`; both can be either a key or a `s3://bucket/key` location.
+    /// If `recursive` is true, every object under prefix `src` is copied under prefix `dest`.
+    /// Returns the amount of copied objects
+    pub fn copy(&self, src: &str, dest: &str, recursive: bool) -> BucketResult<usize> {
+        self.copy_or_move(src, dest, recursive, false)
+    }
+
+    /// ### rename
+    ///
+    /// Move `src` to `dest`, copying it server-side and then removing `src`. See `copy`
+    pub fn rename(&self, src: &str, dest: &str, recursive: bool) -> BucketResult<usize> {
+        self.copy_or_move(src, dest, recursive, true)
+    }
+
+    /// ### restore
+    ///
+    /// Make version `version_id` of `p` the latest one, by copying it on top of the object
+    pub fn restore(&self, p: &str, version_id: &str) -> BucketResult<()> {
+        self.server_copy(&self.bucket, p, Some(version_id), &self.bucket, p, &[])
+            .map_err(|e| format!("Could not restore version: {}", e))
+    }
+
+    /// ### remove_version
+    ///
+    /// Permanently delete version `version_id` of `p`
+    pub fn remove_version(&self, p: &str, version_id: &str) -> BucketResult<()> {
+        let mut bucket: Bucket = self.bucket.clone();
+        bucket.add_query("versionId", version_id);
+        bucket
+            .delete_object(p)
+            .map(|_| ())
+            .map_err(|e| format!("Could not remove version: {}", e))
     }
 
     pub fn change_dir(&mut self, p: &str) -> BucketResult<()> {
@@ -186,20 +839,95 @@ impl S3Bucket {
             .map_err(|e| format!("Could not remove object: {}", e))
     }
 
+    /// ### put
+    ///
+    /// Upload local file `src` to `dest`, encrypting it as configured with `set_encryption`
     pub fn put(&self, src: &Path, dest: &str) -> BucketResult<()> {
+        let size: usize = fs::metadata(src)
+            .map_err(|e| format!("Could not stat file: {}", e))?
+            .len() as usize;
+        if let Some(master) = self.encryption.client.as_ref() {
+            return self.put_encrypted(src, dest, size, master);
+        }
+        let bucket: Bucket = self.upload_bucket(&[]);
+        if size > self.transfer.part_size {
+            let part_size: usize = Self::fit_part_size(size, self.transfer.part_size);
+            let ranges: Vec<(usize, usize)> = Self::part_ranges(size, part_size);
+            return self.put_multipart(&bucket, dest, ranges.len(), |index| {
+                let (start, end) = ranges[index];
+                Self::read_range(src, start, end)
+            });
+        }
         let mut reader = File::open(src).map_err(|e| format!("Could not open file: {}", e))?;
-        self.bucket
+        bucket
             .put_object_stream(&mut reader, dest)
             .map(|_| ())
             .map_err(|e| format!("Could not put file: {}", e))
     }
 
+    /// ### put_recursive
+    ///
+    /// Upload local directory `src` and all of its content to `dest`
+    pub fn put_recursive(&self, src: &Path, dest: &str) -> BucketResult<()> {
+        if !src.is_dir() {
+            return self.put(src, dest);
+        }
+        let dest: String = Self::dir_key(dest);
+        if !dest.is_empty() {
+            self.mkdir(dest.as_str())?;
+        }
+        let entries = fs::read_dir(src).map_err(|e| format!("Could not read directory: {}", e))?;
+        for entry in entries {
+            let entry = entry.map_err(|e| format!("Could not read directory: {}", e))?;
+            let key: String = format!("{}{}", dest, entry.file_name().to_string_lossy());
+            self.put_recursive(entry.path().as_path(), key.as_str())?;
+        }
+        Ok(())
+    }
+
+    /// ### get
+    ///
+    /// Download `src` to local file `dest`. Client-side encrypted objects are decrypted
     pub fn get(&self, src: &str, dest: &Path) -> BucketResult<()> {
-        let mut writer = File::create(dest).map_err(|e| format!("Could not open file: {}", e))?;
-        self.bucket
-            .get_object_stream(src, &mut writer)
-            .map(|_| ())
-            .map_err(|e| format!("Could not get file: {}", e))
+        let size: usize = self.stat(src)?.size;
+        self.download(&self.bucket, src, dest, size)
+    }
+
+    /// ### get_recursive
+    ///
+    /// Download every object under prefix `src` into local directory `dest`
+    pub fn get_recursive(&self, src: &str, dest: &Path) -> BucketResult<()> {
+        let prefix: String = Self::dir_key(src);
+        let objects: Vec<S3File> = self.list_recursive(prefix.as_str())?;
+        if objects.is_empty() {
+            return Err(format!("{}: No such file or directory", src));
+        }
+        // Check every key before writing anything
+        let locals: Vec<PathBuf> = objects
+            .iter()
+            .map(|object| {
+                let key: String = object.path.to_string_lossy().to_string();
+                Self::local_path(
+                    dest,
+                    key.strip_prefix(prefix.as_str()).unwrap_or(key.as_str()),
+                )
+            })
+            .collect::<BucketResult<Vec<PathBuf>>>()?;
+        fs::create_dir_all(dest).map_err(|e| format!("Could not create directory: {}", e))?;
+        for (object, local) in objects.iter().zip(locals) {
+            let key: String = object.path.to_string_lossy().to_string();
+            if object.is_dir {
+                fs::create_dir_all(local.as_path())
+                    .map_err(|e| format!("Could not create directory: {}", e))?;
+                continue;
+            }
+            if let Some(parent) = local.parent() {
+                fs::create_dir_all(parent)
+                    .map_err(|e| format!("Could not create directory: {}", e))?;
+            }
+            self.get(key.as_str(), local.as_path())?;
+        }
+        Ok(())
     }
 
     pub fn mkdir(&self, p: &str) -> BucketResult<()> {
@@ -214,3 +942,1733 @@ impl S3Bucket {
             .map_err(|e| format!("Could not make directory: {}", e))
     }
 }
+
+impl S3Bucket {
+    /// ### put_encrypted
+    ///
+    /// Encrypt `src` with a new data key wrapped by `master` and upload it to `dest`.
+    /// Parts are made of whole segments, so that each one can be encrypted on its own
+    fn put_encrypted(
+        &self,
+        src: &Path,
+        dest: &str,
+        size: usize,
+        master: &MasterKey,
+    ) -> BucketResult<()> {
+        let key: DataKey = DataKey::generate();
+        let metadata: Vec<(String, String)> = key
+            .to_metadata(master)?
+            .into_iter()
+            .map(|(k, v)| (format!("x-amz-meta-{}", k), v))
+            .collect();
+        let bucket: Bucket = self.upload_bucket(metadata.as_slice());
+        let segments: usize = crypto::segments(size);
+        let part_size: usize =
+            crypto::segment_aligned(Self::fit_part_size(size, self.transfer.part_size));
+        let ranges: Vec<(usize, usize)> = Self::part_ranges(size, part_size);
+        let encrypt_part = |index: usize| -> BucketResult<Vec<u8>> {
+            let (start, end) = ranges.get(index).copied().unwrap_or((0, 0));
+            let plain: Vec<u8> = Self::read_range(src, start, end)?;
+            key.encrypt(start / crypto::SEGMENT_SIZE, segments, plain.as_slice())
+        };
+        if crypto::encrypted_size(size) > self.transfer.part_size {
+            return self.put_multipart(&bucket, dest, ranges.len(), encrypt_part);
+        }
+        let sealed: Vec<u8> = encrypt_part(0)?;
+        bucket
+            .put_object(dest, sealed.as_slice())
+            .map(|_| ())
+            .map_err(|e| format!("Could not put file: {}", e))
+    }
+
+    /// ### download
+    ///
+    /// Download object `src` of `size` bytes from `bucket` into `dest`, decrypting it if it is client-side encrypted
+    fn download(&self, bucket: &Bucket, src: &str, dest: &Path, size: usize) -> BucketResult<()> {
+        let (head, _) = bucket
+            .head_object(src)
+            .map_err(|e| format!("Could not stat object: {}", e))?;
+        if let Some(key) = DataKey::from_metadata(
+            &head.metadata.unwrap_or_default(),
+            self.encryption.client.as_ref(),
+        )? {
+            return self.get_encrypted(bucket, src, dest, size, &key);
+        }
+        if size > self.transfer.part_size {
+            let ranges: Vec<(usize, usize)> = Self::part_ranges(size, self.transfer.part_size);
+            return self.get_ranged(bucket, src, dest, size, ranges.as_slice(), |index, data| {
+                Ok((ranges[index].0, data))
+            });
+        }
+        let mut writer = File::create(dest).map_err(|e| format!("Could not open file: {}", e))?;
+        bucket
+            .get_object_to_writer(src, &mut writer)
+            .map(|_| ())
+            .map_err(|e| format!("Could not get file: {}", e))
+    }
+
+    /// ### get_encrypted
+    ///
+    /// Download client-side encrypted object `src` of `size` bytes and decrypt it with `key` into `dest`
+    fn get_encrypted(
+        &self,
+        bucket: &Bucket,
+        src: &str,
+        dest: &Path,
+        size: usize,
+        key: &DataKey,
+    ) -> BucketResult<()> {
+        let plain_size: usize = crypto::decrypted_size(size)?;
+        let segments: usize = crypto::segments(plain_size);
+        let part_size: usize = crypto::segment_aligned(self.transfer.part_size)
+            / crypto::SEGMENT_SIZE
+            * crypto::ENCRYPTED_SEGMENT_SIZE;
+        let ranges: Vec<(usize, usize)> = Self::part_ranges(size, part_size);
+        self.get_ranged(
+            bucket,
+            src,
+            dest,
+            plain_size,
+            ranges.as_slice(),
+            |index, data| {
+                let first_segment: usize = ranges[index].0 / crypto::ENCRYPTED_SEGMENT_SIZE;
+                let plain: Vec<u8> = key.decrypt(first_segment, segments, data.as_slice())?;
+                Ok((first_segment * crypto::SEGMENT_SIZE, plain))
+            },
+        )
+    }
+
+    /// ### upload_bucket
+    ///
+    /// Get a bucket handle sending `headers` and the server-side encryption headers on uploads
+    fn upload_bucket(&self, headers: &[(String, String)]) -> Bucket {
+        let mut bucket: Bucket = self.bucket.clone();
+        headers
+            .iter()
+            .cloned()
+            .chain(
+                self.encryption
+                    .server
+                    .as_ref()
+                    .map(|x| x.headers())
+                    .unwrap_or_default(),
+            )
+            .for_each(|(k, v)| bucket.add_header(k.as_str(), v.as_str()));
+        bucket
+    }
+
+    /// ### read_range
+    ///
+    /// Read bytes `start..end` of local file `src`
+    fn read_range(src: &Path, start: usize, end: usize) -> BucketResult<Vec<u8>> {
+        let mut chunk: Vec<u8> = vec![0; end - start];
+        let mut reader = File::open(src).map_err(|e| format!("Could not open file: {}", e))?;
+        reader
+            .seek(SeekFrom::Start(start as u64))
+            .and_then(|_| reader.read_exact(chunk.as_mut_slice()))
+            .map_err(|e| format!("Could not read file: {}", e))?;
+        Ok(chunk)
+    }
+
+    /// ### put_multipart
+    ///
+    /// Upload `parts` parts to `dest` with a multipart upload initiated from `bucket`,
+    /// sending up to `parallelism` parts at the same time. The body of each part is returned by `read_part`
+    fn put_multipart<F>(
+        &self,
+        bucket: &Bucket,
+        dest: &str,
+        parts: usize,
+        read_part: F,
+    ) -> BucketResult<()>
+    where
+        F: Fn(usize) -> BucketResult<Vec<u8>> + Sync,
+    {
+        let upload = bucket
+            .initiate_multipart_upload(dest, "application/octet-stream")
+            .map_err(|e| format!("Could not initiate multipart upload: {}", e))?;
+        let upload_id: &str = upload.upload_id.as_str();
+        let uploaded: Mutex<Vec<Part>> = Mutex::new(Vec::with_capacity(parts));
+        let result = Self::run_parallel(self.transfer.parallelism, parts, |index| {
+            let chunk: Vec<u8> = read_part(index)?;
+            let part: Part = self
+                .bucket
+                .put_multipart_chunk(
+                    chunk.as_slice(),
+                    dest,
+                    (index + 1) as u32,
+                    upload_id,
+                    "application/octet-stream",
+                )
+                .map_err(|e| format!("Could not put part {}: {}", index + 1, e))?;
+            uploaded.lock().unwrap().push(part);
+            Ok(())
+        });
+        if let Err(err) = result {
+            let _ = self.bucket.abort_upload(dest, upload_id);
+            return Err(err);
+        }
+        let mut uploaded: Vec<Part> = uploaded.into_inner().unwrap();
+        uploaded.sort_by_key(|x| x.part_number);
+        self.bucket
+            .complete_multipart_upload(dest, upload_id, uploaded)
+            .map(|_| ())
+            .map_err(|e| format!("Could not complete multipart upload: {}", e))
+    }
+
+    /// ### get_ranged
+    ///
+    /// Download `ranges` of `src` from `bucket` into `dest`, resized to `size`, fetching up to `parallelism` ranges at the same time.
+    /// `decode` returns, for each range, the data to write and its offset in `dest`
+    fn get_ranged<F>(
+        &self,
+        bucket: &Bucket,
+        src: &str,
+        dest: &Path,
+        size: usize,
+        ranges: &[(usize, usize)],
+        decode: F,
+    ) -> BucketResult<()>
+    where
+        F: Fn(usize, Vec<u8>) -> BucketResult<(usize, Vec<u8>)> + Sync,
+    {
+        File::create(dest)
+            .and_then(|f| f.set_len(size as u64))
+            .map_err(|e| format!("Could not open file: {}", e))?;
+        Self::run_parallel(self.transfer.parallelism, ranges.len(), |index| {
+            let (start, end) = ranges[index];
+            let data: Vec<u8> = bucket
+                .get_object_range(src, start as u64, Some(end as u64 - 1))
+                .map_err(|e| format!("Could not get range {}-{}: {}", start, end, e))?
+                .to_vec();
+            if data.len() != end - start {
+                return Err(format!(
+                    "Could not get range {}-{}: got {} bytes",
+                    start,
+                    end,
+                    data.len()
+                ));
+            }
+            let (offset, data) = decode(index, data)?;
+            let mut writer = OpenOptions::new()
+                .write(true)
+                .open(dest)
+                .map_err(|e| format!("Could not open file: {}", e))?;
+            writer
+                .seek(SeekFrom::Start(offset as u64))
+                .and_then(|_| writer.write_all(data.as_slice()))
+                .map_err(|e| format!("Could not write file: {}", e))
+        })
+    }
+
+    /// ### run_parallel
+    ///
+    /// Run `job` for each index in `0..jobs` on `workers` threads.
+    /// Stops scheduling new jobs once one fails and returns the first error
+    fn run_parallel<F>(workers: usize, jobs: usize, job: F) -> BucketResult<()>
+    where
+        F: Fn(usize) -> BucketResult<()> + Sync,
+    {
+        let next: AtomicUsize = AtomicUsize::new(0);
+        let error: Mutex<Option<String>> = Mutex::new(None);
+        thread::scope(|scope| {
+            for _ in 0..workers.min(jobs) {
+                scope.spawn(|| loop {
+                    if error.lock().unwrap().is_some() {
+                        break;
+                    }
+                    let index: usize = next.fetch_add(1, Ordering::SeqCst);
+                    if index >= jobs {
+                        break;
+                    }
+                    if let Err(err) = job(index) {
+                        error.lock().unwrap().get_or_insert(err);
+                        break;
+                    }
+                });
+            }
+        });
+        match error.into_inner().unwrap() {
+            Some(err) => Err(err),
+            None => Ok(()),
+        }
+    }
+
+    /// ### fit_part_size
+    ///
+    /// Grow `part_size` if needed, so that an object of `size` bytes fits in `MAX_PARTS` parts
+    pub(crate) fn fit_part_size(size: usize, part_size: usize) -> usize {
+        part_size.max(size.div_ceil(MAX_PARTS))
+    }
+
+    /// ### part_ranges
+    ///
+    /// Split `size` bytes into `[start, end)` ranges of `part_size` bytes
+    pub(crate) fn part_ranges(size: usize, part_size: usize) -> Vec<(usize, usize)> {
+        (0..size)
+            .step_by(part_size)
+            .map(|start| (start, (start + part_size).min(size)))
+            .collect()
+    }
+
+    /// ### copy_object
+    ///
+    /// Server-side copy `src_key` (at `src_version_id` if provided) from `src_bucket` to `dest` in `bucket`,
+    /// sending `headers` along with the request.
+    /// The copy may fail after a 200 status code has been sent, so the body must be a `CopyObjectResult`
+    fn copy_object(
+        bucket: &Bucket,
+        src_bucket: &str,
+        src_key: &str,
+        src_version_id: Option<&str>,
+        dest: &str,
+        headers: &[(String, String)],
+    ) -> BucketResult<()> {
+        let mut bucket: Bucket = bucket.clone();
+        // Unlike other requests, copies don't set the content type themselves
+        for (k, v) in headers.iter() {
+            bucket.add_header(k.as_str(), v.as_str());
+        }
+        let copy_source: String = Self::copy_source(src_bucket, src_key, src_version_id);
+        let response = Self::put_copy(&bucket, dest, copy_source.as_str())
+            .map_err(|e| format!("Could not copy {}: {}", src_key, e))?;
+        if let Ok(error) = serde_xml_rs::from_reader::<_, ErrorResponse>(response.as_slice()) {
+            return Err(format!(
+                "Could not copy {}: {} ({})",
+                src_key, error.message, error.code
+            ));
+        }
+        match serde_xml_rs::from_reader::<_, CopyObjectResult>(response.as_slice()) {
+            Ok(result) if !result.e_tag.is_empty() => Ok(()),
+            Ok(_) => Err(format!("Could not copy {}: no ETag in response", src_key)),
+            Err(e) => Err(format!("Could not copy {}: {}", src_key, e)),
+        }
+    }
+
+    /// ### put_copy
+    ///
+    /// Send a PUT request to `dest` copying `copy_source` with `bucket`.
+    /// Unlike `put_object`, the response body is returned rather than the ETag header,
+    /// since that's where S3 reports the outcome of copies
+    fn put_copy(bucket: &Bucket, dest: &str, copy_source: &str) -> Result<ResponseData, String> {
+        AttoRequest::new(bucket, dest, Command::CopyObject { from: copy_source })
+            .and_then(|request| request.response_data(false))
+            .map_err(|e| e.to_string())
+    }
+
+    /// ### header_bucket
+    ///
+    /// Get a handle to `bucket` sending `headers`.
+    /// Content type is returned apart, since requests always set it themselves
+    fn header_bucket(bucket: &Bucket, headers: &[(String, String)]) -> (Bucket, String) {
+        let mut bucket: Bucket = bucket.clone();
+        let mut content_type: String = String::from("application/octet-stream");
+        for (k, v) in headers.iter() {
+            match k.as_str() {
+                "content-type" => content_type = v.clone(),
+                _ => bucket.add_header(k.as_str(), v.as_str()),
+            }
+        }
+        (bucket, content_type)
+    }
+
+    /// ### server_copy
+    ///
+    /// Server-side copy `src_key` from `src_bucket` to `dest_key` in `dest_bucket`, sending `headers` along with the copy.
+    /// Objects larger than 5GB are copied with a multipart copy
+    fn server_copy(
+        &self,
+        src_bucket: &Bucket,
+        src_key: &str,
+        src_version_id: Option<&str>,
+        dest_bucket: &Bucket,
+        dest_key: &str,
+        headers: &[(String, String)],
+    ) -> BucketResult<()> {
+        let mut head_bucket: Bucket = src_bucket.clone();
+        if let Some(version_id) = src_version_id {
+            head_bucket.add_query("versionId", version_id);
+        }
+        let (head, _) = head_bucket
+            .head_object(src_key)
+            .map_err(|e| format!("{}: {}", src_key, e))?;
+        let size: usize = head.content_length.unwrap_or_default() as usize;
+        if size <= MAX_COPY_SIZE {
+            return Self::copy_object(
+                dest_bucket,
+                src_bucket.name.as_str(),
+                src_key,
+                src_version_id,
+                dest_key,
+                headers,
+            );
+        }
+        let copy_source: String =
+            Self::copy_source(src_bucket.name.as_str(), src_key, src_version_id);
+        let ranges: Vec<(usize, usize)> =
+            Self::part_ranges(size, Self::fit_part_size(size, COPY_PART_SIZE));
+        let source: ObjectMetadata = Self::object_metadata(&head_bucket, src_key)?;
+        let headers: Vec<(String, String)> = Self::multipart_copy_headers(&source, headers);
+        let (initiate_bucket, content_type) = Self::header_bucket(dest_bucket, headers.as_slice());
+        let upload = initiate_bucket
+            .initiate_multipart_upload(dest_key, content_type.as_str())
+            .map_err(|e| format!("Could not initiate multipart copy: {}", e))?;
+        let upload_id: &str = upload.upload_id.as_str();
+        let parts: Mutex<Vec<Part>> = Mutex::new(Vec::with_capacity(ranges.len()));
+        let result = Self::run_parallel(self.transfer.parallelism, ranges.len(), |index| {
+            let (start, end) = ranges[index];
+            let part_number: u32 = (index + 1) as u32;
+            let mut bucket: Bucket = dest_bucket.clone();
+            bucket.add_header(
+                "x-amz-copy-source-range",
+                format!("bytes={}-{}", start, end - 1).as_str(),
+            );
+            bucket.add_query("partNumber", part_number.to_string().as_str());
+            bucket.add_query("uploadId", upload_id);
+            let response = Self::put_copy(&bucket, dest_key, copy_source.as_str())
+                .map_err(|e| format!("Could not copy part {}: {}", part_number, e))?;
+            if response.status_code() != 200 {
+                return Err(format!(
+                    "Could not copy part {}: status code {}",
+                    part_number,
+                    response.status_code()
+                ));
+            }
+            let result: CopyPartResult = serde_xml_rs::from_reader(response.as_slice())
+                .map_err(|e| format!("Could not copy part {}: {}", part_number, e))?;
+            parts.lock().unwrap().push(Part {
+                part_number,
+                etag: result.e_tag,
+            });
+            Ok(())
+        });
+        if let Err(err) = result {
+            let _ = dest_bucket.abort_upload(dest_key, upload_id);
+            return Err(err);
+        }
+        let mut parts: Vec<Part> = parts.into_inner().unwrap();
+        parts.sort_by_key(|x| x.part_number);
+        dest_bucket
+            .complete_multipart_upload(dest_key, upload_id, parts)
+            .map(|_| ())
+            .map_err(|e| format!("Could not complete multipart copy: {}", e))
+    }
+
+    /// ### multipart_copy_headers
+    ///
+    /// Get headers to initiate a multipart copy of `source` with, given the `headers` of the copy.
+    /// Multipart uploads don't inherit anything from the copied object, so content type and user metadata
+    /// (unless replaced by `headers`), storage class and tags are taken from `source`
+    fn multipart_copy_headers(
+        source: &ObjectMetadata,
+        headers: &[(String, String)],
+    ) -> Vec<(String, String)> {
+        let replace: bool = headers
+            .iter()
+            .any(|(k, v)| k == "x-amz-metadata-directive" && v == "REPLACE");
+        let mut copied: Vec<(String, String)> = Vec::new();
+        if !replace {
+            if let Some(content_type) = source.content_type.as_ref() {
+                copied.push((String::from("content-type"), content_type.clone()));
+            }
+            source
+                .metadata
+                .iter()
+                .collect::<BTreeMap<&String, &String>>()
+                .into_iter()
+                .for_each(|(k, v)| copied.push((format!("x-amz-meta-{}", k), v.clone())));
+        }
+        copied.push((
+            String::from("x-amz-storage-class"),
+            source.storage_class.clone(),
+        ));
+        if !source.tags.is_empty() {
+            let tags: Vec<String> = source
+                .tags
+                .iter()
+                .map(|(k, v)| format!("{}={}", Self::url_encode(k), Self::url_encode(v)))
+                .collect();
+            copied.push((String::from("x-amz-tagging"), tags.join("&")));
+        }
+        // Headers of the copy win; the metadata directive only applies to single request copies
+        copied.retain(|(k, _)| !headers.iter().any(|(h, _)| h == k));
+        copied.extend(
+            headers
+                .iter()
+                .filter(|(k, _)| k != "x-amz-metadata-directive")
+                .cloned(),
+        );
+        copied
+    }
+
+    /// ### copy_or_move
+    ///
+    /// Server-side copy `src` to `dest`, then remove `src` if `remove` is true.
+    /// Returns the amount of copied objects
+    fn copy_or_move(
+        &self,
+        src: &str,
+        dest: &str,
+        recursive: bool,
+        remove: bool,
+    ) -> BucketResult<usize> {
+        let (src_bucket, src_key) = self.location(src);
+        let (dest_bucket, dest_key) = self.location(dest);
+        // A copy onto itself is allowed for multipart copies; removing the source would lose it
+        if remove && src_bucket.name == dest_bucket.name {
+            let same: bool = match recursive {
+                false => src_key == dest_key,
+                true => Self::dir_key(src_key.as_str()) == Self::dir_key(dest_key.as_str()),
+            };
+            if same {
+                return Err(format!("{} and {} are the same object", src, dest));
+            }
+        }
+        let objects: Vec<(String, String)> = match recursive {
+            false => vec![(src_key, dest_key)],
+            true => {
+                let src_prefix: String = Self::dir_key(src_key.as_str());
+                let dest_prefix: String = Self::dir_key(dest_key.as_str());
+                ObjectIter::new(&src_bucket, src_prefix.clone(), true)
+                    .map(|x| {
+                        x.map(|file| {
+                            let key: String = file.path.to_string_lossy().to_string();
+                            let relative: &str = key
+                                .strip_prefix(src_prefix.as_str())
+                                .unwrap_or(key.as_str());
+                            let dest: String = format!("{}{}", dest_prefix, relative);
+                            (key, dest)
+                        })
+                    })
+                    .collect::<BucketResult<Vec<(String, String)>>>()?
+            }
+        };
+        if objects.is_empty() {
+            return Err(format!("{}: No such file or directory", src));
+        }
+        for (src_key, dest_key) in objects.iter() {
+            self.server_copy(&src_bucket, src_key, None, &dest_bucket, dest_key, &[])?;
+            if remove {
+                src_bucket
+                    .delete_object(src_key)
+                    .map_err(|e| format!("Could not remove object: {}", e))?;
+            }
+        }
+        Ok(objects.len())
+    }
+
+    /// ### location
+    ///
+    /// Resolve `path` into bucket and key; see `parse_location`
+    fn location(&self, path: &str) -> (Bucket, String) {
+        let (name, key) = Self::parse_location(path);
+        let mut bucket: Bucket = self.bucket.clone();
+        if let Some(name) = name {
+            bucket.name = name;
+        }
+        (bucket, key)
+    }
+
+    /// ### parse_location
+    ///
+    /// Split `s3://bucket/key` into bucket name and key.
+    /// Plain paths refer to a key in the current bucket
+    fn parse_location(path: &str) -> (Option<String>, String) {
+        match path.strip_prefix("s3://") {
+            Some(location) => match location.split_once('/') {
+                Some((bucket, key)) => (Some(bucket.to_string()), key.to_string()),
+                None => (Some(location.to_string()), String::new()),
+            },
+            None => (None, path.trim_start_matches('/').to_string()),
+        }
+    }
+
+    /// ### copy_source
+    ///
+    /// Format `x-amz-copy-source` header value
+    fn copy_source(bucket: &str, key: &str, version_id: Option<&str>) -> String {
+        let mut copy_source: String = format!("/{}/{}", bucket, Self::url_encode(key));
+        if let Some(version_id) = version_id {
+            copy_source.push_str(format!("?versionId={}", version_id).as_str());
+        }
+        copy_source
+    }
+
+    /// ### url_encode
+    ///
+    /// Percent-encode key, leaving unreserved characters and slashes as they are
+    fn url_encode(key: &str) -> String {
+        key.bytes()
+            .map(|b| match b {
+                b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' | b'/' => {
+                    (b as char).to_string()
+                }
+                _ => format!("%{:02X}", b),
+            })
+            .collect()
+    }
+
+    /// ### wildmatch
+    ///
+    /// Match `text` against a glob `pattern` supporting `*` and `?`
+    fn wildmatch(pattern: &str, text: &str) -> bool {
+        let pattern: Vec<char> = pattern.chars().collect();
+        let text: Vec<char> = text.chars().collect();
+        let (mut p, mut t): (usize, usize) = (0, 0);
+        // Position of last `*` in pattern and the text position it was matched at
+        let mut backtrack: Option<(usize, usize)> = None;
+        while t < text.len() {
+            if p < pattern.len() && (pattern[p] == '?' || pattern[p] == text[t]) {
+                p += 1;
+                t += 1;
+            } else if p < pattern.len() && pattern[p] == '*' {
+                backtrack = Some((p, t));
+                p += 1;
+            } else if let Some((star, matched)) = backtrack {
+                p = star + 1;
+                t = matched + 1;
+                backtrack = Some((star, t));
+            } else {
+                return false;
+            }
+        }
+        pattern[p..].iter().all(|x| *x == '*')
+    }
+
+    /// ### local_path
+    ///
+    /// Get the path of the object at `relative` key under `dest`.
+    /// Keys come from the bucket, so the ones which could escape `dest` (`..`, `.`, empty components,
+    /// absolute paths) are rejected
+    pub(crate) fn local_path(dest: &Path, relative: &str) -> BucketResult<PathBuf> {
+        let mut local: PathBuf = dest.to_path_buf();
+        // Directories end with a slash
+        let names: &str = relative.strip_suffix('/').unwrap_or(relative);
+        if names.is_empty() {
+            return Ok(local);
+        }
+        for name in names.split('/') {
+            let mut components = Path::new(name).components();
+            match (components.next(), components.next()) {
+                (Some(Component::Normal(component)), None) if component == name => local.push(name),
+                _ => return Err(format!("{}: unsafe object key", relative)),
+            }
+        }
+        Ok(local)
+    }
+
+    /// ### dir_key
+    ///
+    /// Make sure key ends with a slash, as directories do, and doesn't start with one
+    pub(crate) fn dir_key(key: &str) -> String {
+        let key: &str = key.trim_start_matches('/');
+        match key.ends_with('/') || key.is_empty() {
+            true => key.to_string(),
+            false => format!("{}/", key),
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+
+    use super::*;
+
+    use std::collections::BTreeSet;
+    use std::io::{BufRead, BufReader};
+    use std::net::{TcpListener, TcpStream};
+    use std::sync::Arc;
+    use tempfile::TempDir;
+
+    /// Amount of entries in each ListObjectVersions page of the mock server
+    const MOCK_VERSIONS_PAGE: usize = 2;
+
+    /// Status, headers and body of a mock server response
+    type MockResponse = (u16, Vec<(String, String)>, Vec<u8>);
+    /// Object, without data yet, and parts by part number of a pending multipart upload
+    type MockUpload = (MockObject, BTreeMap<u32, Vec<u8>>);
+
+    /// ## MockRequest
+    ///
+    /// Request received by the mock server
+    #[derive(Debug, Clone)]
+    pub(crate) struct MockRequest {
+        pub method: String,
+        pub key: String,
+        pub query: HashMap<String, String>,
+        /// Headers, with lowercase names
+        pub headers: HashMap<String, String>,
+    }
+
+    /// ## MockObject
+    ///
+    /// Object stored by the mock server
+    #[derive(Debug, Clone, Default)]
+    pub(crate) struct MockObject {
+        pub data: Vec<u8>,
+        pub e_tag: String,
+        /// Content type, storage class, server-side encryption and `x-amz-meta-*` headers
+        pub headers: BTreeMap<String, String>,
+        pub tags: Vec<(String, String)>,
+        /// Size reported by HEAD instead of the length of `data`
+        pub size: Option<usize>,
+    }
+
+    /// ## MockState
+    ///
+    /// Objects, versions, pending multipart uploads and requests of the mock server
+    #[derive(Default)]
+    struct MockState {
+        objects: BTreeMap<String, MockObject>,
+        /// Versions and delete markers returned by ListObjectVersions, ordered by key
+        versions: Vec<ObjectVersion>,
+        /// Multipart uploads, by upload id
+        uploads: HashMap<String, MockUpload>,
+        requests: Vec<MockRequest>,
+        /// Whether CopyObject fails with a 200 status code, as S3 may do once it started responding
+        failing_copies: bool,
+    }
+
+    /// ## MockS3
+    ///
+    /// In-memory S3 server for bucket `test`, listening on a local port
+    pub(crate) struct MockS3 {
+        url: String,
+        state: Arc<Mutex<MockState>>,
+    }
+
+    impl MockS3 {
+        pub(crate) fn start() -> Self {
+            let listener: TcpListener = TcpListener::bind("127.0.0.1:0").unwrap();
+            let url: String = format!("http://{}", listener.local_addr().unwrap());
+            let state: Arc<Mutex<MockState>> = Arc::default();
+            let server_state: Arc<Mutex<MockState>> = state.clone();
+            thread::spawn(move || {
+                for stream in listener.incoming() {
+                    let state: Arc<Mutex<MockState>> = server_state.clone();
+                    thread::spawn(move || Self::serve(stream.unwrap(), state.as_ref()));
+                }
+            });
+            Self { url, state }
+        }
+
+        /// ### connect
+        ///
+        /// Connect to bucket `test` with path style addressing
+        pub(crate) fn connect(&self) -> S3Bucket {
+            let mut options: ConnectOptions = ConnectOptions::new("us-east-1");
+            options.endpoint = Some(self.url.clone());
+            options.path_style = true;
+            options.credentials = CredentialsSource::Static {
+                access_key: String::from("minio"),
+                secret_key: String::from("miniostorage"),
+                session_token: None,
+            };
+            S3Bucket::connect("test", &options).unwrap()
+        }
+
+        pub(crate) fn object(&self, key: &str) -> Option<MockObject> {
+            self.state.lock().unwrap().objects.get(key).cloned()
+        }
+
+        pub(crate) fn keys(&self) -> Vec<String> {
+            self.state.lock().unwrap().objects.keys().cloned().collect()
+        }
+
+        pub(crate) fn set_versions(&self, versions: Vec<ObjectVersion>) {
+            self.state.lock().unwrap().versions = versions;
+        }
+
+        /// ### insert
+        ///
+        /// Store `data` at `key` as it is, bypassing the request path
+        pub(crate) fn insert(&self, key: &str, data: &[u8]) {
+            let object = MockObject {
+                data: data.to_vec(),
+                e_tag: format!("{:x}", md5::compute(data)),
+                ..MockObject::default()
+            };
+            self.state
+                .lock()
+                .unwrap()
+                .objects
+                .insert(key.to_string(), object);
+        }
+
+        /// ### set_size
+        ///
+        /// Make HEAD report `size` for `key`, whatever its data
+        pub(crate) fn set_size(&self, key: &str, size: usize) {
+            if let Some(object) = self.state.lock().unwrap().objects.get_mut(key) {
+                object.size = Some(size);
+            }
+        }
+
+        pub(crate) fn fail_copies(&self) {
+            self.state.lock().unwrap().failing_copies = true;
+        }
+
+        /// ### requests
+        ///
+        /// Get the requests received so far with `method`
+        pub(crate) fn requests(&self, method: &str) -> Vec<MockRequest> {
+            self.state
+                .lock()
+                .unwrap()
+                .requests
+                .iter()
+                .filter(|x| x.method == method)
+                .cloned()
+                .collect()
+        }
+
+        /// ### serve
+        ///
+        /// Read a request from `stream` and write the response; connections are not reused
+        fn serve(stream: TcpStream, state: &Mutex<MockState>) {
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut line: String = String::new();
+            reader.read_line(&mut line).unwrap();
+            let mut tokens = line.split_whitespace();
+            let method: String = tokens.next().unwrap_or_default().to_string();
+            let target: String = tokens.next().unwrap_or_default().to_string();
+            let mut headers: HashMap<String, String> = HashMap::new();
+            loop {
+                let mut line: String = String::new();
+                if reader.read_line(&mut line).unwrap() == 0 || line == "\r\n" {
+                    break;
+                }
+                if let Some((k, v)) = line.split_once(':') {
+                    headers.insert(k.trim().to_ascii_lowercase(), v.trim().to_string());
+                }
+            }
+            let length: usize = headers
+                .get("content-length")
+                .and_then(|x| x.parse().ok())
+                .unwrap_or_default();
+            let mut body: Vec<u8> = vec![0; length];
+            reader.read_exact(body.as_mut_slice()).unwrap();
+            let (path, query) = target.split_once('?').unwrap_or((target.as_str(), ""));
+            let request = MockRequest {
+                method,
+                key: Self::decode(path.trim_start_matches("/test").trim_start_matches('/')),
+                query: query
+                    .split('&')
+                    .filter(|x| !x.is_empty())
+                    .map(|x| {
+                        let (k, v) = x.split_once('=').unwrap_or((x, ""));
+                        (
+                            Self::decode(k.replace('+', " ").as_str()),
+                            Self::decode(v.replace('+', " ").as_str()),
+                        )
+                    })
+                    .collect(),
+                headers,
+            };
+            let (status, headers, body) = {
+                let mut state = state.lock().unwrap();
+                state.requests.push(request.clone());
+                state.respond(&request, body)
+            };
+            let mut response: String = format!("HTTP/1.1 {} Mock\r\nConnection: close\r\n", status);
+            if request.method != "HEAD" {
+                response.push_str(format!("Content-Length: {}\r\n", body.len()).as_str());
+            }
+            headers
+                .iter()
+                .for_each(|(k, v)| response.push_str(format!("{}: {}\r\n", k, v).as_str()));
+            response.push_str("\r\n");
+            let mut stream: TcpStream = stream;
+            let _ = stream
+                .write_all(response.as_bytes())
+                .and_then(|_| stream.write_all(body.as_slice()));
+        }
+
+        /// ### decode
+        ///
+        /// Decode percent-encoded `text`
+        fn decode(text: &str) -> String {
+            let bytes: &[u8] = text.as_bytes();
+            let mut decoded: Vec<u8> = Vec::with_capacity(bytes.len());
+            let mut i: usize = 0;
+            while i < bytes.len() {
+                match (bytes[i], text.get(i + 1..i + 3)) {
+                    (b'%', Some(hex)) if u8::from_str_radix(hex, 16).is_ok() => {
+                        decoded.push(u8::from_str_radix(hex, 16).unwrap());
+                        i += 3;
+                    }
+                    (byte, _) => {
+                        decoded.push(byte);
+                        i += 1;
+                    }
+                }
+            }
+            String::from_utf8(decoded).unwrap()
+        }
+    }
+
+    impl MockState {
+        fn respond(&mut self, request: &MockRequest, body: Vec<u8>) -> MockResponse {
+            let query: &HashMap<String, String> = &request.query;
+            let key: &str = request.key.as_str();
+            let upload_id: Option<&String> = query.get("uploadId");
+            match request.method.as_str() {
+                "GET" if query.contains_key("list-type") => (200, vec![], self.list(query)),
+                "GET" if query.contains_key("versions") => (200, vec![], self.list_versions(query)),
+                "GET" if query.contains_key("tagging") => match self.objects.get(key) {
+                    Some(object) => (200, vec![], Self::tagging(object.tags.as_slice())),
+                    None => Self::not_found(),
+                },
+                "PUT" if query.contains_key("tagging") => match self.objects.get_mut(key) {
+                    Some(object) => {
+                        object.tags = Self::parse_tagging(body.as_slice());
+                        (200, vec![], vec![])
+                    }
+                    None => Self::not_found(),
+                },
+                "HEAD" | "GET" => match self.objects.get(key) {
+                    Some(object) => Self::get(request, object),
+                    None => Self::not_found(),
+                },
+                "POST" if query.contains_key("uploads") => {
+                    let upload_id: String = format!("upload-{}", self.requests.len());
+                    let object = MockObject {
+                        headers: Self::stored_headers(&request.headers),
+                        tags: Self::tagging_header(&request.headers),
+                        ..MockObject::default()
+                    };
+                    self.uploads
+                        .insert(upload_id.clone(), (object, BTreeMap::new()));
+                    let body: String = format!("<InitiateMultipartUploadResult><Bucket>test</Bucket><Key>{}</Key><UploadId>{}</UploadId></InitiateMultipartUploadResult>", key, upload_id);
+                    (200, vec![], body.into_bytes())
+                }
+                "POST" if upload_id.is_some() => {
+                    let (mut object, parts) = match self.uploads.remove(upload_id.unwrap()) {
+                        Some(upload) => upload,
+                        None => return Self::not_found(),
+                    };
+                    let digests: Vec<u8> = parts.values().flat_map(|x| md5::compute(x).0).collect();
+                    object.data = parts.values().flatten().copied().collect();
+                    object.e_tag = format!("{:x}-{}", md5::compute(digests), parts.len());
+                    let body: String = format!("<CompleteMultipartUploadResult><Bucket>test</Bucket><Key>{}</Key><ETag>\"{}\"</ETag></CompleteMultipartUploadResult>", key, object.e_tag);
+                    self.objects.insert(key.to_string(), object);
+                    (200, vec![], body.into_bytes())
+                }
+                "PUT" if upload_id.is_some() => {
+                    let part_number: u32 = query["partNumber"].parse().unwrap();
+                    let copy: bool = request.headers.contains_key("x-amz-copy-source");
+                    let data: Vec<u8> = match copy {
+                        true => match self.copy_source(request) {
+                            Some(source) => source.data,
+                            None => return Self::not_found(),
+                        },
+                        false => body,
+                    };
+                    let e_tag: String = format!("\"{:x}\"", md5::compute(data.as_slice()));
+                    match self.uploads.get_mut(upload_id.unwrap()) {
+                        Some((_, parts)) => parts.insert(part_number, data),
+                        None => return Self::not_found(),
+                    };
+                    match copy {
+                        true => (
+                            200,
+                            vec![],
+                            format!("<CopyPartResult><ETag>{}</ETag></CopyPartResult>", e_tag)
+                                .into_bytes(),
+                        ),
+                        false => (200, vec![(String::from("ETag"), e_tag)], vec![]),
+                    }
+                }
+                "PUT" if request.headers.contains_key("x-amz-copy-source") => {
+                    let source: MockObject = match self.copy_source(request) {
+                        Some(source) => source,
+                        None => return Self::not_found(),
+                    };
+                    if self.failing_copies {
+                        return (
+                            200,
+                            vec![],
+                            b"<Error><Code>InternalError</Code><Message>We encountered an internal error</Message></Error>".to_vec(),
+                        );
+                    }
+                    let replace: bool = request
+                        .headers
+                        .get("x-amz-metadata-directive")
+                        .map(|x| x.as_str())
+                        == Some("REPLACE");
+                    let mut headers: BTreeMap<String, String> = match replace {
+                        true => BTreeMap::new(),
+                        false => source
+                            .headers
+                            .into_iter()
+                            .filter(|(k, _)| k == "content-type" || k.starts_with("x-amz-meta-"))
+                            .collect(),
+                    };
+                    // Storage class and encryption are never copied
+                    Self::stored_headers(&request.headers)
+                        .into_iter()
+                        .filter(|(k, _)| {
+                            replace || !(k == "content-type" || k.starts_with("x-amz-meta-"))
+                        })
+                        .for_each(|(k, v)| {
+                            headers.insert(k, v);
+                        });
+                    let object = MockObject {
+                        e_tag: source.e_tag,
+                        data: source.data,
+                        headers,
+                        tags: source.tags,
+                        size: source.size,
+                    };
+                    let body: String = format!(
+                        "<CopyObjectResult><ETag>\"{}\"</ETag></CopyObjectResult>",
+                        object.e_tag
+                    );
+                    self.objects.insert(key.to_string(), object);
+                    (200, vec![], body.into_bytes())
+                }
+                "PUT" => {
+                    let object = MockObject {
+                        e_tag: format!("{:x}", md5::compute(body.as_slice())),
+                        data: body,
+                        headers: Self::stored_headers(&request.headers),
+                        tags: Self::tagging_header(&request.headers),
+                        size: None,
+                    };
+                    let e_tag: String = format!("\"{}\"", object.e_tag);
+                    self.objects.insert(key.to_string(), object);
+                    (200, vec![(String::from("ETag"), e_tag)], vec![])
+                }
+                "DELETE" => {
+                    match upload_id {
+                        Some(upload_id) => self.uploads.remove(upload_id).map(|_| ()),
+                        None => self.objects.remove(key).map(|_| ()),
+                    };
+                    (204, vec![], vec![])
+                }
+                _ => (400, vec![], vec![]),
+            }
+        }
+
+        /// ### get
+        ///
+        /// Respond to HEAD and (ranged) GET requests for `object`
+        fn get(request: &MockRequest, object: &MockObject) -> MockResponse {
+            let mut headers: Vec<(String, String)> = object.headers.clone().into_iter().collect();
+            headers.push((String::from("ETag"), format!("\"{}\"", object.e_tag)));
+            headers.push((
+                String::from("Last-Modified"),
+                String::from("Fri, 01 Jan 2021 00:00:00 GMT"),
+            ));
+            let range: Option<(usize, usize)> = request
+                .headers
+                .get("range")
+                .and_then(|x| x.strip_prefix("bytes="))
+                .and_then(|x| x.split_once('-'))
+                .map(|(start, end)| {
+                    let start: usize = start.parse().unwrap();
+                    let end: usize = end
+                        .parse::<usize>()
+                        .map(|x| x + 1)
+                        .unwrap_or(object.data.len());
+                    (start, end.min(object.data.len()))
+                });
+            let (status, data): (u16, &[u8]) = match range {
+                Some((start, end)) => (206, &object.data[start..end]),
+                None => (200, object.data.as_slice()),
+            };
+            match request.method.as_str() {
+                "HEAD" => {
+                    let size: usize = object.size.unwrap_or(data.len());
+                    headers.push((String::from("Content-Length"), size.to_string()));
+                    (status, headers, vec![])
+                }
+                _ => (status, headers, data.to_vec()),
+            }
+        }
+
+        /// ### list
+        ///
+        /// Respond to ListObjectsV2 with every matching key in a single page
+        fn list(&self, query: &HashMap<String, String>) -> Vec<u8> {
+            let prefix: String = query.get("prefix").cloned().unwrap_or_default();
+            let mut contents: String = String::new();
+            let mut prefixes: BTreeSet<String> = BTreeSet::new();
+            for (key, object) in self
+                .objects
+                .iter()
+                .filter(|(k, _)| k.starts_with(prefix.as_str()))
+            {
+                let common: Option<String> = query.get("delimiter").and_then(|delimiter| {
+                    key[prefix.len()..]
+                        .find(delimiter.as_str())
+                        .map(|i| key[..prefix.len() + i + delimiter.len()].to_string())
+                });
+                match common {
+                    Some(common) => {
+                        prefixes.insert(common);
+                    }
+                    None => contents.push_str(format!("<Contents><Key>{}</Key><LastModified>2021-01-01T00:00:00.000Z</LastModified><ETag>\"{}\"</ETag><Size>{}</Size><StorageClass>STANDARD</StorageClass></Contents>", key, object.e_tag, object.data.len()).as_str()),
+                }
+            }
+            let prefixes: String = prefixes
+                .iter()
+                .map(|x| format!("<CommonPrefixes><Prefix>{}</Prefix></CommonPrefixes>", x))
+                .collect();
+            format!("<ListBucketResult><Name>test</Name><Prefix>{}</Prefix><IsTruncated>false</IsTruncated>{}{}</ListBucketResult>", prefix, contents, prefixes).into_bytes()
+        }
+
+        /// ### list_versions
+        ///
+        /// Respond to ListObjectVersions with up to `MOCK_VERSIONS_PAGE` entries after the markers
+        fn list_versions(&self, query: &HashMap<String, String>) -> Vec<u8> {
+            let prefix: String = query.get("prefix").cloned().unwrap_or_default();
+            let start: usize = self
+                .versions
+                .iter()
+                .position(|x| {
+                    Some(&x.key) == query.get("key-marker")
+                        && Some(&x.version_id) == query.get("version-id-marker")
+                })
+                .map(|x| x + 1)
+                .unwrap_or_default();
+            let matching: Vec<&ObjectVersion> = self.versions[start..]
+                .iter()
+                .filter(|x| x.key.starts_with(prefix.as_str()))
+                .collect();
+            let page: &[&ObjectVersion] = &matching[..matching.len().min(MOCK_VERSIONS_PAGE)];
+            let mut entries: String = String::new();
+            for version in page.iter() {
+                let (tag, e_tag) = match version.delete_marker {
+                    true => ("DeleteMarker", String::new()),
+                    false => (
+                        "Version",
+                        format!(
+                            "<ETag>\"{}\"</ETag><Size>{}</Size>",
+                            version.e_tag.as_deref().unwrap_or_default(),
+                            version.size
+                        ),
+                    ),
+                };
+                entries.push_str(format!("<{}><Key>{}</Key><VersionId>{}</VersionId><IsLatest>{}</IsLatest><LastModified>{}</LastModified>{}</{}>", tag, version.key, version.version_id, version.is_latest, version.last_modified, e_tag, tag).as_str());
+            }
+            let markers: String = match (matching.len() > page.len(), page.last()) {
+                (true, Some(last)) => format!(
+                    "<IsTruncated>true</IsTruncated><NextKeyMarker>{}</NextKeyMarker><NextVersionIdMarker>{}</NextVersionIdMarker>",
+                    last.key, last.version_id
+                ),
+                _ => String::from("<IsTruncated>false</IsTruncated>"),
+            };
+            format!(
+                "<ListVersionsResult><Name>test</Name><Prefix>{}</Prefix>{}{}</ListVersionsResult>",
+                prefix, markers, entries
+            )
+            .into_bytes()
+        }
+
+        /// ### copy_source
+        ///
+        /// Get the object named by the `x-amz-copy-source` header, sliced to `x-amz-copy-source-range`
+        fn copy_source(&self, request: &MockRequest) -> Option<MockObject> {
+            let source: &str = request.headers["x-amz-copy-source"].as_str();
+            let source: &str = source.split('?').next().unwrap_or_default();
+            let (_, key) = source.trim_start_matches('/').split_once('/')?;
+            let mut object: MockObject = self.objects.get(MockS3::decode(key).as_str())?.clone();
+            if let Some((start, end)) = request
+                .headers
+                .get("x-amz-copy-source-range")
+                .and_then(|x| x.strip_prefix("bytes="))
+                .and_then(|x| x.split_once('-'))
+            {
+                let (start, end): (usize, usize) = (start.parse().ok()?, end.parse().ok()?);
+                let len: usize = object.data.len();
+                object.data = object.data[start.min(len)..(end + 1).min(len)].to_vec();
+            }
+            Some(object)
+        }
+
+        /// ### stored_headers
+        ///
+        /// Get the request headers S3 stores along with the object
+        fn stored_headers(headers: &HashMap<String, String>) -> BTreeMap<String, String> {
+            headers
+                .iter()
+                .filter(|(k, _)| {
+                    k.as_str() == "content-type"
+                        || k.as_str() == "x-amz-storage-class"
+                        || k.starts_with("x-amz-meta-")
+                        || k.starts_with("x-amz-server-side-encryption")
+                })
+                .map(|(k, v)| (k.clone(), v.clone()))
+                .collect()
+        }
+
+        /// ### tagging_header
+        ///
+        /// Parse the `x-amz-tagging` header (`k1=v1&k2=v2`)
+        fn tagging_header(headers: &HashMap<String, String>) -> Vec<(String, String)> {
+            headers
+                .get("x-amz-tagging")
+                .map(|x| {
+                    x.split('&')
+                        .filter_map(|x| x.split_once('='))
+                        .map(|(k, v)| (MockS3::decode(k), MockS3::decode(v)))
+                        .collect()
+                })
+                .unwrap_or_default()
+        }
+
+        fn tagging(tags: &[(String, String)]) -> Vec<u8> {
+            let tags: String = tags
+                .iter()
+                .map(|(k, v)| format!("<Tag><Key>{}</Key><Value>{}</Value></Tag>", k, v))
+                .collect();
+            format!("<Tagging><TagSet>{}</TagSet></Tagging>", tags).into_bytes()
+        }
+
+        fn parse_tagging(body: &[u8]) -> Vec<(String, String)> {
+            let text: String = String::from_utf8_lossy(body).to_string();
+            let inner = |tag: &str, name: &str| -> String {
+                tag.split(format!("<{}>", name).as_str())
+                    .nth(1)
+                    .and_then(|x| x.split(format!("</{}>", name).as_str()).next())
+                    .unwrap_or_default()
+                    .to_string()
+            };
+            text.split("<Tag>")
+                .skip(1)
+                .map(|tag| (inner(tag, "Key"), inner(tag, "Value")))
+                .collect()
+        }
+
+        fn not_found() -> MockResponse {
+            (
+                404,
+                vec![],
+                b"<Error><Code>NoSuchKey</Code><Message>Not found</Message></Error>".to_vec(),
+            )
+        }
+    }
+
+    /// ### sample
+    ///
+    /// Get `size` bytes of data which differ from part to part
+    pub(crate) fn sample(size: usize) -> Vec<u8> {
+        (0..size).map(|x| (x % 251) as u8).collect()
+    }
+
+    #[test]
+    fn should_match_wildcards() {
+        assert!(S3Bucket::wildmatch("*.txt", "readme.txt"));
+        assert!(S3Bucket::wildmatch("read??.*", "readme.md"));
+        assert!(S3Bucket::wildmatch("a*b*c", "aXXbYYbc"));
+        assert!(S3Bucket::wildmatch("*", ""));
+        assert!(!S3Bucket::wildmatch("*.txt", "readme.md"));
+        assert!(!S3Bucket::wildmatch("?", ""));
+    }
+
+    #[test]
+    fn should_parse_location() {
+        assert_eq!(
+            S3Bucket::parse_location("s3://other/foo/bar.txt"),
+            (Some(String::from("other")), String::from("foo/bar.txt"))
+        );
+        assert_eq!(
+            S3Bucket::parse_location("s3://other"),
+            (Some(String::from("other")), String::new())
+        );
+        assert_eq!(
+            S3Bucket::parse_location("/foo/bar.txt"),
+            (None, String::from("foo/bar.txt"))
+        );
+    }
+
+    #[test]
+    fn should_resolve_local_paths() {
+        let dest: &Path = Path::new("/tmp/dest");
+        assert_eq!(
+            S3Bucket::local_path(dest, "docs/a.md").unwrap(),
+            PathBuf::from("/tmp/dest/docs/a.md")
+        );
+        assert_eq!(
+            S3Bucket::local_path(dest, "docs/").unwrap(),
+            PathBuf::from("/tmp/dest/docs")
+        );
+        assert_eq!(S3Bucket::local_path(dest, "").unwrap(), dest.to_path_buf());
+        assert!(S3Bucket::local_path(dest, "dir/../../x").is_err());
+        assert!(S3Bucket::local_path(dest, "..").is_err());
+        assert!(S3Bucket::local_path(dest, "/etc/x").is_err());
+        assert!(S3Bucket::local_path(dest, "dir//x").is_err());
+        assert!(S3Bucket::local_path(dest, "./x").is_err());
+    }
+
+    #[test]
+    fn should_format_copy_source() {
+        assert_eq!(
+            S3Bucket::copy_source("bucket", "my file.txt", None),
+            "/bucket/my%20file.txt"
+        );
+        assert_eq!(
+            S3Bucket::copy_source("bucket", "a.txt", Some("abc")),
+            "/bucket/a.txt?versionId=abc"
+        );
+    }
+
+    #[test]
+    fn should_url_encode_keys() {
+        assert_eq!(S3Bucket::url_encode("foo/bar.txt"), "foo/bar.txt");
+        assert_eq!(
+            S3Bucket::url_encode("my docs/résumé+1.pdf"),
+            "my%20docs/r%C3%A9sum%C3%A9%2B1.pdf"
+        );
+    }
+
+    #[test]
+    fn should_split_parts() {
+        assert_eq!(
+            S3Bucket::part_ranges(25, 10),
+            vec![(0, 10), (10, 20), (20, 25)]
+        );
+        assert_eq!(S3Bucket::part_ranges(20, 10), vec![(0, 10), (10, 20)]);
+        assert!(S3Bucket::part_ranges(0, 10).is_empty());
+    }
+
+    #[test]
+    fn should_fit_part_size() {
+        assert_eq!(S3Bucket::fit_part_size(100, 10), 10);
+        assert_eq!(S3Bucket::fit_part_size(MAX_PARTS * 20 + 1, 10), 21);
+    }
+
+    #[test]
+    fn should_run_parallel_jobs() {
+        let done: Mutex<Vec<usize>> = Mutex::new(Vec::new());
+        assert!(S3Bucket::run_parallel(3, 10, |i| {
+            done.lock().unwrap().push(i);
+            Ok(())
+        })
+        .is_ok());
+        let mut done = done.into_inner().unwrap();
+        done.sort_unstable();
+        assert_eq!(done, (0..10).collect::<Vec<usize>>());
+        assert_eq!(
+            S3Bucket::run_parallel(2, 10, |i| match i {
+                4 => Err(String::from("failure")),
+                _ => Ok(()),
+            })
+            .err()
+            .unwrap(),
+            "failure"
+        );
+    }
+
+    #[test]
+    fn should_put_and_get_object() {
+        let server: MockS3 = MockS3::start();
+        let bucket: S3Bucket = server.connect();
+        let tmp: TempDir = TempDir::new().unwrap();
+        let src: PathBuf = tmp.path().join("hello.txt");
+        fs::write(src.as_path(), b"hello").unwrap();
+        assert!(bucket.put(src.as_path(), "hello.txt").is_ok());
+        assert_eq!(server.object("hello.txt").unwrap().data, b"hello");
+        let dest: PathBuf = tmp.path().join("copy.txt");
+        assert!(bucket.get("hello.txt", dest.as_path()).is_ok());
+        assert_eq!(fs::read(dest.as_path()).unwrap(), b"hello");
+        assert!(bucket.get("missing.txt", dest.as_path()).is_err());
+    }
+
+    #[test]
+    fn should_put_and_get_multipart() {
+        let server: MockS3 = MockS3::start();
+        let mut bucket: S3Bucket = server.connect();
+        assert!(bucket.set_part_size(MIN_PART_SIZE).is_ok());
+        let tmp: TempDir = TempDir::new().unwrap();
+        let src: PathBuf = tmp.path().join("large.bin");
+        let data: Vec<u8> = sample(MIN_PART_SIZE * 2 + 100);
+        fs::write(src.as_path(), data.as_slice()).unwrap();
+        assert!(bucket.put(src.as_path(), "large.bin").is_ok());
+        // Three parts, sent in order of part number
+        let parts: Vec<String> = server
+            .requests("PUT")
+            .into_iter()
+            .filter_map(|x| x.query.get("partNumber").cloned())
+            .collect();
+        assert_eq!(parts.len(), 3);
+        let object: MockObject = server.object("large.bin").unwrap();
+        assert!(object.data == data);
+        assert!(object.e_tag.ends_with("-3"));
+        // Ranged download
+        let dest: PathBuf = tmp.path().join("download.bin");
+        assert!(bucket.get("large.bin", dest.as_path()).is_ok());
+        assert!(fs::read(dest.as_path()).unwrap() == data);
+        let mut ranges: Vec<String> = server
+            .requests("GET")
+            .into_iter()
+            .filter_map(|x| x.headers.get("range").cloned())
+            .collect();
+        ranges.sort();
+        assert_eq!(
+            ranges,
+            vec![
+                String::from("bytes=0-5242879"),
+                String::from("bytes=10485760-10485859"),
+                String::from("bytes=5242880-10485759"),
+            ]
+        );
+    }
+
+    #[test]
+    fn should_put_and_get_recursive() {
+        let server: MockS3 = MockS3::start();
+        let bucket: S3Bucket = server.connect();
+        let tmp: TempDir = TempDir::new().unwrap();
+        let src: PathBuf = tmp.path().join("src");
+        fs::create_dir_all(src.join("docs/empty")).unwrap();
+        fs::write(src.join("readme.md"), b"# readme").unwrap();
+        fs::write(src.join("docs/guide.md"), b"# guide").unwrap();
+        assert!(bucket.put_recursive(src.as_path(), "backup").is_ok());
+        assert_eq!(
+            server.keys(),
+            vec![
+                "backup/",
+                "backup/docs/",
+                "backup/docs/empty/",
+                "backup/docs/guide.md",
+                "backup/readme.md",
+            ]
+        );
+        let dest: PathBuf = tmp.path().join("dest");
+        assert!(bucket.get_recursive("backup", dest.as_path()).is_ok());
+        assert_eq!(fs::read(dest.join("readme.md")).unwrap(), b"# readme");
+        assert_eq!(fs::read(dest.join("docs/guide.md")).unwrap(), b"# guide");
+        assert!(dest.join("docs/empty").is_dir());
+        assert!(bucket.get_recursive("missing", dest.as_path()).is_err());
+    }
+
+    #[test]
+    fn should_not_get_recursive_outside_dest() {
+        let server: MockS3 = MockS3::start();
+        let bucket: S3Bucket = server.connect();
+        let tmp: TempDir = TempDir::new().unwrap();
+        let dest: PathBuf = tmp.path().join("a/dest");
+        for key in ["dir/../../x", "dir//x", "dir/./x"] {
+            server.insert("backup/ok.md", b"# ok");
+            server.insert(format!("backup/{}", key).as_str(), b"pwned");
+            assert!(bucket
+                .get_recursive("backup", dest.as_path())
+                .unwrap_err()
+                .contains("unsafe object key"));
+            assert!(!dest.join("ok.md").exists());
+            assert!(!tmp.path().join("x").exists());
+            assert!(!tmp.path().join("a/x").exists());
+            assert!(bucket.remove(format!("backup/{}", key).as_str()).is_ok());
+        }
+    }
+
+    #[test]
+    fn should_set_metadata_tags_and_storage_class() {
+        let server: MockS3 = MockS3::start();
+        let bucket: S3Bucket = server.connect();
+        let tmp: TempDir = TempDir::new().unwrap();
+        let src: PathBuf = tmp.path().join("report.csv");
+        fs::write(src.as_path(), b"a,b").unwrap();
+        assert!(bucket.put(src.as_path(), "report.csv").is_ok());
+        let tags: Vec<(String, String)> = vec![(String::from("team"), String::from("data"))];
+        assert!(bucket.set_tags("report.csv", tags.as_slice()).is_ok());
+        let metadata: Vec<(String, String)> = vec![(String::from("owner"), String::from("omar"))];
+        assert!(bucket
+            .set_metadata("report.csv", metadata.as_slice())
+            .is_ok());
+        assert!(bucket
+            .set_storage_class("report.csv", "standard_ia")
+            .is_ok());
+        let object: MockObject = server.object("report.csv").unwrap();
+        assert_eq!(object.data, b"a,b");
+        assert_eq!(object.tags, tags);
+        assert_eq!(
+            object.headers.get("x-amz-meta-owner").map(|x| x.as_str()),
+            Some("omar")
+        );
+        assert_eq!(
+            object
+                .headers
+                .get("x-amz-storage-class")
+                .map(|x| x.as_str()),
+            Some("STANDARD_IA")
+        );
+        let current: ObjectMetadata = bucket.metadata("report.csv").unwrap();
+        assert_eq!(current.tags, tags);
+        assert_eq!(current.storage_class, "STANDARD_IA");
+    }
+
+    #[test]
+    fn should_copy_and_move_objects() {
+        let server: MockS3 = MockS3::start();
+        let bucket: S3Bucket = server.connect();
+        let tmp: TempDir = TempDir::new().unwrap();
+        fs::create_dir_all(tmp.path().join("docs")).unwrap();
+        fs::write(tmp.path().join("docs/a.md"), b"# a").unwrap();
+        fs::write(tmp.path().join("docs/b.md"), b"# b").unwrap();
+        assert!(bucket
+            .put_recursive(tmp.path().join("docs").as_path(), "docs")
+            .is_ok());
+        assert_eq!(bucket.copy("docs/a.md", "a.md", false).unwrap(), 1);
+        assert_eq!(server.object("a.md").unwrap().data, b"# a");
+        assert_eq!(bucket.rename("docs", "archive", true).unwrap(), 3);
+        assert_eq!(
+            server.keys(),
+            vec!["a.md", "archive/", "archive/a.md", "archive/b.md"]
+        );
+        assert!(bucket.copy("missing.md", "copy.md", false).is_err());
+    }
+
+    #[test]
+    fn should_keep_source_when_copy_fails_with_success_status() {
+        let server: MockS3 = MockS3::start();
+        let bucket: S3Bucket = server.connect();
+        let tmp: TempDir = TempDir::new().unwrap();
+        fs::write(tmp.path().join("a.md"), b"# a").unwrap();
+        assert!(bucket
+            .put(tmp.path().join("a.md").as_path(), "a.md")
+            .is_ok());
+        server.fail_copies();
+        let deletes: usize = server.requests("DELETE").len();
+        let err: String = bucket.rename("a.md", "b.md", false).unwrap_err();
+        assert!(err.contains("InternalError"));
+        assert_eq!(server.requests("DELETE").len(), deletes);
+        assert_eq!(server.object("a.md").unwrap().data, b"# a");
+        assert!(server.object("b.md").is_none());
+    }
+
+    #[test]
+    fn should_not_move_objects_onto_themselves() {
+        let server: MockS3 = MockS3::start();
+        let bucket: S3Bucket = server.connect();
+        server.insert("big", b"big");
+        server.insert("p/a.md", b"# a");
+        server.set_size("big", MAX_COPY_SIZE + 1);
+        assert!(bucket.rename("big", "/big", false).is_err());
+        assert!(bucket.rename("p", "p/", true).is_err());
+        assert!(bucket.rename("s3://test/big", "big", false).is_err());
+        assert!(server.requests("PUT").is_empty());
+        assert!(server.requests("DELETE").is_empty());
+        assert_eq!(server.object("big").unwrap().data, b"big");
+        assert_eq!(server.object("p/a.md").unwrap().data, b"# a");
+        // Copying onto itself is left to S3
+        assert!(bucket.copy("big", "/big", false).is_ok());
+        assert_eq!(server.object("big").unwrap().data, b"big");
+    }
+
+    #[test]
+    fn should_preserve_content_type_when_setting_metadata() {
+        let server: MockS3 = MockS3::start();
+        let bucket: S3Bucket = server.connect();
+        let tmp: TempDir = TempDir::new().unwrap();
+        let src: PathBuf = tmp.path().join("empty.txt");
+        fs::write(src.as_path(), b"").unwrap();
+        assert!(bucket.put(src.as_path(), "empty.txt").is_ok());
+        let metadata: Vec<(String, String)> = vec![(String::from("owner"), String::from("me"))];
+        assert!(bucket
+            .set_metadata("empty.txt", metadata.as_slice())
+            .is_ok());
+        let current: ObjectMetadata = bucket.metadata("empty.txt").unwrap();
+        assert_eq!(
+            current.content_type.as_deref(),
+            Some("application/octet-stream")
+        );
+        assert_eq!(current.metadata["owner"], "me");
+        // Content type of non empty objects comes from a ranged GET
+        fs::write(src.as_path(), b"hello").unwrap();
+        assert!(bucket.put(src.as_path(), "hello.txt").is_ok());
+        assert_eq!(
+            bucket
+                .metadata("hello.txt")
+                .unwrap()
+                .content_type
+                .as_deref(),
+            Some("application/octet-stream")
+        );
+    }
+
+    #[test]
+    fn should_keep_encryption_when_changing_metadata_and_storage_class() {
+        let server: MockS3 = MockS3::start();
+        let mut bucket: S3Bucket = server.connect();
+        bucket.set_encryption(EncryptionOptions {
+            client: None,
+            server: Some(ServerSideEncryption::Kms(Some(String::from("key-1")))),
+        });
+        let tmp: TempDir = TempDir::new().unwrap();
+        let src: PathBuf = tmp.path().join("secret.txt");
+        fs::write(src.as_path(), b"secret").unwrap();
+        assert!(bucket.put(src.as_path(), "secret.txt").is_ok());
+        // Encryption is not configured anymore, it must come from the object
+        bucket.set_encryption(EncryptionOptions::default());
+        let metadata: Vec<(String, String)> = vec![(String::from("owner"), String::from("me"))];
+        assert!(bucket
+            .set_metadata("secret.txt", metadata.as_slice())
+            .is_ok());
+        assert!(bucket
+            .set_storage_class("secret.txt", "standard_ia")
+            .is_ok());
+        let object: MockObject = server.object("secret.txt").unwrap();
+        assert_eq!(object.data, b"secret");
+        assert_eq!(object.headers["x-amz-server-side-encryption"], "aws:kms");
+        assert_eq!(
+            object.headers["x-amz-server-side-encryption-aws-kms-key-id"],
+            "key-1"
+        );
+        assert_eq!(object.headers["x-amz-meta-owner"], "me");
+        assert_eq!(object.headers["x-amz-storage-class"], "STANDARD_IA");
+        let current: ObjectMetadata = bucket.metadata("secret.txt").unwrap();
+        assert_eq!(
+            current.server_side_encryption,
+            Some(ServerSideEncryption::Kms(Some(String::from("key-1"))))
+        );
+        assert_eq!(
+            current.content_type.as_deref(),
+            Some("application/octet-stream")
+        );
+    }
+
+    #[test]
+    fn should_carry_source_properties_to_multipart_copy() {
+        let source = ObjectMetadata {
+            content_type: Some(String::from("text/plain")),
+            storage_class: String::from("STANDARD_IA"),
+            metadata: vec![
+                (String::from("owner"), String::from("me")),
+                (String::from("app"), String::from("brol")),
+            ]
+            .into_iter()
+            .collect(),
+            tags: vec![
+                (String::from("env"), String::from("prod")),
+                (String::from("team"), String::from("a&b")),
+            ],
+            ..ObjectMetadata::default()
+        };
+        let header = |k: &str, v: &str| (k.to_string(), v.to_string());
+        assert_eq!(
+            S3Bucket::multipart_copy_headers(&source, &[]),
+            vec![
+                header("content-type", "text/plain"),
+                header("x-amz-meta-app", "brol"),
+                header("x-amz-meta-owner", "me"),
+                header("x-amz-storage-class", "STANDARD_IA"),
+                header("x-amz-tagging", "env=prod&team=a%26b"),
+            ]
+        );
+        // Storage class change
+        assert_eq!(
+            S3Bucket::multipart_copy_headers(
+                &source,
+                &[
+                    header("x-amz-metadata-directive", "COPY"),
+                    header("x-amz-storage-class", "GLACIER"),
+                ]
+            ),
+            vec![
+                header("content-type", "text/plain"),
+                header("x-amz-meta-app", "brol"),
+                header("x-amz-meta-owner", "me"),
+                header("x-amz-tagging", "env=prod&team=a%26b"),
+                header("x-amz-storage-class", "GLACIER"),
+            ]
+        );
+        // Metadata replacement
+        assert_eq!(
+            S3Bucket::multipart_copy_headers(
+                &source,
+                &[
+                    header("x-amz-metadata-directive", "REPLACE"),
+                    header("content-type", "text/plain"),
+                    header("x-amz-storage-class", "STANDARD_IA"),
+                    header("x-amz-meta-owner", "you"),
+                ]
+            ),
+            vec![
+                header("x-amz-tagging", "env=prod&team=a%26b"),
+                header("content-type", "text/plain"),
+                header("x-amz-storage-class", "STANDARD_IA"),
+                header("x-amz-meta-owner", "you"),
+            ]
+        );
+    }
+
+    #[test]
+    fn should_keep_data_key_when_setting_metadata() {
+        let server: MockS3 = MockS3::start();
+        let mut bucket: S3Bucket = server.connect();
+        bucket.set_encryption(EncryptionOptions {
+            client: Some(MasterKey::new([3; 32])),
+            server: None,
+        });
+        let tmp: TempDir = TempDir::new().unwrap();
+        let src: PathBuf = tmp.path().join("secret.txt");
+        fs::write(src.as_path(), b"secret").unwrap();
+        assert!(bucket.put(src.as_path(), "secret.txt").is_ok());
+        let metadata: Vec<(String, String)> = vec![(String::from("owner"), String::from("me"))];
+        assert!(bucket
+            .set_metadata("secret.txt", metadata.as_slice())
+            .is_ok());
+        let object: MockObject = server.object("secret.txt").unwrap();
+        assert_eq!(object.headers["x-amz-meta-owner"], "me");
+        assert!(object.headers.contains_key("x-amz-meta-cse-key"));
+        let dest: PathBuf = tmp.path().join("plain.txt");
+        assert!(bucket.get("secret.txt", dest.as_path()).is_ok());
+        assert_eq!(fs::read(dest.as_path()).unwrap(), b"secret");
+        // Encryption metadata can't be overwritten
+        let metadata: Vec<(String, String)> = vec![(String::from("CSE-key"), String::from("x"))];
+        assert!(bucket
+            .set_metadata("secret.txt", metadata.as_slice())
+            .is_err());
+    }
+
+    #[test]
+    fn should_list_versions_across_pages() {
+        let server: MockS3 = MockS3::start();
+        let bucket: S3Bucket = server.connect();
+        let version = |key: &str, version_id: &str, day: u8, delete_marker: bool| ObjectVersion {
+            key: key.to_string(),
+            version_id: version_id.to_string(),
+            is_latest: day == 4,
+            last_modified: format!("2021-01-0{}T00:00:00.000Z", day),
+            size: 5,
+            e_tag: Some(String::from("5d41402abc4b2a76b9719d911017c592")),
+            delete_marker,
+        };
+        server.set_versions(vec![
+            version("a.txt", "v4", 4, true),
+            version("a.txt", "v2", 2, false),
+            version("a.txt", "v3", 3, false),
+            version("a.txt", "v1", 1, false),
+            version("a.txt.bak", "v5", 4, false),
+        ]);
+        let versions: Vec<ObjectVersion> = bucket.versions("/a.txt").unwrap();
+        assert_eq!(
+            versions
+                .iter()
+                .map(|x| x.version_id.as_str())
+                .collect::<Vec<&str>>(),
+            vec!["v4", "v3", "v2", "v1"]
+        );
+        assert!(versions[0].delete_marker);
+        assert_eq!(
+            versions[1].e_tag.as_deref(),
+            Some("5d41402abc4b2a76b9719d911017c592")
+        );
+        // Versions are listed on the bucket root, with the markers of the previous page
+        let requests: Vec<MockRequest> = server
+            .requests("GET")
+            .into_iter()
+            .filter(|x| x.query.contains_key("versions"))
+            .collect();
+        assert_eq!(requests.len(), 3);
+        assert!(requests.iter().all(|x| x.key.is_empty()));
+        assert!(requests.iter().all(|x| x.query["prefix"] == "a.txt"));
+        assert!(!requests[0].query.contains_key("key-marker"));
+        assert_eq!(requests[1].query["key-marker"], "a.txt");
+        assert_eq!(requests[1].query["version-id-marker"], "v2");
+        assert_eq!(requests[2].query["version-id-marker"], "v1");
+    }
+}
@@ -15,30 +15,175 @@
  *
  *   0. You just DO WHAT THE FUCK YOU WANT TO.
 */
+use crate::sync::{SyncDirection, SyncOptions};
+
+use std::fmt;
 use std::path::PathBuf;
 use std::str::FromStr;
 
+/// Default validity of a presigned URL (1 hour)
+const DEFAULT_PRESIGN_EXPIRY: u32 = 3600;
+
 #[derive(Debug, Eq, PartialEq)]
 pub enum Command {
     Pwd,
     ChangeDir(String),
-    Copy(String, String),
+    Copy(String, String, bool),
+    Du(Option<String>),
+    Find(String),
     Help,
-    List(Option<String>),
+    List(Option<String>, bool),
     Mkdir(String),
-    Remove(String),
+    Move(String, String, bool),
+    Presign(String, bool, u32),
+    Remove(String, Option<String>),
+    Restore(String, String),
+    SetMetadata(String, Vec<(String, String)>),
+    SetTags(String, Vec<(String, String)>),
     Stat(String),
-    Put(PathBuf, String),
-    Get(String, PathBuf),
+    StorageClass(String, String),
+    Put(PathBuf, String, bool),
+    Get(String, PathBuf, bool, Option<String>),
+    Set(String, String),
+    Sync(PathBuf, String, SyncDirection, SyncOptions),
+    Versions(String),
     Quit,
 }
 
+/// ## ParseError
+///
+/// Describes why a command couldn't be parsed
+#[derive(Debug, Eq, PartialEq)]
+pub enum ParseError {
+    Empty,
+    UnknownCommand(String),
+    UnknownOption(String),
+    MissingArgument(&'static str),
+    InvalidArgument(&'static str, String),
+    ConflictingOptions(&'static str, &'static str),
+    TooManyCommands,
+    UnterminatedQuote,
+    TrailingEscape,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Empty => write!(f, "Empty command"),
+            Self::UnknownCommand(cmd) => write!(f, "Unknown command `{}`", cmd),
+            Self::UnknownOption(opt) => write!(f, "Unknown option `{}`", opt),
+            Self::MissingArgument(name) => write!(f, "Missing `{}` field", name),
+            Self::InvalidArgument(name, value) => write!(f, "Invalid `{}` field: {}", name, value),
+            Self::ConflictingOptions(a, b) => {
+                write!(f, "`{}` and `{}` can't be used together", a, b)
+            }
+            Self::TooManyCommands => write!(f, "Expected a single command"),
+            Self::UnterminatedQuote => write!(f, "Unterminated quote"),
+            Self::TrailingEscape => write!(f, "Trailing escape character"),
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+impl Command {
+    /// ### parse_script
+    ///
+    /// Parse a sequence of commands separated by `;` or newlines.
+    /// Arguments can be quoted with `'` or `"` and characters escaped with `\`; `#` starts a comment
+    pub fn parse_script(s: &str) -> Result<Vec<Self>, ParseError> {
+        Self::tokenize(s)?
+            .iter()
+            .map(|tokens| Self::from_tokens(tokens.as_slice()))
+            .collect()
+    }
+
+    /// ### tokenize
+    ///
+    /// Split `s` into statements, each made of shell-like tokens
+    fn tokenize(s: &str) -> Result<Vec<Vec<String>>, ParseError> {
+        let mut statements: Vec<Vec<String>> = Vec::new();
+        let mut tokens: Vec<String> = Vec::new();
+        let mut token: Option<String> = None;
+        let mut chars = s.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '\'' => {
+                    let token = token.get_or_insert_with(String::new);
+                    loop {
+                        match chars.next() {
+                            Some('\'') => break,
+                            Some(c) => token.push(c),
+                            None => return Err(ParseError::UnterminatedQuote),
+                        }
+                    }
+                }
+                '"' => {
+                    let token = token.get_or_insert_with(String::new);
+                    loop {
+                        match chars.next() {
+                            Some('"') => break,
+                            Some('\\') => match chars.next() {
+                                Some(c @ '"') | Some(c @ '\\') => token.push(c),
+                                Some(c) => {
+                                    token.push('\\');
+                                    token.push(c);
+                                }
+                                None => return Err(ParseError::UnterminatedQuote),
+                            },
+                            Some(c) => token.push(c),
+                            None => return Err(ParseError::UnterminatedQuote),
+                        }
+                    }
+                }
+                '\\' => match chars.next() {
+                    Some('\n') => {} // Line continuation
+                    Some(c) => token.get_or_insert_with(String::new).push(c),
+                    None => return Err(ParseError::TrailingEscape),
+                },
+                '#' if token.is_none() => {
+                    // Skip comment until end of line
+                    while chars.peek().map(|x| *x != '\n').unwrap_or(false) {
+                        chars.next();
+                    }
+                }
+                ';' | '\n' => {
+                    tokens.extend(token.take());
+                    if !tokens.is_empty() {
+                        statements.push(std::mem::take(&mut tokens));
+                    }
+                }
+                c if c.is_whitespace() => tokens.extend(token.take()),
+                c => token.get_or_insert_with(String::new).push(c),
+            }
+        }
+        tokens.extend(token.take());
+        if !tokens.is_empty() {
+            statements.push(tokens);
+        }
+        Ok(statements)
+    }
+}
+
 impl FromStr for Command {
-    type Err = &'static str;
+    type Err = ParseError;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        // Split string by space
-        let mut args = s.split_ascii_whitespace();
+        let mut statements = Self::tokenize(s)?.into_iter();
+        match (statements.next(), statements.next()) {
+            (Some(tokens), None) => Self::from_tokens(tokens.as_slice()),
+            (None, _) => Err(ParseError::Empty),
+            (Some(_), Some(_)) => Err(ParseError::TooManyCommands),
+        }
+    }
+}
+
+impl Command {
+    /// ### from_tokens
+    ///
+    /// Make command from tokens
+    fn from_tokens(tokens: &[String]) -> Result<Self, ParseError> {
+        let mut args = tokens.iter().map(|x| x.as_str());
         // Match args
         match args.next() {
             Some(cmd) => match cmd.to_ascii_uppercase().as_str() {
@@ -46,58 +191,310 @@ impl FromStr for Command {
                 "QUIT" => Ok(Self::Quit),
                 "CD" => match args.next() {
                     Some(p) => Ok(Self::ChangeDir(p.to_string())),
-                    None => Err("Missing `dir` field"),
+                    None => Err(ParseError::MissingArgument("dir")),
                 },
-                "COPY" => {
-                    let src: String = match args.next() {
+                "COPY" | "CP" | "MOVE" | "MV" => {
+                    let mut src = args.next();
+                    let recursive: bool = src == Some("-r");
+                    if recursive {
+                        src = args.next();
+                    }
+                    let src: String = match src {
                         Some(s) => s.to_string(),
-                        None => return Err("Missing `src` field"),
+                        None => return Err(ParseError::MissingArgument("src")),
                     };
-                    match args.next() {
-                        Some(d) => Ok(Self::Copy(src, d.to_string())),
-                        None => Err("Missing `dest` field"),
+                    let dest: String = match args.next() {
+                        Some(d) => d.to_string(),
+                        None => return Err(ParseError::MissingArgument("dest")),
+                    };
+                    match cmd.to_ascii_uppercase().as_str() {
+                        "COPY" | "CP" => Ok(Self::Copy(src, dest, recursive)),
+                        _ => Ok(Self::Move(src, dest, recursive)),
                     }
                 }
                 "HELP" => Ok(Self::Help),
-                "LIST" => match args.next() {
-                    Some(p) => Ok(Self::List(Some(p.to_string()))),
-                    None => Ok(Self::List(None)),
+                "DU" => Ok(Self::Du(args.next().map(|x| x.to_string()))),
+                "FIND" => match args.next() {
+                    Some(p) => Ok(Self::Find(p.to_string())),
+                    None => Err(ParseError::MissingArgument("pattern")),
                 },
+                "LIST" | "LS" => {
+                    let mut dir = args.next();
+                    let recursive: bool = dir == Some("-r");
+                    if recursive {
+                        dir = args.next();
+                    }
+                    Ok(Self::List(dir.map(|x| x.to_string()), recursive))
+                }
                 "MKDIR" => match args.next() {
                     Some(p) => Ok(Self::Mkdir(p.to_string())),
-                    None => Err("Missing `dir` field"),
+                    None => Err(ParseError::MissingArgument("dir")),
                 },
-                "RM" => match args.next() {
-                    Some(p) => Ok(Self::Remove(p.to_string())),
-                    None => Err("Missing `file` field"),
+                "PRESIGN" => {
+                    let put: bool = match args.next().map(|x| x.to_ascii_uppercase()).as_deref() {
+                        Some("GET") => false,
+                        Some("PUT") => true,
+                        Some(m) => {
+                            return Err(ParseError::InvalidArgument("method", m.to_string()))
+                        }
+                        None => return Err(ParseError::MissingArgument("method")),
+                    };
+                    let file: String = match args.next() {
+                        Some(f) => f.to_string(),
+                        None => return Err(ParseError::MissingArgument("file")),
+                    };
+                    match args.next() {
+                        Some(e) => match e.parse::<u32>() {
+                            Ok(expiry) => Ok(Self::Presign(file, put, expiry)),
+                            Err(_) => Err(ParseError::InvalidArgument("expiry", e.to_string())),
+                        },
+                        None => Ok(Self::Presign(file, put, DEFAULT_PRESIGN_EXPIRY)),
+                    }
+                }
+                "SETMETA" => match args.next() {
+                    Some(p) => Ok(Self::SetMetadata(p.to_string(), Self::parse_pairs(args)?)),
+                    None => Err(ParseError::MissingArgument("file")),
                 },
+                "TAG" => match args.next() {
+                    Some(p) => Ok(Self::SetTags(p.to_string(), Self::parse_pairs(args)?)),
+                    None => Err(ParseError::MissingArgument("file")),
+                },
+                "STORAGECLASS" => {
+                    let file: String = match args.next() {
+                        Some(f) => f.to_string(),
+                        None => return Err(ParseError::MissingArgument("file")),
+                    };
+                    match args.next() {
+                        Some(c) => Ok(Self::StorageClass(file, c.to_string())),
+                        None => Err(ParseError::MissingArgument("class")),
+                    }
+                }
+                "RESTORE" => {
+                    let file: String = match args.next() {
+                        Some(f) => f.to_string(),
+                        None => return Err(ParseError::MissingArgument("file")),
+                    };
+                    match args.next() {
+                        Some(v) => Ok(Self::Restore(file, v.to_string())),
+                        None => Err(ParseError::MissingArgument("version")),
+                    }
+                }
+                "RM" => {
+                    let mut file = args.next();
+                    let mut version_id: Option<String> = None;
+                    if file == Some("--version-id") {
+                        version_id = match args.next() {
+                            Some(v) => Some(v.to_string()),
+                            None => return Err(ParseError::MissingArgument("version-id")),
+                        };
+                        file = args.next();
+                    }
+                    match file {
+                        Some(p) => Ok(Self::Remove(p.to_string(), version_id)),
+                        None => Err(ParseError::MissingArgument("file")),
+                    }
+                }
                 "STAT" => match args.next() {
                     Some(p) => Ok(Self::Stat(p.to_string())),
-                    None => Err("Missing `dir` field"),
+                    None => Err(ParseError::MissingArgument("dir")),
                 },
                 "PUT" => {
-                    let src: PathBuf = match args.next() {
+                    let mut src = args.next();
+                    let recursive: bool = src == Some("-r");
+                    if recursive {
+                        src = args.next();
+                    }
+                    let src: PathBuf = match src {
                         Some(s) => PathBuf::from(s),
-                        None => return Err("Missing `src` field"),
+                        None => return Err(ParseError::MissingArgument("src")),
                     };
                     match args.next() {
-                        Some(d) => Ok(Self::Put(src, d.to_string())),
-                        None => Err("Missing `dest` field"),
+                        Some(d) => Ok(Self::Put(src, d.to_string(), recursive)),
+                        None => Err(ParseError::MissingArgument("dest")),
                     }
                 }
                 "GET" => {
-                    let src: String = match args.next() {
+                    let mut recursive: bool = false;
+                    let mut version_id: Option<String> = None;
+                    let mut src = args.next();
+                    loop {
+                        match src {
+                            Some("-r") => recursive = true,
+                            Some("--version-id") => match args.next() {
+                                Some(v) => version_id = Some(v.to_string()),
+                                None => return Err(ParseError::MissingArgument("version-id")),
+                            },
+                            _ => break,
+                        }
+                        src = args.next();
+                    }
+                    if recursive && version_id.is_some() {
+                        return Err(ParseError::ConflictingOptions("-r", "--version-id"));
+                    }
+                    let src: String = match src {
                         Some(s) => s.to_string(),
-                        None => return Err("Missing `src` field"),
+                        None => return Err(ParseError::MissingArgument("src")),
                     };
                     match args.next() {
-                        Some(d) => Ok(Self::Get(src, PathBuf::from(d))),
-                        None => Err("Missing `dest` field"),
+                        Some(d) => Ok(Self::Get(src, PathBuf::from(d), recursive, version_id)),
+                        None => Err(ParseError::MissingArgument("dest")),
                     }
                 }
-                _ => Err("Unknown command"),
+                "SET" => {
+                    let option: String = match args.next() {
+                        Some(o) => o.to_ascii_lowercase(),
+                        None => return Err(ParseError::MissingArgument("option")),
+                    };
+                    match args.next() {
+                        Some(v) => Ok(Self::Set(option, v.to_string())),
+                        None => Err(ParseError::MissingArgument("value")),
+                    }
+                }
+                "VERSIONS" => match args.next() {
+                    Some(p) => Ok(Self::Versions(p.to_string())),
+                    None => Err(ParseError::MissingArgument("file")),
+                },
+                "SYNC" => {
+                    let mut options: SyncOptions = SyncOptions::default();
+                    let mut positionals: Vec<&str> = Vec::new();
+                    for arg in args {
+                        match arg {
+                            "--delete" => options.delete = true,
+                            "--dry-run" => options.dry_run = true,
+                            "--checksum" => options.checksum = true,
+                            flag if flag.starts_with("--") => {
+                                return Err(ParseError::UnknownOption(flag.to_string()))
+                            }
+                            arg => positionals.push(arg),
+                        }
+                    }
+                    let mut positionals = positionals.into_iter();
+                    let local: PathBuf = match positionals.next() {
+                        Some(l) => PathBuf::from(l),
+                        None => return Err(ParseError::MissingArgument("local")),
+                    };
+                    let remote: String = match positionals.next() {
+                        Some(r) => r.to_string(),
+                        None => return Err(ParseError::MissingArgument("remote")),
+                    };
+                    match positionals.next() {
+                        Some(d) => Ok(Self::Sync(
+                            local,
+                            remote,
+                            SyncDirection::from_str(d).map_err(|_| {
+                                ParseError::InvalidArgument("direction", d.to_string())
+                            })?,
+                            options,
+                        )),
+                        None => Err(ParseError::MissingArgument("direction")),
+                    }
+                }
+                _ => Err(ParseError::UnknownCommand(cmd.to_string())),
             },
-            None => Err("Unknown command"),
+            None => Err(ParseError::Empty),
         }
     }
 }
+
+impl Command {
+    /// ### parse_pairs
+    ///
+    /// Parse `key=value` arguments
+    fn parse_pairs<'a>(
+        args: impl Iterator<Item = &'a str>,
+    ) -> Result<Vec<(String, String)>, ParseError> {
+        args.map(|x| match x.split_once('=') {
+            Some((k, v)) if !k.is_empty() => Ok((k.to_string(), v.to_string())),
+            _ => Err(ParseError::InvalidArgument("pair", x.to_string())),
+        })
+        .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn should_tokenize_quoted_arguments() {
+        assert_eq!(
+            Command::tokenize(r#"get 'my file.txt' "my \"local\" file.txt""#).unwrap(),
+            vec![vec![
+                String::from("get"),
+                String::from("my file.txt"),
+                String::from("my \"local\" file.txt")
+            ]]
+        );
+        assert_eq!(
+            Command::tokenize(r"rm my\ file.txt").unwrap(),
+            vec![vec![String::from("rm"), String::from("my file.txt")]]
+        );
+        assert_eq!(
+            Command::tokenize("rm ''").unwrap(),
+            vec![vec![String::from("rm"), String::new()]]
+        );
+    }
+
+    #[test]
+    fn should_tokenize_statements() {
+        assert_eq!(
+            Command::tokenize("cd foo; ls\n# a comment\n\npwd # another; one").unwrap(),
+            vec![
+                vec![String::from("cd"), String::from("foo")],
+                vec![String::from("ls")],
+                vec![String::from("pwd")],
+            ]
+        );
+        assert_eq!(
+            Command::tokenize("cd 'foo;bar'").unwrap(),
+            vec![vec![String::from("cd"), String::from("foo;bar")]]
+        );
+    }
+
+    #[test]
+    fn should_fail_tokenizing() {
+        assert_eq!(
+            Command::tokenize("cd 'foo").unwrap_err(),
+            ParseError::UnterminatedQuote
+        );
+        assert_eq!(
+            Command::tokenize("cd \"foo").unwrap_err(),
+            ParseError::UnterminatedQuote
+        );
+        assert_eq!(
+            Command::tokenize("cd foo\\").unwrap_err(),
+            ParseError::TrailingEscape
+        );
+    }
+
+    #[test]
+    fn should_parse_commands() {
+        assert_eq!(
+            Command::from_str("GET -r 'my dir' /tmp/my\\ dir").unwrap(),
+            Command::Get(
+                String::from("my dir"),
+                PathBuf::from("/tmp/my dir"),
+                true,
+                None
+            )
+        );
+        assert_eq!(
+            Command::parse_script("cd foo; pwd").unwrap(),
+            vec![Command::ChangeDir(String::from("foo")), Command::Pwd]
+        );
+        assert_eq!(
+            Command::from_str("cd foo; pwd").unwrap_err(),
+            ParseError::TooManyCommands
+        );
+        assert_eq!(Command::from_str("  ").unwrap_err(), ParseError::Empty);
+        assert_eq!(
+            Command::from_str("foo").unwrap_err(),
+            ParseError::UnknownCommand(String::from("foo"))
+        );
+        assert_eq!(
+            Command::from_str("cd").unwrap_err(),
+            ParseError::MissingArgument("dir")
+        );
+    }
+}
@@ -0,0 +1,427 @@
+/**
+ *
+ *
+ *           DO WHAT THE FUCK YOU WANT TO PUBLIC LICENSE
+ *                   Version 2, December 2004
+ *
+ *  Copyright (C) 2021 Christian Visintin
+ *
+ *  Everyone is permitted to copy and distribute verbatim or modified
+ *  copies of this license document, and changing it is allowed as long
+ *  as the name is changed.
+ *
+ *             DO WHAT THE FUCK YOU WANT TO PUBLIC LICENSE
+ *    TERMS AND CONDITIONS FOR COPYING, DISTRIBUTION AND MODIFICATION
+ *
+ *   0. You just DO WHAT THE FUCK YOU WANT TO.
+*/
+use aes_gcm::aead::{Aead, NewAead, Payload};
+use aes_gcm::Aes256Gcm;
+use keyring::Keyring;
+use rand::rngs::OsRng;
+use rand::RngCore;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+/// Size of the plaintext of each encrypted segment
+pub const SEGMENT_SIZE: usize = 64 * 1024;
+/// Size of the GCM authentication tag appended to each segment
+pub const TAG_SIZE: usize = 16;
+/// Size of an encrypted segment
+pub const ENCRYPTED_SEGMENT_SIZE: usize = SEGMENT_SIZE + TAG_SIZE;
+
+const KEY_SIZE: usize = 32;
+const NONCE_SIZE: usize = 12;
+const NONCE_PREFIX_SIZE: usize = 7;
+
+/// Algorithm name stored in object metadata
+const ALGORITHM: &str = "AES256-GCM-STREAM";
+/// Prefix of the object metadata keys describing client-side encryption
+pub const METADATA_PREFIX: &str = "cse-";
+const META_ALGORITHM: &str = "cse-algorithm";
+const META_KEY: &str = "cse-key";
+const META_NONCE: &str = "cse-nonce";
+
+pub type CryptoResult<T> = Result<T, String>;
+
+/// ## MasterKey
+///
+/// Key used to wrap the data key of each object
+#[derive(Clone)]
+pub struct MasterKey {
+    key: [u8; KEY_SIZE],
+}
+
+impl MasterKey {
+    pub fn new(key: [u8; KEY_SIZE]) -> Self {
+        Self { key }
+    }
+
+    /// ### from_keyfile
+    ///
+    /// Read master key from `path`, which contains either 32 raw bytes or the key encoded as base64
+    pub fn from_keyfile(path: &Path) -> CryptoResult<Self> {
+        let data: Vec<u8> = fs::read(path).map_err(|e| format!("Could not read keyfile: {}", e))?;
+        match data.len() {
+            KEY_SIZE => Self::from_slice(data.as_slice()),
+            _ => Self::from_base64(String::from_utf8_lossy(data.as_slice()).trim()),
+        }
+    }
+
+    /// ### from_keyring
+    ///
+    /// Read master key, encoded as base64, from the keyring entry of `service` for the current user
+    pub fn from_keyring(service: &str) -> CryptoResult<Self> {
+        let username: String = whoami::username();
+        let storage: Keyring = Keyring::new(service, username.as_str());
+        let key: String = storage
+            .get_password()
+            .map_err(|e| format!("Could not get key from keyring: {}", e))?;
+        Self::from_base64(key.trim())
+    }
+
+    fn from_base64(key: &str) -> CryptoResult<Self> {
+        let key: Vec<u8> =
+            base64::decode(key).map_err(|e| format!("Could not decode master key: {}", e))?;
+        Self::from_slice(key.as_slice())
+    }
+
+    fn from_slice(key: &[u8]) -> CryptoResult<Self> {
+        if key.len() != KEY_SIZE {
+            return Err(format!(
+                "Master key must be {} bytes long (got {})",
+                KEY_SIZE,
+                key.len()
+            ));
+        }
+        let mut buf: [u8; KEY_SIZE] = [0; KEY_SIZE];
+        buf.copy_from_slice(key);
+        Ok(Self::new(buf))
+    }
+}
+
+impl std::fmt::Debug for MasterKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "MasterKey(***)")
+    }
+}
+
+/// ## DataKey
+///
+/// Per-object key. Objects are split in segments of `SEGMENT_SIZE`, each one sealed with
+/// a nonce made of a random prefix, the segment index and a flag telling whether it is the last one;
+/// segments can't be reordered, dropped or truncated without failing authentication
+pub struct DataKey {
+    cipher: Aes256Gcm,
+    key: [u8; KEY_SIZE],
+    nonce_prefix: [u8; NONCE_PREFIX_SIZE],
+}
+
+impl DataKey {
+    /// ### generate
+    ///
+    /// Generate a random data key
+    pub fn generate() -> Self {
+        let mut key: [u8; KEY_SIZE] = [0; KEY_SIZE];
+        let mut nonce_prefix: [u8; NONCE_PREFIX_SIZE] = [0; NONCE_PREFIX_SIZE];
+        OsRng.fill_bytes(&mut key);
+        OsRng.fill_bytes(&mut nonce_prefix);
+        Self::new(key, nonce_prefix)
+    }
+
+    fn new(key: [u8; KEY_SIZE], nonce_prefix: [u8; NONCE_PREFIX_SIZE]) -> Self {
+        Self {
+            cipher: Aes256Gcm::new(&key.into()),
+            key,
+            nonce_prefix,
+        }
+    }
+
+    /// ### to_metadata
+    ///
+    /// Wrap data key with `master` and return the object metadata (without `x-amz-meta-` prefix) to store it
+    pub fn to_metadata(&self, master: &MasterKey) -> CryptoResult<Vec<(String, String)>> {
+        let mut nonce: [u8; NONCE_SIZE] = [0; NONCE_SIZE];
+        OsRng.fill_bytes(&mut nonce);
+        let wrapped: Vec<u8> = Aes256Gcm::new(&master.key.into())
+            .encrypt(&nonce.into(), &self.key[..])
+            .map_err(|_| String::from("Could not wrap data key"))?;
+        let mut wrapped_key: Vec<u8> = nonce.to_vec();
+        wrapped_key.extend(wrapped);
+        Ok(vec![
+            (META_ALGORITHM.to_string(), ALGORITHM.to_string()),
+            (META_KEY.to_string(), base64::encode(wrapped_key)),
+            (META_NONCE.to_string(), base64::encode(self.nonce_prefix)),
+        ])
+    }
+
+    /// ### from_metadata
+    ///
+    /// Unwrap data key stored in object metadata with `master`.
+    /// Returns `None` if the object is not client-side encrypted
+    pub fn from_metadata(
+        metadata: &HashMap<String, String>,
+        master: Option<&MasterKey>,
+    ) -> CryptoResult<Option<Self>> {
+        let algorithm: &str = match metadata.get(META_ALGORITHM) {
+            None => return Ok(None),
+            Some(algorithm) => algorithm.as_str(),
+        };
+        if algorithm != ALGORITHM {
+            return Err(format!("Unsupported encryption algorithm `{}`", algorithm));
+        }
+        let master: &MasterKey = master
+            .ok_or("Object is client-side encrypted, but no master key has been configured")?;
+        let wrapped_key: Vec<u8> = metadata
+            .get(META_KEY)
+            .ok_or("Missing wrapped data key")
+            .map(|x| base64::decode(x).map_err(|e| format!("Invalid wrapped data key: {}", e)))??;
+        let nonce_prefix: Vec<u8> = metadata
+            .get(META_NONCE)
+            .ok_or("Missing nonce")
+            .map(|x| base64::decode(x).map_err(|e| format!("Invalid nonce: {}", e)))??;
+        if wrapped_key.len() != NONCE_SIZE + KEY_SIZE + TAG_SIZE
+            || nonce_prefix.len() != NONCE_PREFIX_SIZE
+        {
+            return Err(String::from("Invalid encryption metadata"));
+        }
+        let mut nonce: [u8; NONCE_SIZE] = [0; NONCE_SIZE];
+        nonce.copy_from_slice(&wrapped_key[..NONCE_SIZE]);
+        let key: Vec<u8> = Aes256Gcm::new(&master.key.into())
+            .decrypt(&nonce.into(), &wrapped_key[NONCE_SIZE..])
+            .map_err(|_| String::from("Could not unwrap data key: wrong master key?"))?;
+        let mut buf: [u8; KEY_SIZE] = [0; KEY_SIZE];
+        buf.copy_from_slice(key.as_slice());
+        let mut prefix: [u8; NONCE_PREFIX_SIZE] = [0; NONCE_PREFIX_SIZE];
+        prefix.copy_from_slice(nonce_prefix.as_slice());
+        Ok(Some(Self::new(buf, prefix)))
+    }
+
+    /// ### encrypt
+    ///
+    /// Encrypt `plain`, which starts at segment `first_segment` of an object made of `segments` segments.
+    /// `plain` must be made of whole segments, except for the last segment of the object
+    pub fn encrypt(
+        &self,
+        first_segment: usize,
+        segments: usize,
+        plain: &[u8],
+    ) -> CryptoResult<Vec<u8>> {
+        let mut sealed: Vec<u8> = Vec::with_capacity(encrypted_size(plain.len()));
+        for (i, chunk) in Self::chunks(plain, SEGMENT_SIZE).enumerate() {
+            let index: usize = first_segment + i;
+            let nonce = self.nonce(index, index + 1 == segments)?;
+            let chunk: Vec<u8> = self
+                .cipher
+                .encrypt(&nonce.into(), chunk)
+                .map_err(|_| format!("Could not encrypt segment {}", index))?;
+            sealed.extend(chunk);
+        }
+        Ok(sealed)
+    }
+
+    /// ### decrypt
+    ///
+    /// Decrypt `sealed`, which starts at segment `first_segment` of an object made of `segments` segments
+    pub fn decrypt(
+        &self,
+        first_segment: usize,
+        segments: usize,
+        sealed: &[u8],
+    ) -> CryptoResult<Vec<u8>> {
+        let mut plain: Vec<u8> = Vec::with_capacity(sealed.len());
+        for (i, chunk) in Self::chunks(sealed, ENCRYPTED_SEGMENT_SIZE).enumerate() {
+            let index: usize = first_segment + i;
+            let nonce = self.nonce(index, index + 1 == segments)?;
+            let chunk: Vec<u8> = self
+                .cipher
+                .decrypt(&nonce.into(), Payload::from(chunk))
+                .map_err(|_| {
+                    format!("Could not decrypt segment {}: authentication failed", index)
+                })?;
+            plain.extend(chunk);
+        }
+        Ok(plain)
+    }
+
+    fn nonce(&self, index: usize, last: bool) -> CryptoResult<[u8; NONCE_SIZE]> {
+        if index > u32::MAX as usize {
+            return Err(String::from("Object is too large to be encrypted"));
+        }
+        let mut nonce: [u8; NONCE_SIZE] = [0; NONCE_SIZE];
+        nonce[..NONCE_PREFIX_SIZE].copy_from_slice(&self.nonce_prefix);
+        nonce[NONCE_PREFIX_SIZE..NONCE_SIZE - 1].copy_from_slice(&(index as u32).to_be_bytes());
+        nonce[NONCE_SIZE - 1] = last as u8;
+        Ok(nonce)
+    }
+
+    /// ### chunks
+    ///
+    /// Split `data` in chunks of `size`; empty data yields a single empty chunk
+    fn chunks(data: &[u8], size: usize) -> impl Iterator<Item = &[u8]> {
+        let empty: Option<&[u8]> = match data.is_empty() {
+            true => Some(data),
+            false => None,
+        };
+        data.chunks(size).chain(empty)
+    }
+}
+
+/// ### segments
+///
+/// Amount of segments an object of `size` bytes is split into
+pub fn segments(size: usize) -> usize {
+    std::cmp::max(1, size.div_ceil(SEGMENT_SIZE))
+}
+
+/// ### is_encrypted
+///
+/// Returns whether object `metadata` (without `x-amz-meta-` prefix) describes a client-side encrypted object
+pub fn is_encrypted(metadata: &HashMap<String, String>) -> bool {
+    metadata.contains_key(META_ALGORITHM)
+}
+
+/// ### encrypted_size
+///
+/// Size of an object of `size` bytes once encrypted
+pub fn encrypted_size(size: usize) -> usize {
+    size + segments(size) * TAG_SIZE
+}
+
+/// ### decrypted_size
+///
+/// Size of an encrypted object of `size` bytes once decrypted
+pub fn decrypted_size(size: usize) -> CryptoResult<usize> {
+    let segments: usize = std::cmp::max(1, size.div_ceil(ENCRYPTED_SEGMENT_SIZE));
+    match size.checked_sub(segments * TAG_SIZE) {
+        Some(size) => Ok(size),
+        None => Err(String::from("Encrypted object is truncated")),
+    }
+}
+
+/// ### segment_aligned
+///
+/// Round `part_size` up to a whole amount of segments
+pub fn segment_aligned(part_size: usize) -> usize {
+    segments(part_size) * SEGMENT_SIZE
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use tempfile::TempDir;
+
+    fn data_key() -> DataKey {
+        DataKey::new([7; KEY_SIZE], [1; NONCE_PREFIX_SIZE])
+    }
+
+    #[test]
+    fn should_compute_sizes() {
+        assert_eq!(segments(0), 1);
+        assert_eq!(segments(SEGMENT_SIZE), 1);
+        assert_eq!(segments(SEGMENT_SIZE + 1), 2);
+        assert_eq!(encrypted_size(0), TAG_SIZE);
+        assert_eq!(
+            encrypted_size(SEGMENT_SIZE + 1),
+            SEGMENT_SIZE + 1 + 2 * TAG_SIZE
+        );
+        assert_eq!(decrypted_size(TAG_SIZE).unwrap(), 0);
+        assert_eq!(
+            decrypted_size(SEGMENT_SIZE + 1 + 2 * TAG_SIZE).unwrap(),
+            SEGMENT_SIZE + 1
+        );
+        assert!(decrypted_size(TAG_SIZE - 1).is_err());
+        assert_eq!(segment_aligned(1), SEGMENT_SIZE);
+        assert_eq!(segment_aligned(3 * SEGMENT_SIZE), 3 * SEGMENT_SIZE);
+        assert_eq!(segment_aligned(3 * SEGMENT_SIZE + 5), 4 * SEGMENT_SIZE);
+    }
+
+    #[test]
+    fn should_encrypt_and_decrypt() {
+        let key: DataKey = data_key();
+        let plain: Vec<u8> = (0..(2 * SEGMENT_SIZE + 100)).map(|x| x as u8).collect();
+        let sealed: Vec<u8> = key.encrypt(0, 3, plain.as_slice()).unwrap();
+        assert_eq!(sealed.len(), encrypted_size(plain.len()));
+        assert_eq!(key.decrypt(0, 3, sealed.as_slice()).unwrap(), plain);
+        // Decrypt by parts
+        let first: Vec<u8> = key
+            .decrypt(0, 3, &sealed[..ENCRYPTED_SEGMENT_SIZE])
+            .unwrap();
+        let rest: Vec<u8> = key
+            .decrypt(1, 3, &sealed[ENCRYPTED_SEGMENT_SIZE..])
+            .unwrap();
+        assert_eq!(first.as_slice(), &plain[..SEGMENT_SIZE]);
+        assert_eq!(rest.as_slice(), &plain[SEGMENT_SIZE..]);
+        // Empty object
+        let sealed: Vec<u8> = key.encrypt(0, 1, &[]).unwrap();
+        assert_eq!(sealed.len(), TAG_SIZE);
+        assert!(key.decrypt(0, 1, sealed.as_slice()).unwrap().is_empty());
+    }
+
+    #[test]
+    fn should_detect_tampering() {
+        let key: DataKey = data_key();
+        let plain: Vec<u8> = vec![42; 2 * SEGMENT_SIZE];
+        let mut sealed: Vec<u8> = key.encrypt(0, 2, plain.as_slice()).unwrap();
+        // Truncated object
+        assert!(key
+            .decrypt(0, 1, &sealed[..ENCRYPTED_SEGMENT_SIZE])
+            .is_err());
+        // Reordered segments
+        assert!(key
+            .decrypt(1, 2, &sealed[..ENCRYPTED_SEGMENT_SIZE])
+            .is_err());
+        // Flipped bit
+        sealed[10] ^= 1;
+        assert!(key.decrypt(0, 2, sealed.as_slice()).is_err());
+    }
+
+    #[test]
+    fn should_wrap_data_key() {
+        let master: MasterKey = MasterKey::new([3; KEY_SIZE]);
+        let key: DataKey = DataKey::generate();
+        let metadata: HashMap<String, String> =
+            key.to_metadata(&master).unwrap().into_iter().collect();
+        let sealed: Vec<u8> = key.encrypt(0, 1, b"hello").unwrap();
+        let unwrapped: DataKey = DataKey::from_metadata(&metadata, Some(&master))
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            unwrapped.decrypt(0, 1, sealed.as_slice()).unwrap(),
+            b"hello"
+        );
+        // Wrong or missing master key
+        assert!(DataKey::from_metadata(&metadata, Some(&MasterKey::new([4; KEY_SIZE]))).is_err());
+        assert!(DataKey::from_metadata(&metadata, None).is_err());
+        // Not encrypted
+        assert!(DataKey::from_metadata(&HashMap::new(), None)
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn should_read_master_key_from_keyfile() {
+        let tmp: TempDir = TempDir::new().unwrap();
+        let raw = tmp.path().join("raw.key");
+        fs::write(raw.as_path(), [5; KEY_SIZE]).unwrap();
+        assert_eq!(
+            MasterKey::from_keyfile(raw.as_path()).unwrap().key,
+            [5; KEY_SIZE]
+        );
+        let encoded = tmp.path().join("base64.key");
+        fs::write(
+            encoded.as_path(),
+            format!("{}\n", base64::encode([6; KEY_SIZE])),
+        )
+        .unwrap();
+        assert_eq!(
+            MasterKey::from_keyfile(encoded.as_path()).unwrap().key,
+            [6; KEY_SIZE]
+        );
+        let short = tmp.path().join("short.key");
+        fs::write(short.as_path(), base64::encode([6; 16])).unwrap();
+        assert!(MasterKey::from_keyfile(short.as_path()).is_err());
+    }
+}
@@ -21,17 +21,45 @@ extern crate s3;
 // -- mod
 mod bucket;
 mod command;
+mod crypto;
+mod sync;
+mod versions;
 
 // -- locals
-use bucket::S3Bucket;
+use bucket::{
+    ConnectOptions, CredentialsSource, EncryptionOptions, S3Bucket, ServerSideEncryption,
+};
 use command::Command;
+use crypto::MasterKey;
+use sync::SyncPlan;
 
 // -- ext
 use std::env;
+use std::fmt;
+use std::fs;
 use std::io;
 use std::io::Write;
+use std::path::PathBuf;
 use std::process::exit;
-use std::str::FromStr;
+
+/// ## Mode
+///
+/// Describes how commands are read
+enum Mode {
+    Interactive,
+    Commands(String),
+    Script(PathBuf),
+}
+
+/// ## Args
+///
+/// Command line arguments
+struct Args {
+    bucket: String,
+    connect: ConnectOptions,
+    encryption: EncryptionOptions,
+    mode: Mode,
+}
 
 fn main() {
     let args: Vec<String> = env::args().collect();
@@ -39,90 +67,475 @@ fn main() {
         usage();
         exit(255);
     }
-    if args.len() < 3 {
-        usage();
-        exit(255);
+    let args: Args = match parse_args(&args[1..]) {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("{}", e);
+            usage();
+            exit(255);
+        }
+    };
+    let mut bucket: S3Bucket = match S3Bucket::connect(args.bucket.as_str(), &args.connect) {
+        Ok(b) => b,
+        Err(e) => {
+            eprintln!("{}", e);
+            exit(255);
+        }
+    };
+    bucket.set_encryption(args.encryption);
+    match args.mode {
+        Mode::Interactive => interactive(&mut bucket),
+        Mode::Commands(script) => exit(run_script(&mut bucket, script.as_str())),
+        Mode::Script(path) => match fs::read_to_string(path.as_path()) {
+            Ok(script) => exit(run_script(&mut bucket, script.as_str())),
+            Err(e) => {
+                eprintln!("Could not read script {}: {}", path.display(), e);
+                exit(255);
+            }
+        },
     }
-    let bucket_name: &String = args.get(1).unwrap();
-    let region: &String = args.get(2).unwrap();
-    let profile: Option<String> = args.get(3).map(|x| x.to_string());
-    let mut bucket: S3Bucket =
-        match S3Bucket::connect(bucket_name.as_str(), region.as_str(), profile.as_deref()) {
-            Ok(b) => b,
-            Err(e) => panic!("{}", e),
-        };
+}
+
+/// ### interactive
+///
+/// Read and execute commands from stdin until `QUIT`
+fn interactive(bucket: &mut S3Bucket) {
     loop {
-        match input() {
-            Command::ChangeDir(dir) => match bucket.change_dir(dir.as_str()) {
-                Ok(_) => println!("CD OK!"),
-                Err(e) => println!("CD ERR: {}", e),
-            },
-            Command::Get(src, dest) => match bucket.get(src.as_str(), dest.as_path()) {
-                Ok(_) => println!("GET OK!"),
-                Err(e) => println!("GET ERR: {}", e),
-            },
-            Command::List(dir) => {
-                bucket
-                    .list(dir.as_deref())
-                    .ok()
-                    .unwrap()
+        for command in input().into_iter() {
+            if command == Command::Quit {
+                return;
+            }
+            execute(bucket, command);
+        }
+    }
+}
+
+/// ### run_script
+///
+/// Execute all the commands in `script`, stopping at the first failure.
+/// Returns the exit code: 0 on success, 1 if a command failed, 2 if the script couldn't be parsed
+fn run_script(bucket: &mut S3Bucket, script: &str) -> i32 {
+    let commands: Vec<Command> = match Command::parse_script(script) {
+        Ok(commands) => commands,
+        Err(e) => {
+            eprintln!("Parse error: {}", e);
+            return 2;
+        }
+    };
+    for command in commands.into_iter() {
+        if command == Command::Quit {
+            break;
+        }
+        if !execute(bucket, command) {
+            return 1;
+        }
+    }
+    0
+}
+
+/// ### execute
+///
+/// Execute `command` on `bucket`. Returns whether the command succeeded
+fn execute(bucket: &mut S3Bucket, command: Command) -> bool {
+    match command {
+        Command::ChangeDir(dir) => match bucket.change_dir(dir.as_str()) {
+            Ok(_) => ok("CD OK!"),
+            Err(e) => fail("CD", e),
+        },
+        Command::Get(src, dest, recursive, version_id) => {
+            let result = match (recursive, version_id) {
+                (_, Some(version_id)) => {
+                    bucket.get_version(src.as_str(), dest.as_path(), version_id.as_str())
+                }
+                (true, None) => bucket.get_recursive(src.as_str(), dest.as_path()),
+                (false, None) => bucket.get(src.as_str(), dest.as_path()),
+            };
+            match result {
+                Ok(_) => ok("GET OK!"),
+                Err(e) => fail("GET", e),
+            }
+        }
+        Command::Copy(src, dest, recursive) => {
+            match bucket.copy(src.as_str(), dest.as_str(), recursive) {
+                Ok(n) => ok(format!("COPY OK! {} object(s)", n)),
+                Err(e) => fail("COPY", e),
+            }
+        }
+        Command::Move(src, dest, recursive) => {
+            match bucket.rename(src.as_str(), dest.as_str(), recursive) {
+                Ok(n) => ok(format!("MOVE OK! {} object(s)", n)),
+                Err(e) => fail("MOVE", e),
+            }
+        }
+        Command::Du(dir) => match bucket.du(dir.as_deref()) {
+            Ok(usage) => {
+                usage
                     .iter()
-                    .for_each(|x| println!("{:?}", x));
+                    .for_each(|(prefix, size)| println!("{}\t{}", size, prefix));
+                true
             }
-            Command::Mkdir(dir) => match bucket.mkdir(dir.as_str()) {
-                Ok(_) => println!("MKDIR OK!"),
-                Err(e) => println!("MKDIR ERR: {}", e),
-            },
-            Command::Put(src, dest) => match bucket.put(src.as_path(), dest.as_str()) {
-                Ok(_) => println!("PUT OK!"),
-                Err(err) => println!("PUT ERR: {}", err),
-            },
-            Command::Pwd => println!("PWD: {}", bucket.pwd().ok().unwrap().display()),
-            Command::Remove(p) => match bucket.remove(p.as_str()) {
-                Ok(_) => println!("RM OK!"),
-                Err(e) => println!("RM ERR: {}", e),
-            },
-            Command::Stat(p) => match bucket.stat(p.as_ref()) {
-                Ok(f) => println!("STAT OK: {:?}", f),
-                Err(e) => println!("STAT ERR: {}", e),
+            Err(e) => fail("DU", e),
+        },
+        Command::Find(pattern) => {
+            for entry in bucket.find(pattern.as_str()) {
+                match entry {
+                    Ok(f) => println!("{}", f.path.display()),
+                    Err(e) => return fail("FIND", e),
+                }
+            }
+            true
+        }
+        Command::List(dir, recursive) => {
+            for entry in bucket.list_iter(dir.as_deref(), recursive) {
+                match entry {
+                    Ok(f) => println!("{:?}", f),
+                    Err(e) => return fail("LIST", e),
+                }
+            }
+            true
+        }
+        Command::Mkdir(dir) => match bucket.mkdir(dir.as_str()) {
+            Ok(_) => ok("MKDIR OK!"),
+            Err(e) => fail("MKDIR", e),
+        },
+        Command::Put(src, dest, recursive) => {
+            let result = match recursive {
+                true => bucket.put_recursive(src.as_path(), dest.as_str()),
+                false => bucket.put(src.as_path(), dest.as_str()),
+            };
+            match result {
+                Ok(_) => ok("PUT OK!"),
+                Err(err) => fail("PUT", err),
+            }
+        }
+        Command::Pwd => match bucket.pwd() {
+            Ok(p) => ok(format!("PWD: {}", p.display())),
+            Err(e) => fail("PWD", e),
+        },
+        Command::Remove(p, version_id) => {
+            let result = match version_id {
+                Some(version_id) => bucket.remove_version(p.as_str(), version_id.as_str()),
+                None => bucket.remove(p.as_str()),
+            };
+            match result {
+                Ok(_) => ok("RM OK!"),
+                Err(e) => fail("RM", e),
+            }
+        }
+        Command::Restore(p, version_id) => match bucket.restore(p.as_str(), version_id.as_str()) {
+            Ok(_) => ok("RESTORE OK!"),
+            Err(e) => fail("RESTORE", e),
+        },
+        Command::Versions(p) => match bucket.versions(p.as_str()) {
+            Ok(versions) => {
+                versions.iter().for_each(|v| {
+                    println!(
+                        "{}\t{}\t{}\t{}{}",
+                        v.version_id,
+                        v.last_modified,
+                        v.size,
+                        match v.delete_marker {
+                            true => "(delete marker)",
+                            false => "",
+                        },
+                        match v.is_latest {
+                            true => " (latest)",
+                            false => "",
+                        }
+                    )
+                });
+                true
+            }
+            Err(e) => fail("VERSIONS", e),
+        },
+        Command::Set(option, value) => {
+            let result = match option.as_str() {
+                "part_size" => {
+                    parse_size(value.as_str()).and_then(|size| bucket.set_part_size(size))
+                }
+                "parallelism" => value
+                    .parse::<usize>()
+                    .map_err(|e| format!("Invalid parallelism: {}", e))
+                    .and_then(|n| bucket.set_parallelism(n)),
+                _ => Err(format!("Unknown option `{}`", option)),
+            };
+            match result {
+                Ok(_) => ok("SET OK!"),
+                Err(e) => fail("SET", e),
+            }
+        }
+        Command::Stat(p) => match bucket.stat(p.as_ref()) {
+            Ok(f) if f.is_dir => ok(format!("STAT OK: {:?}", f)),
+            Ok(f) => match bucket.metadata(p.as_str()) {
+                Ok(meta) => {
+                    println!("STAT OK: {:?}", f);
+                    println!("Content-Type: {}", meta.content_type.unwrap_or_default());
+                    println!("ETag: {}", meta.e_tag.unwrap_or_default());
+                    println!("Storage class: {}", meta.storage_class);
+                    meta.metadata
+                        .iter()
+                        .for_each(|(k, v)| println!("Metadata: {}={}", k, v));
+                    meta.tags
+                        .iter()
+                        .for_each(|(k, v)| println!("Tag: {}={}", k, v));
+                    true
+                }
+                Err(e) => fail("STAT", e),
             },
-            Command::Quit => break,
-            Command::Help => help(),
-            _ => println!("Asp un sec"),
+            Err(e) => fail("STAT", e),
+        },
+        Command::SetMetadata(p, metadata) => {
+            match bucket.set_metadata(p.as_str(), metadata.as_slice()) {
+                Ok(_) => ok("SETMETA OK!"),
+                Err(e) => fail("SETMETA", e),
+            }
         }
+        Command::SetTags(p, tags) => match bucket.set_tags(p.as_str(), tags.as_slice()) {
+            Ok(_) => ok("TAG OK!"),
+            Err(e) => fail("TAG", e),
+        },
+        Command::StorageClass(p, class) => {
+            match bucket.set_storage_class(p.as_str(), class.as_str()) {
+                Ok(_) => ok("STORAGECLASS OK!"),
+                Err(e) => fail("STORAGECLASS", e),
+            }
+        }
+        Command::Presign(p, put, expiry) => match bucket.presign(p.as_str(), put, expiry) {
+            Ok(url) => ok(url),
+            Err(e) => fail("PRESIGN", e),
+        },
+        Command::Sync(local, remote, direction, options) => {
+            match SyncPlan::new(bucket, local.as_path(), remote.as_str(), direction, options) {
+                Ok(plan) if options.dry_run => ok(plan),
+                Ok(plan) => match plan.apply(bucket) {
+                    Ok(_) => ok(format!("SYNC OK! {} action(s)", plan.actions().len())),
+                    Err(e) => fail("SYNC", e),
+                },
+                Err(e) => fail("SYNC", e),
+            }
+        }
+        Command::Help => {
+            help();
+            true
+        }
+        Command::Quit => true,
     }
 }
 
+/// ### ok
+///
+/// Print command output and report success
+fn ok<S: fmt::Display>(output: S) -> bool {
+    println!("{}", output);
+    true
+}
+
+/// ### fail
+///
+/// Print command error and report failure
+fn fail<E: fmt::Display>(cmd: &str, err: E) -> bool {
+    eprintln!("{} ERR: {}", cmd, err);
+    false
+}
+
 fn usage() {
-    println!("Usage: aws-s3-cli <bucket> <region> [profile]");
+    println!("Usage: aws-s3-cli [options] <bucket> <region> [profile]");
+    println!();
+    println!("    -c <commands>                   Run `;` separated commands and exit");
+    println!("    -f <script>                     Run commands from script file and exit");
+    println!("    --endpoint <url>                Use a custom S3-compatible endpoint");
+    println!("    --path-style                    Use path-style bucket addressing");
+    println!("    --env                           Take credentials from environment");
+    println!("    --profile <name>                Take credentials from profile");
+    println!("    --access-key <key>              Use static credentials (secret is prompted if not provided)");
+    println!("    --secret-key <secret>           Secret key for static credentials");
+    println!("    --session-token <token>         Session token for static credentials");
+    println!("    --role-arn <arn>                Assume role with web identity token");
+    println!("    --web-identity-token-file <p>   Web identity token file (defaults to $AWS_WEB_IDENTITY_TOKEN_FILE)");
+    println!("    --keyfile <path>                Encrypt uploads on client side with master key from file");
+    println!("    --keyring <service>             Encrypt uploads on client side with master key from keyring");
+    println!("    --sse <s3|kms>                  Request server-side encryption on uploads");
+    println!("    --sse-kms-key-id <id>           KMS key used for server-side encryption");
+}
+
+/// ### parse_args
+///
+/// Parse command line arguments
+fn parse_args(args: &[String]) -> Result<Args, String> {
+    let mut mode: Mode = Mode::Interactive;
+    let mut encryption: EncryptionOptions = EncryptionOptions::default();
+    let mut sse_kms_key_id: Option<String> = None;
+    let mut positionals: Vec<&str> = Vec::new();
+    let mut endpoint: Option<String> = None;
+    let mut path_style: bool = false;
+    let mut from_env: bool = false;
+    let mut profile: Option<String> = None;
+    let mut access_key: Option<String> = None;
+    let mut secret_key: Option<String> = None;
+    let mut session_token: Option<String> = None;
+    let mut role_arn: Option<String> = None;
+    let mut token_file: Option<String> = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = |name: &str| {
+            args.next()
+                .map(|x| x.to_string())
+                .ok_or(format!("Missing value for `{}`", name))
+        };
+        match arg.as_str() {
+            "-c" => mode = Mode::Commands(value("-c")?),
+            "-f" => mode = Mode::Script(PathBuf::from(value("-f")?)),
+            "--keyfile" => {
+                let keyfile: PathBuf = PathBuf::from(value("--keyfile")?);
+                encryption.client = Some(MasterKey::from_keyfile(keyfile.as_path())?);
+            }
+            "--keyring" => {
+                encryption.client = Some(MasterKey::from_keyring(value("--keyring")?.as_str())?)
+            }
+            "--sse" => {
+                encryption.server = match value("--sse")?.to_ascii_lowercase().as_str() {
+                    "s3" => Some(ServerSideEncryption::S3),
+                    "kms" => Some(ServerSideEncryption::Kms(None)),
+                    mode => {
+                        return Err(format!(
+                            "Invalid SSE mode `{}`: expected `s3` or `kms`",
+                            mode
+                        ))
+                    }
+                }
+            }
+            "--sse-kms-key-id" => sse_kms_key_id = Some(value("--sse-kms-key-id")?),
+            "--endpoint" => endpoint = Some(value("--endpoint")?),
+            "--path-style" => path_style = true,
+            "--env" => from_env = true,
+            "--profile" => profile = Some(value("--profile")?),
+            "--access-key" => access_key = Some(value("--access-key")?),
+            "--secret-key" => secret_key = Some(value("--secret-key")?),
+            "--session-token" => session_token = Some(value("--session-token")?),
+            "--role-arn" => role_arn = Some(value("--role-arn")?),
+            "--web-identity-token-file" => token_file = Some(value("--web-identity-token-file")?),
+            flag if flag.starts_with('-') => return Err(format!("Unknown option `{}`", flag)),
+            arg => positionals.push(arg),
+        }
+    }
+    if positionals.len() < 2 {
+        return Err(String::from("Missing `bucket` or `region`"));
+    }
+    let mut options: ConnectOptions = ConnectOptions::new(positionals[1]);
+    options.endpoint = endpoint;
+    options.path_style = path_style;
+    let profile: Option<String> = profile.or_else(|| positionals.get(2).map(|x| x.to_string()));
+    options.credentials = match (access_key, role_arn) {
+        (Some(access_key), _) => {
+            let secret_key: String = match secret_key {
+                Some(secret) => secret,
+                None => rpassword::read_password_from_tty(Some("Secret key: "))
+                    .map_err(|e| format!("Could not read secret key: {}", e))?,
+            };
+            CredentialsSource::Static {
+                access_key,
+                secret_key,
+                session_token,
+            }
+        }
+        (None, Some(role_arn)) => {
+            let token_file: String = token_file
+                .or_else(|| env::var("AWS_WEB_IDENTITY_TOKEN_FILE").ok())
+                .ok_or("Missing web identity token file")?;
+            CredentialsSource::WebIdentity {
+                role_arn,
+                token_file: PathBuf::from(token_file),
+                session_name: String::from("aws-s3-cli"),
+            }
+        }
+        (None, None) if from_env => CredentialsSource::Environment,
+        (None, None) => CredentialsSource::Profile(profile),
+    };
+    if let Some(key_id) = sse_kms_key_id {
+        match encryption.server {
+            None | Some(ServerSideEncryption::Kms(_)) => {
+                encryption.server = Some(ServerSideEncryption::Kms(Some(key_id)))
+            }
+            Some(ServerSideEncryption::S3) => {
+                return Err(String::from("`--sse-kms-key-id` requires `--sse kms`"))
+            }
+        }
+    }
+    Ok(Args {
+        bucket: positionals[0].to_string(),
+        connect: options,
+        encryption,
+        mode,
+    })
 }
 
-fn input() -> Command {
+/// ### input
+///
+/// Read a line from stdin and parse it into commands
+fn input() -> Vec<Command> {
     loop {
         print!(">> ");
         let _ = io::stdout().flush();
         let mut input: String = String::new();
-        io::stdin()
-            .read_line(&mut input)
-            .expect("Failed to read stdin");
-        // Try to create command
-        if let Ok(cmd) = Command::from_str(input.as_str()) {
-            return cmd;
-        }
-        println!("Unknown command");
+        match io::stdin().read_line(&mut input) {
+            Ok(0) => return vec![Command::Quit],
+            Ok(_) => {}
+            Err(e) => panic!("Failed to read stdin: {}", e),
+        }
+        match Command::parse_script(input.as_str()) {
+            Ok(commands) => return commands,
+            Err(e) => println!("Parse error: {}", e),
+        }
     }
 }
 
+/// ### parse_size
+///
+/// Parse a size in bytes, optionally followed by a `K`, `M` or `G` suffix
+fn parse_size(s: &str) -> Result<usize, String> {
+    let s: String = s.to_ascii_uppercase();
+    let (number, multiplier): (&str, usize) = match s.chars().last() {
+        Some('K') => (&s[..s.len() - 1], 1024),
+        Some('M') => (&s[..s.len() - 1], 1024 * 1024),
+        Some('G') => (&s[..s.len() - 1], 1024 * 1024 * 1024),
+        _ => (s.as_str(), 1),
+    };
+    number
+        .parse::<usize>()
+        .map_err(|e| format!("Invalid size: {}", e))
+        .and_then(|x| {
+            x.checked_mul(multiplier)
+                .ok_or_else(|| String::from("Invalid size: number too large"))
+        })
+}
+
 pub fn help() {
     println!("CD <dir>                            Change working directory");
-    println!("GET <file> <dest>                   Download `file` to `dest`");
+    println!("COPY [-r] <src> <dest>              Copy `src` to `dest` (`s3://bucket/key` for other buckets)");
+    println!("DU [dir]                            Print size of each sub directory of `dir`");
+    println!("FIND <pattern>                      Find files matching `pattern` (`*`, `?`)");
+    println!("GET [-r] [--version-id <id>] <file> <dest> Download `file` to `dest`");
     println!("HELP                                Print this help");
-    println!("LIST [dir]                          List files in directory");
+    println!("LIST [-r] [dir]                     List files in directory");
     println!("MKDIR <dir>                         Make directory");
-    println!("PUT <file> <dest>                   Upload local file `file` to `dest`");
+    println!("MOVE [-r] <src> <dest>              Move `src` to `dest` (`s3://bucket/key` for other buckets)");
+    println!(
+        "PRESIGN <get|put> <file> [expiry]   Generate a presigned URL valid for `expiry` seconds"
+    );
+    println!("PUT [-r] <file> <dest>              Upload local file `file` to `dest`");
     println!("PWD                                 Print working directory");
     println!("QUIT                                Quit suppaftp");
-    println!("RM <file>                           Remove file");
+    println!("RESTORE <file> <version>            Restore `version` of `file` as the latest one");
+    println!("RM [--version-id <id>] <file>       Remove file, or permanently delete a version");
+    println!("SET <part_size|parallelism> <value> Configure multipart transfers");
+    println!("SETMETA <file> [key=value...]       Replace user metadata of `file`");
     println!("STAT <file>                         Stat `file`");
+    println!("STORAGECLASS <file> <class>         Change storage class of `file`");
+    println!("SYNC [opts] <local> <remote> <up|down> Sync `local` and `remote` trees");
+    println!("     --delete                       Remove extraneous files from destination");
+    println!("     --dry-run                      Only print what would be done");
+    println!("     --checksum                     Compare files by ETag instead of size and mtime");
+    println!("TAG <file> [key=value...]           Replace tags of `file`");
+    println!("VERSIONS <file>                     List versions and delete markers of `file`");
     println!();
 }
@@ -0,0 +1,574 @@
+/**
+ *
+ *
+ *           DO WHAT THE FUCK YOU WANT TO PUBLIC LICENSE
+ *                   Version 2, December 2004
+ *
+ *  Copyright (C) 2021 Christian Visintin
+ *
+ *  Everyone is permitted to copy and distribute verbatim or modified
+ *  copies of this license document, and changing it is allowed as long
+ *  as the name is changed.
+ *
+ *             DO WHAT THE FUCK YOU WANT TO PUBLIC LICENSE
+ *    TERMS AND CONDITIONS FOR COPYING, DISTRIBUTION AND MODIFICATION
+ *
+ *   0. You just DO WHAT THE FUCK YOU WANT TO.
+*/
+use crate::bucket::{BucketResult, S3Bucket, S3File};
+use crate::crypto;
+
+use chrono::{DateTime, Utc};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::SystemTime;
+
+/// ## SyncDirection
+///
+/// Describes which side of the sync is the source
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum SyncDirection {
+    /// Local tree is uploaded to the bucket
+    Up,
+    /// Remote tree is downloaded to the local directory
+    Down,
+}
+
+impl FromStr for SyncDirection {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_uppercase().as_str() {
+            "UP" => Ok(Self::Up),
+            "DOWN" => Ok(Self::Down),
+            _ => Err("Invalid direction: expected `up` or `down`"),
+        }
+    }
+}
+
+/// ## SyncOptions
+///
+/// Sync command options
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub struct SyncOptions {
+    /// Remove files on the destination which don't exist on the source
+    pub delete: bool,
+    /// Only print the plan, without transferring anything
+    pub dry_run: bool,
+    /// Compare files by ETag/MD5 instead of size and modification time
+    pub checksum: bool,
+}
+
+/// ## SyncAction
+///
+/// A single operation required to bring destination in sync with source
+#[derive(Debug, Eq, PartialEq)]
+pub enum SyncAction {
+    Upload(PathBuf, String),
+    Download(String, PathBuf),
+    DeleteLocal(PathBuf),
+    DeleteRemote(String),
+}
+
+impl fmt::Display for SyncAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Upload(src, dest) => write!(f, "upload: {} -> {}", src.display(), dest),
+            Self::Download(src, dest) => write!(f, "download: {} -> {}", src, dest.display()),
+            Self::DeleteLocal(p) => write!(f, "delete: {}", p.display()),
+            Self::DeleteRemote(p) => write!(f, "delete: {}", p),
+        }
+    }
+}
+
+/// ## LocalFile
+///
+/// A regular file in the local tree
+struct LocalFile {
+    path: PathBuf,
+    size: usize,
+    modified: SystemTime,
+}
+
+/// ## SyncPlan
+///
+/// The list of actions which bring destination in sync with source
+#[derive(Debug, Default)]
+pub struct SyncPlan {
+    actions: Vec<SyncAction>,
+}
+
+impl SyncPlan {
+    /// ### new
+    ///
+    /// Compare local tree at `local` with remote tree at `remote` and compute the actions required to sync them
+    pub fn new(
+        bucket: &S3Bucket,
+        local: &Path,
+        remote: &str,
+        direction: SyncDirection,
+        options: SyncOptions,
+    ) -> BucketResult<Self> {
+        let prefix: String = S3Bucket::dir_key(remote);
+        // A missing source must not look like an empty one, or `delete` would wipe the remote tree
+        if direction == SyncDirection::Up && !local.is_dir() {
+            return Err(match local.exists() {
+                true => format!("{}: Not a directory", local.display()),
+                false => format!("{}: No such file or directory", local.display()),
+            });
+        }
+        let local_files: BTreeMap<String, LocalFile> = match local.exists() {
+            true => Self::local_tree(local)?,
+            false => BTreeMap::new(),
+        };
+        let remote_files: BTreeMap<String, S3File> = bucket
+            .list_recursive(prefix.as_str())?
+            .into_iter()
+            .filter(|x| !x.is_dir)
+            .map(|x| {
+                let key: String = x.path.to_string_lossy().to_string();
+                (
+                    key.strip_prefix(prefix.as_str())
+                        .unwrap_or(key.as_str())
+                        .to_string(),
+                    x,
+                )
+            })
+            .collect();
+        let part_size: usize = bucket.transfer_options().part_size;
+        let mut actions: Vec<SyncAction> = Vec::new();
+        match direction {
+            SyncDirection::Up => {
+                for (name, file) in local_files.iter() {
+                    let changed: bool = match remote_files.get(name) {
+                        None => true,
+                        Some(remote) => {
+                            let encrypted: bool = Self::is_encrypted(bucket, file, remote)?;
+                            Self::is_changed(
+                                file, remote, direction, options, part_size, encrypted,
+                            )?
+                        }
+                    };
+                    if changed {
+                        actions.push(SyncAction::Upload(
+                            file.path.clone(),
+                            format!("{}{}", prefix, name),
+                        ));
+                    }
+                }
+                if options.delete {
+                    remote_files
+                        .keys()
+                        .filter(|x| !local_files.contains_key(*x))
+                        .for_each(|x| {
+                            actions.push(SyncAction::DeleteRemote(format!("{}{}", prefix, x)))
+                        });
+                }
+            }
+            SyncDirection::Down => {
+                // Names come from the bucket and must not point outside of `local`
+                let locals: Vec<PathBuf> = remote_files
+                    .keys()
+                    .map(|name| S3Bucket::local_path(local, name))
+                    .collect::<BucketResult<Vec<PathBuf>>>()?;
+                for ((name, remote), dest) in remote_files.iter().zip(locals) {
+                    let changed: bool = match local_files.get(name) {
+                        None => true,
+                        Some(file) => {
+                            let encrypted: bool = Self::is_encrypted(bucket, file, remote)?;
+                            Self::is_changed(
+                                file, remote, direction, options, part_size, encrypted,
+                            )?
+                        }
+                    };
+                    if changed {
+                        actions.push(SyncAction::Download(format!("{}{}", prefix, name), dest));
+                    }
+                }
+                if options.delete {
+                    local_files
+                        .iter()
+                        .filter(|(name, _)| !remote_files.contains_key(*name))
+                        .for_each(|(_, file)| {
+                            actions.push(SyncAction::DeleteLocal(file.path.clone()))
+                        });
+                }
+            }
+        }
+        Ok(Self { actions })
+    }
+
+    /// ### actions
+    ///
+    /// Get planned actions
+    pub fn actions(&self) -> &[SyncAction] {
+        self.actions.as_slice()
+    }
+
+    /// ### apply
+    ///
+    /// Execute planned actions
+    pub fn apply(&self, bucket: &S3Bucket) -> BucketResult<()> {
+        for action in self.actions.iter() {
+            match action {
+                SyncAction::Upload(src, dest) => bucket.put(src.as_path(), dest.as_str())?,
+                SyncAction::Download(src, dest) => {
+                    if let Some(parent) = dest.parent() {
+                        fs::create_dir_all(parent)
+                            .map_err(|e| format!("Could not create directory: {}", e))?;
+                    }
+                    bucket.get(src.as_str(), dest.as_path())?
+                }
+                SyncAction::DeleteLocal(p) => fs::remove_file(p)
+                    .map_err(|e| format!("Could not remove {}: {}", p.display(), e))?,
+                SyncAction::DeleteRemote(p) => bucket.remove(p.as_str())?,
+            }
+        }
+        Ok(())
+    }
+
+    /// ### local_tree
+    ///
+    /// Collect regular files under `root`, keyed by their path relative to `root` joined with slashes
+    fn local_tree(root: &Path) -> BucketResult<BTreeMap<String, LocalFile>> {
+        let mut files: BTreeMap<String, LocalFile> = BTreeMap::new();
+        let mut dirs: Vec<(PathBuf, String)> = vec![(root.to_path_buf(), String::new())];
+        while let Some((dir, base)) = dirs.pop() {
+            let entries = fs::read_dir(dir.as_path())
+                .map_err(|e| format!("Could not read directory: {}", e))?;
+            for entry in entries {
+                let entry = entry.map_err(|e| format!("Could not read directory: {}", e))?;
+                let name: String = format!("{}{}", base, entry.file_name().to_string_lossy());
+                let metadata = entry
+                    .metadata()
+                    .map_err(|e| format!("Could not stat file: {}", e))?;
+                if metadata.is_dir() {
+                    dirs.push((entry.path(), format!("{}/", name)));
+                } else if metadata.is_file() {
+                    files.insert(
+                        name,
+                        LocalFile {
+                            path: entry.path(),
+                            size: metadata.len() as usize,
+                            modified: metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH),
+                        },
+                    );
+                }
+            }
+        }
+        Ok(files)
+    }
+
+    /// ### is_encrypted
+    ///
+    /// Returns whether `remote` is the client-side encrypted copy of `local`.
+    /// Objects as large as the encrypted file are assumed to be encrypted if a master key is set, otherwise their metadata is checked
+    fn is_encrypted(bucket: &S3Bucket, local: &LocalFile, remote: &S3File) -> BucketResult<bool> {
+        if remote.size != crypto::encrypted_size(local.size) {
+            return Ok(false);
+        }
+        match bucket.encryption().client.is_some() {
+            true => Ok(true),
+            false => bucket.is_client_side_encrypted(remote.path.to_string_lossy().as_ref()),
+        }
+    }
+
+    /// ### is_changed
+    ///
+    /// Returns whether the destination file differs from the source one.
+    /// If `encrypted`, the remote file is the client-side encrypted copy of the local one, whose ETag can't be computed locally
+    fn is_changed(
+        local: &LocalFile,
+        remote: &S3File,
+        direction: SyncDirection,
+        options: SyncOptions,
+        part_size: usize,
+        encrypted: bool,
+    ) -> BucketResult<bool> {
+        let remote_size: usize = match encrypted {
+            true => crypto::encrypted_size(local.size),
+            false => local.size,
+        };
+        if remote_size != remote.size {
+            return Ok(true);
+        }
+        if options.checksum && !encrypted {
+            let e_tag: String =
+                Self::local_e_tag(local.path.as_path(), remote.e_tag.as_str(), part_size)?;
+            return Ok(e_tag != remote.e_tag);
+        }
+        let local_modified: DateTime<Utc> = DateTime::from(local.modified);
+        let remote_modified: DateTime<Utc> =
+            DateTime::parse_from_rfc3339(remote.last_modified.as_str())
+                .map(DateTime::from)
+                .map_err(|e| format!("Invalid last modified date: {}", e))?;
+        Ok(match direction {
+            SyncDirection::Up => local_modified > remote_modified,
+            SyncDirection::Down => remote_modified > local_modified,
+        })
+    }
+
+    /// ### local_e_tag
+    ///
+    /// Compute the ETag S3 would assign to the local file.
+    /// Multipart ETags (`<md5>-<parts>`) are computed as the MD5 of the parts digests, using `part_size`
+    fn local_e_tag(path: &Path, remote_e_tag: &str, part_size: usize) -> BucketResult<String> {
+        let reader = File::open(path).map_err(|e| format!("Could not open file: {}", e))?;
+        let size: usize = reader
+            .metadata()
+            .map_err(|e| format!("Could not stat file: {}", e))?
+            .len() as usize;
+        Self::e_tag(reader, size, remote_e_tag.contains('-'), part_size)
+            .map_err(|e| format!("Could not read file: {}", e))
+    }
+
+    /// ### e_tag
+    ///
+    /// Compute ETag for the `size` bytes read from `reader`, either as a plain or as a multipart upload.
+    /// Data is hashed as it is read, one part at a time
+    fn e_tag<R: Read>(
+        mut reader: R,
+        size: usize,
+        multipart: bool,
+        part_size: usize,
+    ) -> io::Result<String> {
+        let ranges: Vec<(usize, usize)> = match multipart {
+            true => S3Bucket::part_ranges(size, S3Bucket::fit_part_size(size, part_size)),
+            false => vec![(0, size)],
+        };
+        let mut digests: Vec<md5::Digest> = Vec::with_capacity(ranges.len());
+        for (start, end) in ranges.iter() {
+            let mut context: md5::Context = md5::Context::new();
+            let read: u64 = io::copy(
+                &mut reader.by_ref().take((end - start) as u64),
+                &mut context,
+            )?;
+            if read != (end - start) as u64 {
+                return Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "file changed while reading",
+                ));
+            }
+            digests.push(context.compute());
+        }
+        if !multipart {
+            return Ok(format!("{:x}", digests[0]));
+        }
+        let digests: Vec<u8> = digests.iter().flat_map(|x| x.0).collect();
+        Ok(format!(
+            "{:x}-{}",
+            md5::compute(digests.as_slice()),
+            ranges.len()
+        ))
+    }
+}
+
+impl fmt::Display for SyncPlan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for action in self.actions.iter() {
+            writeln!(f, "{}", action)?;
+        }
+        write!(f, "{} action(s)", self.actions.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::bucket::tests::{sample, MockS3};
+    use crate::bucket::EncryptionOptions;
+    use crate::crypto::MasterKey;
+
+    use std::time::Duration;
+    use tempfile::TempDir;
+
+    #[test]
+    fn should_parse_sync_direction() {
+        assert_eq!(SyncDirection::from_str("up").unwrap(), SyncDirection::Up);
+        assert_eq!(
+            SyncDirection::from_str("DOWN").unwrap(),
+            SyncDirection::Down
+        );
+        assert!(SyncDirection::from_str("sideways").is_err());
+    }
+
+    #[test]
+    fn should_compute_e_tag() {
+        assert_eq!(
+            SyncPlan::e_tag(&b"hello"[..], 5, false, 8).unwrap(),
+            "5d41402abc4b2a76b9719d911017c592"
+        );
+        let digests: Vec<u8> = [md5::compute(b"hell").0, md5::compute(b"o").0].concat();
+        assert_eq!(
+            SyncPlan::e_tag(&b"hello"[..], 5, true, 4).unwrap(),
+            format!("{:x}-2", md5::compute(digests.as_slice()))
+        );
+        // File shrank while reading
+        assert!(SyncPlan::e_tag(&b"hell"[..], 5, true, 4).is_err());
+    }
+
+    #[test]
+    fn should_compute_local_e_tag_of_multipart_upload() {
+        let server: MockS3 = MockS3::start();
+        let mut bucket: S3Bucket = server.connect();
+        let part_size: usize = 5 * 1024 * 1024;
+        assert!(bucket.set_part_size(part_size).is_ok());
+        let tmp: TempDir = TempDir::new().unwrap();
+        let src: PathBuf = tmp.path().join("large.bin");
+        fs::write(src.as_path(), sample(part_size * 2 + 1)).unwrap();
+        assert!(bucket.put(src.as_path(), "large.bin").is_ok());
+        let remote_e_tag: String = server.object("large.bin").unwrap().e_tag;
+        assert_eq!(
+            SyncPlan::local_e_tag(src.as_path(), remote_e_tag.as_str(), part_size).unwrap(),
+            remote_e_tag
+        );
+    }
+
+    #[test]
+    fn should_tell_whether_file_changed() {
+        let local = LocalFile {
+            path: PathBuf::from("/tmp/a.txt"),
+            size: 5,
+            modified: SystemTime::UNIX_EPOCH + Duration::from_secs(1609459200), // 2021-01-01
+        };
+        let mut remote = S3File {
+            name: String::from("a.txt"),
+            path: PathBuf::from("a.txt"),
+            size: 5,
+            last_modified: String::from("2021-01-01T00:00:00.000Z"),
+            e_tag: String::from("5d41402abc4b2a76b9719d911017c592"),
+            is_dir: false,
+        };
+        let options = SyncOptions::default();
+        assert!(
+            !SyncPlan::is_changed(&local, &remote, SyncDirection::Up, options, 8, false).unwrap()
+        );
+        // Remote is newer
+        remote.last_modified = String::from("2021-01-02T00:00:00.000Z");
+        assert!(
+            !SyncPlan::is_changed(&local, &remote, SyncDirection::Up, options, 8, false).unwrap()
+        );
+        assert!(
+            SyncPlan::is_changed(&local, &remote, SyncDirection::Down, options, 8, false).unwrap()
+        );
+        // Client-side encrypted copy; ETag is not checked
+        remote.size = crypto::encrypted_size(5);
+        let checksum = SyncOptions {
+            checksum: true,
+            ..SyncOptions::default()
+        };
+        assert!(
+            SyncPlan::is_changed(&local, &remote, SyncDirection::Up, checksum, 8, false).unwrap()
+        );
+        assert!(
+            !SyncPlan::is_changed(&local, &remote, SyncDirection::Up, checksum, 8, true).unwrap()
+        );
+        // Size differs
+        remote.size = 6;
+        assert!(
+            SyncPlan::is_changed(&local, &remote, SyncDirection::Up, options, 8, false).unwrap()
+        );
+    }
+
+    #[test]
+    fn should_not_sync_client_side_encrypted_copies() {
+        let server: MockS3 = MockS3::start();
+        let mut bucket: S3Bucket = server.connect();
+        bucket.set_encryption(EncryptionOptions {
+            client: Some(MasterKey::new([3; 32])),
+            server: None,
+        });
+        let tmp: TempDir = TempDir::new().unwrap();
+        fs::write(tmp.path().join("a.txt"), b"hello").unwrap();
+        assert!(bucket.put_recursive(tmp.path(), "backup").is_ok());
+        let options = SyncOptions {
+            checksum: true,
+            ..SyncOptions::default()
+        };
+        let plan: SyncPlan =
+            SyncPlan::new(&bucket, tmp.path(), "backup", SyncDirection::Down, options).unwrap();
+        assert!(plan.actions().is_empty());
+        // Encryption is told by the object metadata without a master key
+        let bucket: S3Bucket = server.connect();
+        let plan: SyncPlan =
+            SyncPlan::new(&bucket, tmp.path(), "backup", SyncDirection::Down, options).unwrap();
+        assert!(plan.actions().is_empty());
+        assert_eq!(server.requests("HEAD").len(), 1);
+    }
+
+    #[test]
+    fn should_not_sync_down_outside_local_dir() {
+        let server: MockS3 = MockS3::start();
+        let bucket: S3Bucket = server.connect();
+        let tmp: TempDir = TempDir::new().unwrap();
+        let local: PathBuf = tmp.path().join("a/local");
+        fs::create_dir_all(local.as_path()).unwrap();
+        fs::write(local.join("keep.txt"), b"keep").unwrap();
+        server.insert("backup/ok.txt", b"ok");
+        server.insert("backup/dir/../../x", b"pwned");
+        let options = SyncOptions {
+            delete: true,
+            ..SyncOptions::default()
+        };
+        assert!(SyncPlan::new(
+            &bucket,
+            local.as_path(),
+            "backup",
+            SyncDirection::Down,
+            options
+        )
+        .unwrap_err()
+        .contains("unsafe object key"));
+        assert!(local.join("keep.txt").exists());
+        assert!(!tmp.path().join("a/x").exists());
+    }
+
+    #[test]
+    fn should_not_sync_up_missing_local_dir() {
+        let server: MockS3 = MockS3::start();
+        let bucket: S3Bucket = server.connect();
+        let tmp: TempDir = TempDir::new().unwrap();
+        server.insert("backup/a.txt", b"a");
+        let options = SyncOptions {
+            delete: true,
+            ..SyncOptions::default()
+        };
+        let missing: PathBuf = tmp.path().join("typo");
+        assert!(SyncPlan::new(
+            &bucket,
+            missing.as_path(),
+            "backup",
+            SyncDirection::Up,
+            options
+        )
+        .unwrap_err()
+        .contains("No such file or directory"));
+        let file: PathBuf = tmp.path().join("file.txt");
+        fs::write(file.as_path(), b"file").unwrap();
+        assert!(SyncPlan::new(
+            &bucket,
+            file.as_path(),
+            "backup",
+            SyncDirection::Up,
+            options
+        )
+        .unwrap_err()
+        .contains("Not a directory"));
+        assert!(server.object("backup/a.txt").is_some());
+        // A missing destination is an empty tree
+        let plan: SyncPlan = SyncPlan::new(
+            &bucket,
+            missing.as_path(),
+            "backup",
+            SyncDirection::Down,
+            options,
+        )
+        .unwrap();
+        assert_eq!(plan.actions().len(), 1);
+    }
+}
@@ -0,0 +1,167 @@
+/**
+ *
+ *
+ *           DO WHAT THE FUCK YOU WANT TO PUBLIC LICENSE
+ *                   Version 2, December 2004
+ *
+ *  Copyright (C) 2021 Christian Visintin
+ *
+ *  Everyone is permitted to copy and distribute verbatim or modified
+ *  copies of this license document, and changing it is allowed as long
+ *  as the name is changed.
+ *
+ *             DO WHAT THE FUCK YOU WANT TO PUBLIC LICENSE
+ *    TERMS AND CONDITIONS FOR COPYING, DISTRIBUTION AND MODIFICATION
+ *
+ *   0. You just DO WHAT THE FUCK YOU WANT TO.
+*/
+use serde::de::IgnoredAny;
+use serde::Deserialize;
+
+/// ## ObjectVersion
+///
+/// A version of an object, or a delete marker
+#[derive(Debug, Clone, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "PascalCase")]
+pub struct ObjectVersion {
+    pub key: String,
+    pub version_id: String,
+    pub is_latest: bool,
+    pub last_modified: String,
+    #[serde(default)]
+    pub size: usize,
+    #[serde(rename = "ETag", default)]
+    pub e_tag: Option<String>,
+    /// Whether this version is a delete marker
+    #[serde(skip)]
+    pub delete_marker: bool,
+}
+
+/// ## VersionsPage
+///
+/// A page of `ListObjectVersions` results
+#[derive(Debug, Default)]
+pub struct VersionsPage {
+    pub versions: Vec<ObjectVersion>,
+    pub is_truncated: bool,
+    pub next_key_marker: Option<String>,
+    pub next_version_id_marker: Option<String>,
+}
+
+/// ## ListVersionsResult
+///
+/// `ListObjectVersions` response body.
+/// Versions and delete markers are interleaved, so children are read in order
+#[derive(Debug, Deserialize)]
+struct ListVersionsResult {
+    #[serde(rename = "$value", default)]
+    entries: Vec<ListVersionsEntry>,
+}
+
+#[derive(Debug, Deserialize)]
+enum ListVersionsEntry {
+    Version(ObjectVersion),
+    DeleteMarker(ObjectVersion),
+    IsTruncated(bool),
+    NextKeyMarker(String),
+    NextVersionIdMarker(String),
+    Name(IgnoredAny),
+    Prefix(IgnoredAny),
+    KeyMarker(IgnoredAny),
+    VersionIdMarker(IgnoredAny),
+    MaxKeys(IgnoredAny),
+    Delimiter(IgnoredAny),
+    EncodingType(IgnoredAny),
+    CommonPrefixes(IgnoredAny),
+}
+
+impl VersionsPage {
+    /// ### parse
+    ///
+    /// Parse `ListObjectVersions` response body
+    pub fn parse(body: &[u8]) -> Result<Self, String> {
+        let result: ListVersionsResult = serde_xml_rs::from_reader(body)
+            .map_err(|e| format!("Could not parse versions: {}", e))?;
+        let mut page: Self = Self::default();
+        for entry in result.entries.into_iter() {
+            match entry {
+                ListVersionsEntry::Version(mut v) => {
+                    v.e_tag = v.e_tag.map(|x| x.trim_matches('"').to_string());
+                    page.versions.push(v);
+                }
+                ListVersionsEntry::DeleteMarker(mut v) => {
+                    v.delete_marker = true;
+                    page.versions.push(v);
+                }
+                ListVersionsEntry::IsTruncated(truncated) => page.is_truncated = truncated,
+                ListVersionsEntry::NextKeyMarker(marker) => page.next_key_marker = Some(marker),
+                ListVersionsEntry::NextVersionIdMarker(marker) => {
+                    page.next_version_id_marker = Some(marker)
+                }
+                _ => {}
+            }
+        }
+        Ok(page)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn should_parse_versions_page() {
+        let body: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<ListVersionsResult xmlns="http://s3.amazonaws.com/doc/2006-03-01/">
+    <Name>bucket</Name>
+    <Prefix>my-image.jpg</Prefix>
+    <KeyMarker></KeyMarker>
+    <VersionIdMarker></VersionIdMarker>
+    <MaxKeys>1000</MaxKeys>
+    <IsTruncated>true</IsTruncated>
+    <NextKeyMarker>my-image.jpg</NextKeyMarker>
+    <NextVersionIdMarker>1</NextVersionIdMarker>
+    <Version>
+        <Key>my-image.jpg</Key>
+        <VersionId>3</VersionId>
+        <IsLatest>true</IsLatest>
+        <LastModified>2009-10-12T17:50:30.000Z</LastModified>
+        <ETag>"fba9dede5f27731c9771645a39863328"</ETag>
+        <Size>434234</Size>
+        <StorageClass>STANDARD</StorageClass>
+    </Version>
+    <DeleteMarker>
+        <Key>my-image.jpg</Key>
+        <VersionId>2</VersionId>
+        <IsLatest>false</IsLatest>
+        <LastModified>2009-10-11T12:50:30.000Z</LastModified>
+    </DeleteMarker>
+    <Version>
+        <Key>my-image.jpg</Key>
+        <VersionId>1</VersionId>
+        <IsLatest>false</IsLatest>
+        <LastModified>2009-10-10T17:50:30.000Z</LastModified>
+        <ETag>"fba9dede5f27731c9771645a39863328"</ETag>
+        <Size>434234</Size>
+        <StorageClass>STANDARD</StorageClass>
+    </Version>
+</ListVersionsResult>"#;
+        let page: VersionsPage = VersionsPage::parse(body.as_bytes()).unwrap();
+        assert!(page.is_truncated);
+        assert_eq!(page.next_key_marker.as_deref(), Some("my-image.jpg"));
+        assert_eq!(page.next_version_id_marker.as_deref(), Some("1"));
+        assert_eq!(page.versions.len(), 3);
+        assert_eq!(page.versions[0].version_id, "3");
+        assert!(page.versions[0].is_latest);
+        assert_eq!(page.versions[0].size, 434234);
+        assert_eq!(
+            page.versions[0].e_tag.as_deref(),
+            Some("fba9dede5f27731c9771645a39863328")
+        );
+        assert!(!page.versions[0].delete_marker);
+        assert!(page.versions[1].delete_marker);
+        assert_eq!(page.versions[1].size, 0);
+        assert_eq!(page.versions[2].version_id, "1");
+    }
+}
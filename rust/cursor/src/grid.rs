@@ -0,0 +1,165 @@
+use std::ops::{Add, Rem, Sub};
+
+use crate::{Cursor, Navigable, Unit};
+
+/// A cursor over the cells of a grid, moving in row-major order
+///
+/// Each axis is a [`Cursor`], with its own bounds, step and edge behaviour.
+/// Stepping past the end of a row moves to the next row, if the column axis wraps.
+#[derive(Clone)]
+pub struct GridCursor<T>
+where
+    T: Add<Output = T> + Sub<Output = T> + Clone + PartialOrd + Unit + Default + Rem<Output = T>,
+{
+    row: Cursor<T>,
+    col: Cursor<T>,
+}
+
+impl<T> GridCursor<T>
+where
+    T: Add<Output = T> + Sub<Output = T> + Clone + Rem<Output = T> + PartialOrd + Unit + Default,
+{
+    /// Create a new cursor over a grid of `rows` x `cols` cells
+    pub fn new(rows: T, cols: T) -> Self {
+        Self::from_axes(Cursor::new(rows), Cursor::new(cols))
+    }
+
+    /// Create a new cursor from a cursor for each axis
+    pub fn from_axes(row: Cursor<T>, col: Cursor<T>) -> Self {
+        GridCursor { row, col }
+    }
+
+    /// Move the cursor to the next cell, continuing on the next row at the end of a row
+    pub fn step(&mut self) {
+        if self.col.step_wrapping() {
+            self.row.step();
+        }
+    }
+
+    /// Move the cursor to the previous cell, continuing on the previous row at the start of a row
+    pub fn step_back(&mut self) {
+        if self.col.step_back_wrapping() {
+            self.row.step_back();
+        }
+    }
+
+    /// Move the cursor to the next row, on the same column
+    pub fn step_row(&mut self) {
+        self.row.step();
+    }
+
+    /// Move the cursor to the previous row, on the same column
+    pub fn step_row_back(&mut self) {
+        self.row.step_back();
+    }
+
+    /// Move the cursor to the next column, without leaving the row
+    pub fn step_col(&mut self) {
+        self.col.step();
+    }
+
+    /// Move the cursor to the previous column, without leaving the row
+    pub fn step_col_back(&mut self) {
+        self.col.step_back();
+    }
+
+    /// Move the cursor to the cell at `row`, `col`. Returns `false`, leaving the cursor where it is, if the cell is out of bounds
+    pub fn set(&mut self, row: T, col: T) -> bool {
+        let (mut row_cursor, mut col_cursor) = (self.row.clone(), self.col.clone());
+        if row_cursor.set(row) && col_cursor.set(col) {
+            self.row = row_cursor;
+            self.col = col_cursor;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Move the cursor back to the first cell
+    pub fn reset(&mut self) {
+        self.row.reset();
+        self.col.reset();
+    }
+
+    /// Get the current row of the cursor
+    pub fn row(&self) -> T {
+        self.row.value()
+    }
+
+    /// Get the current column of the cursor
+    pub fn col(&self) -> T {
+        self.col.value()
+    }
+}
+
+impl<T> Navigable for GridCursor<T>
+where
+    T: Add<Output = T> + Sub<Output = T> + Clone + Rem<Output = T> + PartialOrd + Unit + Default,
+{
+    type Position = (T, T);
+
+    fn step(&mut self) {
+        GridCursor::step(self)
+    }
+
+    fn step_back(&mut self) {
+        GridCursor::step_back(self)
+    }
+
+    fn position(&self) -> (T, T) {
+        (self.row(), self.col())
+    }
+
+    fn reset(&mut self) {
+        GridCursor::reset(self)
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use crate::Edge;
+
+    #[test]
+    fn test_should_step_grid_in_row_major_order() {
+        let mut cursor = GridCursor::new(2usize, 3);
+        let cells: Vec<(usize, usize)> = (0..6)
+            .map(|_| {
+                cursor.step();
+                cursor.position()
+            })
+            .collect();
+        assert_eq!(cells, vec![(0, 1), (0, 2), (1, 0), (1, 1), (1, 2), (0, 0)]);
+        cursor.step_back();
+        assert_eq!(cursor.position(), (1, 2));
+        cursor.step_back();
+        cursor.step_back();
+        cursor.step_back();
+        assert_eq!(cursor.position(), (0, 2));
+        // a single column moves to the next row on each step
+        let mut cursor = GridCursor::new(3u8, 1);
+        cursor.step();
+        assert_eq!(cursor.position(), (1, 0));
+    }
+
+    #[test]
+    fn test_should_move_grid_along_axes() {
+        let mut cursor =
+            GridCursor::from_axes(Cursor::new(3i32), Cursor::new(4).with_edge(Edge::Clamp));
+        cursor.step_row_back();
+        assert_eq!(cursor.row(), 2);
+        for _ in 0..5 {
+            cursor.step_col();
+        }
+        assert_eq!(cursor.col(), 3);
+        // clamped columns don't move to the next row
+        cursor.step();
+        assert_eq!(cursor.position(), (2, 3));
+        assert!(cursor.set(1, 1));
+        assert!(!cursor.set(1, 4));
+        assert_eq!(cursor.position(), (1, 1));
+        cursor.reset();
+        assert_eq!(cursor.position(), (0, 0));
+    }
+}
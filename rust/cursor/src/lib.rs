@@ -1,40 +1,248 @@
+mod grid;
+mod navigable;
+mod ring;
 mod unit;
 
-use std::ops::{Add, Rem};
+use std::ops::{Add, Rem, Sub};
 
-use self::unit::Unit;
+pub use self::grid::GridCursor;
+pub use self::navigable::Navigable;
+pub use self::ring::RingCursor;
+pub use self::unit::Unit;
+
+/// What a cursor does when a step would take it past one of its bounds
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Edge {
+    /// Continue from the other bound, as if the range was circular
+    #[default]
+    Wrap,
+    /// Stay on the current value
+    Clamp,
+    /// Step back the other way and keep moving in that direction
+    Bounce,
+}
 
 /// A cursor that can be used to iterate over a collection of items
+///
+/// The cursor moves in the range `[lower, upper)`, by a configurable step.
+/// By default the range starts at `T::default()`, the step is `T::unit()` and the cursor wraps at the bounds.
 #[derive(Clone)]
 pub struct Cursor<T>
 where
-    T: Add<Output = T> + Clone + PartialEq + Unit + Default + Rem<Output = T>,
+    T: Add<Output = T> + Sub<Output = T> + Clone + PartialOrd + Unit + Default + Rem<Output = T>,
 {
     value: T,
-    limit: T,
+    lower: T,
+    upper: T,
+    step: T,
+    edge: Edge,
+    /// Whether `step()` moves towards the upper bound; only changes on bounce
+    forward: bool,
 }
 
 impl<T> Cursor<T>
 where
-    T: Add<Output = T> + Clone + Rem<Output = T> + PartialEq + Unit + Default,
+    T: Add<Output = T> + Sub<Output = T> + Clone + Rem<Output = T> + PartialOrd + Unit + Default,
 {
     /// Create a new cursor
     pub fn new(limit: T) -> Self {
+        Self::bounded(T::default(), limit)
+    }
+
+    /// Create a new cursor moving in `[lower, upper)`, starting from `lower`.
+    ///
+    /// Wrapping panics for integers if the range is empty.
+    pub fn bounded(lower: T, upper: T) -> Self {
         Cursor {
-            value: T::default(),
-            limit,
+            value: lower.clone(),
+            lower,
+            upper,
+            step: T::unit(),
+            edge: Edge::default(),
+            forward: true,
         }
     }
 
+    /// Set the amount the cursor moves by on each step
+    pub fn with_step(mut self, step: T) -> Self {
+        self.step = step;
+        self
+    }
+
+    /// Set the behaviour of the cursor at the bounds
+    pub fn with_edge(mut self, edge: Edge) -> Self {
+        self.edge = edge;
+        self
+    }
+
     /// Move the cursor to the next position
     pub fn step(&mut self) {
-        self.value = (self.value.clone() + T::unit()) % self.limit.clone();
+        (self.value, self.forward) = self.advance(self.forward);
+    }
+
+    /// Move the cursor to the previous position
+    pub fn step_back(&mut self) {
+        let (value, forward) = self.advance(!self.forward);
+        self.value = value;
+        self.forward = !forward;
+    }
+
+    /// Get the value the cursor would have after `step()`, without moving it
+    pub fn peek_next(&self) -> T {
+        self.advance(self.forward).0
+    }
+
+    /// Move the cursor to `value`. Returns `false`, leaving the cursor where it is, if `value` is out of bounds
+    pub fn set(&mut self, value: T) -> bool {
+        let in_bounds = (value >= self.lower || value.approx_eq(&self.lower))
+            && value < self.upper
+            && !value.approx_eq(&self.upper);
+        if in_bounds {
+            self.value = value;
+        }
+        in_bounds
+    }
+
+    /// Move the cursor back to the lower bound, going forward
+    pub fn reset(&mut self) {
+        self.value = self.lower.clone();
+        self.forward = true;
     }
 
     /// Get the current value of the cursor
     pub fn value(&self) -> T {
         self.value.clone()
     }
+
+    /// Move the cursor to the next position. Returns whether it wrapped around the upper bound
+    pub(crate) fn step_wrapping(&mut self) -> bool {
+        let previous = self.value();
+        self.step();
+        // a step forward which doesn't wrap always increases the value
+        self.edge == Edge::Wrap && self.value <= previous
+    }
+
+    /// Move the cursor to the previous position. Returns whether it wrapped around the lower bound
+    pub(crate) fn step_back_wrapping(&mut self) -> bool {
+        let previous = self.value();
+        self.step_back();
+        self.edge == Edge::Wrap && self.value >= previous
+    }
+
+    /// Get the position after a step towards the upper bound if `forward`, and the direction to keep.
+    /// Distances are compared before adding or subtracting, so integers never overflow at the bounds
+    fn advance(&self, forward: bool) -> (T, bool) {
+        let (value, step) = (self.value.clone(), self.step.clone());
+        match forward {
+            true if self.fits_forward() => (value + step, true),
+            false if self.fits_backward() => (self.snap(value - step), false),
+            true => match self.edge {
+                Edge::Wrap => {
+                    let room = self.upper.clone() - value;
+                    (self.wrap_from_lower(step, room), true)
+                }
+                Edge::Clamp => (value, true),
+                Edge::Bounce if self.fits_backward() => (self.snap(value - step), false),
+                Edge::Bounce => (value, true),
+            },
+            false => match self.edge {
+                Edge::Wrap => {
+                    let room = value - self.lower.clone();
+                    (self.wrap_from_upper(step, room), false)
+                }
+                Edge::Clamp => (value, false),
+                Edge::Bounce if self.fits_forward() => (value + step, true),
+                Edge::Bounce => (value, false),
+            },
+        }
+    }
+
+    /// Whether the cursor can step forward without reaching the upper bound
+    fn fits_forward(&self) -> bool {
+        // across zero the room may not fit in signed integers, but the step can be added to the value
+        let (reached, bound) = match Self::crosses_zero(&self.value, &self.upper) {
+            true => (self.value.clone() + self.step.clone(), self.upper.clone()),
+            false => (self.step.clone(), self.upper.clone() - self.value.clone()),
+        };
+        reached < bound && !reached.approx_eq(&bound)
+    }
+
+    /// Whether the cursor can step back without going below the lower bound
+    fn fits_backward(&self) -> bool {
+        let (bound, reached) = match Self::crosses_zero(&self.lower, &self.value) {
+            true => (self.lower.clone(), self.value.clone() - self.step.clone()),
+            false => (self.step.clone(), self.value.clone() - self.lower.clone()),
+        };
+        bound < reached || bound.approx_eq(&reached)
+    }
+
+    /// Whether `high - low` may not fit in `T`, which only happens for signed integers across zero
+    fn crosses_zero(low: &T, high: &T) -> bool {
+        *low < T::default() && *high >= T::default()
+    }
+
+    /// Get the position `step` past the upper bound, counting from the lower one
+    fn wrap_from_lower(&self, step: T, room: T) -> T {
+        // room may be slightly greater than step for floats
+        if step.approx_eq(&room) || step < room {
+            return self.lower.clone();
+        }
+        let wrapped = self.lower.clone() + self.rem_span(step - room);
+        match wrapped.approx_eq(&self.upper) {
+            true => self.lower.clone(),
+            false => wrapped,
+        }
+    }
+
+    /// Get the position `step` below the lower bound, counting back from the upper one
+    fn wrap_from_upper(&self, step: T, room: T) -> T {
+        let wrapped = self.upper.clone() - self.rem_span(step - room);
+        match wrapped.approx_eq(&self.upper) || wrapped.approx_eq(&self.lower) {
+            true => self.lower.clone(),
+            false => wrapped,
+        }
+    }
+
+    /// Snap values which are only off the lower bound because of float rounding
+    fn snap(&self, value: T) -> T {
+        match value.approx_eq(&self.lower) {
+            true => self.lower.clone(),
+            false => value,
+        }
+    }
+
+    /// Get `distance` modulo the span of the range, without computing the span if it may not fit in `T`
+    fn rem_span(&self, distance: T) -> T {
+        let within = Self::crosses_zero(&self.lower, &self.upper)
+            && self.lower.clone() + distance.clone() < self.upper;
+        match within {
+            true => distance,
+            false => distance % (self.upper.clone() - self.lower.clone()),
+        }
+    }
+}
+
+impl<T> Navigable for Cursor<T>
+where
+    T: Add<Output = T> + Sub<Output = T> + Clone + Rem<Output = T> + PartialOrd + Unit + Default,
+{
+    type Position = T;
+
+    fn step(&mut self) {
+        Cursor::step(self)
+    }
+
+    fn step_back(&mut self) {
+        Cursor::step_back(self)
+    }
+
+    fn position(&self) -> T {
+        self.value()
+    }
+
+    fn reset(&mut self) {
+        Cursor::reset(self)
+    }
 }
 
 #[cfg(test)]
@@ -52,4 +260,151 @@ mod test {
         cursor.step();
         assert_eq!(cursor.value, 0);
     }
+
+    #[test]
+    fn test_should_step_back() {
+        let mut cursor = Cursor::new(3usize);
+        cursor.step_back();
+        assert_eq!(cursor.value(), 2);
+        cursor.step_back();
+        assert_eq!(cursor.value(), 1);
+        cursor.step();
+        assert_eq!(cursor.value(), 2);
+    }
+
+    #[test]
+    fn test_should_step_by_within_bounds() {
+        let mut cursor = Cursor::bounded(10u8, 20).with_step(4);
+        let values: Vec<u8> = (0..4)
+            .map(|_| {
+                cursor.step();
+                cursor.value()
+            })
+            .collect();
+        assert_eq!(values, vec![14, 18, 12, 16]);
+        cursor.step_back();
+        assert_eq!(cursor.value(), 12);
+        cursor.step_back();
+        assert_eq!(cursor.value(), 18);
+        // no overflow at the numeric limits
+        let mut cursor = Cursor::bounded(250u8, 255).with_step(3);
+        cursor.step();
+        cursor.step();
+        assert_eq!(cursor.value(), 251);
+        let mut cursor = Cursor::bounded(-2i32, 2);
+        cursor.step_back();
+        assert_eq!(cursor.value(), 1);
+    }
+
+    #[test]
+    fn test_should_not_overflow_wide_signed_ranges() {
+        // the span of the range doesn't fit in i8
+        let mut cursor = Cursor::bounded(-100i8, 100);
+        cursor.step();
+        assert_eq!(cursor.value(), -99);
+        for _ in 0..199 {
+            cursor.step();
+        }
+        assert_eq!(cursor.value(), -100);
+        cursor.step_back();
+        assert_eq!(cursor.value(), 99);
+        let mut cursor = Cursor::bounded(-100i8, 100).with_step(60);
+        let values: Vec<i8> = (0..4)
+            .map(|_| {
+                cursor.step();
+                cursor.value()
+            })
+            .collect();
+        assert_eq!(values, vec![-40, 20, 80, -60]);
+        cursor.step_back();
+        assert_eq!(cursor.value(), 80);
+        cursor.set(-90);
+        cursor.step_back();
+        assert_eq!(cursor.value(), 50);
+        let mut cursor = Cursor::bounded(i8::MIN, i8::MAX)
+            .with_step(100)
+            .with_edge(Edge::Bounce);
+        let values: Vec<i8> = (0..4)
+            .map(|_| {
+                cursor.step();
+                cursor.value()
+            })
+            .collect();
+        assert_eq!(values, vec![-28, 72, -28, -128]);
+        let mut cursor = Cursor::bounded(-100i8, 100).with_edge(Edge::Clamp);
+        cursor.set(99);
+        cursor.step();
+        assert_eq!(cursor.value(), 99);
+    }
+
+    #[test]
+    fn test_should_clamp_cursor() {
+        let mut cursor = Cursor::new(3usize).with_edge(Edge::Clamp);
+        cursor.step_back();
+        assert_eq!(cursor.value(), 0);
+        for _ in 0..5 {
+            cursor.step();
+        }
+        assert_eq!(cursor.value(), 2);
+        assert_eq!(cursor.peek_next(), 2);
+    }
+
+    #[test]
+    fn test_should_bounce_cursor() {
+        let mut cursor = Cursor::new(3usize).with_edge(Edge::Bounce);
+        let values: Vec<usize> = (0..6)
+            .map(|_| {
+                cursor.step();
+                cursor.value()
+            })
+            .collect();
+        assert_eq!(values, vec![1, 2, 1, 0, 1, 2]);
+        // step back goes against the current direction
+        cursor.step_back();
+        assert_eq!(cursor.value(), 1);
+        cursor.step();
+        assert_eq!(cursor.value(), 2);
+        // a single position can't bounce
+        let mut cursor = Cursor::new(1usize).with_edge(Edge::Bounce);
+        cursor.step();
+        assert_eq!(cursor.value(), 0);
+    }
+
+    #[test]
+    fn test_should_set_and_reset_cursor() {
+        let mut cursor = Cursor::bounded(1i64, 10);
+        assert!(cursor.set(5));
+        assert_eq!(cursor.value(), 5);
+        assert_eq!(cursor.peek_next(), 6);
+        assert_eq!(cursor.value(), 5);
+        assert!(!cursor.set(10));
+        assert!(!cursor.set(0));
+        assert_eq!(cursor.value(), 5);
+        cursor.reset();
+        assert_eq!(cursor.value(), 1);
+    }
+
+    #[test]
+    fn test_should_wrap_float_cursor() {
+        let mut cursor = Cursor::new(1.0f64).with_step(0.1);
+        for _ in 0..9 {
+            cursor.step();
+        }
+        assert!(cursor.value().approx_eq(&0.9));
+        assert_eq!(cursor.peek_next(), 0.0);
+        cursor.step();
+        assert_eq!(cursor.value(), 0.0);
+        cursor.step_back();
+        assert!(cursor.value().approx_eq(&0.9));
+        for _ in 0..9 {
+            cursor.step_back();
+        }
+        assert_eq!(cursor.value(), 0.0);
+        let mut cursor = Cursor::new(1.0f32).with_step(0.3);
+        for _ in 0..4 {
+            cursor.step();
+        }
+        assert!(cursor.value().approx_eq(&0.2));
+        assert!(!cursor.set(1.0 - f32::EPSILON));
+    }
 }
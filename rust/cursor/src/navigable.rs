@@ -0,0 +1,19 @@
+/// A trait for cursors which move back and forth over a set of positions.
+///
+/// Widgets can take any `Navigable` to handle navigation keys, whatever the shape of the data.
+pub trait Navigable {
+    /// The type the position of the cursor is expressed with
+    type Position;
+
+    /// Move the cursor to the next position
+    fn step(&mut self);
+
+    /// Move the cursor to the previous position
+    fn step_back(&mut self);
+
+    /// Get the current position of the cursor
+    fn position(&self) -> Self::Position;
+
+    /// Move the cursor back to its initial position
+    fn reset(&mut self);
+}
@@ -0,0 +1,152 @@
+use std::collections::VecDeque;
+
+use crate::{Cursor, Navigable};
+
+/// A bounded buffer with a cursor over its items, e.g. for a history
+///
+/// Pushing over capacity evicts the oldest item.
+/// The cursor wraps between the oldest and the newest item.
+#[derive(Clone)]
+pub struct RingCursor<T> {
+    items: VecDeque<T>,
+    capacity: usize,
+    /// Index in `items`, from the oldest item
+    cursor: Cursor<usize>,
+}
+
+impl<T> RingCursor<T> {
+    /// Create a new empty buffer holding up to `capacity` items
+    pub fn new(capacity: usize) -> Self {
+        RingCursor {
+            items: VecDeque::with_capacity(capacity),
+            capacity,
+            cursor: Cursor::new(0),
+        }
+    }
+
+    /// Push a new item, evicting the oldest one if the buffer is full, and move the cursor on it
+    pub fn push(&mut self, item: T) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.items.len() == self.capacity {
+            self.items.pop_front();
+        }
+        self.items.push_back(item);
+        self.cursor = Cursor::new(self.items.len());
+        self.reset();
+    }
+
+    /// Move the cursor to the next newer item, or to the oldest from the newest
+    pub fn step(&mut self) {
+        if !self.items.is_empty() {
+            self.cursor.step();
+        }
+    }
+
+    /// Move the cursor to the next older item, or to the newest from the oldest
+    pub fn step_back(&mut self) {
+        if !self.items.is_empty() {
+            self.cursor.step_back();
+        }
+    }
+
+    /// Move the cursor back to the newest item
+    pub fn reset(&mut self) {
+        self.cursor.set(self.items.len().saturating_sub(1));
+    }
+
+    /// Get the item under the cursor
+    pub fn current(&self) -> Option<&T> {
+        self.items.get(self.cursor.value())
+    }
+
+    /// Get the items, from the oldest to the newest
+    pub fn items(&self) -> &VecDeque<T> {
+        &self.items
+    }
+
+    /// Get the amount of items in the buffer
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    /// Get whether the buffer has no items
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+}
+
+impl<T> Navigable for RingCursor<T> {
+    /// Index of the item under the cursor, from the oldest; `None` if the buffer is empty
+    type Position = Option<usize>;
+
+    fn step(&mut self) {
+        RingCursor::step(self)
+    }
+
+    fn step_back(&mut self) {
+        RingCursor::step_back(self)
+    }
+
+    fn position(&self) -> Option<usize> {
+        match self.items.is_empty() {
+            true => None,
+            false => Some(self.cursor.value()),
+        }
+    }
+
+    fn reset(&mut self) {
+        RingCursor::reset(self)
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    #[test]
+    fn test_should_wrap_ring_between_oldest_and_newest() {
+        let mut ring = RingCursor::new(3);
+        assert_eq!(ring.current(), None);
+        assert_eq!(ring.position(), None);
+        ring.step_back();
+        for command in ["ls", "cd", "pwd", "exit"] {
+            ring.push(command);
+        }
+        assert_eq!(ring.items(), &VecDeque::from(vec!["cd", "pwd", "exit"]));
+        assert_eq!(ring.current(), Some(&"exit"));
+        ring.step_back();
+        assert_eq!(ring.current(), Some(&"pwd"));
+        ring.step_back();
+        ring.step_back();
+        assert_eq!(ring.current(), Some(&"exit"));
+        ring.step();
+        assert_eq!(ring.current(), Some(&"cd"));
+        assert_eq!(ring.position(), Some(0));
+        ring.reset();
+        assert_eq!(ring.position(), Some(2));
+    }
+
+    #[test]
+    fn test_should_navigate_any_cursor() {
+        fn walk<N: Navigable>(cursor: &mut N, steps: usize) -> N::Position {
+            for _ in 0..steps {
+                cursor.step();
+            }
+            cursor.position()
+        }
+
+        let mut ring = RingCursor::new(0);
+        ring.push(1);
+        assert!(ring.is_empty());
+        assert_eq!(walk(&mut ring, 2), None);
+        let mut ring = RingCursor::new(2);
+        ring.push(1);
+        ring.push(2);
+        assert_eq!(ring.len(), 2);
+        assert_eq!(walk(&mut ring, 3), Some(0));
+        assert_eq!(walk(&mut Cursor::new(4u16), 5), 1);
+    }
+}
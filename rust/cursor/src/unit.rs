@@ -3,6 +3,12 @@
 /// E.g. 1 for integers, 1.0 for floats, etc.
 pub trait Unit {
     fn unit() -> Self;
+
+    /// Whether `self` and `other` should be considered the same value.
+    ///
+    /// Exact for integers; floats tolerate the rounding errors accumulated by repeated steps,
+    /// so that e.g. `0.1` added ten times to `0.0` reaches `1.0`.
+    fn approx_eq(&self, other: &Self) -> bool;
 }
 
 /// Implement One for common numeric types.
@@ -12,6 +18,10 @@ macro_rules! impl_one_for_numeric {
             fn unit() -> Self {
                 1
             }
+
+            fn approx_eq(&self, other: &Self) -> bool {
+                self == other
+            }
         }
     )*)
 }
@@ -25,6 +35,12 @@ macro_rules! impl_one_for_floats {
             fn unit() -> Self {
                 1.0
             }
+
+            fn approx_eq(&self, other: &Self) -> bool {
+                // relative tolerance, but never smaller than the absolute one around zero
+                let scale = self.abs().max(other.abs()).max(1.0);
+                (self - other).abs() <= <$t>::EPSILON * 64.0 * scale
+            }
         }
     )*)
 }
@@ -4,15 +4,291 @@
 
 use std::fs::File;
 use std::fs::OpenOptions;
-use std::io::{self, BufRead, Write};
-use std::path::Path;
+use std::io::{self, BufRead, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use std::time::Duration;
 
+/// Interval between attempts to acquire a lock with a timeout
+#[cfg(unix)]
+const LOCK_POLL_INTERVAL: Duration = Duration::from_millis(10);
+/// Size of blocks read backwards by `read_last_lines`
+const TAIL_BLOCK_SIZE: u64 = 64 * 1024;
+/// Default interval between checks for new data in followed files
+const FOLLOW_POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// ## LockMode
+///
+/// Advisory lock mode
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum LockMode {
+    /// Many processes can hold a shared lock at once (readers)
+    Shared,
+    /// Only one process can hold an exclusive lock (writers)
+    Exclusive,
+}
+
+/// ## FileOptions
+///
+/// Options used to open a file, optionally acquiring an advisory lock on it
+#[derive(Clone, Debug)]
+pub struct FileOptions {
+    read: bool,
+    write: bool,
+    append: bool,
+    create: bool,
+    truncate: bool,
+    lock: Option<LockMode>,
+    timeout: Option<Duration>,
+}
+
+impl Default for FileOptions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FileOptions {
+    /// ### new
+    ///
+    /// Instantiate options to open a file for reading
+    pub fn new() -> Self {
+        FileOptions {
+            read: true,
+            write: false,
+            append: false,
+            create: false,
+            truncate: false,
+            lock: None,
+            timeout: None,
+        }
+    }
+
+    /// ### read
+    ///
+    /// Set read access
+    pub fn read(&mut self, read: bool) -> &mut Self {
+        self.read = read;
+        self
+    }
+
+    /// ### write
+    ///
+    /// Set write access
+    pub fn write(&mut self, write: bool) -> &mut Self {
+        self.write = write;
+        self
+    }
+
+    /// ### append
+    ///
+    /// Set append mode
+    pub fn append(&mut self, append: bool) -> &mut Self {
+        self.append = append;
+        self
+    }
+
+    /// ### create
+    ///
+    /// Create file if it doesn't exist
+    pub fn create(&mut self, create: bool) -> &mut Self {
+        self.create = create;
+        self
+    }
+
+    /// ### truncate
+    ///
+    /// Truncate file when opened. If a lock is requested, the file is truncated once the lock is held
+    pub fn truncate(&mut self, truncate: bool) -> &mut Self {
+        self.truncate = truncate;
+        self
+    }
+
+    /// ### lock
+    ///
+    /// Acquire an advisory lock on the file when opened. The lock is released when the file is closed
+    pub fn lock(&mut self, mode: LockMode) -> &mut Self {
+        self.lock = Some(mode);
+        self
+    }
+
+    /// ### timeout
+    ///
+    /// Set how long to wait for the lock. Waits forever if not set
+    pub fn timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// ### open
+    ///
+    /// Open file with these options
+    pub fn open<P>(&self, filename: P) -> io::Result<File> where P: AsRef<Path>, {
+        let file: File = OpenOptions::new()
+            .read(self.read)
+            .write(self.write)
+            .append(self.append)
+            .create(self.create)
+            .truncate(self.truncate && self.lock.is_none())
+            .open(filename)?;
+        if let Some(mode) = self.lock {
+            lock_file(&file, mode, self.timeout)?;
+            if self.truncate {
+                file.set_len(0)?;
+            }
+        }
+        Ok(file)
+    }
+}
 
 /// ### open_file
 /// 
-/// Open file provided as parameter
-pub fn open_file<P>(filename: P, create: bool, write: bool, append: bool) -> io::Result<File> where P: AsRef<Path>, {
-    OpenOptions::new().create(create).write(write).append(append).truncate(!append).open(filename)
+/// Open file provided as parameter with `options`
+pub fn open_file<P>(filename: P, options: &FileOptions) -> io::Result<File> where P: AsRef<Path>, {
+    options.open(filename)
+}
+
+/// ### lock_file
+///
+/// Acquire an advisory lock (`flock`) on `file`, waiting at most `timeout` if provided.
+/// Returns an error of kind `WouldBlock` if the lock couldn't be acquired in time
+#[cfg(unix)]
+pub fn lock_file(file: &File, mode: LockMode, timeout: Option<Duration>) -> io::Result<()> {
+    use std::os::unix::io::AsRawFd;
+    use std::time::Instant;
+    let operation: libc::c_int = match mode {
+        LockMode::Shared => libc::LOCK_SH,
+        LockMode::Exclusive => libc::LOCK_EX,
+    };
+    let deadline: Option<Instant> = timeout.map(|x| Instant::now() + x);
+    loop {
+        let flags: libc::c_int = match deadline {
+            Some(_) => operation | libc::LOCK_NB,
+            None => operation,
+        };
+        if unsafe { libc::flock(file.as_raw_fd(), flags) } == 0 {
+            return Ok(());
+        }
+        let err: io::Error = io::Error::last_os_error();
+        match (err.kind(), deadline) {
+            (io::ErrorKind::Interrupted, _) => continue,
+            (io::ErrorKind::WouldBlock, Some(deadline)) if Instant::now() < deadline => {
+                thread::sleep(LOCK_POLL_INTERVAL)
+            }
+            _ => return Err(err),
+        }
+    }
+}
+
+/// ### unlock_file
+///
+/// Release the advisory lock held on `file`
+#[cfg(unix)]
+pub fn unlock_file(file: &File) -> io::Result<()> {
+    use std::os::unix::io::AsRawFd;
+    match unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_UN) } {
+        0 => Ok(()),
+        _ => Err(io::Error::last_os_error()),
+    }
+}
+
+#[cfg(not(unix))]
+pub fn lock_file(_file: &File, _mode: LockMode, _timeout: Option<Duration>) -> io::Result<()> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "file locking is only supported on unix"))
+}
+
+#[cfg(not(unix))]
+pub fn unlock_file(_file: &File) -> io::Result<()> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "file locking is only supported on unix"))
+}
+
+/// ### write_atomic
+///
+/// Replace the content of `filename` with `data` atomically: data is written to a temporary file
+/// in the same directory, synced to disk and then renamed over `filename`.
+/// Readers see either the old or the new content, never a partially written file.
+/// If `filename` is a symlink, the file it points to is replaced and the link is kept;
+/// a dangling symlink is replaced by a regular file
+pub fn write_atomic<P>(filename: P, data: &[u8]) -> io::Result<()> where P: AsRef<Path>, {
+    let filename: PathBuf = resolve_link(filename.as_ref());
+    let filename: &Path = filename.as_path();
+    let tmp: PathBuf = temp_path(filename);
+    let result: io::Result<()> = write_synced(tmp.as_path(), filename, data)
+        .and_then(|_| std::fs::rename(tmp.as_path(), filename));
+    if result.is_err() {
+        let _ = std::fs::remove_file(tmp.as_path());
+    }
+    result?;
+    sync_parent(filename)
+}
+
+/// ### resolve_link
+///
+/// Get the path of the file `filename` points to, if it is a symlink to an existing file
+fn resolve_link(filename: &Path) -> PathBuf {
+    match std::fs::symlink_metadata(filename) {
+        Ok(metadata) if metadata.file_type().is_symlink() => {
+            std::fs::canonicalize(filename).unwrap_or_else(|_| filename.to_path_buf())
+        }
+        _ => filename.to_path_buf(),
+    }
+}
+
+/// ### temp_path
+///
+/// Get a unique temporary path next to `filename`
+fn temp_path(filename: &Path) -> PathBuf {
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+    let name: String = filename
+        .file_name()
+        .map(|x| x.to_string_lossy().to_string())
+        .unwrap_or_default();
+    filename.with_file_name(format!(
+        ".{}.{}.{}.tmp",
+        name,
+        std::process::id(),
+        COUNTER.fetch_add(1, Ordering::Relaxed)
+    ))
+}
+
+/// ### write_synced
+///
+/// Write `data` to `tmp` and sync it to disk, preserving permissions of `filename` if it exists.
+/// When replacing a file, `tmp` is created accessible by the owner only and gets the original
+/// permissions before any data is written, so the data is never more exposed than it was
+fn write_synced(tmp: &Path, filename: &Path, data: &[u8]) -> io::Result<()> {
+    let original: Option<std::fs::Permissions> =
+        std::fs::metadata(filename).ok().map(|x| x.permissions());
+    let mut options: OpenOptions = OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    if original.is_some() {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file: File = options.open(tmp)?;
+    if let Some(permissions) = original {
+        file.set_permissions(permissions)?;
+    }
+    file.write_all(data)?;
+    file.sync_all()
+}
+
+/// ### sync_parent
+///
+/// Sync the directory containing `filename`, so that the rename survives a crash
+#[cfg(unix)]
+fn sync_parent(filename: &Path) -> io::Result<()> {
+    match filename.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => File::open(dir)?.sync_all(),
+        _ => File::open(".")?.sync_all(),
+    }
+}
+
+#[cfg(not(unix))]
+fn sync_parent(_filename: &Path) -> io::Result<()> {
+    Ok(())
 }
 
 /// ### read_file
@@ -22,35 +298,452 @@ pub fn read_file<P>(filename: P) -> io::Result<String> where P: AsRef<Path>, {
     std::fs::read_to_string(filename)
 }
 
+/// ## Lines
+///
+/// Lazy iterator over the lines of a file, without line terminators
+pub struct Lines {
+    reader: io::BufReader<File>,
+}
+
+impl Iterator for Lines {
+    type Item = io::Result<String>;
+
+    fn next(&mut self) -> Option<io::Result<String>> {
+        let mut line: String = String::new();
+        match self.reader.read_line(&mut line) {
+            Ok(0) => None,
+            Ok(_) => {
+                if line.ends_with('\n') {
+                    line.pop();
+                    if line.ends_with('\r') {
+                        line.pop();
+                    }
+                }
+                Some(Ok(line))
+            }
+            Err(err) => Some(Err(err)),
+        }
+    }
+}
+
+/// ### lines
+///
+/// Iterate over the lines of file, reading them as they are consumed
+pub fn lines<P>(filename: P) -> io::Result<Lines> where P: AsRef<Path>, {
+    let file: File = File::open(filename)?;
+    Ok(Lines {
+        reader: io::BufReader::new(file),
+    })
+}
+
 /// ### read_lines
 /// 
 /// Read lines from file
 #[allow(dead_code)]
 pub fn read_lines<P>(filename: P) -> io::Result<Vec<String>> where P: AsRef<Path>, {
-    let file: File = File::open(filename)?;
-    let reader = io::BufReader::new(file).lines();
-    let mut lines: Vec<String> = Vec::new();
-    for line in reader {
-        if let Ok(line) = line {
-            lines.push(line);
-        }
-    }
-    Ok(lines)
+    Ok(lines(filename)?.filter_map(|line| line.ok()).collect())
 }
 
 /// ### write_lines
 /// 
-/// Write lines to file
+/// Write lines to file. The file is replaced atomically (see `write_atomic`)
 #[allow(dead_code)]
 pub fn write_lines<P>(filename: P, lines: Vec<String>) -> io::Result<()> where P: AsRef<Path> {
-    match open_file(filename, true, true, false) {
-        Ok(mut f) => {
-            for line in lines.iter() {
-                writeln!(f, "{}", line)?;
+    write_lines_with(filename, lines, &TextFormat::default())
+}
+
+/// ## Encoding
+///
+/// Text encoding
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Encoding {
+    Utf8,
+    Utf16Le,
+    Utf16Be,
+    Latin1,
+}
+
+impl Encoding {
+    /// ### bom
+    ///
+    /// Get the byte order mark of this encoding
+    pub fn bom(&self) -> &'static [u8] {
+        match self {
+            Encoding::Utf8 => &[0xEF, 0xBB, 0xBF],
+            Encoding::Utf16Le => &[0xFF, 0xFE],
+            Encoding::Utf16Be => &[0xFE, 0xFF],
+            Encoding::Latin1 => &[],
+        }
+    }
+
+    /// ### detect
+    ///
+    /// Detect encoding from the byte order mark at the beginning of `data`
+    pub fn detect(data: &[u8]) -> Option<Encoding> {
+        [Encoding::Utf8, Encoding::Utf16Le, Encoding::Utf16Be]
+            .iter()
+            .copied()
+            .find(|x| data.starts_with(x.bom()))
+    }
+}
+
+/// ## LineEnding
+///
+/// Line terminator
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum LineEnding {
+    Lf,
+    CrLf,
+}
+
+impl LineEnding {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LineEnding::Lf => "\n",
+            LineEnding::CrLf => "\r\n",
+        }
+    }
+}
+
+/// ## DecodeMode
+///
+/// How invalid sequences are handled when decoding
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum DecodeMode {
+    /// Fail with `InvalidData`
+    Strict,
+    /// Replace them with U+FFFD
+    Lossy,
+}
+
+/// ## TextFormat
+///
+/// Describes how a text file is encoded, so that it can be written back the way it was read
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct TextFormat {
+    pub encoding: Encoding,
+    /// Whether the file starts with a byte order mark
+    pub bom: bool,
+    pub line_ending: LineEnding,
+    /// Whether the last line is terminated
+    pub final_newline: bool,
+}
+
+impl Default for TextFormat {
+    fn default() -> Self {
+        TextFormat {
+            encoding: Encoding::Utf8,
+            bom: false,
+            line_ending: LineEnding::Lf,
+            final_newline: true,
+        }
+    }
+}
+
+/// ## ReadOptions
+///
+/// Options used to decode text files
+#[derive(Clone, Copy, Debug)]
+pub struct ReadOptions {
+    /// Encoding to decode with. If not set, it's detected from the BOM, falling back to UTF-8
+    pub encoding: Option<Encoding>,
+    pub mode: DecodeMode,
+}
+
+impl Default for ReadOptions {
+    fn default() -> Self {
+        ReadOptions {
+            encoding: None,
+            mode: DecodeMode::Strict,
+        }
+    }
+}
+
+/// ### read_text
+///
+/// Read and decode entire file. Returns the text, without BOM, and its format
+pub fn read_text<P>(filename: P, options: &ReadOptions) -> io::Result<(String, TextFormat)> where P: AsRef<Path>, {
+    let data: Vec<u8> = std::fs::read(filename)?;
+    let detected: Option<Encoding> = Encoding::detect(data.as_slice());
+    let encoding: Encoding = options.encoding.or(detected).unwrap_or(Encoding::Utf8);
+    let bom: bool = detected == Some(encoding);
+    let data: &[u8] = match bom {
+        true => &data[encoding.bom().len()..],
+        false => data.as_slice(),
+    };
+    let text: String = decode(data, encoding, options.mode)?;
+    let line_ending: LineEnding = match text.find('\n') {
+        Some(pos) if text[..pos].ends_with('\r') => LineEnding::CrLf,
+        _ => LineEnding::Lf,
+    };
+    let final_newline: bool = text.is_empty() || text.ends_with('\n');
+    Ok((text, TextFormat { encoding, bom, line_ending, final_newline }))
+}
+
+/// ### read_lines_with
+///
+/// Read lines from file decoding it according to `options`. Returns lines and the file format
+pub fn read_lines_with<P>(filename: P, options: &ReadOptions) -> io::Result<(Vec<String>, TextFormat)> where P: AsRef<Path>, {
+    let (text, format) = read_text(filename, options)?;
+    let lines: Vec<String> = text
+        .lines()
+        .map(|line| line.to_string())
+        .collect();
+    Ok((lines, format))
+}
+
+/// ### write_lines_with
+///
+/// Write lines to file with the provided format. The file is replaced atomically (see `write_atomic`)
+pub fn write_lines_with<P>(filename: P, lines: Vec<String>, format: &TextFormat) -> io::Result<()> where P: AsRef<Path>, {
+    let mut text: String = lines.join(format.line_ending.as_str());
+    if format.final_newline && !lines.is_empty() {
+        text.push_str(format.line_ending.as_str());
+    }
+    let mut data: Vec<u8> = Vec::new();
+    if format.bom {
+        data.extend_from_slice(format.encoding.bom());
+    }
+    data.extend(encode(text.as_str(), format.encoding)?);
+    write_atomic(filename, data.as_slice())
+}
+
+/// ### decode
+///
+/// Decode `data` with `encoding`
+fn decode(data: &[u8], encoding: Encoding, mode: DecodeMode) -> io::Result<String> {
+    let invalid = |err: String| io::Error::new(io::ErrorKind::InvalidData, err);
+    match (encoding, mode) {
+        (Encoding::Utf8, DecodeMode::Strict) => String::from_utf8(data.to_vec()).map_err(|e| invalid(e.to_string())),
+        (Encoding::Utf8, DecodeMode::Lossy) => Ok(String::from_utf8_lossy(data).to_string()),
+        (Encoding::Latin1, _) => Ok(data.iter().map(|x| *x as char).collect()),
+        (Encoding::Utf16Le, _) | (Encoding::Utf16Be, _) => {
+            let units = data.chunks_exact(2).map(|x| match encoding {
+                Encoding::Utf16Le => u16::from_le_bytes([x[0], x[1]]),
+                _ => u16::from_be_bytes([x[0], x[1]]),
+            });
+            let mut text: String = String::with_capacity(data.len() / 2);
+            for c in char::decode_utf16(units) {
+                match (c, mode) {
+                    (Ok(c), _) => text.push(c),
+                    (Err(_), DecodeMode::Lossy) => text.push(char::REPLACEMENT_CHARACTER),
+                    (Err(err), DecodeMode::Strict) => return Err(invalid(err.to_string())),
+                }
+            }
+            match (data.len() % 2, mode) {
+                (0, _) => Ok(text),
+                (_, DecodeMode::Lossy) => {
+                    text.push(char::REPLACEMENT_CHARACTER);
+                    Ok(text)
+                }
+                (_, DecodeMode::Strict) => Err(invalid(String::from("odd number of bytes in UTF-16 data"))),
+            }
+        }
+    }
+}
+
+/// ### encode
+///
+/// Encode `text` with `encoding`. Fails with `InvalidInput` if `text` can't be represented in Latin-1
+fn encode(text: &str, encoding: Encoding) -> io::Result<Vec<u8>> {
+    match encoding {
+        Encoding::Utf8 => Ok(text.as_bytes().to_vec()),
+        Encoding::Utf16Le => Ok(text.encode_utf16().flat_map(|x| x.to_le_bytes()).collect()),
+        Encoding::Utf16Be => Ok(text.encode_utf16().flat_map(|x| x.to_be_bytes()).collect()),
+        Encoding::Latin1 => text
+            .chars()
+            .map(|c| match c as u32 {
+                x if x <= 0xFF => Ok(x as u8),
+                _ => Err(io::Error::new(io::ErrorKind::InvalidInput, format!("'{}' can't be encoded in Latin-1", c))),
+            })
+            .collect(),
+    }
+}
+
+/// ### read_last_lines
+///
+/// Read the last `n` lines of file (`tail -n`). The file is read backwards in blocks,
+/// so only its tail is loaded, whatever its size. Invalid UTF-8 is replaced with U+FFFD
+pub fn read_last_lines<P>(filename: P, n: usize) -> io::Result<Vec<String>> where P: AsRef<Path>, {
+    let mut file: File = File::open(filename)?;
+    let mut pos: u64 = file.metadata()?.len();
+    let mut blocks: Vec<Vec<u8>> = Vec::new();
+    let mut newlines: usize = 0;
+    // The terminator of the last line doesn't start a new line, hence the `n + 1` newlines
+    while pos > 0 && n > 0 && newlines <= n {
+        let len: u64 = std::cmp::min(TAIL_BLOCK_SIZE, pos);
+        pos -= len;
+        let mut block: Vec<u8> = vec![0; len as usize];
+        file.seek(SeekFrom::Start(pos))?;
+        file.read_exact(block.as_mut_slice())?;
+        newlines += block.iter().filter(|x| **x == b'\n').count();
+        blocks.push(block);
+    }
+    let data: Vec<u8> = blocks.into_iter().rev().flatten().collect();
+    let text: std::borrow::Cow<str> = String::from_utf8_lossy(data.as_slice());
+    let lines: Vec<&str> = text.lines().collect();
+    Ok(lines[lines.len().saturating_sub(n)..]
+        .iter()
+        .map(|line| line.to_string())
+        .collect())
+}
+
+/// ## Follow
+///
+/// Iterator over the lines appended to a file (`tail -f`). Iterating blocks until a new line is available.
+/// Truncated files are read again from the beginning; rotated files (replaced by a new file at the same path)
+/// are re-opened once the old file has been read to its end
+pub struct Follow {
+    path: PathBuf,
+    reader: Option<io::BufReader<File>>,
+    identity: Option<(u64, u64)>,
+    position: u64,
+    partial: Vec<u8>,
+    interval: Duration,
+}
+
+/// ### follow
+///
+/// Follow file, yielding the lines appended to it from now on.
+/// Combine with `read_last_lines` to get `tail -n N -f` semantics
+pub fn follow<P>(filename: P) -> io::Result<Follow> where P: AsRef<Path>, {
+    let mut follow: Follow = Follow {
+        path: filename.as_ref().to_path_buf(),
+        reader: None,
+        identity: None,
+        position: 0,
+        partial: Vec::new(),
+        interval: FOLLOW_POLL_INTERVAL,
+    };
+    follow.open()?;
+    follow.position = follow.reader.as_mut().map(|x| x.seek(SeekFrom::End(0))).transpose()?.unwrap_or(0);
+    Ok(follow)
+}
+
+impl Follow {
+    /// ### interval
+    ///
+    /// Set interval between checks for new data
+    pub fn interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// ### poll
+    ///
+    /// Get next line if one is available, without blocking
+    pub fn poll(&mut self) -> io::Result<Option<String>> {
+        if self.reader.is_none() && !self.open()? {
+            return Ok(None);
+        }
+        if let Some(line) = self.read_line()? {
+            return Ok(Some(line));
+        }
+        // At the end of file: check whether it has been truncated or rotated
+        let metadata: std::fs::Metadata = match std::fs::metadata(self.path.as_path()) {
+            Ok(metadata) => metadata,
+            // Rotated away and not re-created yet
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(err),
+        };
+        if Self::identity_of(&metadata) != self.identity {
+            // Flush the unterminated last line of the rotated file
+            let line: Option<String> = self.take_partial();
+            self.open()?;
+            return match line {
+                Some(line) => Ok(Some(line)),
+                None => self.read_line(),
+            };
+        }
+        if metadata.len() < self.position {
+            self.partial.clear();
+            self.position = 0;
+            if let Some(reader) = self.reader.as_mut() {
+                reader.seek(SeekFrom::Start(0))?;
+            }
+            return self.read_line();
+        }
+        Ok(None)
+    }
+
+    /// ### open
+    ///
+    /// (Re)open file from its beginning. Returns whether the file exists
+    fn open(&mut self) -> io::Result<bool> {
+        let file: File = match File::open(self.path.as_path()) {
+            Ok(file) => file,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                self.reader = None;
+                self.identity = None;
+                return Ok(false);
+            }
+            Err(err) => return Err(err),
+        };
+        self.identity = Self::identity_of(&file.metadata()?);
+        self.reader = Some(io::BufReader::new(file));
+        self.position = 0;
+        Ok(true)
+    }
+
+    /// ### read_line
+    ///
+    /// Read a complete line from the current file, if available
+    fn read_line(&mut self) -> io::Result<Option<String>> {
+        let reader: &mut io::BufReader<File> = match self.reader.as_mut() {
+            Some(reader) => reader,
+            None => return Ok(None),
+        };
+        let bytes: usize = reader.read_until(b'\n', &mut self.partial)?;
+        self.position += bytes as u64;
+        match self.partial.last() {
+            Some(b'\n') => {
+                self.partial.pop();
+                if self.partial.last() == Some(&b'\r') {
+                    self.partial.pop();
+                }
+                Ok(self.take_partial().or_else(|| Some(String::new())))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    /// ### take_partial
+    ///
+    /// Take the line buffered so far, if any
+    fn take_partial(&mut self) -> Option<String> {
+        match self.partial.is_empty() {
+            true => None,
+            false => {
+                let line: String = String::from_utf8_lossy(self.partial.as_slice()).to_string();
+                self.partial.clear();
+                Some(line)
             }
-            Ok(())
-        },
-        Err(err) => Err(err)
+        }
+    }
+
+    #[cfg(unix)]
+    fn identity_of(metadata: &std::fs::Metadata) -> Option<(u64, u64)> {
+        use std::os::unix::fs::MetadataExt;
+        Some((metadata.dev(), metadata.ino()))
+    }
+
+    #[cfg(not(unix))]
+    fn identity_of(_metadata: &std::fs::Metadata) -> Option<(u64, u64)> {
+        None
+    }
+}
+
+impl Iterator for Follow {
+    type Item = io::Result<String>;
+
+    fn next(&mut self) -> Option<io::Result<String>> {
+        loop {
+            match self.poll() {
+                Ok(Some(line)) => return Some(Ok(line)),
+                Ok(None) => thread::sleep(self.interval),
+                Err(err) => return Some(Err(err)),
+            }
+        }
     }
 }
 
@@ -62,7 +755,107 @@ mod tests {
     #[test]
     fn test_utils_file_open() {
         let tmpfile: tempfile::NamedTempFile = tempfile::NamedTempFile::new().unwrap();
-        assert!(open_file(tmpfile.path(), true, true, true).is_ok());
+        assert!(open_file(tmpfile.path(), FileOptions::new().create(true).write(true).append(true)).is_ok());
+    }
+
+    #[test]
+    fn test_utils_file_options() {
+        let sample_file: tempfile::NamedTempFile = write_sample_file();
+        // Append
+        let mut file: File = FileOptions::new().write(true).append(true).open(sample_file.path()).unwrap();
+        writeln!(file, "row 4").unwrap();
+        drop(file);
+        assert_eq!(read_lines(sample_file.path()).unwrap().len(), 4);
+        // Truncate once locked
+        let mut file: File = FileOptions::new().write(true).truncate(true).lock(LockMode::Exclusive).open(sample_file.path()).unwrap();
+        writeln!(file, "row 1").unwrap();
+        drop(file);
+        assert_eq!(read_file(sample_file.path()).unwrap(), String::from("row 1\n"));
+        // Don't create unless asked
+        let tmpdir: tempfile::TempDir = tempfile::TempDir::new().unwrap();
+        assert!(FileOptions::new().write(true).open(tmpdir.path().join("new.txt")).is_err());
+        assert!(FileOptions::new().write(true).create(true).open(tmpdir.path().join("new.txt")).is_ok());
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_utils_file_lock() {
+        let sample_file: tempfile::NamedTempFile = write_sample_file();
+        let timeout: Duration = Duration::from_millis(50);
+        // Shared locks can be held together
+        let reader: File = FileOptions::new().lock(LockMode::Shared).open(sample_file.path()).unwrap();
+        let other: File = FileOptions::new().lock(LockMode::Shared).timeout(timeout).open(sample_file.path()).unwrap();
+        // But they exclude writers
+        let started: std::time::Instant = std::time::Instant::now();
+        let err: io::Error = FileOptions::new().write(true).lock(LockMode::Exclusive).timeout(timeout).open(sample_file.path()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::WouldBlock);
+        assert!(started.elapsed() >= timeout);
+        drop(other);
+        unlock_file(&reader).unwrap();
+        let writer: File = FileOptions::new().write(true).lock(LockMode::Exclusive).timeout(timeout).open(sample_file.path()).unwrap();
+        assert!(lock_file(&reader, LockMode::Shared, Some(Duration::from_millis(0))).is_err());
+        // Lock is released once the file is closed
+        drop(writer);
+        assert!(lock_file(&reader, LockMode::Exclusive, Some(timeout)).is_ok());
+    }
+
+    #[test]
+    #[cfg(not(unix))]
+    fn test_utils_file_lock_unsupported() {
+        let sample_file: tempfile::NamedTempFile = write_sample_file();
+        let err: io::Error = FileOptions::new().lock(LockMode::Shared).open(sample_file.path()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::Unsupported);
+        assert_eq!(unlock_file(sample_file.as_file()).unwrap_err().kind(), io::ErrorKind::Unsupported);
+    }
+
+    #[test]
+    fn test_utils_file_lines() {
+        let mut tmpfile: tempfile::NamedTempFile = tempfile::NamedTempFile::new().unwrap();
+        write!(tmpfile, "row 1\r\nrow 2\n\nrow 4").unwrap();
+        let mut iter: Lines = lines(tmpfile.path()).unwrap();
+        assert_eq!(iter.next().unwrap().unwrap(), String::from("row 1"));
+        // Lines are read lazily
+        write!(tmpfile, " and more").unwrap();
+        assert_eq!(iter.collect::<io::Result<Vec<String>>>().unwrap(), vec![String::from("row 2"), String::new(), String::from("row 4 and more")]);
+        assert!(lines(Path::new("/sample.file123123.txt")).is_err());
+    }
+
+    #[test]
+    fn test_utils_file_write_atomic() {
+        let tmpdir: tempfile::TempDir = tempfile::TempDir::new().unwrap();
+        let path: PathBuf = tmpdir.path().join("atomic.txt");
+        assert!(write_atomic(path.as_path(), b"first").is_ok());
+        assert_eq!(read_file(path.as_path()).unwrap(), String::from("first"));
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(path.as_path(), std::fs::Permissions::from_mode(0o600)).unwrap();
+        }
+        assert!(write_atomic(path.as_path(), b"second").is_ok());
+        assert_eq!(read_file(path.as_path()).unwrap(), String::from("second"));
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            assert_eq!(std::fs::metadata(path.as_path()).unwrap().permissions().mode() & 0o777, 0o600);
+        }
+        // No temporary file is left behind
+        assert_eq!(std::fs::read_dir(tmpdir.path()).unwrap().count(), 1);
+        assert!(write_atomic(tmpdir.path().join("missing/atomic.txt"), b"data").is_err());
+        assert_eq!(std::fs::read_dir(tmpdir.path()).unwrap().count(), 1);
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_utils_file_write_atomic_symlink() {
+        let tmpdir: tempfile::TempDir = tempfile::TempDir::new().unwrap();
+        let target: PathBuf = tmpdir.path().join("target.txt");
+        let link: PathBuf = tmpdir.path().join("link.txt");
+        std::fs::write(target.as_path(), "first").unwrap();
+        std::os::unix::fs::symlink(target.as_path(), link.as_path()).unwrap();
+        assert!(write_atomic(link.as_path(), b"second").is_ok());
+        assert!(std::fs::symlink_metadata(link.as_path()).unwrap().file_type().is_symlink());
+        assert_eq!(read_file(target.as_path()).unwrap(), String::from("second"));
+        assert_eq!(std::fs::read_dir(tmpdir.path()).unwrap().count(), 2);
     }
 
     #[test]
@@ -109,6 +902,121 @@ mod tests {
         assert!(write_lines(Path::new("/sample.file1231234.txt"), in_lines).is_err());
     }
 
+    #[test]
+    fn test_utils_file_detect_encoding() {
+        assert_eq!(Encoding::detect(b"\xEF\xBB\xBFabc"), Some(Encoding::Utf8));
+        assert_eq!(Encoding::detect(b"\xFF\xFEa\x00"), Some(Encoding::Utf16Le));
+        assert_eq!(Encoding::detect(b"\xFE\xFF\x00a"), Some(Encoding::Utf16Be));
+        assert_eq!(Encoding::detect(b"abc"), None);
+    }
+
+    #[test]
+    fn test_utils_file_read_utf16_crlf() {
+        let mut tmpfile: tempfile::NamedTempFile = tempfile::NamedTempFile::new().unwrap();
+        let data: Vec<u8> = "[section]\r\nname=Zoë\r\n".encode_utf16().flat_map(|x| x.to_le_bytes()).collect();
+        tmpfile.write_all(b"\xFF\xFE").unwrap();
+        tmpfile.write_all(data.as_slice()).unwrap();
+        let (lines, format) = read_lines_with(tmpfile.path(), &ReadOptions::default()).unwrap();
+        assert_eq!(lines, vec![String::from("[section]"), String::from("name=Zoë")]);
+        assert_eq!(format, TextFormat { encoding: Encoding::Utf16Le, bom: true, line_ending: LineEnding::CrLf, final_newline: true });
+        // Round trip
+        let original: Vec<u8> = std::fs::read(tmpfile.path()).unwrap();
+        assert!(write_lines_with(tmpfile.path(), lines, &format).is_ok());
+        assert_eq!(std::fs::read(tmpfile.path()).unwrap(), original);
+    }
+
+    #[test]
+    fn test_utils_file_read_latin1() {
+        let mut tmpfile: tempfile::NamedTempFile = tempfile::NamedTempFile::new().unwrap();
+        tmpfile.write_all(b"caf\xE9\nna\xEFve").unwrap();
+        // Not valid UTF-8
+        let err: io::Error = read_lines_with(tmpfile.path(), &ReadOptions::default()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        let (lines, _) = read_lines_with(tmpfile.path(), &ReadOptions { encoding: None, mode: DecodeMode::Lossy }).unwrap();
+        assert_eq!(lines, vec![String::from("caf\u{FFFD}"), String::from("na\u{FFFD}ve")]);
+        let options: ReadOptions = ReadOptions { encoding: Some(Encoding::Latin1), mode: DecodeMode::Strict };
+        let (lines, format) = read_lines_with(tmpfile.path(), &options).unwrap();
+        assert_eq!(lines, vec![String::from("café"), String::from("naïve")]);
+        assert_eq!(format, TextFormat { encoding: Encoding::Latin1, bom: false, line_ending: LineEnding::Lf, final_newline: false });
+        // Round trip
+        assert!(write_lines_with(tmpfile.path(), lines, &format).is_ok());
+        assert_eq!(std::fs::read(tmpfile.path()).unwrap(), b"caf\xE9\nna\xEFve".to_vec());
+        // Characters out of Latin-1
+        let err: io::Error = write_lines_with(tmpfile.path(), vec![String::from("€")], &format).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn test_utils_file_read_invalid_utf16() {
+        let mut tmpfile: tempfile::NamedTempFile = tempfile::NamedTempFile::new().unwrap();
+        // BOM, 'a', unpaired surrogate and a dangling byte
+        tmpfile.write_all(b"\xFE\xFF\x00a\xD8\x00\x00").unwrap();
+        assert!(read_text(tmpfile.path(), &ReadOptions::default()).is_err());
+        let (text, format) = read_text(tmpfile.path(), &ReadOptions { encoding: None, mode: DecodeMode::Lossy }).unwrap();
+        assert_eq!(text, String::from("a\u{FFFD}\u{FFFD}"));
+        assert_eq!(format.encoding, Encoding::Utf16Be);
+    }
+
+    #[test]
+    fn test_utils_file_read_last_lines() {
+        let sample_file: tempfile::NamedTempFile = write_sample_file();
+        assert_eq!(read_last_lines(sample_file.path(), 2).unwrap(), vec![String::from("Mauris ultricies consequat eros,"), String::from("nec scelerisque magna imperdiet metus.")]);
+        assert_eq!(read_last_lines(sample_file.path(), 10).unwrap().len(), 3);
+        assert!(read_last_lines(sample_file.path(), 0).unwrap().is_empty());
+        // Spanning many blocks
+        let mut tmpfile: tempfile::NamedTempFile = tempfile::NamedTempFile::new().unwrap();
+        for i in 0..20000 {
+            writeln!(tmpfile, "line {}", i).unwrap();
+        }
+        write!(tmpfile, "unterminated").unwrap();
+        let lines: Vec<String> = read_last_lines(tmpfile.path(), 15000).unwrap();
+        assert_eq!(lines.len(), 15000);
+        assert_eq!(lines.first().unwrap(), "line 5001");
+        assert_eq!(lines.last().unwrap(), "unterminated");
+        let empty: tempfile::NamedTempFile = tempfile::NamedTempFile::new().unwrap();
+        assert!(read_last_lines(empty.path(), 5).unwrap().is_empty());
+        assert!(read_last_lines(Path::new("/sample.file123123.txt"), 5).is_err());
+    }
+
+    #[test]
+    fn test_utils_file_follow() {
+        let sample_file: tempfile::NamedTempFile = write_sample_file();
+        let mut follow: Follow = follow(sample_file.path()).unwrap();
+        // Existing content is skipped
+        assert!(follow.poll().unwrap().is_none());
+        let mut file: File = FileOptions::new().write(true).append(true).open(sample_file.path()).unwrap();
+        write!(file, "row 4\r\nrow").unwrap();
+        assert_eq!(follow.poll().unwrap(), Some(String::from("row 4")));
+        // Partial lines are not yielded
+        assert!(follow.poll().unwrap().is_none());
+        writeln!(file, " 5").unwrap();
+        assert_eq!(follow.poll().unwrap(), Some(String::from("row 5")));
+        // Truncation
+        file.set_len(0).unwrap();
+        writeln!(file, "new").unwrap();
+        assert_eq!(follow.poll().unwrap(), Some(String::from("new")));
+        assert!(follow.poll().unwrap().is_none());
+    }
+
+    #[test]
+    fn test_utils_file_follow_rotation() {
+        let tmpdir: tempfile::TempDir = tempfile::TempDir::new().unwrap();
+        let path: PathBuf = tmpdir.path().join("app.log");
+        std::fs::write(path.as_path(), b"old\n").unwrap();
+        let mut follow: Follow = follow(path.as_path()).unwrap().interval(Duration::from_millis(10));
+        let mut file: File = FileOptions::new().write(true).append(true).open(path.as_path()).unwrap();
+        write!(file, "last").unwrap();
+        std::fs::rename(path.as_path(), tmpdir.path().join("app.log.1")).unwrap();
+        // Written to the rotated file before it's closed
+        writeln!(file, " line").unwrap();
+        drop(file);
+        assert_eq!(follow.poll().unwrap(), Some(String::from("last line")));
+        assert!(follow.poll().unwrap().is_none());
+        std::fs::write(path.as_path(), b"first\nsecond\n").unwrap();
+        let handle = thread::spawn(move || follow.take(2).collect::<io::Result<Vec<String>>>());
+        assert_eq!(handle.join().unwrap().unwrap(), vec![String::from("first"), String::from("second")]);
+    }
+
     /// ### write_sample_file
     /// Write a sample file
     fn write_sample_file() -> tempfile::NamedTempFile {
@@ -0,0 +1,850 @@
+//! Cron expressions, built on top of range expressions.
+
+mod scheduler;
+
+use std::fmt;
+use std::str::FromStr;
+
+use chrono::{
+    DateTime, Datelike, Duration, LocalResult, NaiveDate, NaiveDateTime, NaiveTime, Offset,
+    TimeZone, Timelike, Weekday,
+};
+
+pub use self::scheduler::{AsyncScheduler, Scheduler};
+use crate::parser::{parse_range_within, ParseErrorKind};
+use crate::range_set::RangeSet;
+
+/// Month names, starting from January
+const MONTHS: [&str; 12] = [
+    "JAN", "FEB", "MAR", "APR", "MAY", "JUN", "JUL", "AUG", "SEP", "OCT", "NOV", "DEC",
+];
+/// Day of week names, starting from Sunday
+const DAYS: [&str; 7] = ["SUN", "MON", "TUE", "WED", "THU", "FRI", "SAT"];
+/// Schedules which don't match within this amount of days never will (e.g. `0 0 30 2 *`).
+/// 28 years cover every combination of leap year and weekday
+const SEARCH_DAYS: i64 = 366 * 28;
+
+/// An error raised while parsing a cron expression.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CronError {
+    /// What went wrong
+    pub kind: CronErrorKind,
+    /// Byte offset in the expression where the error was found
+    pub position: usize,
+}
+
+/// The kind of a [`CronError`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CronErrorKind {
+    /// The expression doesn't have 5 or 6 fields
+    FieldCount(usize),
+    /// An `@` alias which doesn't exist
+    UnknownAlias(String),
+    /// A field which is not a valid range expression
+    Range(Field, ParseErrorKind),
+    /// A name or a modifier which is not allowed in the field
+    InvalidToken(Field, String),
+}
+
+impl fmt::Display for CronError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.kind {
+            CronErrorKind::FieldCount(count) => write!(f, "expected 5 or 6 fields, found {count}")?,
+            CronErrorKind::UnknownAlias(alias) => write!(f, "unknown alias '{alias}'")?,
+            CronErrorKind::Range(field, kind) => write!(f, "{field}: {kind}")?,
+            CronErrorKind::InvalidToken(field, token) => {
+                write!(f, "{field}: invalid token '{token}'")?
+            }
+        }
+        write!(f, " at position {}", self.position)
+    }
+}
+
+impl std::error::Error for CronError {}
+
+/// A field of a cron expression
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Field {
+    Second,
+    Minute,
+    Hour,
+    DayOfMonth,
+    Month,
+    DayOfWeek,
+}
+
+impl Field {
+    /// Lowest and highest value of the field. Both 0 and 7 are Sunday in day of week
+    fn bounds(&self) -> (u32, u32) {
+        match self {
+            Self::Second | Self::Minute => (0, 59),
+            Self::Hour => (0, 23),
+            Self::DayOfMonth => (1, 31),
+            Self::Month => (1, 12),
+            Self::DayOfWeek => (0, 7),
+        }
+    }
+
+    /// Names which can be used in place of numbers
+    fn names(&self) -> (&'static [&'static str], u32) {
+        match self {
+            Self::Month => (&MONTHS, 1),
+            Self::DayOfWeek => (&DAYS, 0),
+            _ => (&[], 0),
+        }
+    }
+}
+
+impl fmt::Display for Field {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Self::Second => "second",
+            Self::Minute => "minute",
+            Self::Hour => "hour",
+            Self::DayOfMonth => "day of month",
+            Self::Month => "month",
+            Self::DayOfWeek => "day of week",
+        };
+        write!(f, "{name}")
+    }
+}
+
+/// Day of month and day of week constraints.
+///
+/// As in Vixie cron, when both fields are restricted a day matches if either of them does;
+/// when either field starts with `*` (e.g. `*/2`), a day must match both.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+struct Days {
+    /// Whether day of month starts with `*` or `?`
+    any_month_day: bool,
+    month_days: RangeSet<u32>,
+    /// `L` and `L-n`: days before the last day of the month
+    before_last_day: Vec<u32>,
+    /// `LW`: last weekday of the month
+    last_weekday: bool,
+    /// `nW`: weekday nearest to day `n`
+    nearest_weekday: Vec<u32>,
+    /// Whether day of week starts with `*` or `?`
+    any_week_day: bool,
+    /// Days of week, from 0 (Sunday) to 6
+    week_days: RangeSet<u32>,
+    /// `nL`: last day `n` of the month
+    last_week_day: Vec<u32>,
+    /// `n#k`: day `n` in the `k`th week of the month
+    nth_week_day: Vec<(u32, u32)>,
+}
+
+impl Days {
+    fn matches(&self, date: NaiveDate) -> bool {
+        match self.any_month_day || self.any_week_day {
+            true => self.matches_month_day(date) && self.matches_week_day(date),
+            false => self.matches_month_day(date) || self.matches_week_day(date),
+        }
+    }
+
+    fn matches_month_day(&self, date: NaiveDate) -> bool {
+        let day = date.day();
+        let last = last_day_of_month(date);
+        self.month_days.contains(day)
+            || self
+                .before_last_day
+                .iter()
+                .any(|n| last.checked_sub(*n) == Some(day))
+            || (self.last_weekday && date == nearest_weekday(date, last))
+            || self
+                .nearest_weekday
+                .iter()
+                .any(|n| date == nearest_weekday(date, (*n).min(last)))
+    }
+
+    fn matches_week_day(&self, date: NaiveDate) -> bool {
+        let week_day = date.weekday().num_days_from_sunday();
+        let day = date.day();
+        self.week_days.contains(week_day)
+            || self
+                .last_week_day
+                .iter()
+                .any(|n| *n == week_day && day + 7 > last_day_of_month(date))
+            || self
+                .nth_week_day
+                .iter()
+                .any(|(n, k)| *n == week_day && (day - 1) / 7 + 1 == *k)
+    }
+}
+
+/// A parsed cron expression.
+///
+/// Both the classic 5-field format (`minute hour day-of-month month day-of-week`) and the
+/// 6-field format with leading seconds are supported, as well as:
+///
+/// - aliases: `@yearly` (`@annually`), `@monthly`, `@weekly`, `@daily` (`@midnight`), `@hourly`
+/// - month and day of week names: `JAN-MAR`, `MON-FRI`
+/// - `?` as `*` in day fields
+/// - `L` (last day), `L-n` (n days before the last day), `LW` (last weekday) and `nW` (weekday nearest to n) in day of month
+/// - `nL` (last day n of the month) and `n#k` (k-th day n of the month) in day of week
+///
+/// The rest of each field is a range expression (see [`crate::parser::parse_items`]), so
+/// steps and exclusions can be used too: `*/15`, `0-30/10,!20`.
+///
+/// # Example
+///
+/// ```rust
+/// use chrono::TimeZone;
+/// use chrono_tz::Europe::Rome;
+///
+/// let cron: Cron = "0 9 * * MON-FRI".parse().unwrap();
+/// let next = cron.next_after(&Rome.with_ymd_and_hms(2024, 3, 29, 10, 0, 0).unwrap());
+/// assert_eq!(next.unwrap(), Rome.with_ymd_and_hms(2024, 4, 1, 9, 0, 0).unwrap());
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cron {
+    seconds: RangeSet<u32>,
+    minutes: RangeSet<u32>,
+    hours: RangeSet<u32>,
+    months: RangeSet<u32>,
+    days: Days,
+}
+
+impl FromStr for Cron {
+    type Err = CronError;
+
+    fn from_str(expression: &str) -> Result<Self, Self::Err> {
+        let trimmed = expression.trim_start();
+        let offset = expression.len() - trimmed.len();
+        if trimmed.starts_with('@') {
+            let alias = trimmed.trim_end();
+            let expanded = match alias.to_ascii_lowercase().as_str() {
+                "@yearly" | "@annually" => "0 0 1 1 *",
+                "@monthly" => "0 0 1 * *",
+                "@weekly" => "0 0 * * 0",
+                "@daily" | "@midnight" => "0 0 * * *",
+                "@hourly" => "0 * * * *",
+                _ => {
+                    return Err(CronError {
+                        kind: CronErrorKind::UnknownAlias(alias.to_string()),
+                        position: offset,
+                    })
+                }
+            };
+            return Self::from_str(expanded);
+        }
+        let fields: Vec<(usize, &str)> = split_fields(expression);
+        let (seconds, rest) = match fields.len() {
+            5 => (None, &fields[..]),
+            6 => (Some(fields[0]), &fields[1..]),
+            count => {
+                return Err(CronError {
+                    kind: CronErrorKind::FieldCount(count),
+                    position: offset,
+                })
+            }
+        };
+        let (minutes, hours, month_days, months, week_days) =
+            (rest[0], rest[1], rest[2], rest[3], rest[4]);
+        let seconds = match seconds {
+            Some(seconds) => parse_field(Field::Second, seconds)?,
+            None => RangeSet::from_interval(0, 0),
+        };
+        let mut days = Days::default();
+        parse_month_days(month_days, &mut days)?;
+        parse_week_days(week_days, &mut days)?;
+        Ok(Self {
+            seconds,
+            minutes: parse_field(Field::Minute, minutes)?,
+            hours: parse_field(Field::Hour, hours)?,
+            months: parse_field(Field::Month, months)?,
+            days,
+        })
+    }
+}
+
+impl Cron {
+    /// Get the first fire time strictly after `after`, in its timezone.
+    ///
+    /// Fire times are computed on local time, so `0 9 * * *` fires at 9:00 whatever the UTC offset is.
+    /// On DST changes, jobs fire the way Vixie cron runs them:
+    ///
+    /// - times skipped when clocks go forward fire at the end of the gap, unless the job runs every hour;
+    /// - times repeated when clocks go back fire once, unless the job runs every hour.
+    pub fn next_after<Tz: TimeZone>(&self, after: &DateTime<Tz>) -> Option<DateTime<Tz>> {
+        let tz = after.timezone();
+        let every_hour = self.hours.len() == 24;
+        let mut from = after.naive_local().with_nanosecond(0)? + Duration::seconds(1);
+        // within the first occurrence of repeated times, hourly jobs still have to fire on
+        // the second occurrence of the times already passed
+        if let LocalResult::Ambiguous(first, second) = tz.from_local_datetime(&after.naive_local())
+        {
+            if every_hour && &first == after {
+                let fold = first.offset().fix().local_minus_utc()
+                    - second.offset().fix().local_minus_utc();
+                from -= Duration::seconds(i64::from(fold));
+            }
+        }
+        loop {
+            let naive = self.next_naive(from)?;
+            from = naive + Duration::seconds(1);
+            let fire = match tz.from_local_datetime(&naive) {
+                LocalResult::Single(fire) => Some(fire),
+                LocalResult::Ambiguous(first, second) => match every_hour {
+                    true => [first, second].into_iter().find(|x| x > after),
+                    false => Some(first),
+                },
+                LocalResult::None if every_hour => None,
+                LocalResult::None => Self::end_of_gap(&tz, naive),
+            };
+            if let Some(fire) = fire.filter(|x| x > after) {
+                return Some(fire);
+            }
+        }
+    }
+
+    /// Get the next `n` fire times after `after`
+    pub fn next_n<Tz: TimeZone>(&self, after: &DateTime<Tz>, n: usize) -> Vec<DateTime<Tz>> {
+        self.upcoming(after.clone()).take(n).collect()
+    }
+
+    /// Iterate over the fire times after `after`
+    pub fn upcoming<Tz: TimeZone>(&self, after: DateTime<Tz>) -> Upcoming<'_, Tz> {
+        Upcoming {
+            cron: self,
+            last: after,
+        }
+    }
+
+    /// Get the first local time from `from` on matching the expression
+    fn next_naive(&self, from: NaiveDateTime) -> Option<NaiveDateTime> {
+        let mut date = from.date();
+        let mut start = Some(from.time());
+        let last_date = date + Duration::days(SEARCH_DAYS);
+        while date <= last_date {
+            if !self.months.contains(date.month()) {
+                date = NaiveDate::from_ymd_opt(date.year(), date.month(), 1)?
+                    .checked_add_months(chrono::Months::new(1))?;
+                start = None;
+                continue;
+            }
+            if self.days.matches(date) {
+                if let Some(time) = self.next_time(start.unwrap_or(NaiveTime::MIN)) {
+                    return Some(date.and_time(time));
+                }
+            }
+            date = date.succ_opt()?;
+            start = None;
+        }
+        None
+    }
+
+    /// Get the first time of the day from `start` on matching hours, minutes and seconds
+    fn next_time(&self, start: NaiveTime) -> Option<NaiveTime> {
+        for hour in self.hours.iter().skip_while(|h| *h < start.hour()) {
+            let first_minute = match hour == start.hour() {
+                true => start.minute(),
+                false => 0,
+            };
+            for minute in self.minutes.iter().skip_while(|m| *m < first_minute) {
+                let first_second = match hour == start.hour() && minute == start.minute() {
+                    true => start.second(),
+                    false => 0,
+                };
+                if let Some(second) = self.seconds.iter().find(|s| *s >= first_second) {
+                    return NaiveTime::from_hms_opt(hour, minute, second);
+                }
+            }
+        }
+        None
+    }
+
+    /// Get the first valid time after `naive`, which falls in a DST gap
+    fn end_of_gap<Tz: TimeZone>(tz: &Tz, naive: NaiveDateTime) -> Option<DateTime<Tz>> {
+        // gaps start and end on minute boundaries and last a few hours at most
+        let mut probe = naive.with_second(0)?;
+        for _ in 0..(24 * 60) {
+            probe += Duration::minutes(1);
+            if let Some(fire) = tz.from_local_datetime(&probe).earliest() {
+                return Some(fire);
+            }
+        }
+        None
+    }
+}
+
+/// Iterator over the fire times of a [`Cron`]
+pub struct Upcoming<'a, Tz: TimeZone> {
+    cron: &'a Cron,
+    last: DateTime<Tz>,
+}
+
+impl<Tz: TimeZone> Iterator for Upcoming<'_, Tz> {
+    type Item = DateTime<Tz>;
+
+    fn next(&mut self) -> Option<DateTime<Tz>> {
+        let next = self.cron.next_after(&self.last)?;
+        self.last = next.clone();
+        Some(next)
+    }
+}
+
+/// Split `expression` by whitespace, keeping the byte offset of each field
+fn split_fields(expression: &str) -> Vec<(usize, &str)> {
+    let mut fields = Vec::new();
+    let mut start: Option<usize> = None;
+    for (index, c) in expression.char_indices() {
+        match (c.is_whitespace(), start) {
+            (true, Some(from)) => {
+                fields.push((from, &expression[from..index]));
+                start = None;
+            }
+            (false, None) => start = Some(index),
+            _ => {}
+        }
+    }
+    if let Some(from) = start {
+        fields.push((from, &expression[from..]));
+    }
+    fields
+}
+
+/// Split a field by commas, keeping the byte offset of each item
+fn split_items((offset, text): (usize, &str)) -> impl Iterator<Item = (usize, &str)> {
+    text.split(',').scan(offset, |position, item| {
+        let start = *position;
+        *position += item.len() + 1;
+        Some((start, item))
+    })
+}
+
+/// Parse a field made of range expressions, names and `*`
+fn parse_field(field: Field, (offset, text): (usize, &str)) -> Result<RangeSet<u32>, CronError> {
+    let (min, max) = field.bounds();
+    // `?` only stands for any day
+    if let Some(position) = text.find('?') {
+        if field != Field::DayOfMonth && field != Field::DayOfWeek {
+            return Err(CronError {
+                kind: CronErrorKind::InvalidToken(field, String::from("?")),
+                position: offset + position,
+            });
+        }
+    }
+    let (expression, replaced) = replace_names(field, offset, text)?;
+    let expression = expression.replace(['*', '?'], &format!("{min}-{max}"));
+    parse_range_within(&expression, min, max).map_err(|err| CronError {
+        kind: CronErrorKind::Range(field, err.kind),
+        // positions are shifted by replaced names
+        position: match replaced || text.contains(['*', '?']) {
+            true => offset,
+            false => offset + err.position,
+        },
+    })
+}
+
+/// Replace month and day names with their number
+fn replace_names(field: Field, offset: usize, text: &str) -> Result<(String, bool), CronError> {
+    let (names, first) = field.names();
+    let mut expression = String::with_capacity(text.len());
+    let mut replaced = false;
+    let mut rest = text;
+    while let Some(start) = rest.find(|c: char| c.is_ascii_alphabetic()) {
+        let end = rest[start..]
+            .find(|c: char| !c.is_ascii_alphabetic())
+            .map_or(rest.len(), |x| start + x);
+        let name = &rest[start..end];
+        let value = names
+            .iter()
+            .position(|x| x.eq_ignore_ascii_case(name))
+            .ok_or_else(|| CronError {
+                kind: CronErrorKind::InvalidToken(field, name.to_string()),
+                position: offset + (text.len() - rest.len()) + start,
+            })?;
+        expression.push_str(&rest[..start]);
+        expression.push_str(&(value as u32 + first).to_string());
+        replaced = true;
+        rest = &rest[end..];
+    }
+    expression.push_str(rest);
+    Ok((expression, replaced))
+}
+
+/// Parse day of month, with its `L` and `W` modifiers
+fn parse_month_days(field: (usize, &str), days: &mut Days) -> Result<(), CronError> {
+    days.any_month_day = field.1.starts_with(['*', '?']);
+    let mut plain: Vec<(usize, &str)> = Vec::new();
+    for (position, item) in split_items(field) {
+        let upper = item.to_ascii_uppercase();
+        let invalid = || CronError {
+            kind: CronErrorKind::InvalidToken(Field::DayOfMonth, item.to_string()),
+            position,
+        };
+        if upper == "LW" {
+            days.last_weekday = true;
+        } else if let Some(before) = upper.strip_prefix('L') {
+            let before = match before.strip_prefix('-') {
+                Some(n) => n
+                    .parse::<u32>()
+                    .ok()
+                    .filter(|n| *n < 31)
+                    .ok_or_else(invalid)?,
+                None if before.is_empty() => 0,
+                None => return Err(invalid()),
+            };
+            days.before_last_day.push(before);
+        } else if let Some(day) = upper.strip_suffix('W') {
+            let day = day
+                .parse::<u32>()
+                .ok()
+                .filter(|n| (1..=31).contains(n))
+                .ok_or_else(invalid)?;
+            days.nearest_weekday.push(day);
+        } else {
+            plain.push((position, item));
+        }
+    }
+    days.month_days = parse_plain(Field::DayOfMonth, &plain)?;
+    Ok(())
+}
+
+/// Parse day of week, with its `L` and `#` modifiers
+fn parse_week_days(field: (usize, &str), days: &mut Days) -> Result<(), CronError> {
+    days.any_week_day = field.1.starts_with(['*', '?']);
+    let mut plain: Vec<(usize, &str)> = Vec::new();
+    for (position, item) in split_items(field) {
+        let invalid = || CronError {
+            kind: CronErrorKind::InvalidToken(Field::DayOfWeek, item.to_string()),
+            position,
+        };
+        let week_day = |day: &str| -> Result<u32, CronError> {
+            let (day, _) = replace_names(Field::DayOfWeek, position, day)?;
+            day.parse::<u32>()
+                .ok()
+                .filter(|n| *n <= 7)
+                .map(|n| n % 7)
+                .ok_or_else(invalid)
+        };
+        if let Some((day, nth)) = item.split_once('#') {
+            let nth = nth
+                .parse::<u32>()
+                .ok()
+                .filter(|n| (1..=5).contains(n))
+                .ok_or_else(invalid)?;
+            days.nth_week_day.push((week_day(day)?, nth));
+        } else if let Some(day) = item
+            .strip_suffix(['L', 'l'])
+            .filter(|day| !day.is_empty() && !day.contains(['-', '/', '*']))
+        {
+            days.last_week_day.push(week_day(day)?);
+        } else {
+            plain.push((position, item));
+        }
+    }
+    let mut week_days = parse_plain(Field::DayOfWeek, &plain)?;
+    // 7 is Sunday as well
+    if week_days.contains(7) {
+        week_days.remove(7, 7);
+        week_days.insert(0, 0);
+    }
+    days.week_days = week_days;
+    Ok(())
+}
+
+/// Parse the items of a day field which are not modifiers
+fn parse_plain(field: Field, items: &[(usize, &str)]) -> Result<RangeSet<u32>, CronError> {
+    let mut set = RangeSet::new();
+    for item in items {
+        set = set.union(&parse_field(field, *item)?);
+    }
+    Ok(set)
+}
+
+/// Get the last day of the month of `date`
+fn last_day_of_month(date: NaiveDate) -> u32 {
+    (28..=31)
+        .rev()
+        .find(|day| date.with_day(*day).is_some())
+        .unwrap_or(28)
+}
+
+/// Get the weekday nearest to `day` in the month of `date`, without leaving the month
+fn nearest_weekday(date: NaiveDate, day: u32) -> NaiveDate {
+    let target = date.with_day(day).unwrap_or(date);
+    let last = last_day_of_month(date);
+    match target.weekday() {
+        Weekday::Sat if day > 1 => target - Duration::days(1),
+        Weekday::Sat => target + Duration::days(2),
+        Weekday::Sun if day < last => target + Duration::days(1),
+        Weekday::Sun => target - Duration::days(2),
+        _ => target,
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use chrono_tz::Europe::Rome;
+    use chrono_tz::Tz;
+
+    use super::*;
+
+    fn at(year: i32, month: u32, day: u32, hour: u32, minute: u32, second: u32) -> DateTime<Tz> {
+        Rome.with_ymd_and_hms(year, month, day, hour, minute, second)
+            .unwrap()
+    }
+
+    fn next_n(expression: &str, after: DateTime<Tz>, n: usize) -> Vec<String> {
+        expression
+            .parse::<Cron>()
+            .unwrap()
+            .next_n(&after, n)
+            .into_iter()
+            .map(|x| x.format("%a %Y-%m-%d %H:%M:%S %z").to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_should_parse_fields() {
+        let cron: Cron = "*/15 9-17 * JAN,jul-sep mon-fri".parse().unwrap();
+        assert_eq!(cron.seconds.to_string(), "0");
+        assert_eq!(cron.minutes.to_string(), "0-45/15");
+        assert_eq!(cron.hours.to_string(), "9-17");
+        assert_eq!(cron.months.to_string(), "1,7-9");
+        assert!(cron.days.any_month_day);
+        assert!(!cron.days.any_week_day);
+        assert_eq!(cron.days.week_days.to_string(), "1-5");
+        let cron: Cron = "30 0 12 L,L-2,15W,LW * 5L,SUN#2,7".parse().unwrap();
+        assert_eq!(cron.seconds.to_string(), "30");
+        assert!(cron.days.month_days.is_empty());
+        assert_eq!(cron.days.before_last_day, vec![0, 2]);
+        assert_eq!(cron.days.nearest_weekday, vec![15]);
+        assert!(cron.days.last_weekday);
+        assert_eq!(cron.days.last_week_day, vec![5]);
+        assert_eq!(cron.days.nth_week_day, vec![(0, 2)]);
+        assert_eq!(cron.days.week_days.to_string(), "0");
+        assert_eq!(
+            "@daily".parse::<Cron>().unwrap(),
+            "0 0 * * *".parse::<Cron>().unwrap()
+        );
+    }
+
+    #[test]
+    fn test_should_report_errors() {
+        let error = |expression: &str| expression.parse::<Cron>().unwrap_err();
+        assert_eq!(error("* * * *").kind, CronErrorKind::FieldCount(4));
+        assert_eq!(
+            error(" @often"),
+            CronError {
+                kind: CronErrorKind::UnknownAlias(String::from("@often")),
+                position: 1
+            }
+        );
+        assert_eq!(
+            error("0 24 * * *"),
+            CronError {
+                kind: CronErrorKind::Range(Field::Hour, ParseErrorKind::OutOfRange),
+                position: 2
+            }
+        );
+        assert_eq!(
+            error("0 0 1,x * *"),
+            CronError {
+                kind: CronErrorKind::InvalidToken(Field::DayOfMonth, String::from("x")),
+                position: 6
+            }
+        );
+        assert_eq!(
+            error("0 0 * FOO *"),
+            CronError {
+                kind: CronErrorKind::InvalidToken(Field::Month, String::from("FOO")),
+                position: 6
+            }
+        );
+        assert_eq!(
+            error("0 0 * * MON#6"),
+            CronError {
+                kind: CronErrorKind::InvalidToken(Field::DayOfWeek, String::from("MON#6")),
+                position: 8
+            }
+        );
+        assert_eq!(
+            error("? * * * *"),
+            CronError {
+                kind: CronErrorKind::InvalidToken(Field::Minute, String::from("?")),
+                position: 0
+            }
+        );
+        assert_eq!(
+            error("0 0 0 * ? *").kind,
+            CronErrorKind::InvalidToken(Field::Month, String::from("?"))
+        );
+        assert!("0 0 ? * MON".parse::<Cron>().is_ok());
+        assert_eq!(
+            error("0 0 L-40 * *").kind,
+            CronErrorKind::InvalidToken(Field::DayOfMonth, String::from("L-40"))
+        );
+        assert_eq!(
+            error("0 0 1-5/0 * *").to_string(),
+            "day of month: step must be greater than zero at position 8"
+        );
+    }
+
+    #[test]
+    fn test_should_compute_next_fire_times() {
+        assert_eq!(
+            next_n("0 9 * * MON-FRI", at(2024, 3, 29, 10, 0, 0), 2),
+            vec![
+                "Mon 2024-04-01 09:00:00 +0200",
+                "Tue 2024-04-02 09:00:00 +0200"
+            ]
+        );
+        assert_eq!(
+            next_n("*/20 * * * * *", at(2024, 1, 1, 23, 59, 30), 3),
+            vec![
+                "Mon 2024-01-01 23:59:40 +0100",
+                "Tue 2024-01-02 00:00:00 +0100",
+                "Tue 2024-01-02 00:00:20 +0100"
+            ]
+        );
+        assert_eq!(
+            next_n("@yearly", at(2024, 1, 1, 0, 0, 0), 1),
+            vec!["Wed 2025-01-01 00:00:00 +0100"]
+        );
+        // February 29th
+        assert_eq!(
+            next_n("0 12 29 2 *", at(2024, 3, 1, 0, 0, 0), 1),
+            vec!["Tue 2028-02-29 12:00:00 +0100"]
+        );
+        assert!("0 0 30 2 *"
+            .parse::<Cron>()
+            .unwrap()
+            .next_after(&at(2024, 1, 1, 0, 0, 0))
+            .is_none());
+    }
+
+    #[test]
+    fn test_should_match_day_modifiers() {
+        // either day of month or day of week
+        assert_eq!(
+            next_n("0 0 13 * FRI", at(2024, 9, 1, 0, 0, 0), 3),
+            vec![
+                "Fri 2024-09-06 00:00:00 +0200",
+                "Fri 2024-09-13 00:00:00 +0200",
+                "Fri 2024-09-20 00:00:00 +0200"
+            ]
+        );
+        assert_eq!(
+            next_n("0 0 L * *", at(2024, 1, 31, 0, 0, 0), 2),
+            vec![
+                "Thu 2024-02-29 00:00:00 +0100",
+                "Sun 2024-03-31 00:00:00 +0100"
+            ]
+        );
+        assert_eq!(
+            next_n("0 0 L-1 * *", at(2024, 2, 1, 0, 0, 0), 1),
+            vec!["Wed 2024-02-28 00:00:00 +0100"]
+        );
+        // August 31st 2024 is a Saturday
+        assert_eq!(
+            next_n("0 0 LW * *", at(2024, 8, 1, 0, 0, 0), 1),
+            vec!["Fri 2024-08-30 00:00:00 +0200"]
+        );
+        // June 1st 2024 is a Saturday: the nearest weekday in June is Monday 3rd
+        assert_eq!(
+            next_n("0 0 1W * *", at(2024, 5, 15, 0, 0, 0), 1),
+            vec!["Mon 2024-06-03 00:00:00 +0200"]
+        );
+        // September 15th 2024 is a Sunday
+        assert_eq!(
+            next_n("0 0 15W * *", at(2024, 9, 1, 0, 0, 0), 1),
+            vec!["Mon 2024-09-16 00:00:00 +0200"]
+        );
+        assert_eq!(
+            next_n("0 0 * * FRIL", at(2024, 9, 1, 0, 0, 0), 2),
+            vec![
+                "Fri 2024-09-27 00:00:00 +0200",
+                "Fri 2024-10-25 00:00:00 +0200"
+            ]
+        );
+        assert_eq!(
+            next_n("0 0 ? * 1#2", at(2024, 9, 1, 0, 0, 0), 2),
+            vec![
+                "Mon 2024-09-09 00:00:00 +0200",
+                "Mon 2024-10-14 00:00:00 +0200"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_should_match_stepped_days() {
+        assert_eq!(
+            next_n("0 0 */2 * *", at(2024, 9, 1, 0, 0, 0), 3),
+            vec![
+                "Tue 2024-09-03 00:00:00 +0200",
+                "Thu 2024-09-05 00:00:00 +0200",
+                "Sat 2024-09-07 00:00:00 +0200"
+            ]
+        );
+        assert_eq!(
+            next_n("0 0 */2 * *", at(2024, 9, 28, 0, 0, 0), 2),
+            vec![
+                "Sun 2024-09-29 00:00:00 +0200",
+                "Tue 2024-10-01 00:00:00 +0200"
+            ]
+        );
+        assert_eq!(
+            next_n("0 0 * * */2", at(2024, 9, 1, 0, 0, 0), 3),
+            vec![
+                "Tue 2024-09-03 00:00:00 +0200",
+                "Thu 2024-09-05 00:00:00 +0200",
+                "Sat 2024-09-07 00:00:00 +0200"
+            ]
+        );
+        // a starred field restricts days along with the other one
+        assert_eq!(
+            next_n("0 0 */10 * MON", at(2024, 1, 2, 0, 0, 0), 2),
+            vec![
+                "Mon 2024-03-11 00:00:00 +0100",
+                "Mon 2024-04-01 00:00:00 +0200"
+            ]
+        );
+        assert_eq!(
+            next_n("0 0 1-7 * */3", at(2024, 9, 1, 0, 0, 0), 2),
+            vec![
+                "Wed 2024-09-04 00:00:00 +0200",
+                "Sat 2024-09-07 00:00:00 +0200"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_should_handle_dst_changes() {
+        // clocks go forward from 2:00 to 3:00 on March 31st 2024
+        assert_eq!(
+            next_n("30 2 * * *", at(2024, 3, 30, 12, 0, 0), 3),
+            vec![
+                "Sun 2024-03-31 03:00:00 +0200",
+                "Mon 2024-04-01 02:30:00 +0200",
+                "Tue 2024-04-02 02:30:00 +0200"
+            ]
+        );
+        assert_eq!(
+            next_n("30 * * * *", at(2024, 3, 31, 1, 0, 0), 2),
+            vec![
+                "Sun 2024-03-31 01:30:00 +0100",
+                "Sun 2024-03-31 03:30:00 +0200"
+            ]
+        );
+        // clocks go back from 3:00 to 2:00 on October 27th 2024
+        assert_eq!(
+            next_n("30 2 * * *", at(2024, 10, 26, 12, 0, 0), 2),
+            vec![
+                "Sun 2024-10-27 02:30:00 +0200",
+                "Mon 2024-10-28 02:30:00 +0100"
+            ]
+        );
+        assert_eq!(
+            next_n("30 * * * *", at(2024, 10, 27, 1, 0, 0), 3),
+            vec![
+                "Sun 2024-10-27 01:30:00 +0200",
+                "Sun 2024-10-27 02:30:00 +0200",
+                "Sun 2024-10-27 02:30:00 +0100"
+            ]
+        );
+    }
+}
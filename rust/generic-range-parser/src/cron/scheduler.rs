@@ -0,0 +1,298 @@
+//! Run jobs on cron schedules.
+
+use std::future::Future;
+use std::thread;
+use std::time::Duration;
+
+use chrono::{DateTime, TimeZone, Utc};
+use tokio::task::JoinHandle;
+
+use super::Cron;
+
+/// The time schedulers compute fire times from.
+///
+/// The blocking [`Scheduler`] waits with [`Clock::sleep`], while the [`AsyncScheduler`] always
+/// waits with tokio's timer.
+pub trait Clock {
+    /// Get the current time
+    fn now(&self) -> DateTime<Utc>;
+
+    /// Block the calling thread for `duration`
+    fn sleep(&self, duration: Duration) {
+        thread::sleep(duration);
+    }
+}
+
+/// The system clock
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+}
+
+/// A job of the blocking [`Scheduler`]
+struct Job<Tz: TimeZone> {
+    cron: Cron,
+    task: Box<dyn FnMut() + Send>,
+    next: Option<DateTime<Tz>>,
+}
+
+/// A scheduler which runs jobs on the calling thread.
+///
+/// Jobs run one after the other: a job which takes long delays the following ones, and fire
+/// times missed meanwhile are skipped.
+pub struct Scheduler<Tz: TimeZone, C: Clock = SystemClock> {
+    timezone: Tz,
+    clock: C,
+    jobs: Vec<Job<Tz>>,
+}
+
+impl<Tz: TimeZone> Scheduler<Tz> {
+    /// Create a scheduler computing fire times in `timezone`
+    pub fn new(timezone: Tz) -> Self {
+        Self::with_clock(timezone, SystemClock)
+    }
+}
+
+impl<Tz: TimeZone, C: Clock> Scheduler<Tz, C> {
+    /// Create a scheduler computing fire times in `timezone`, from the time told by `clock`
+    pub fn with_clock(timezone: Tz, clock: C) -> Self {
+        Self {
+            timezone,
+            clock,
+            jobs: Vec::new(),
+        }
+    }
+
+    /// Schedule `task` to run at the fire times of `cron`
+    pub fn add<F>(&mut self, cron: Cron, task: F)
+    where
+        F: FnMut() + Send + 'static,
+    {
+        let next = cron.next_after(&self.now());
+        self.jobs.push(Job {
+            cron,
+            task: Box::new(task),
+            next,
+        });
+    }
+
+    /// Get the first fire time among all jobs
+    pub fn next_fire(&self) -> Option<DateTime<Tz>> {
+        self.jobs.iter().filter_map(|job| job.next.clone()).min()
+    }
+
+    /// Run the jobs due at `now`. Returns the amount of jobs which ran
+    pub fn tick(&mut self, now: &DateTime<Tz>) -> usize {
+        let mut ran = 0;
+        for job in self.jobs.iter_mut() {
+            if job.next.as_ref().is_some_and(|next| next <= now) {
+                (job.task)();
+                job.next = job.cron.next_after(now);
+                ran += 1;
+            }
+        }
+        ran
+    }
+
+    /// Run jobs until `deadline`, sleeping between fire times
+    pub fn run_until(&mut self, deadline: &DateTime<Tz>) {
+        while let Some(next) = self.next_fire().filter(|next| next <= deadline) {
+            if let Ok(delay) = next.clone().signed_duration_since(self.now()).to_std() {
+                self.clock.sleep(delay);
+            }
+            // the clock may be late by a few ms: never tick before the fire time
+            let now = self.now().max(next);
+            self.tick(&now);
+        }
+    }
+
+    fn now(&self) -> DateTime<Tz> {
+        self.clock.now().with_timezone(&self.timezone)
+    }
+}
+
+/// A scheduler which runs each job in its own tokio task.
+///
+/// Must be used inside a tokio runtime. Jobs are aborted when the scheduler is dropped.
+pub struct AsyncScheduler<Tz: TimeZone, C: Clock = SystemClock> {
+    timezone: Tz,
+    clock: C,
+    tasks: Vec<JoinHandle<()>>,
+}
+
+impl<Tz> AsyncScheduler<Tz>
+where
+    Tz: TimeZone + Send + Sync + 'static,
+    Tz::Offset: Send + Sync,
+{
+    /// Create a scheduler computing fire times in `timezone`
+    pub fn new(timezone: Tz) -> Self {
+        Self::with_clock(timezone, SystemClock)
+    }
+}
+
+impl<Tz, C> AsyncScheduler<Tz, C>
+where
+    Tz: TimeZone + Send + Sync + 'static,
+    Tz::Offset: Send + Sync,
+    C: Clock + Clone + Send + 'static,
+{
+    /// Create a scheduler computing fire times in `timezone`, from the time told by `clock`
+    pub fn with_clock(timezone: Tz, clock: C) -> Self {
+        Self {
+            timezone,
+            clock,
+            tasks: Vec::new(),
+        }
+    }
+
+    /// Schedule `task` to run at the fire times of `cron`.
+    ///
+    /// Each run is awaited before computing the next fire time, so runs of a job never overlap.
+    pub fn add<F, Fut>(&mut self, cron: Cron, mut task: F)
+    where
+        F: FnMut() -> Fut + Send + 'static,
+        Fut: Future<Output = ()> + Send,
+    {
+        let timezone = self.timezone.clone();
+        let clock = self.clock.clone();
+        self.tasks.push(tokio::spawn(async move {
+            let mut last = clock.now().with_timezone(&timezone);
+            while let Some(next) = cron.next_after(&last) {
+                let now = clock.now().with_timezone(&timezone);
+                if let Ok(delay) = next.clone().signed_duration_since(now).to_std() {
+                    tokio::time::sleep(delay).await;
+                }
+                task().await;
+                last = next.max(clock.now().with_timezone(&timezone));
+            }
+        }));
+    }
+
+    /// Get the amount of jobs still scheduled
+    pub fn running(&self) -> usize {
+        self.tasks.iter().filter(|task| !task.is_finished()).count()
+    }
+
+    /// Abort all jobs
+    pub fn shutdown(&mut self) {
+        for task in self.tasks.drain(..) {
+            task.abort();
+        }
+    }
+}
+
+impl<Tz: TimeZone, C: Clock> Drop for AsyncScheduler<Tz, C> {
+    fn drop(&mut self) {
+        for task in self.tasks.iter() {
+            task.abort();
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{Arc, Mutex};
+
+    use chrono_tz::Europe::Rome;
+
+    use super::*;
+
+    /// A clock which only moves forward when slept on
+    struct ManualClock(Mutex<DateTime<Utc>>);
+
+    impl Clock for ManualClock {
+        fn now(&self) -> DateTime<Utc> {
+            *self.0.lock().unwrap()
+        }
+
+        fn sleep(&self, duration: Duration) {
+            *self.0.lock().unwrap() += chrono::Duration::from_std(duration).unwrap();
+        }
+    }
+
+    /// A clock following tokio's timer, which can be paused
+    #[derive(Clone)]
+    struct TokioClock {
+        start: DateTime<Utc>,
+        instant: tokio::time::Instant,
+    }
+
+    impl Clock for TokioClock {
+        fn now(&self) -> DateTime<Utc> {
+            self.start + chrono::Duration::from_std(self.instant.elapsed()).unwrap()
+        }
+    }
+
+    /// A quarter of a second past midnight
+    fn start() -> DateTime<Utc> {
+        Rome.with_ymd_and_hms(2024, 3, 1, 0, 0, 0)
+            .unwrap()
+            .with_timezone(&Utc)
+            + chrono::Duration::milliseconds(250)
+    }
+
+    #[test]
+    fn test_should_tick_due_jobs() {
+        let mut scheduler = Scheduler::new(Rome);
+        let counter = Arc::new(AtomicUsize::new(0));
+        let job_counter = counter.clone();
+        scheduler.add("@yearly".parse().unwrap(), move || {
+            job_counter.fetch_add(1, Ordering::SeqCst);
+        });
+        let next = scheduler.next_fire().unwrap();
+        assert_eq!(next.format("%m-%d %H:%M:%S").to_string(), "01-01 00:00:00");
+        assert_eq!(scheduler.tick(&(next - chrono::Duration::seconds(1))), 0);
+        assert_eq!(scheduler.tick(&next), 1);
+        assert_eq!(counter.load(Ordering::SeqCst), 1);
+        assert!(scheduler.next_fire().unwrap() > next);
+    }
+
+    #[test]
+    fn test_should_run_until_deadline() {
+        let mut scheduler = Scheduler::with_clock(Rome, ManualClock(Mutex::new(start())));
+        let counter = Arc::new(AtomicUsize::new(0));
+        let job_counter = counter.clone();
+        scheduler.add("* * * * * *".parse().unwrap(), move || {
+            job_counter.fetch_add(1, Ordering::SeqCst);
+        });
+        let deadline = (start() + chrono::Duration::milliseconds(2500)).with_timezone(&Rome);
+        scheduler.run_until(&deadline);
+        // at 00:00:01 and 00:00:02
+        assert_eq!(counter.load(Ordering::SeqCst), 2);
+        // slept until the last fire time
+        assert_eq!(
+            scheduler.now().format("%H:%M:%S%.3f").to_string(),
+            "00:00:02.000"
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_should_run_async_jobs() {
+        let clock = TokioClock {
+            start: start(),
+            instant: tokio::time::Instant::now(),
+        };
+        let mut scheduler = AsyncScheduler::with_clock(Rome, clock);
+        let counter = Arc::new(AtomicUsize::new(0));
+        let job_counter = counter.clone();
+        scheduler.add("* * * * * *".parse().unwrap(), move || {
+            let job_counter = job_counter.clone();
+            async move {
+                job_counter.fetch_add(1, Ordering::SeqCst);
+            }
+        });
+        assert_eq!(scheduler.running(), 1);
+        tokio::time::sleep(Duration::from_millis(2500)).await;
+        scheduler.shutdown();
+        assert_eq!(scheduler.running(), 0);
+        // at 00:00:01 and 00:00:02
+        assert_eq!(counter.load(Ordering::SeqCst), 2);
+    }
+}
@@ -1,15 +1,73 @@
-use std::cmp::{Eq, Ord};
+mod cron;
+mod parser;
+mod range_set;
+mod step;
+mod unit;
+
 use std::error::Error;
-use std::ops::Add;
 use std::str::FromStr;
 
+use chrono::{Duration, TimeZone};
+use chrono_tz::Europe::Rome;
+
+use self::cron::{AsyncScheduler, Cron, Scheduler};
+use self::parser::{parse_range, parse_values, parse_values_until};
+
 fn main() {
     let range = parse_range::<u64>("0-3").unwrap();
-    assert_eq!(range, vec![0, 1, 2, 3]);
-    println!("{:?}", range);
+    assert_eq!(range.iter().collect::<Vec<u64>>(), vec![0, 1, 2, 3]);
+    println!("{}", range);
     let range = parse_range::<u64>("0,1,2,3").unwrap();
-    assert_eq!(range, vec![0, 1, 2, 3]);
+    assert_eq!(range.iter().collect::<Vec<u64>>(), vec![0, 1, 2, 3]);
+    println!("{}", range);
+    let range = parse_range::<u64>("0-20/5,!10").unwrap();
+    assert_eq!(range.iter().collect::<Vec<u64>>(), vec![0, 5, 15, 20]);
+    println!("{}", range);
+    let range = parse_range::<i8>("-2-2,125-").unwrap();
+    assert_eq!(range.to_string(), "-2-2,125-");
+    println!("{}", range);
+    let range = parse_range::<u32>("1-4000000000").unwrap();
+    assert_eq!(range.len(), 4000000000);
+    println!("{}", range);
+    let range = parse_range::<u32>("0-4000000000/2").unwrap();
+    assert_eq!(range.len(), 2000000001);
+    println!("{}", range);
+    let range = parse_values::<f64>("0-1/0.25").unwrap();
+    assert_eq!(range, vec![0.0, 0.25, 0.5, 0.75, 1.0]);
     println!("{:?}", range);
+    let range = parse_values_until::<f64>("0.5-/0.25", 1.0).unwrap();
+    assert_eq!(range, vec![0.5, 0.75, 1.0]);
+    println!("{:?}", range);
+    match parse_range::<u8>("1-3,x") {
+        Ok(_) => unreachable!(),
+        Err(err) => println!("{err}"),
+    }
+
+    let cron: Cron = "0 30 2 * * SUN#1,SUNL".parse().unwrap();
+    let after = Rome.with_ymd_and_hms(2024, 3, 1, 0, 0, 0).unwrap();
+    for fire in cron.next_n(&after, 4) {
+        println!("{}", fire);
+    }
+    match "0 25 * * *".parse::<Cron>() {
+        Ok(_) => unreachable!(),
+        Err(err) => println!("{err}"),
+    }
+    let mut scheduler = Scheduler::new(Rome);
+    scheduler.add("0 0 * * * *".parse().unwrap(), || println!("tick"));
+    let next = scheduler.next_fire().unwrap();
+    println!("next tick at {}", next);
+    // nothing is due yet, so this doesn't wait
+    scheduler.run_until(&(next - Duration::seconds(1)));
+    assert_eq!(scheduler.tick(&next), 1);
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    runtime.block_on(async {
+        let mut scheduler = AsyncScheduler::new(Rome);
+        scheduler.add("0 0 * * * *".parse().unwrap(), || async {
+            println!("async tick")
+        });
+        println!("{} jobs running", scheduler.running());
+        scheduler.shutdown();
+    });
 
     let range = parse_range_alt::<u64>("0-3").unwrap();
     assert_eq!(range, vec![0, 1, 2, 3]);
@@ -19,83 +77,6 @@ fn main() {
     println!("{:?}", range);
 }
 
-/// A trait for types that have a unit value.
-///
-/// E.g. 1 for integers, 1.0 for floats, etc.
-pub trait Unit {
-    fn unit() -> Self;
-}
-
-/// Implement One for common numeric types.
-macro_rules! impl_one_for_numeric {
-    ($($t:ty)*) => ($(
-        impl Unit for $t {
-            fn unit() -> Self {
-                1
-            }
-        }
-    )*)
-}
-
-impl_one_for_numeric!(usize u8 u16 u32 u64 isize i8 i16 i32 i64);
-
-/// Parse a range string to a vector of usize
-///
-/// # Arguments
-/// - range_str: &str - the range string to parse
-///
-/// # Returns
-/// - Result<Vec<T>, anyhow::Error> - the parsed range
-///
-/// # Example
-///
-/// ```rust
-/// let range: Vec<u64> = parse_range::<u64>("0-3").unwrap();
-/// assert_eq!(range, vec![0, 1, 2]);
-///
-/// let range: Vec<u64> = parse_range::<u64>("0,1,2,3").unwrap();
-/// assert_eq!(range, vec![0, 1, 2, 3]);
-/// ```
-fn parse_range<T>(range_str: &str) -> Result<Vec<T>, Box<dyn Error>>
-where
-    T: FromStr + Add<Output = T> + Eq + Ord + Unit + Copy,
-{
-    // parse both format: 0-3 or 0,1,2,3
-    if range_str.contains('-') {
-        let mut range = range_str.split('-');
-        let start = range
-            .next()
-            .ok_or_else(|| "invalid range: start token not found")?;
-        let end = range
-            .next()
-            .ok_or_else(|| "invalid range: end token not found")?;
-        let start = start
-            .parse::<T>()
-            .map_err(|_| "invalid range: start is not a number")?;
-        let end = end
-            .parse::<T>()
-            .map_err(|_| "invalid range: end is not a number")?;
-
-        let mut range = Vec::new();
-        let mut x = start;
-        while x <= end {
-            range.push(x);
-            x = x + T::unit();
-        }
-
-        Ok(range)
-    } else {
-        let range = range_str
-            .split(',')
-            .map(|s| {
-                s.parse::<T>()
-                    .map_err(|_| "invalid range values: not a number")
-            })
-            .collect::<Result<Vec<T>, _>>()?;
-        Ok(range)
-    }
-}
-
 fn parse_range_alt<T>(range_str: &str) -> Result<Vec<T>, Box<dyn Error>>
 where
     T: FromStr + TryInto<isize> + TryFrom<isize>,
@@ -103,12 +84,8 @@ where
     // parse both format: 0-3 or 0,1,2,3
     if range_str.contains('-') {
         let mut range = range_str.split('-');
-        let start = range
-            .next()
-            .ok_or_else(|| "invalid range: start token not found")?;
-        let end = range
-            .next()
-            .ok_or_else(|| "invalid range: end token not found")?;
+        let start = range.next().ok_or("invalid range: start token not found")?;
+        let end = range.next().ok_or("invalid range: end token not found")?;
         let start = start
             .parse::<isize>()
             .map_err(|_| "invalid range: start is not a number")?;
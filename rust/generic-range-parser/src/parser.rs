@@ -0,0 +1,628 @@
+use std::cmp::Ordering;
+use std::fmt;
+use std::str::FromStr;
+
+use crate::range_set::RangeSet;
+use crate::step::{Bounded, Discrete, Step};
+use crate::unit::Unit;
+
+/// An error raised while parsing a range expression.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    /// What went wrong
+    pub kind: ParseErrorKind,
+    /// Byte offset in the expression where the error was found
+    pub position: usize,
+}
+
+/// The kind of a [`ParseError`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseErrorKind {
+    /// The expression is empty
+    Empty,
+    /// A character which is not allowed at this position
+    UnexpectedChar(char),
+    /// The expression ends where a value was expected
+    UnexpectedEnd,
+    /// A token which can't be parsed as the target type
+    InvalidNumber(String),
+    /// A step which is not greater than zero
+    InvalidStep,
+    /// A range whose end is lower than its start
+    ReversedRange,
+    /// The expression only contains exclusions
+    OnlyExclusions,
+    /// A value outside of the allowed bounds
+    OutOfRange,
+    /// An open-ended range where values must be enumerated without a limit
+    OpenEnded,
+}
+
+impl ParseError {
+    fn new(kind: ParseErrorKind, position: usize) -> Self {
+        Self { kind, position }
+    }
+}
+
+impl fmt::Display for ParseErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Empty => write!(f, "empty range"),
+            Self::UnexpectedChar(c) => write!(f, "unexpected character '{c}'"),
+            Self::UnexpectedEnd => write!(f, "unexpected end of range"),
+            Self::InvalidNumber(token) => write!(f, "invalid number '{token}'"),
+            Self::InvalidStep => write!(f, "step must be greater than zero"),
+            Self::ReversedRange => write!(f, "range end is lower than its start"),
+            Self::OnlyExclusions => write!(f, "range only contains exclusions"),
+            Self::OutOfRange => write!(f, "value out of range"),
+            Self::OpenEnded => write!(f, "open-ended range without a limit"),
+        }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at position {}", self.kind, self.position)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// A single item of a range expression, e.g. `0-100/5` or `!3`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RangeItem<T> {
+    pub start: T,
+    /// Inclusive end; `None` if the range is open-ended
+    pub end: Option<T>,
+    pub step: T,
+    /// Whether the values are removed from the range
+    pub exclude: bool,
+    /// Byte offset of the item in the expression
+    pub position: usize,
+}
+
+impl<T> RangeItem<T>
+where
+    T: PartialOrd + Bounded + Step + Copy,
+{
+    /// Enumerate the values of this item, up to `limit` if provided.
+    pub fn values(&self, limit: Option<T>) -> Vec<T> {
+        let mut end = self.end.unwrap_or_else(T::max_value);
+        if let Some(limit) = limit {
+            if limit < end {
+                end = limit;
+            }
+        }
+        let mut values = Vec::new();
+        let mut n = 0;
+        while let Some(x) = T::forward(self.start, self.step, n) {
+            // stop on overflow or when float precision doesn't let values grow anymore
+            if x > end || values.last().is_some_and(|last| x <= *last) {
+                break;
+            }
+            values.push(x);
+            n += 1;
+        }
+        values
+    }
+}
+
+impl<T> RangeItem<T>
+where
+    T: Default + Discrete,
+{
+    /// Get the values of this item as a set, up to `limit` if provided.
+    pub fn to_set(self, limit: Option<T>) -> RangeSet<T> {
+        let end = match (self.end, limit) {
+            (Some(end), Some(limit)) => end.min(limit),
+            (Some(end), None) => end,
+            (None, Some(limit)) => limit,
+            (None, None) => T::max_value(),
+        };
+        let step = self.step.index() - T::default().index();
+        RangeSet::from_progression(self.start, end, step)
+    }
+}
+
+/// Parse a range expression into its items.
+///
+/// The grammar is a comma separated list of items, each one being:
+///
+/// - a value: `3`, `-2`, `0.5`
+/// - a range: `0-3`, `-5--1`
+/// - an open-ended range, which ends at the highest value of `T`: `5-`
+/// - a range or a value followed by a step: `0-100/5`, `5/15` (same as `5-/15`)
+/// - any of the above preceded by `!` to exclude its values: `!3`, `!10-20/2`
+///
+/// Whitespace around tokens is ignored.
+pub fn parse_items<T>(range_str: &str) -> Result<Vec<RangeItem<T>>, ParseError>
+where
+    T: FromStr + PartialOrd + Default + Unit + Copy,
+{
+    let mut parser = Parser {
+        input: range_str,
+        pos: 0,
+    };
+    parser.skip_whitespace();
+    if parser.peek().is_none() {
+        return Err(ParseError::new(ParseErrorKind::Empty, 0));
+    }
+    let mut items = Vec::new();
+    loop {
+        items.push(parser.item()?);
+        parser.skip_whitespace();
+        match parser.peek() {
+            None => break,
+            Some(',') => parser.pos += 1,
+            Some(c) => {
+                return Err(ParseError::new(
+                    ParseErrorKind::UnexpectedChar(c),
+                    parser.pos,
+                ))
+            }
+        }
+    }
+    if items.iter().all(|item| item.exclude) {
+        return Err(ParseError::new(ParseErrorKind::OnlyExclusions, 0));
+    }
+    Ok(items)
+}
+
+/// Parse a range expression to a sorted vector of distinct values.
+///
+/// See [`parse_items`] for the grammar. Every value is materialized, so prefer [`parse_range`]
+/// for integers; this function is meant for floats, whose values can't be grouped in intervals.
+/// Open-ended ranges are reported as [`ParseErrorKind::OpenEnded`], use [`parse_values_until`] to end them.
+///
+/// # Example
+///
+/// ```rust
+/// let range: Vec<u64> = parse_values::<u64>("0-10/5,!5,7").unwrap();
+/// assert_eq!(range, vec![0, 7, 10]);
+///
+/// let range: Vec<i32> = parse_values::<i32>("-2--1").unwrap();
+/// assert_eq!(range, vec![-2, -1]);
+/// ```
+pub fn parse_values<T>(range_str: &str) -> Result<Vec<T>, ParseError>
+where
+    T: FromStr + PartialOrd + Default + Unit + Bounded + Step + Copy,
+{
+    let items: Vec<RangeItem<T>> = parse_items(range_str)?;
+    if let Some(item) = items
+        .iter()
+        .find(|item| !item.exclude && item.end.is_none())
+    {
+        return Err(ParseError::new(ParseErrorKind::OpenEnded, item.position));
+    }
+    Ok(collect_values(&items, None))
+}
+
+/// Parse a range expression to a sorted vector of distinct values, up to `limit`.
+///
+/// Open-ended ranges end at `limit`, values above it are dropped.
+///
+/// # Example
+///
+/// ```rust
+/// let range: Vec<f64> = parse_values_until::<f64>("0.5-/0.25", 1.0).unwrap();
+/// assert_eq!(range, vec![0.5, 0.75, 1.0]);
+/// ```
+pub fn parse_values_until<T>(range_str: &str, limit: T) -> Result<Vec<T>, ParseError>
+where
+    T: FromStr + PartialOrd + Default + Unit + Bounded + Step + Copy,
+{
+    let items: Vec<RangeItem<T>> = parse_items(range_str)?;
+    Ok(collect_values(&items, Some(limit)))
+}
+
+/// Enumerate the values included by `items`, but not excluded, up to `limit` if provided.
+fn collect_values<T>(items: &[RangeItem<T>], limit: Option<T>) -> Vec<T>
+where
+    T: PartialOrd + Bounded + Step + Copy,
+{
+    let mut values: Vec<T> = items
+        .iter()
+        .filter(|item| !item.exclude)
+        .flat_map(|item| item.values(limit))
+        .collect();
+    values.sort_by(|a, b| a.partial_cmp(b).expect("parsed values are comparable"));
+    values.dedup_by(|a, b| a == b);
+    // exclusions don't need to be enumerated past the highest included value
+    let limit: Option<T> = values.last().copied();
+    let excluded: Vec<T> = items
+        .iter()
+        .filter(|item| item.exclude)
+        .flat_map(|item| item.values(limit))
+        .collect();
+    values.retain(|x| !excluded.contains(x));
+    values
+}
+
+/// Parse a range expression to a [`RangeSet`].
+///
+/// See [`parse_items`] for the grammar. Ranges are stored as arithmetic progressions,
+/// so `1-4000000000` and `0-4000000000/2` take no more memory than `1-2`.
+///
+/// # Example
+///
+/// ```rust
+/// let range: RangeSet<u32> = parse_range::<u32>("1-4000000000,!2").unwrap();
+/// assert_eq!(range.len(), 3999999999);
+/// assert_eq!(range.to_string(), "1,3-4000000000");
+/// ```
+pub fn parse_range<T>(range_str: &str) -> Result<RangeSet<T>, ParseError>
+where
+    T: FromStr + Default + Unit + Discrete,
+{
+    let items: Vec<RangeItem<T>> = parse_items(range_str)?;
+    Ok(build_set(&items))
+}
+
+/// Parse a range expression to a [`RangeSet`] of values from `min` to `max`.
+///
+/// Open-ended ranges end at `max`, values out of bounds are reported as [`ParseErrorKind::OutOfRange`].
+///
+/// # Example
+///
+/// ```rust
+/// let minutes: RangeSet<u32> = parse_range_within::<u32>("45-/5", 0, 59).unwrap();
+/// assert_eq!(minutes.to_string(), "45-55/5");
+/// ```
+pub fn parse_range_within<T>(range_str: &str, min: T, max: T) -> Result<RangeSet<T>, ParseError>
+where
+    T: FromStr + Default + Unit + Discrete,
+{
+    let mut items: Vec<RangeItem<T>> = parse_items(range_str)?;
+    for item in items.iter_mut() {
+        if item.start < min || item.start > max || item.end.is_some_and(|end| end > max) {
+            return Err(ParseError::new(ParseErrorKind::OutOfRange, item.position));
+        }
+        item.end = item.end.or(Some(max));
+    }
+    Ok(build_set(&items))
+}
+
+/// Build the set of values included by `items`, but not excluded.
+fn build_set<T>(items: &[RangeItem<T>]) -> RangeSet<T>
+where
+    T: Default + Discrete,
+{
+    let included: RangeSet<T> = items
+        .iter()
+        .filter(|item| !item.exclude)
+        .fold(RangeSet::new(), |set, item| set.union(&item.to_set(None)));
+    let limit: Option<T> = included.last();
+    let excluded: RangeSet<T> = items
+        .iter()
+        .filter(|item| item.exclude)
+        .fold(RangeSet::new(), |set, item| set.union(&item.to_set(limit)));
+    included.difference(&excluded)
+}
+
+/// Recursive descent parser for range expressions.
+struct Parser<'a> {
+    input: &'a str,
+    pos: usize,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<char> {
+        self.input[self.pos..].chars().next()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(c) = self.peek().filter(|c| c.is_whitespace()) {
+            self.pos += c.len_utf8();
+        }
+    }
+
+    /// Consume `c` if it's the next non-whitespace character.
+    fn eat(&mut self, c: char) -> bool {
+        self.skip_whitespace();
+        if self.peek() == Some(c) {
+            self.pos += c.len_utf8();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn item<T>(&mut self) -> Result<RangeItem<T>, ParseError>
+    where
+        T: FromStr + PartialOrd + Default + Unit + Copy,
+    {
+        self.skip_whitespace();
+        let position = self.pos;
+        let exclude = self.eat('!');
+        let start: T = self.number()?;
+        let mut end: Option<T> = Some(start);
+        let is_range = self.eat('-');
+        if is_range {
+            self.skip_whitespace();
+            end = match self.peek() {
+                None | Some(',') | Some('/') => None,
+                Some(_) => Some(self.number()?),
+            };
+        }
+        let mut step = T::unit();
+        if self.eat('/') {
+            self.skip_whitespace();
+            let step_position = self.pos;
+            step = self.number()?;
+            if step.partial_cmp(&T::default()) != Some(Ordering::Greater) {
+                return Err(ParseError::new(ParseErrorKind::InvalidStep, step_position));
+            }
+            // `5/15` starts at 5 and runs to the end, like in cron
+            if !is_range {
+                end = None;
+            }
+        }
+        if end.is_some_and(|end| end < start) {
+            return Err(ParseError::new(ParseErrorKind::ReversedRange, position));
+        }
+        Ok(RangeItem {
+            start,
+            end,
+            step,
+            exclude,
+            position,
+        })
+    }
+
+    /// Parse a number, optionally signed and with a fractional part.
+    fn number<T>(&mut self) -> Result<T, ParseError>
+    where
+        T: FromStr,
+    {
+        self.skip_whitespace();
+        let start = self.pos;
+        let bytes = self.input.as_bytes();
+        let mut end = start;
+        if matches!(bytes.get(end), Some(b'-') | Some(b'+')) {
+            end += 1;
+        }
+        while bytes
+            .get(end)
+            .is_some_and(|b| b.is_ascii_digit() || *b == b'.')
+        {
+            end += 1;
+        }
+        let token = &self.input[start..end];
+        if !token.bytes().any(|b| b.is_ascii_digit()) {
+            return Err(match self.peek() {
+                Some(c) => ParseError::new(ParseErrorKind::UnexpectedChar(c), start),
+                None => ParseError::new(ParseErrorKind::UnexpectedEnd, start),
+            });
+        }
+        self.pos = end;
+        token
+            .parse::<T>()
+            .map_err(|_| ParseError::new(ParseErrorKind::InvalidNumber(token.to_string()), start))
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    fn error(kind: ParseErrorKind, position: usize) -> ParseError {
+        ParseError { kind, position }
+    }
+
+    #[test]
+    fn test_should_parse_basic_ranges() {
+        assert_eq!(parse_values::<u64>("0-3").unwrap(), vec![0, 1, 2, 3]);
+        assert_eq!(
+            parse_values::<u64>("3,1, 2 ,0,1").unwrap(),
+            vec![0, 1, 2, 3]
+        );
+        assert_eq!(parse_values::<u8>("7").unwrap(), vec![7]);
+        assert_eq!(parse_values::<u8>("1-2,5-6").unwrap(), vec![1, 2, 5, 6]);
+    }
+
+    #[test]
+    fn test_should_parse_steps() {
+        assert_eq!(
+            parse_values::<u32>("0-20/5").unwrap(),
+            vec![0, 5, 10, 15, 20]
+        );
+        assert_eq!(parse_values::<u32>("1-10/4").unwrap(), vec![1, 5, 9]);
+        assert_eq!(
+            parse_values_until::<u8>("200/20", u8::MAX).unwrap(),
+            vec![200, 220, 240]
+        );
+    }
+
+    #[test]
+    fn test_should_parse_open_ended_ranges() {
+        assert_eq!(
+            parse_values_until::<u8>("250-", u8::MAX).unwrap(),
+            vec![250, 251, 252, 253, 254, 255]
+        );
+        assert_eq!(
+            parse_values_until::<i8>("120-/3", i8::MAX).unwrap(),
+            vec![120, 123, 126]
+        );
+        assert_eq!(
+            parse_values_until::<u64>("5-,2", 7).unwrap(),
+            vec![2, 5, 6, 7]
+        );
+        assert_eq!(
+            parse_values_until::<f64>("0-/0.5,3", 1.0).unwrap(),
+            vec![0.0, 0.5, 1.0]
+        );
+        // values would be enumerated up to u64::MAX
+        assert_eq!(
+            parse_values::<u64>("1,5-").unwrap_err(),
+            error(ParseErrorKind::OpenEnded, 2)
+        );
+        assert_eq!(
+            parse_values::<f64>("0/0.5").unwrap_err(),
+            error(ParseErrorKind::OpenEnded, 0)
+        );
+        assert_eq!(
+            parse_items::<u64>("5-").unwrap(),
+            vec![RangeItem {
+                start: 5,
+                end: None,
+                step: 1,
+                exclude: false,
+                position: 0
+            }]
+        );
+    }
+
+    #[test]
+    fn test_should_parse_exclusions() {
+        assert_eq!(
+            parse_values::<u32>("1-10,!3,!5-8").unwrap(),
+            vec![1, 2, 4, 9, 10]
+        );
+        assert_eq!(
+            parse_values::<u32>("0-10,!0-/2").unwrap(),
+            vec![1, 3, 5, 7, 9]
+        );
+        // exclusions are applied after all the inclusions
+        assert_eq!(parse_values::<u32>("!2,1-3").unwrap(), vec![1, 3]);
+        // open-ended exclusions end at the highest included value
+        assert_eq!(parse_values::<u64>("!10-,1-3").unwrap(), vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn test_should_parse_negative_numbers() {
+        assert_eq!(parse_values::<i32>("-3--1").unwrap(), vec![-3, -2, -1]);
+        assert_eq!(parse_values::<i32>("-2-2/2").unwrap(), vec![-2, 0, 2]);
+        assert_eq!(parse_values::<i64>("-1,!-1,-5").unwrap(), vec![-5]);
+        assert_eq!(
+            parse_values_until::<i8>("126-", i8::MAX).unwrap(),
+            vec![126, 127]
+        );
+    }
+
+    #[test]
+    fn test_should_parse_floats() {
+        assert_eq!(
+            parse_values::<f64>("0-1/0.25").unwrap(),
+            vec![0.0, 0.25, 0.5, 0.75, 1.0]
+        );
+        assert_eq!(parse_values::<f64>("0-1/0.1").unwrap().len(), 11);
+        assert_eq!(
+            parse_values::<f32>("-1.5-1.5").unwrap(),
+            vec![-1.5, -0.5, 0.5, 1.5]
+        );
+        assert_eq!(parse_values::<f64>("0.5,!0.5,2").unwrap(), vec![2.0]);
+    }
+
+    #[test]
+    fn test_should_parse_range_set() {
+        let range = parse_range::<u32>("1-4000000000,!2").unwrap();
+        assert_eq!(range.to_string(), "1,3-4000000000");
+        assert_eq!(range.len(), 3999999999);
+        // stepped ranges aren't expanded either
+        let range = parse_range::<u32>("0-4000000000/2,!10-20").unwrap();
+        assert_eq!(range.len(), 1999999995);
+        assert_eq!(range.to_string(), "0-8/2,22-4000000000/2");
+        assert!(range.contains(3999999998));
+        assert!(!range.contains(3999999999));
+        assert_eq!(
+            parse_range::<u64>("5/15").unwrap().len(),
+            (u64::MAX - 5) as u128 / 15 + 1
+        );
+        assert_eq!(parse_range::<u64>("0-/2,!1-").unwrap().to_string(), "0");
+        assert_eq!(
+            parse_range::<u64>("0-20/5,!10,3-4").unwrap().to_string(),
+            "0,3-5,15,20"
+        );
+        assert_eq!(
+            parse_range::<u64>("10-,!100-").unwrap().to_string(),
+            "10-99"
+        );
+        assert_eq!(
+            parse_range::<i64>("!-5,-10-").unwrap().to_string(),
+            "-10--6,-4-"
+        );
+        assert_eq!(parse_range::<u8>("0-/2,!10-").unwrap().len(), 5);
+        assert!(parse_range::<u8>("1,!1").unwrap().is_empty());
+        assert_eq!(
+            parse_range::<u8>("1-300").unwrap_err(),
+            error(ParseErrorKind::InvalidNumber(String::from("300")), 2)
+        );
+    }
+
+    #[test]
+    fn test_should_parse_range_within_bounds() {
+        assert_eq!(
+            parse_range_within::<u32>("45-/5", 0, 59)
+                .unwrap()
+                .to_string(),
+            "45-55/5"
+        );
+        assert_eq!(
+            parse_range_within::<u32>("1-,!10-", 1, 31)
+                .unwrap()
+                .to_string(),
+            "1-9"
+        );
+        assert_eq!(
+            parse_range_within::<u32>("1-5,7-60", 0, 59).unwrap_err(),
+            error(ParseErrorKind::OutOfRange, 4)
+        );
+        assert_eq!(
+            parse_range_within::<u32>("0", 1, 31).unwrap_err(),
+            error(ParseErrorKind::OutOfRange, 0)
+        );
+    }
+
+    #[test]
+    fn test_should_report_errors_with_position() {
+        assert_eq!(
+            parse_values::<u8>("  ").unwrap_err(),
+            error(ParseErrorKind::Empty, 0)
+        );
+        assert_eq!(
+            parse_values::<u8>("1,,2").unwrap_err(),
+            error(ParseErrorKind::UnexpectedChar(','), 2)
+        );
+        assert_eq!(
+            parse_values::<u8>("1-3,").unwrap_err(),
+            error(ParseErrorKind::UnexpectedEnd, 4)
+        );
+        assert_eq!(
+            parse_values::<u8>("1-3 x").unwrap_err(),
+            error(ParseErrorKind::UnexpectedChar('x'), 4)
+        );
+        assert_eq!(
+            parse_values::<u8>("1-300").unwrap_err(),
+            error(ParseErrorKind::InvalidNumber(String::from("300")), 2)
+        );
+        assert_eq!(
+            parse_values::<u32>("-1-3").unwrap_err(),
+            error(ParseErrorKind::InvalidNumber(String::from("-1")), 0)
+        );
+        assert_eq!(
+            parse_values::<u32>("1.5").unwrap_err(),
+            error(ParseErrorKind::InvalidNumber(String::from("1.5")), 0)
+        );
+        assert_eq!(
+            parse_values::<u32>("0-10/0").unwrap_err(),
+            error(ParseErrorKind::InvalidStep, 5)
+        );
+        assert_eq!(
+            parse_values::<i32>("0-10/-2").unwrap_err(),
+            error(ParseErrorKind::InvalidStep, 5)
+        );
+        assert_eq!(
+            parse_values::<u32>("1,10-2").unwrap_err(),
+            error(ParseErrorKind::ReversedRange, 2)
+        );
+        assert_eq!(
+            parse_values::<u32>("!1,!2").unwrap_err(),
+            error(ParseErrorKind::OnlyExclusions, 0)
+        );
+        assert_eq!(
+            parse_values::<u32>("1--").unwrap_err().to_string(),
+            "unexpected character '-' at position 2"
+        );
+    }
+}
@@ -0,0 +1,1118 @@
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::fmt;
+use std::marker::PhantomData;
+
+use crate::step::Discrete;
+
+/// Most values of a pattern repeated by the set, when splitting it into the runs it's printed as
+const PATTERN_LEN: usize = 64;
+
+/// A set of values stored as sorted arithmetic progressions.
+///
+/// E.g. `{1, 2, 3, 10, 20, 30}` is stored as `1-3` and `10-30/10`, so `0-4000000000/2` takes no
+/// more memory than `1-2`. Progressions never share values, but may interleave, e.g. `0-/2` and `1-/4`.
+#[derive(Debug, Clone)]
+pub struct RangeSet<T> {
+    /// Progressions sorted by start, over the indices of the values
+    runs: Vec<Run>,
+    /// Highest end among `runs[..=i]`, to find the runs reaching a value
+    reach: Vec<u128>,
+    marker: PhantomData<T>,
+}
+
+impl<T> Default for RangeSet<T> {
+    fn default() -> Self {
+        Self {
+            runs: Vec::new(),
+            reach: Vec::new(),
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<T> RangeSet<T>
+where
+    T: Discrete,
+{
+    /// Create an empty set
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create a set with the values from `start` to `end`, both included.
+    ///
+    /// The set is empty if `end` is lower than `start`.
+    pub fn from_interval(start: T, end: T) -> Self {
+        Self::from_progression(start, end, 1)
+    }
+
+    /// Create a set with the values from `start` to `end`, both included, `step` apart.
+    ///
+    /// The set is empty if `end` is lower than `start`; `step` must be greater than zero.
+    pub fn from_progression(start: T, end: T, step: u128) -> Self {
+        Self::from_runs(
+            Run::new(start.index(), end.index(), step)
+                .into_iter()
+                .collect(),
+        )
+    }
+
+    /// Returns whether the set contains `value`
+    pub fn contains(&self, value: T) -> bool {
+        self.has(value.index())
+    }
+
+    /// Returns the amount of values in the set
+    pub fn len(&self) -> u128 {
+        self.runs.iter().map(Run::len).sum()
+    }
+
+    /// Returns whether the set is empty
+    pub fn is_empty(&self) -> bool {
+        self.runs.is_empty()
+    }
+
+    /// Get the lowest value of the set
+    pub fn first(&self) -> Option<T> {
+        self.runs.first().map(|run| T::from_index(run.start))
+    }
+
+    /// Get the highest value of the set
+    pub fn last(&self) -> Option<T> {
+        self.reach.last().map(|end| T::from_index(*end))
+    }
+
+    /// Iterate over the values of the set in ascending order, without materializing them
+    pub fn iter(&self) -> Iter<'_, T> {
+        Iter {
+            runs: &self.runs,
+            active: BinaryHeap::new(),
+            marker: PhantomData,
+        }
+    }
+
+    /// Insert the values from `start` to `end`, both included
+    pub fn insert(&mut self, start: T, end: T) {
+        *self = self.union(&Self::from_interval(start, end));
+    }
+
+    /// Remove the values from `start` to `end`, both included
+    pub fn remove(&mut self, start: T, end: T) {
+        *self = self.difference(&Self::from_interval(start, end));
+    }
+
+    /// Get the values which are in `self` or in `other`
+    pub fn union(&self, other: &Self) -> Self {
+        let mut runs: Vec<Run> = self.runs.clone();
+        runs.extend(other.difference(self).runs);
+        Self::from_runs(runs)
+    }
+
+    /// Get the values which are both in `self` and in `other`
+    pub fn intersection(&self, other: &Self) -> Self {
+        let runs: Vec<Run> = self
+            .runs
+            .iter()
+            .flat_map(|run| {
+                other
+                    .reaching(run.start, run.end)
+                    .filter_map(|(common, _)| run.intersection(common))
+            })
+            .collect();
+        Self::from_runs(runs)
+    }
+
+    /// Get the values which are in `self` but not in `other`
+    pub fn difference(&self, other: &Self) -> Self {
+        let mut runs: Vec<Run> = Vec::new();
+        for run in self.runs.iter() {
+            let mut pieces: Vec<Run> = vec![*run];
+            for (removed, reach) in other.reaching(run.start, run.end) {
+                let mut left: Vec<Run> = Vec::with_capacity(pieces.len());
+                for piece in pieces {
+                    // runs left to remove are sorted before this one, so they can't reach past `reach`
+                    match piece.start > reach {
+                        true => runs.push(piece),
+                        false => left.extend(piece.difference(removed)),
+                    }
+                }
+                pieces = left;
+            }
+            runs.extend(pieces);
+        }
+        Self::from_runs(runs)
+    }
+
+    /// Iterate backwards over the runs which may have values from `start` to `end`, along with
+    /// the highest end among them and the runs before
+    fn reaching(&self, start: u128, end: u128) -> impl Iterator<Item = (&Run, u128)> {
+        let reached = self.runs.partition_point(|run| run.start <= end);
+        self.runs[..reached]
+            .iter()
+            .zip(self.reach[..reached].iter().copied())
+            .rev()
+            .take_while(move |(_, reach)| *reach >= start)
+            .filter(move |(run, _)| run.end >= start)
+    }
+
+    /// Build a set from runs which don't share any value, merging the contiguous ones
+    fn from_runs(runs: Vec<Run>) -> Self {
+        let mut runs: Vec<Run> = runs.into_iter().flat_map(Run::split_pair).collect();
+        runs.sort_unstable();
+        let mut merged: Vec<Run> = Vec::with_capacity(runs.len());
+        for run in runs {
+            Self::push(&mut merged, run);
+        }
+        // the rest of a split progression may start after the next run
+        merged.sort_unstable();
+        let reach: Vec<u128> = merged
+            .iter()
+            .scan(0, |reach, run| {
+                *reach = run.end.max(*reach);
+                Some(*reach)
+            })
+            .collect();
+        Self {
+            runs: merged,
+            reach,
+            marker: PhantomData,
+        }
+    }
+
+    /// Split the set into the runs it's printed as, which only depend on its values.
+    ///
+    /// Goes once through the values from the lowest one, a segment at a time. A segment repeats
+    /// a pattern of at most `PATTERN_LEN` values at least three times: the pattern going on the
+    /// furthest wins, then the shortest one. Values following a segment are left to the next
+    /// one, so that values next to other values are printed along with them. Without any
+    /// pattern, the segment is the interval from the lowest value.
+    fn canonical_runs(&self) -> Vec<Run> {
+        let max: u128 = T::max_value().index();
+        let mut runs: Vec<Run> = Vec::new();
+        let mut next: Option<u128> = self.runs.first().map(|run| run.start);
+        while let Some(start) = next {
+            let (end, segment) = self.segment(start, max);
+            runs.extend(segment);
+            next = end.checked_add(1).and_then(|from| self.next_value(from));
+        }
+        runs
+    }
+
+    /// Get the end of the segment from `start`, which must be in the set, along with its runs
+    fn segment(&self, start: u128, max: u128) -> (u128, Vec<Run>) {
+        // each value following `start` ends a pattern, repeated with the distance from `start`
+        let mut offsets: Vec<u128> = vec![0];
+        let mut best: Option<(u128, u128, Vec<u128>, Vec<u128>)> = None;
+        let mut next: Option<u128> = start.checked_add(1).and_then(|from| self.next_value(from));
+        while let Some(value) = next.filter(|_| offsets.len() <= PATTERN_LEN) {
+            let step = value - start;
+            if let Some((end, counts)) = self.repeat(start, step, offsets.as_slice(), max) {
+                if best.as_ref().is_none_or(|(best_end, ..)| end > *best_end) {
+                    best = Some((end, step, offsets.clone(), counts));
+                }
+            }
+            offsets.push(step);
+            next = value.checked_add(1).and_then(|from| self.next_value(from));
+        }
+        match best {
+            Some((end, step, offsets, counts)) => (
+                end,
+                Self::pattern_runs(start, step, offsets.as_slice(), counts.as_slice()),
+            ),
+            None => {
+                let end = match start < max && self.has(start + 1) {
+                    true => start + 1,
+                    false => start,
+                };
+                (end, vec![Run::new(start, end, 1).expect("non empty run")])
+            }
+        }
+    }
+
+    /// Get the last value of the segment repeating `offsets` from `start` with `step`, along
+    /// with how many times each offset repeats, if the pattern repeats at least three times
+    fn repeat(
+        &self,
+        start: u128,
+        step: u128,
+        offsets: &[u128],
+        max: u128,
+    ) -> Option<(u128, Vec<u128>)> {
+        let third = step.checked_mul(3)?.checked_add(start)? - 1;
+        if third > max || !self.has(start + 2 * step) {
+            return None;
+        }
+        // a pattern made of a shorter one goes on as far as it, and the shorter one wins
+        if Self::is_repeating(step, offsets) {
+            return None;
+        }
+        // the values up to the third repetition must be the pattern ones, which stops at the
+        // first one which isn't
+        let mut from: u128 = start + step;
+        for value in [1, 2]
+            .iter()
+            .flat_map(|n| offsets.iter().map(move |offset| start + n * step + offset))
+        {
+            if self.next_value(from) != Some(value) {
+                return None;
+            }
+            from = value + 1;
+        }
+        if self.next_value(from).is_some_and(|next| next <= third) {
+            return None;
+        }
+        // the pattern ends with the first missing value, or the first value out of the pattern
+        let lens: Vec<u128> = offsets
+            .iter()
+            .map(|offset| self.progression_len(start + offset, step, max))
+            .collect();
+        let times: u128 = lens.iter().copied().min()?;
+        let missing: usize = lens.iter().position(|len| *len == times)?;
+        let mut end: u128 = (start + offsets[missing] + (times - 1) * step)
+            .saturating_add(step - 1)
+            .min(max);
+        if let Some(extra) = self.first_extra(start, end, step, offsets) {
+            end = extra - 1;
+        }
+        loop {
+            let counts: Vec<u128> = offsets
+                .iter()
+                .map(|offset| (end - start - offset) / step + 1)
+                .collect();
+            let last: u128 = offsets
+                .iter()
+                .zip(counts.iter())
+                .map(|(offset, count)| start + offset + (count - 1) * step)
+                .max()?;
+            // values next to the following ones are left to them
+            match step > 1 && last > start && last < max && self.has(last + 1) {
+                true => end = last - 1,
+                false => return Some((last, counts)),
+            }
+        }
+    }
+
+    /// Returns whether `offsets`, repeated with `step`, repeat with a lower step
+    fn is_repeating(step: u128, offsets: &[u128]) -> bool {
+        (2..=offsets.len() as u128)
+            .filter(|size| {
+                step.is_multiple_of(*size) && (offsets.len() as u128).is_multiple_of(*size)
+            })
+            .any(|size| {
+                offsets.iter().all(|offset| {
+                    offsets
+                        .binary_search(&((offset + step / size) % step))
+                        .is_ok()
+                })
+            })
+    }
+
+    /// Get the runs of a segment from `start`, repeating `offsets` `counts` times with `step`.
+    ///
+    /// Offsets which make up a progression with a lower step are joined, the lowest step first.
+    fn pattern_runs(start: u128, step: u128, offsets: &[u128], counts: &[u128]) -> Vec<Run> {
+        let mut joined: Vec<bool> = vec![false; offsets.len()];
+        let mut runs: Vec<Run> = Vec::new();
+        for size in (2..=offsets.len() as u128)
+            .rev()
+            .filter(|size| step.is_multiple_of(*size))
+        {
+            let lower: u128 = step / size;
+            for first in offsets.iter().take_while(|offset| **offset < lower) {
+                let members: Option<Vec<usize>> = (0..size)
+                    .map(|n| {
+                        offsets
+                            .binary_search(&(first + n * lower))
+                            .ok()
+                            .filter(|index| !joined[*index])
+                    })
+                    .collect();
+                let Some(members) = members else {
+                    continue;
+                };
+                // the values of the members must follow each other up to the end
+                let len: u128 = members.iter().map(|index| counts[*index]).sum();
+                if members
+                    .iter()
+                    .enumerate()
+                    .all(|(n, index)| counts[*index] == (len - n as u128).div_ceil(size))
+                {
+                    members.iter().for_each(|index| joined[*index] = true);
+                    runs.extend(Run::new(
+                        start + first,
+                        start + first + (len - 1) * lower,
+                        lower,
+                    ));
+                }
+            }
+        }
+        runs.extend(
+            offsets
+                .iter()
+                .zip(counts.iter())
+                .zip(joined.iter())
+                .filter(|(_, joined)| !**joined)
+                .filter_map(|((offset, count), _)| {
+                    Run::new(start + offset, start + offset + (count - 1) * step, step)
+                }),
+        );
+        runs
+    }
+
+    /// Returns whether the set contains the value at `index`
+    fn has(&self, index: u128) -> bool {
+        self.reaching(index, index)
+            .any(|(run, _)| run.contains(index))
+    }
+
+    /// Get the lowest value of the set from `from`
+    fn next_value(&self, from: u128) -> Option<u128> {
+        let after = self
+            .runs
+            .get(self.runs.partition_point(|run| run.start <= from))
+            .map(|run| run.start);
+        self.reaching(from, from)
+            .filter_map(|(run, _)| run.slice(from, run.end))
+            .map(|run| run.start)
+            .chain(after)
+            .min()
+    }
+
+    /// Get how many values of the progression with `step` from `start`, which must be in the
+    /// set, follow each other in the set, looking for a missing one in longer and longer slices
+    fn progression_len(&self, start: u128, step: u128, max: u128) -> u128 {
+        let mut len: u128 = 0;
+        let mut slice: u128 = PATTERN_LEN as u128;
+        loop {
+            let from = start + len * step;
+            let to = slice
+                .checked_mul(step)
+                .and_then(|x| x.checked_add(from))
+                .map_or(max, |x| x.min(max));
+            let values = Run::new(from, to, step).expect("non empty run");
+            if self.count(&values) < values.len() {
+                // the first `found` values are in the set, the first `missing` ones are not
+                let (mut found, mut missing) = (0, values.len());
+                while missing - found > 1 {
+                    let middle = found + (missing - found) / 2;
+                    let head = Run::new(from, from + (middle - 1) * step, step);
+                    match head.is_some_and(|head| self.count(&head) == middle) {
+                        true => found = middle,
+                        false => missing = middle,
+                    }
+                }
+                return len + found;
+            }
+            len += values.len();
+            if values.end.checked_add(step).is_none_or(|next| next > max) {
+                return len;
+            }
+            slice = slice.saturating_mul(2);
+        }
+    }
+
+    /// Get how many values of `run` are in the set
+    fn count(&self, run: &Run) -> u128 {
+        self.reaching(run.start, run.end)
+            .filter_map(|(other, _)| other.intersection(run))
+            .map(|common| common.len())
+            .sum()
+    }
+
+    /// Get the first value of the set from `start` to `end` whose distance from `start` modulo
+    /// `step` is none of `offsets`
+    fn first_extra(&self, start: u128, end: u128, step: u128, offsets: &[u128]) -> Option<u128> {
+        self.reaching(start, end)
+            .filter_map(|(run, _)| run.slice(start, end))
+            .filter_map(|run| {
+                // distances repeat after `cycle` values, so either one of the first
+                // `offsets.len() + 1` values is out, or none is
+                let cycle = step / gcd(run.step, step);
+                (0..run.len().min(cycle).min(offsets.len() as u128 + 1))
+                    .map(|n| run.start + n * run.step)
+                    .find(|value| offsets.binary_search(&((value - start) % step)).is_err())
+            })
+            .min()
+    }
+
+    /// Push `run` after `merged`, joining it with the last runs if they're contiguous.
+    ///
+    /// Values adjacent to other values are kept in intervals rather than in progressions, three
+    /// or more equally spaced values are kept in progressions, and progressions of two values
+    /// are split, so that the same values are stored the same way.
+    fn push(merged: &mut Vec<Run>, run: Run) {
+        if run.step > 1 && run.len() == 2 {
+            for single in run.split_pair() {
+                Self::push(merged, single);
+            }
+            return;
+        }
+        let last = match merged.last_mut() {
+            // interleaved runs are left as they are
+            Some(last) if last.end < run.start => last,
+            _ => return merged.push(run),
+        };
+        if last.end + 1 == run.start {
+            match (last.step, run.step) {
+                (1, 1) => last.end = run.end,
+                (1, _) => {
+                    last.end = run.start;
+                    Self::push_rest(merged, run.tail());
+                }
+                (_, 1) => {
+                    let start = last.end;
+                    let rest = merged.pop().and_then(|last| last.init());
+                    Self::push_rest(merged, rest);
+                    Self::push(merged, Run::new(start, run.end, 1).expect("non empty run"));
+                }
+                (_, _) => {
+                    let (start, end) = (last.end, run.start);
+                    let rest = merged.pop().and_then(|last| last.init());
+                    Self::push_rest(merged, rest);
+                    Self::push(merged, Run::new(start, end, 1).expect("non empty run"));
+                    Self::push_rest(merged, run.tail());
+                }
+            }
+        } else if last.step > 1
+            && last.end + last.step == run.start
+            && (run.step == last.step || run.start == run.end)
+        {
+            // the progression goes on
+            last.end = run.end;
+        } else if run.step > 1 && last.start == last.end && last.end + run.step == run.start {
+            // the progression starts earlier, maybe even before the last value
+            let start = last.start;
+            merged.pop();
+            Self::push(
+                merged,
+                Run {
+                    start,
+                    end: run.end,
+                    step: run.step,
+                },
+            );
+        } else if let [.., first, second] = *merged.as_slice() {
+            // three equally spaced values
+            if first.start == first.end
+                && second.start == second.end
+                && first.end < second.start
+                && second.start - first.start == run.start - second.start
+                && run.start == run.end
+            {
+                let start = first.start;
+                merged.truncate(merged.len() - 2);
+                Self::push(
+                    merged,
+                    Run {
+                        start,
+                        end: run.end,
+                        step: run.start - second.start,
+                    },
+                );
+            } else {
+                merged.push(run);
+            }
+        } else {
+            merged.push(run);
+        }
+    }
+
+    fn push_rest(merged: &mut Vec<Run>, rest: Option<Run>) {
+        if let Some(rest) = rest {
+            Self::push(merged, rest);
+        }
+    }
+}
+
+impl<T> PartialEq for RangeSet<T>
+where
+    T: Discrete,
+{
+    fn eq(&self, other: &Self) -> bool {
+        // the same values may be stored as different progressions
+        self.len() == other.len() && self.difference(other).is_empty()
+    }
+}
+
+impl<T> Eq for RangeSet<T> where T: Discrete {}
+
+impl<T> FromIterator<T> for RangeSet<T>
+where
+    T: Discrete,
+{
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut values: Vec<u128> = iter.into_iter().map(T::index).collect();
+        values.sort_unstable();
+        values.dedup();
+        Self::from_runs(values.into_iter().map(Run::single).collect())
+    }
+}
+
+impl<'a, T> IntoIterator for &'a RangeSet<T>
+where
+    T: Discrete,
+{
+    type Item = T;
+    type IntoIter = Iter<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// Prints the set as a range expression which parses back to the same set.
+///
+/// Sets with the same values are printed the same way, however they're stored. Intervals are
+/// printed as `a-b` (`a-` if they reach the highest value of `T`) and progressions of at least
+/// three values as `a-b/step`, when that's shorter than listing them. Items are sorted by their
+/// first value, and listed values next to each other are printed as intervals.
+impl<T> fmt::Display for RangeSet<T>
+where
+    T: Discrete + fmt::Display,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut tokens: Vec<(u128, String)> = Vec::new();
+        let mut listed: Vec<u128> = Vec::new();
+        for run in self.canonical_runs() {
+            match Self::token(run) {
+                Some(token) => tokens.push((run.start, token)),
+                None => listed.extend((0..run.len()).map(|n| run.start + n * run.step)),
+            }
+        }
+        listed.sort_unstable();
+        let mut intervals: Vec<Run> = Vec::new();
+        for index in listed {
+            match intervals.last_mut() {
+                Some(last) if last.end + 1 == index => last.end = index,
+                _ => intervals.push(Run::single(index)),
+            }
+        }
+        tokens.extend(intervals.into_iter().map(|run| {
+            let token = Self::token(run).unwrap_or_else(|| T::from_index(run.start).to_string());
+            (run.start, token)
+        }));
+        tokens.sort_unstable();
+        let tokens: Vec<String> = tokens.into_iter().map(|(_, token)| token).collect();
+        write!(f, "{}", tokens.join(","))
+    }
+}
+
+impl<T> RangeSet<T>
+where
+    T: Discrete + fmt::Display,
+{
+    /// Print `run` as a range expression item, unless it's a single value or a progression which
+    /// is shorter to list
+    fn token(run: Run) -> Option<String> {
+        let max: u128 = T::max_value().index();
+        let (start, end) = (T::from_index(run.start), T::from_index(run.end));
+        if run.start == run.end {
+            return None;
+        }
+        let open = run.end.checked_add(run.step).is_none_or(|next| next > max);
+        let stepped = match (open, run.step) {
+            (true, 1) => return Some(format!("{start}-")),
+            (false, 1) => return Some(format!("{start}-{end}")),
+            (true, step) => format!("{start}-/{step}"),
+            (false, step) => format!("{start}-{end}/{step}"),
+        };
+        // listed values take at least one character each, plus the commas
+        if run.len() * 2 - 1 > stepped.len() as u128 {
+            return Some(stepped);
+        }
+        let listed_len: usize = (0..run.len())
+            .map(|n| T::from_index(run.start + n * run.step).to_string().len() + 1)
+            .sum::<usize>()
+            - 1;
+        (stepped.len() < listed_len).then_some(stepped)
+    }
+}
+
+/// An arithmetic progression of indices from `start` to `end`, both included.
+///
+/// `end` is always in the progression, and single values have a step of 1.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+struct Run {
+    start: u128,
+    end: u128,
+    step: u128,
+}
+
+impl Run {
+    /// Create the progression from `start` to the last value not greater than `end`, if any
+    fn new(start: u128, end: u128, step: u128) -> Option<Self> {
+        if end < start {
+            return None;
+        }
+        let end = end - (end - start) % step;
+        Some(Self {
+            start,
+            end,
+            step: if start == end { 1 } else { step },
+        })
+    }
+
+    fn single(value: u128) -> Self {
+        Self {
+            start: value,
+            end: value,
+            step: 1,
+        }
+    }
+
+    /// Split progressions of two values into single values
+    fn split_pair(self) -> Vec<Self> {
+        match self.step > 1 && self.len() == 2 {
+            true => vec![Self::single(self.start), Self::single(self.end)],
+            false => vec![self],
+        }
+    }
+
+    fn len(&self) -> u128 {
+        (self.end - self.start) / self.step + 1
+    }
+
+    fn contains(&self, value: u128) -> bool {
+        self.start <= value && value <= self.end && (value - self.start).is_multiple_of(self.step)
+    }
+
+    /// The progression without its first value
+    fn tail(&self) -> Option<Self> {
+        Self::new(self.start + self.step, self.end, self.step)
+    }
+
+    /// The progression without its last value
+    fn init(&self) -> Option<Self> {
+        match self.start == self.end {
+            true => None,
+            false => Self::new(self.start, self.end - self.step, self.step),
+        }
+    }
+
+    /// The values of the progression from `from` to `to`
+    fn slice(&self, from: u128, to: u128) -> Option<Self> {
+        let skipped = from.saturating_sub(self.start).div_ceil(self.step);
+        Self::new(
+            self.start + skipped * self.step,
+            to.min(self.end),
+            self.step,
+        )
+    }
+
+    /// Get the values which are both in `self` and in `other`.
+    ///
+    /// They make up a progression whose step is the least common multiple of the steps, which
+    /// starts at the solution of `x = self.start (mod self.step)`, `x = other.start (mod other.step)`.
+    fn intersection(&self, other: &Self) -> Option<Self> {
+        let (low, high) = (self.start.max(other.start), self.end.min(other.end));
+        if low > high {
+            return None;
+        }
+        let gcd = gcd(self.step, other.step);
+        let distance = other.start as i128 - self.start as i128;
+        if distance % gcd as i128 != 0 {
+            return None;
+        }
+        let modulus = other.step / gcd;
+        let distance = (distance / gcd as i128).rem_euclid(modulus as i128) as u128;
+        let n = distance * inverse(self.step / gcd % modulus, modulus) % modulus;
+        let step = self.step / gcd * other.step;
+        let first = self.start + n * self.step;
+        let first = match first < low {
+            true => first + (low - first).div_ceil(step) * step,
+            false => first - (first - low) / step * step,
+        };
+        Self::new(first, high, step)
+    }
+
+    /// Get the values of `self` which are not in `other`
+    fn difference(&self, other: &Self) -> Vec<Self> {
+        let common = match self.intersection(other) {
+            Some(common) => common,
+            None => return vec![*self],
+        };
+        // between removed values there are `phases - 1` values left, which are stored either as
+        // progressions with the step of the removed values or as the gaps between them
+        let phases = common.step / self.step;
+        let gaps = common.len() - 1;
+        let (mut from, mut to) = (common.start, common.end);
+        let mut runs: Vec<Self> = Vec::new();
+        if gaps > 0 && phases > gaps + 1 {
+            runs.extend((0..gaps).filter_map(|n| {
+                let removed = common.start + n * common.step;
+                Self::new(
+                    removed + self.step,
+                    removed + common.step - self.step,
+                    self.step,
+                )
+            }));
+        } else if gaps > 0 && phases > 1 {
+            // progressions also take the values up to a step before and after the removed ones
+            from = common
+                .start
+                .saturating_sub(common.step - self.step)
+                .max(self.start);
+            to = (common.end + common.step - self.step).min(self.end);
+            runs.extend((1..phases).filter_map(|n| {
+                let start = common.start + n * self.step;
+                let start = match start >= from + common.step {
+                    true => start - common.step,
+                    false => start,
+                };
+                Self::new(start, to, common.step)
+            }));
+        }
+        if from > self.start {
+            runs.extend(self.slice(self.start, from - 1));
+        }
+        runs.extend(self.slice(to + 1, self.end));
+        runs
+    }
+}
+
+fn gcd(a: u128, b: u128) -> u128 {
+    match b {
+        0 => a,
+        b => gcd(b, a % b),
+    }
+}
+
+/// Returns the inverse of `a` modulo `modulus`, `a` and `modulus` being coprime
+fn inverse(a: u128, modulus: u128) -> u128 {
+    let (mut r, mut next_r) = (modulus as i128, a as i128);
+    let (mut t, mut next_t) = (0i128, 1i128);
+    while next_r != 0 {
+        let q = r / next_r;
+        (r, next_r) = (next_r, r - q * next_r);
+        (t, next_t) = (next_t, t - q * next_t);
+    }
+    t.rem_euclid(modulus as i128) as u128
+}
+
+/// Lazy iterator over the values of a [`RangeSet`]
+pub struct Iter<'a, T> {
+    /// Runs which haven't been reached yet
+    runs: &'a [Run],
+    /// Rest of the reached runs, by lowest value
+    active: BinaryHeap<Reverse<Run>>,
+    marker: PhantomData<T>,
+}
+
+impl<T> Iterator for Iter<'_, T>
+where
+    T: Discrete,
+{
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        // interleaved runs may have values lower than the rest of the active ones
+        while let Some(run) = self.runs.first() {
+            if self
+                .active
+                .peek()
+                .is_some_and(|Reverse(active)| active.start < run.start)
+            {
+                break;
+            }
+            self.active.push(Reverse(*run));
+            self.runs = &self.runs[1..];
+        }
+        let Reverse(run) = self.active.pop()?;
+        if let Some(rest) = run.tail() {
+            self.active.push(Reverse(rest));
+        }
+        Some(T::from_index(run.start))
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use crate::parser::{parse_range, parse_values};
+
+    fn set(intervals: &[(i32, i32)]) -> RangeSet<i32> {
+        intervals.iter().fold(RangeSet::new(), |set, (start, end)| {
+            set.union(&RangeSet::from_interval(*start, *end))
+        })
+    }
+
+    #[test]
+    fn test_should_merge_intervals() {
+        let range = set(&[(5, 7), (1, 2), (3, 3), (10, 12), (11, 20)]);
+        assert_eq!(range.to_string(), "1-3,5-7,10-20");
+        assert_eq!(range.len(), 17);
+        assert_eq!(range.first(), Some(1));
+        assert_eq!(range.last(), Some(20));
+        assert!(RangeSet::from_interval(3, 1).is_empty());
+        let range: RangeSet<u8> = [9, 1, 2, 255, 3, 254, 2].into_iter().collect();
+        assert_eq!(range.to_string(), "1-3,9,254-");
+        assert_eq!(range.len(), 6);
+    }
+
+    #[test]
+    fn test_should_tell_whether_contains_value() {
+        let range = set(&[(1, 3), (10, 20)]);
+        assert!(range.contains(1));
+        assert!(range.contains(3));
+        assert!(range.contains(15));
+        assert!(!range.contains(0));
+        assert!(!range.contains(5));
+        assert!(!range.contains(21));
+        assert!(!RangeSet::<i32>::new().contains(0));
+        let range = RangeSet::from_progression(-10i64, i64::MAX, 3);
+        assert!(range.contains(-10));
+        assert!(range.contains(i64::MAX - 2));
+        assert!(!range.contains(i64::MAX - 1));
+        assert!(!range.contains(0));
+        assert_eq!(range.last(), Some(i64::MAX - 2));
+    }
+
+    #[test]
+    fn test_should_iterate_lazily() {
+        let range: RangeSet<u32> = RangeSet::from_interval(1, 4000000000);
+        assert_eq!(range.iter().take(3).collect::<Vec<u32>>(), vec![1, 2, 3]);
+        assert_eq!(range.len(), 4000000000);
+        let range = set(&[(-2, -1), (5, 5), (i32::MAX - 1, i32::MAX)]);
+        assert_eq!(
+            range.iter().collect::<Vec<i32>>(),
+            vec![-2, -1, 5, i32::MAX - 1, i32::MAX]
+        );
+        assert_eq!((&range).into_iter().count(), 5);
+        // interleaved progressions
+        let range: RangeSet<u64> = parse_range("0-/4,1-/6").unwrap();
+        assert_eq!(
+            range.iter().take(8).collect::<Vec<u64>>(),
+            vec![0, 1, 4, 7, 8, 12, 13, 16]
+        );
+        assert_eq!(
+            range.len(),
+            (u64::MAX / 4 + 1) as u128 + ((u64::MAX - 1) / 6 + 1) as u128
+        );
+    }
+
+    #[test]
+    fn test_should_compute_set_operations() {
+        let a = set(&[(1, 10), (20, 30)]);
+        let b = set(&[(5, 25), (40, 50)]);
+        assert_eq!(a.union(&b).to_string(), "1-30,40-50");
+        assert_eq!(a.intersection(&b).to_string(), "5-10,20-25");
+        assert_eq!(a.difference(&b).to_string(), "1-4,26-30");
+        assert_eq!(b.difference(&a).to_string(), "11-19,40-50");
+        let c = set(&[(0, 2), (4, 4), (8, 21), (29, 35)]);
+        assert_eq!(a.difference(&c).to_string(), "3,5-7,22-28");
+        assert!(a.difference(&a).is_empty());
+        assert!(a.intersection(&RangeSet::new()).is_empty());
+        let mut d = RangeSet::from_interval(u8::MIN, u8::MAX);
+        d.remove(0, 0);
+        d.remove(255, 255);
+        d.remove(10, 19);
+        assert_eq!(d.to_string(), "1-9,20-254");
+        d.insert(5, 255);
+        assert_eq!(d.to_string(), "1-");
+    }
+
+    #[test]
+    fn test_should_compute_set_operations_on_progressions() {
+        let evens = RangeSet::from_progression(0u64, u64::MAX, 2);
+        let odds = RangeSet::from_progression(1u64, u64::MAX, 2);
+        let thirds = RangeSet::from_progression(0u64, u64::MAX, 3);
+        assert_eq!(evens.union(&odds), RangeSet::from_interval(0, u64::MAX));
+        assert!(evens.intersection(&odds).is_empty());
+        assert_eq!(
+            evens.intersection(&thirds),
+            RangeSet::from_progression(0, u64::MAX, 6)
+        );
+        assert_eq!(evens.difference(&thirds).to_string(), "2-/6,4-/6");
+        assert_eq!(
+            thirds.difference(&evens),
+            RangeSet::from_progression(3, u64::MAX, 6)
+        );
+        assert_eq!(
+            evens.union(&thirds).len(),
+            evens.len() + thirds.len() - evens.intersection(&thirds).len()
+        );
+        // dense ranges without sparse values are stored as gaps
+        let range: RangeSet<u32> = parse_range("0-4000000000,!0-/1000000").unwrap();
+        assert_eq!(range.len(), 4000000000 - 4000);
+        assert!(!range.contains(3000000));
+        assert!(range.contains(3000001));
+    }
+
+    #[test]
+    fn test_should_match_enumerated_values() {
+        for expr in [
+            "0-100/3,1-100/5,!0-100/7",
+            "0-100/4,2-100/4,!10-20",
+            "0-100/6,0-100/10,!30-100/15",
+            "5-95/9,!5-95/2,50",
+            "0-50,60-100/2,!1-100/3,!99",
+            "-100-100/6,-99-100/4,!-50-50/5",
+        ] {
+            let range: RangeSet<i16> = parse_range(expr).unwrap();
+            let values = parse_values::<i16>(expr).unwrap();
+            assert_eq!(range.iter().collect::<Vec<i16>>(), values, "{expr}");
+            assert_eq!(range.len(), values.len() as u128, "{expr}");
+            assert!(
+                (-120..120).all(|x| range.contains(x) == values.contains(&x)),
+                "{expr}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_should_display_canonical_expression() {
+        assert_eq!(set(&[]).to_string(), "");
+        assert_eq!(set(&[(1, 3), (5, 5), (7, 9)]).to_string(), "1-3,5,7-9");
+        assert_eq!(set(&[(-3, -1), (2, i32::MAX)]).to_string(), "-3--1,2-");
+        assert_eq!(
+            set(&[(i32::MAX, i32::MAX)]).to_string(),
+            i32::MAX.to_string()
+        );
+        let range: RangeSet<u32> = (0..=100).step_by(5).collect();
+        assert_eq!(range.to_string(), "0-100/5");
+        assert_eq!(range, RangeSet::from_progression(0, 100, 5));
+        // not shorter than listing values
+        let range: RangeSet<u32> = [1, 3, 5].into_iter().collect();
+        assert_eq!(range.to_string(), "1,3,5");
+        assert_eq!(RangeSet::from_progression(1u32, 5, 2).to_string(), "1,3,5");
+        let range: RangeSet<u32> = [1, 10, 20, 30, 40, 50, 60, 61, 62].into_iter().collect();
+        assert_eq!(range.to_string(), "1,10-50/10,60-62");
+        // values next to intervals belong to them
+        let range: RangeSet<u32> = parse_range("0-100/10,101-105,!50").unwrap();
+        assert_eq!(range.to_string(), "0-40/10,60-90/10,100-105");
+        assert_eq!(
+            RangeSet::from_progression(1u8, u8::MAX, 2).to_string(),
+            "1-/2"
+        );
+        // interleaved progressions make up a denser one
+        assert_eq!(parse_range::<u64>("0-/2,1-/2").unwrap().to_string(), "0-");
+        let evens: RangeSet<u64> = parse_range("0-/2").unwrap();
+        let odds: RangeSet<u64> = parse_range("1-/2").unwrap();
+        assert_eq!(evens.union(&odds).to_string(), "0-");
+        assert_eq!(evens.union(&odds), parse_range("0-").unwrap());
+        assert_eq!(
+            parse_range::<u64>("0-10/2,1-11/2").unwrap().to_string(),
+            "0-11"
+        );
+        assert_eq!(
+            parse_range::<u64>("0-8/4,2-10/4").unwrap().to_string(),
+            "0-10/2"
+        );
+        assert_eq!(
+            parse_range::<u64>("0-/4,1-/4").unwrap().to_string(),
+            "0-/4,1-/4"
+        );
+        assert_eq!(
+            parse_range::<u64>("0-/4,1-/6").unwrap().to_string(),
+            "0-/4,1-/6"
+        );
+        // the same values print the same way, however they're stored
+        let stepped: RangeSet<i16> = parse_range("1-31/6,14").unwrap();
+        let listed: RangeSet<i16> = [1, 7, 13, 14, 19, 25, 31].into_iter().collect();
+        assert_eq!(stepped, listed);
+        assert_eq!(stepped.to_string(), "1,7,13-14,19-31/6");
+        assert_eq!(listed.to_string(), stepped.to_string());
+    }
+
+    #[test]
+    fn test_should_print_scattered_values() {
+        let mut seed: u64 = 0x2545f4914f6cdd1d;
+        let values: Vec<u32> = (0..2000)
+            .map(|_| {
+                seed ^= seed << 13;
+                seed ^= seed >> 7;
+                seed ^= seed << 17;
+                seed as u32
+            })
+            .collect();
+        let range: RangeSet<u32> = values.iter().copied().collect();
+        let printed = range.to_string();
+        assert_eq!(printed.split(',').count(), 2000);
+        assert_eq!(parse_range::<u32>(&printed).unwrap(), range);
+        // long patterns are printed without going through each of their values
+        let range: RangeSet<u32> = parse_range("0-/3,1-/5,2-/7").unwrap();
+        assert_eq!(parse_range::<u32>(&range.to_string()).unwrap(), range);
+    }
+
+    #[test]
+    fn test_should_round_trip_expressions() {
+        for expr in [
+            "0-59/5,!30",
+            "1-10,!3,20-",
+            "-5--1,3,7,11,15,19",
+            "!0,0-1000/7,500-510",
+            "0-/3,1-/3,!100-200/2",
+        ] {
+            let range: RangeSet<i16> = parse_range(expr).unwrap();
+            let printed = range.to_string();
+            assert_eq!(
+                parse_range::<i16>(&printed).unwrap(),
+                range,
+                "{expr} -> {printed}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_should_compare_and_print_random_expressions_by_value() {
+        // xorshift, so that failures can be reproduced
+        let mut seed: u64 = 0x2545f4914f6cdd1d;
+        let mut random = |bound: u64| {
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            seed % bound
+        };
+        for _ in 0..500 {
+            let items: Vec<String> = (0..1 + random(5))
+                .map(|index| {
+                    let start = random(80) as i16 - 40;
+                    let end = start + random(50) as i16;
+                    let item = match random(3) {
+                        0 => start.to_string(),
+                        1 => format!("{start}-{end}"),
+                        _ => format!("{start}-{end}/{}", 1 + random(12)),
+                    };
+                    // expressions with only exclusions are rejected
+                    match index > 0 && random(4) == 0 {
+                        true => format!("!{item}"),
+                        false => item,
+                    }
+                })
+                .collect();
+            let expr = items.join(",");
+            let range: RangeSet<i16> = parse_range(&expr).unwrap();
+            let values: RangeSet<i16> = parse_values::<i16>(&expr).unwrap().into_iter().collect();
+            assert_eq!(range, values, "{expr}");
+            assert_eq!(range.to_string(), values.to_string(), "{expr}");
+            // empty expressions don't parse
+            let printed = range.to_string();
+            if !range.is_empty() {
+                let parsed: RangeSet<i16> = parse_range(&printed).unwrap();
+                assert_eq!(parsed, range, "{expr} -> {printed}");
+                assert_eq!(parsed.to_string(), printed, "{expr} -> {printed}");
+            }
+            // set operations give the same values as the enumerated ones
+            let other: RangeSet<i16> = RangeSet::from_progression(-20, 30, 1 + random(6) as u128);
+            for (result, expected) in [
+                (
+                    range.union(&other),
+                    values.iter().chain(other.iter()).collect::<RangeSet<i16>>(),
+                ),
+                (
+                    range.intersection(&other),
+                    values.iter().filter(|x| other.contains(*x)).collect(),
+                ),
+                (
+                    range.difference(&other),
+                    values.iter().filter(|x| !other.contains(*x)).collect(),
+                ),
+            ] {
+                assert_eq!(result, expected, "{expr}");
+                assert_eq!(result.to_string(), expected.to_string(), "{expr}");
+            }
+        }
+    }
+}
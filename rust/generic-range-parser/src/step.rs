@@ -0,0 +1,79 @@
+/// A trait for types with a highest value.
+///
+/// Open-ended ranges (e.g. `5-`) end at [`Bounded::max_value`].
+pub trait Bounded {
+    fn max_value() -> Self;
+}
+
+/// A trait for types which can be enumerated in steps.
+pub trait Step: Sized {
+    /// Returns `start + step * n`, or `None` if it can't be represented by `Self`.
+    fn forward(start: Self, step: Self, n: u64) -> Option<Self>;
+}
+
+/// A trait for integer types, whose values can be grouped in arithmetic progressions.
+pub trait Discrete: Copy + Ord + Bounded + Step {
+    /// Returns the position of `self` from the lowest value.
+    fn index(self) -> u128;
+    /// Returns the value at position `index` from the lowest value.
+    ///
+    /// `index` must not be greater than the index of the highest value.
+    fn from_index(index: u128) -> Self;
+}
+
+/// Implement Bounded, Step and Discrete for common numeric types.
+///
+/// Computations are carried out on i128, which can represent any value of these types.
+macro_rules! impl_step_for_numeric {
+    ($($t:ty)*) => ($(
+        impl Bounded for $t {
+            fn max_value() -> Self {
+                <$t>::MAX
+            }
+        }
+
+        impl Step for $t {
+            fn forward(start: Self, step: Self, n: u64) -> Option<Self> {
+                (step as i128)
+                    .checked_mul(n as i128)
+                    .and_then(|x| x.checked_add(start as i128))
+                    .and_then(|x| <$t>::try_from(x).ok())
+            }
+        }
+
+        impl Discrete for $t {
+            fn index(self) -> u128 {
+                (self as i128 - <$t>::MIN as i128) as u128
+            }
+
+            fn from_index(index: u128) -> Self {
+                (index as i128 + <$t>::MIN as i128) as $t
+            }
+        }
+    )*)
+}
+
+impl_step_for_numeric!(usize u8 u16 u32 u64 isize i8 i16 i32 i64);
+
+/// Implement Bounded and Step for common float types.
+///
+/// Values are computed as `start + step * n` rather than by accumulating `step`,
+/// so that rounding errors don't pile up.
+macro_rules! impl_step_for_floats {
+    ($($t:ty)*) => ($(
+        impl Bounded for $t {
+            fn max_value() -> Self {
+                <$t>::MAX
+            }
+        }
+
+        impl Step for $t {
+            fn forward(start: Self, step: Self, n: u64) -> Option<Self> {
+                let value = start + step * n as $t;
+                value.is_finite().then_some(value)
+            }
+        }
+    )*)
+}
+
+impl_step_for_floats!(f32 f64);
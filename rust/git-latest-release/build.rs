@@ -0,0 +1,25 @@
+/**
+ *
+ *
+ *           DO WHAT THE FUCK YOU WANT TO PUBLIC LICENSE
+ *                   Version 2, December 2004
+ *
+ *  Copyright (C) 2021 Christian Visintin
+ *
+ *  Everyone is permitted to copy and distribute verbatim or modified
+ *  copies of this license document, and changing it is allowed as long
+ *  as the name is changed.
+ *
+ *             DO WHAT THE FUCK YOU WANT TO PUBLIC LICENSE
+ *    TERMS AND CONDITIONS FOR COPYING, DISTRIBUTION AND MODIFICATION
+ *
+ *   0. You just DO WHAT THE FUCK YOU WANT TO.
+*/
+use std::env;
+
+fn main() {
+    // Expose the target triple, used to pick the release asset to update to
+    let target: String = env::var("TARGET").unwrap_or_default();
+    println!("cargo:rustc-env=TARGET={}", target);
+    println!("cargo:rerun-if-changed=build.rs");
+}
@@ -0,0 +1,699 @@
+/**
+ *
+ *
+ *           DO WHAT THE FUCK YOU WANT TO PUBLIC LICENSE
+ *                   Version 2, December 2004
+ *
+ *  Copyright (C) 2021 Christian Visintin
+ *
+ *  Everyone is permitted to copy and distribute verbatim or modified
+ *  copies of this license document, and changing it is allowed as long
+ *  as the name is changed.
+ *
+ *             DO WHAT THE FUCK YOU WANT TO PUBLIC LICENSE
+ *    TERMS AND CONDITIONS FOR COPYING, DISTRIBUTION AND MODIFICATION
+ *
+ *   0. You just DO WHAT THE FUCK YOU WANT TO.
+*/
+use semver::{Version, VersionReq};
+use serde::Deserialize;
+use std::cell::Cell;
+use std::env;
+use std::fmt;
+use std::io::{Read, Write};
+
+/// Default API base URL
+pub const GITHUB_API_URL: &str = "https://api.github.com";
+/// Content type accepted from API endpoints
+const API_CONTENT_TYPE: &str = "application/vnd.github.v3+json";
+/// Amount of releases requested for each page
+const PER_PAGE: usize = 100;
+
+/// ## Release
+///
+/// A release of a repository
+#[derive(Debug, Clone, Deserialize, Eq, PartialEq)]
+pub struct Release {
+    pub tag_name: String,
+    #[serde(default)]
+    pub name: Option<String>,
+    #[serde(default)]
+    pub draft: bool,
+    #[serde(default)]
+    pub prerelease: bool,
+    #[serde(default)]
+    pub published_at: Option<String>,
+    #[serde(default)]
+    pub html_url: Option<String>,
+    #[serde(default)]
+    pub assets: Vec<Asset>,
+}
+
+impl Release {
+    /// ### version
+    ///
+    /// Get release version from tag name (a leading `v` is ignored).
+    /// Returns `None` if the tag is not a semantic version
+    pub fn version(&self) -> Option<Version> {
+        Version::parse(self.tag_name.trim_start_matches('v')).ok()
+    }
+}
+
+/// ## Asset
+///
+/// A file attached to a release
+#[derive(Debug, Clone, Deserialize, Eq, PartialEq)]
+pub struct Asset {
+    pub name: String,
+    #[serde(default)]
+    pub size: u64,
+    #[serde(default)]
+    pub content_type: Option<String>,
+    pub browser_download_url: String,
+}
+
+/// ## RateLimit
+///
+/// API rate limit status, as reported by the last response
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct RateLimit {
+    pub limit: u64,
+    pub remaining: u64,
+    /// Time the limit resets at, as seconds since the unix epoch
+    pub reset: u64,
+}
+
+/// ## ReleaseFilter
+///
+/// Describes which releases are eligible when looking for the latest one
+#[derive(Debug, Clone, Default)]
+pub struct ReleaseFilter {
+    /// Version requirement (e.g. `^1.2`) the release must satisfy
+    pub constraint: Option<VersionReq>,
+    /// Whether prereleases are eligible
+    pub prerelease: bool,
+    /// Whether drafts are eligible
+    pub draft: bool,
+}
+
+impl ReleaseFilter {
+    /// ### new
+    ///
+    /// Make a filter matching stable releases which satisfy `constraint`, if provided
+    pub fn new(constraint: Option<&str>) -> Result<Self, ReleaseError> {
+        let constraint: Option<VersionReq> = match constraint {
+            Some(c) => Some(
+                VersionReq::parse(c).map_err(|e| ReleaseError::InvalidConstraint(e.to_string()))?,
+            ),
+            None => None,
+        };
+        Ok(Self {
+            constraint,
+            prerelease: false,
+            draft: false,
+        })
+    }
+
+    /// ### matches
+    ///
+    /// Returns whether `release` is eligible, along with its version
+    fn matches(&self, release: &Release) -> Option<Version> {
+        if (release.draft && !self.draft) || (release.prerelease && !self.prerelease) {
+            return None;
+        }
+        let version: Version = release.version()?;
+        if !version.pre.is_empty() && !self.prerelease {
+            return None;
+        }
+        match self.constraint.as_ref() {
+            Some(constraint) if !Self::satisfies(constraint, &version) => None,
+            _ => Some(version),
+        }
+    }
+
+    /// ### satisfies
+    ///
+    /// Returns whether `version` satisfies `constraint`.
+    /// Prereleases are compared by their version core, since `VersionReq` only matches them when the requirement is a prerelease too
+    fn satisfies(constraint: &VersionReq, version: &Version) -> bool {
+        let core: Version = Version::new(version.major, version.minor, version.patch);
+        constraint.matches(version) || (!version.pre.is_empty() && constraint.matches(&core))
+    }
+}
+
+/// ## ReleaseError
+///
+/// Release client error
+#[derive(Debug)]
+pub enum ReleaseError {
+    /// Server returned an error status
+    Http(u16, String),
+    /// Request couldn't be sent or response couldn't be read
+    Transport(String),
+    /// Response body couldn't be parsed
+    Parse(String),
+    /// Rate limit exceeded; resets at the provided time (seconds since the unix epoch)
+    RateLimited(u64),
+    InvalidConstraint(String),
+    /// No release matches the filter
+    NotFound,
+}
+
+impl fmt::Display for ReleaseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Http(code, msg) => write!(f, "Server returned {}: {}", code, msg),
+            Self::Transport(err) => write!(f, "Request failed: {}", err),
+            Self::Parse(err) => write!(f, "Could not parse response: {}", err),
+            Self::RateLimited(reset) => write!(f, "Rate limit exceeded; resets at {}", reset),
+            Self::InvalidConstraint(err) => write!(f, "Invalid version constraint: {}", err),
+            Self::NotFound => write!(f, "No matching release found"),
+        }
+    }
+}
+
+impl std::error::Error for ReleaseError {}
+
+/// ## ReleaseClient
+///
+/// Client for the releases API of GitHub and of compatible servers (GitHub Enterprise, Gitea)
+pub struct ReleaseClient {
+    agent: ureq::Agent,
+    base_url: String,
+    /// Token set with `token`
+    token: Option<String>,
+    /// `GITHUB_TOKEN`; only used for api.github.com
+    env_token: Option<String>,
+    rate_limit: Cell<Option<RateLimit>>,
+}
+
+impl Default for ReleaseClient {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ReleaseClient {
+    /// ### new
+    ///
+    /// Instantiate a client for api.github.com, authenticating with `GITHUB_TOKEN` if set
+    pub fn new() -> Self {
+        Self {
+            agent: ureq::AgentBuilder::new().build(),
+            base_url: GITHUB_API_URL.to_string(),
+            token: None,
+            env_token: env::var("GITHUB_TOKEN").ok().filter(|x| !x.is_empty()),
+            rate_limit: Cell::new(None),
+        }
+    }
+
+    /// ### base_url
+    ///
+    /// Set API base URL (e.g. `https://github.example.com/api/v3` or `https://gitea.example.com/api/v1`).
+    /// `GITHUB_TOKEN` is not sent to other servers than api.github.com: set one with `token`
+    pub fn base_url(mut self, base_url: &str) -> Self {
+        self.base_url = base_url.trim_end_matches('/').to_string();
+        self
+    }
+
+    /// ### token
+    ///
+    /// Set token used to authenticate requests. The token is only sent to the host of the base URL
+    pub fn token(mut self, token: Option<&str>) -> Self {
+        self.token = token.map(|x| x.to_string());
+        self
+    }
+
+    /// ### rate_limit
+    ///
+    /// Get rate limit status reported by the last response
+    pub fn rate_limit(&self) -> Option<RateLimit> {
+        self.rate_limit.get()
+    }
+
+    /// ### releases
+    ///
+    /// List all the releases of `owner/repository`, following pagination
+    pub fn releases(&self, owner: &str, repository: &str) -> Result<Vec<Release>, ReleaseError> {
+        let mut releases: Vec<Release> = Vec::new();
+        let mut url: Option<String> = Some(format!(
+            "{}/repos/{}/{}/releases?per_page={}",
+            self.base_url, owner, repository, PER_PAGE
+        ));
+        while let Some(page_url) = url {
+            let response: ureq::Response = self.get(page_url.as_str(), API_CONTENT_TYPE)?;
+            url = response.header("Link").and_then(Self::next_page);
+            let page: Vec<Release> = response
+                .into_json()
+                .map_err(|e| ReleaseError::Parse(e.to_string()))?;
+            releases.extend(page);
+        }
+        Ok(releases)
+    }
+
+    /// ### latest
+    ///
+    /// Get the release of `owner/repository` with the highest version matching `filter`
+    pub fn latest(
+        &self,
+        owner: &str,
+        repository: &str,
+        filter: &ReleaseFilter,
+    ) -> Result<Release, ReleaseError> {
+        Self::select(self.releases(owner, repository)?, filter).ok_or(ReleaseError::NotFound)
+    }
+
+    /// ### select
+    ///
+    /// Select the release with the highest version matching `filter`
+    pub fn select(releases: Vec<Release>, filter: &ReleaseFilter) -> Option<Release> {
+        releases
+            .into_iter()
+            .filter_map(|release| filter.matches(&release).map(|version| (version, release)))
+            .max_by(|(a, _), (b, _)| a.cmp(b))
+            .map(|(_, release)| release)
+    }
+
+    /// ### download
+    ///
+    /// Download `url` into `writer`.
+    /// `progress` is called after each chunk with the amount of bytes written so far and the total size, if known.
+    /// Returns the amount of bytes written
+    pub fn download<W, F>(
+        &self,
+        url: &str,
+        writer: &mut W,
+        mut progress: F,
+    ) -> Result<u64, ReleaseError>
+    where
+        W: Write,
+        F: FnMut(u64, Option<u64>),
+    {
+        let response: ureq::Response = self.get(url, "application/octet-stream")?;
+        let total: Option<u64> = response
+            .header("Content-Length")
+            .and_then(|x| x.parse::<u64>().ok());
+        let mut reader = response.into_reader();
+        let mut buffer: [u8; 8192] = [0; 8192];
+        let mut written: u64 = 0;
+        progress(written, total);
+        loop {
+            let bytes: usize = match reader.read(&mut buffer) {
+                Ok(0) => break,
+                Ok(bytes) => bytes,
+                Err(err) if err.kind() == std::io::ErrorKind::Interrupted => continue,
+                Err(err) => return Err(ReleaseError::Transport(err.to_string())),
+            };
+            writer
+                .write_all(&buffer[..bytes])
+                .map_err(|e| ReleaseError::Transport(e.to_string()))?;
+            written += bytes as u64;
+            progress(written, total);
+        }
+        Ok(written)
+    }
+
+    /// ### get
+    ///
+    /// Send a GET request to `url`, keeping track of the rate limit
+    fn get(&self, url: &str, accept: &str) -> Result<ureq::Response, ReleaseError> {
+        if let Some(limit) = self.rate_limit.get() {
+            if limit.remaining == 0 && limit.reset > Self::now() {
+                return Err(ReleaseError::RateLimited(limit.reset));
+            }
+        }
+        let mut request = self.agent.get(url).set("Accept", accept);
+        if let Some(token) = self.auth_token(url) {
+            request = request.set("Authorization", format!("token {}", token).as_str());
+        }
+        match request.call() {
+            Ok(response) => {
+                self.update_rate_limit(&response);
+                Ok(response)
+            }
+            Err(ureq::Error::Status(code, response)) => {
+                self.update_rate_limit(&response);
+                let limit: Option<RateLimit> = self.rate_limit.get();
+                match (code, limit) {
+                    (403, Some(limit)) | (429, Some(limit)) if limit.remaining == 0 => {
+                        Err(ReleaseError::RateLimited(limit.reset))
+                    }
+                    (429, _) => Err(ReleaseError::RateLimited(
+                        Self::now()
+                            + response
+                                .header("Retry-After")
+                                .and_then(|x| x.parse::<u64>().ok())
+                                .unwrap_or(60),
+                    )),
+                    _ => Err(ReleaseError::Http(code, response.status_text().to_string())),
+                }
+            }
+            Err(err) => Err(ReleaseError::Transport(err.to_string())),
+        }
+    }
+
+    /// ### auth_token
+    ///
+    /// Get the token to authenticate a request to `url` with.
+    /// Tokens are never sent to other hosts than the one of the base URL (e.g. asset downloads)
+    fn auth_token(&self, url: &str) -> Option<&str> {
+        if Self::origin(url) != Self::origin(self.base_url.as_str()) {
+            return None;
+        }
+        match self.token.as_deref() {
+            Some(token) => Some(token),
+            None if self.base_url == GITHUB_API_URL => self.env_token.as_deref(),
+            None => None,
+        }
+    }
+
+    /// ### origin
+    ///
+    /// Get scheme and authority of `url` (e.g. `https://api.github.com`), lowercase
+    fn origin(url: &str) -> String {
+        let (scheme, rest) = url.split_once("://").unwrap_or(("", url));
+        let authority: &str = rest.split(&['/', '?', '#'][..]).next().unwrap_or_default();
+        format!("{}://{}", scheme, authority).to_ascii_lowercase()
+    }
+
+    /// ### update_rate_limit
+    ///
+    /// Read rate limit from response headers
+    fn update_rate_limit(&self, response: &ureq::Response) {
+        let header = |name: &str| response.header(name).and_then(|x| x.parse::<u64>().ok());
+        if let (Some(limit), Some(remaining), Some(reset)) = (
+            header("X-RateLimit-Limit"),
+            header("X-RateLimit-Remaining"),
+            header("X-RateLimit-Reset"),
+        ) {
+            self.rate_limit.set(Some(RateLimit {
+                limit,
+                remaining,
+                reset,
+            }));
+        }
+    }
+
+    /// ### next_page
+    ///
+    /// Get the URL of the next page from a `Link` header
+    fn next_page(link: &str) -> Option<String> {
+        link.split(',').find_map(|part| {
+            let mut tokens = part.split(';');
+            let url: &str = tokens.next()?.trim();
+            match tokens.any(|x| x.trim() == "rel=\"next\"") {
+                true => Some(
+                    url.trim_start_matches('<')
+                        .trim_end_matches('>')
+                        .to_string(),
+                ),
+                false => None,
+            }
+        })
+    }
+
+    fn now() -> u64 {
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|x| x.as_secs())
+            .unwrap_or(0)
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+
+    use super::*;
+
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+    use std::sync::mpsc::{self, Receiver};
+    use std::thread;
+
+    /// Status, headers and body of a mock server response
+    pub(crate) type MockResponse = (u16, Vec<(&'static str, String)>, Vec<u8>);
+
+    /// ### mock_server
+    ///
+    /// Serve `responses` (status, headers, body) in order on a local port, one per connection.
+    /// `{url}` in header values and in textual bodies is replaced with the server URL.
+    /// Returns the server URL and a channel receiving the head of each request
+    pub(crate) fn mock_server(responses: Vec<MockResponse>) -> (String, Receiver<String>) {
+        let listener: TcpListener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url: String = format!("http://{}", listener.local_addr().unwrap());
+        let (tx, rx) = mpsc::channel();
+        let base_url: String = url.clone();
+        thread::spawn(move || {
+            for (status, headers, body) in responses.into_iter() {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut head: String = String::new();
+                loop {
+                    let mut line: String = String::new();
+                    if reader.read_line(&mut line).unwrap() == 0 || line == "\r\n" {
+                        break;
+                    }
+                    head.push_str(line.as_str());
+                }
+                let _ = tx.send(head);
+                let body: Vec<u8> = match String::from_utf8(body) {
+                    Ok(text) => text.replace("{url}", base_url.as_str()).into_bytes(),
+                    Err(err) => err.into_bytes(),
+                };
+                let mut response: String = format!(
+                    "HTTP/1.1 {} Mock\r\nContent-Length: {}\r\nConnection: close\r\n",
+                    status,
+                    body.len()
+                );
+                headers.iter().for_each(|(k, v)| {
+                    let v: String = v.replace("{url}", base_url.as_str());
+                    response.push_str(format!("{}: {}\r\n", k, v).as_str())
+                });
+                response.push_str("\r\n");
+                stream.write_all(response.as_bytes()).unwrap();
+                stream.write_all(body.as_slice()).unwrap();
+            }
+        });
+        (url, rx)
+    }
+
+    fn release(tag: &str, prerelease: bool, draft: bool) -> String {
+        format!(
+            r#"{{"tag_name":"{}","name":"{}","draft":{},"prerelease":{},"assets":[{{"name":"app.tar.gz","size":3,"content_type":"application/gzip","browser_download_url":"https://example.com/{}/app.tar.gz"}}]}}"#,
+            tag, tag, draft, prerelease, tag
+        )
+    }
+
+    fn releases() -> Vec<Release> {
+        serde_json::from_str(
+            format!(
+                "[{},{},{},{},{},{}]",
+                release("v2.0.0", false, true),
+                release("v1.3.0-rc1", true, false),
+                release("v1.2.5", false, false),
+                release("v1.10.0", false, false),
+                release("0.9.0", false, false),
+                release("nightly", true, false)
+            )
+            .as_str(),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn should_select_latest_release() {
+        let filter: ReleaseFilter = ReleaseFilter::new(None).unwrap();
+        assert_eq!(
+            ReleaseClient::select(releases(), &filter).unwrap().tag_name,
+            "v1.10.0"
+        );
+        let filter: ReleaseFilter = ReleaseFilter::new(Some("~1.2")).unwrap();
+        assert_eq!(
+            ReleaseClient::select(releases(), &filter).unwrap().tag_name,
+            "v1.2.5"
+        );
+        let filter: ReleaseFilter = ReleaseFilter::new(Some("<1")).unwrap();
+        assert_eq!(
+            ReleaseClient::select(releases(), &filter).unwrap().tag_name,
+            "0.9.0"
+        );
+        let mut filter: ReleaseFilter = ReleaseFilter::new(Some("~1.3")).unwrap();
+        assert!(ReleaseClient::select(releases(), &filter).is_none());
+        filter.prerelease = true;
+        assert_eq!(
+            ReleaseClient::select(releases(), &filter).unwrap().tag_name,
+            "v1.3.0-rc1"
+        );
+        let mut filter: ReleaseFilter = ReleaseFilter::new(Some("^2")).unwrap();
+        assert!(ReleaseClient::select(releases(), &filter).is_none());
+        filter.draft = true;
+        assert_eq!(
+            ReleaseClient::select(releases(), &filter).unwrap().tag_name,
+            "v2.0.0"
+        );
+        assert!(ReleaseFilter::new(Some("^a.b")).is_err());
+    }
+
+    #[test]
+    fn should_parse_link_header() {
+        assert_eq!(
+            ReleaseClient::next_page(
+                r#"<https://api.github.com/repositories/1/releases?page=1>; rel="prev", <https://api.github.com/repositories/1/releases?page=3>; rel="next", <https://api.github.com/repositories/1/releases?page=5>; rel="last""#
+            )
+            .as_deref(),
+            Some("https://api.github.com/repositories/1/releases?page=3")
+        );
+        assert!(ReleaseClient::next_page(
+            r#"<https://api.github.com/repositories/1/releases?page=1>; rel="first""#
+        )
+        .is_none());
+    }
+
+    #[test]
+    fn should_list_releases_with_pagination() {
+        let (url, requests) = mock_server(vec![
+            (
+                200,
+                vec![
+                    (
+                        "Link",
+                        String::from("<{url}/repos/veeso/brol/releases?page=2>; rel=\"next\""),
+                    ),
+                    ("X-RateLimit-Limit", String::from("60")),
+                    ("X-RateLimit-Remaining", String::from("59")),
+                    ("X-RateLimit-Reset", String::from("1600000000")),
+                ],
+                format!("[{}]", release("v1.0.0", false, false)).into_bytes(),
+            ),
+            (
+                200,
+                vec![
+                    ("X-RateLimit-Limit", String::from("60")),
+                    ("X-RateLimit-Remaining", String::from("58")),
+                    ("X-RateLimit-Reset", String::from("1600000000")),
+                ],
+                format!("[{}]", release("v1.1.0", false, false)).into_bytes(),
+            ),
+        ]);
+        let client: ReleaseClient = ReleaseClient::new()
+            .base_url(format!("{}/", url).as_str())
+            .token(Some("secret"));
+        let releases: Vec<Release> = client.releases("veeso", "brol").unwrap();
+        assert_eq!(
+            releases
+                .iter()
+                .map(|x| x.tag_name.as_str())
+                .collect::<Vec<&str>>(),
+            vec!["v1.0.0", "v1.1.0"]
+        );
+        assert_eq!(
+            releases[0].assets[0].browser_download_url,
+            "https://example.com/v1.0.0/app.tar.gz"
+        );
+        let first: String = requests.recv().unwrap();
+        assert!(first.starts_with("GET /repos/veeso/brol/releases?per_page=100 "));
+        assert!(first.to_lowercase().contains("authorization: token secret"));
+        assert!(requests
+            .recv()
+            .unwrap()
+            .starts_with("GET /repos/veeso/brol/releases?page=2 "));
+        assert_eq!(
+            client.rate_limit(),
+            Some(RateLimit {
+                limit: 60,
+                remaining: 58,
+                reset: 1600000000
+            })
+        );
+    }
+
+    #[test]
+    fn should_honor_rate_limit() {
+        let reset: u64 = ReleaseClient::now() + 3600;
+        let (url, requests) = mock_server(vec![(
+            403,
+            vec![
+                ("X-RateLimit-Limit", String::from("60")),
+                ("X-RateLimit-Remaining", String::from("0")),
+                ("X-RateLimit-Reset", reset.to_string()),
+            ],
+            b"{\"message\":\"API rate limit exceeded\"}".to_vec(),
+        )]);
+        let client: ReleaseClient = ReleaseClient::new().base_url(url.as_str()).token(None);
+        let filter: ReleaseFilter = ReleaseFilter::new(None).unwrap();
+        match client.latest("veeso", "brol", &filter) {
+            Err(ReleaseError::RateLimited(at)) => assert_eq!(at, reset),
+            other => panic!("Expected rate limit error, got {:?}", other),
+        }
+        assert!(!requests
+            .recv()
+            .unwrap()
+            .to_lowercase()
+            .contains("authorization"));
+        // No more requests are sent until the limit resets
+        match client.releases("veeso", "brol") {
+            Err(ReleaseError::RateLimited(at)) => assert_eq!(at, reset),
+            other => panic!("Expected rate limit error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn should_report_http_errors() {
+        let (url, _) = mock_server(vec![(404, Vec::new(), b"{}".to_vec())]);
+        let client: ReleaseClient = ReleaseClient::new().base_url(url.as_str());
+        assert!(matches!(
+            client.releases("veeso", "brol"),
+            Err(ReleaseError::Http(404, _))
+        ));
+    }
+
+    #[test]
+    fn should_only_send_tokens_to_base_url_host() {
+        // GITHUB_TOKEN is only sent to api.github.com
+        let mut client: ReleaseClient = ReleaseClient::new();
+        client.env_token = Some(String::from("secret"));
+        assert_eq!(
+            client.auth_token("https://api.github.com/repos/veeso/brol/releases"),
+            Some("secret")
+        );
+        assert!(client
+            .auth_token("https://github.com/veeso/brol/releases/download/v1.0.0/app")
+            .is_none());
+        let (url, requests) = mock_server(vec![(200, Vec::new(), b"[]".to_vec())]);
+        let mut client: ReleaseClient = ReleaseClient::new().base_url(url.as_str());
+        client.env_token = Some(String::from("secret"));
+        assert!(client.releases("veeso", "brol").unwrap().is_empty());
+        assert!(!requests
+            .recv()
+            .unwrap()
+            .to_ascii_lowercase()
+            .contains("authorization"));
+        // Tokens are not sent to asset hosts
+        let (url, requests) = mock_server(vec![(200, Vec::new(), b"[]".to_vec())]);
+        let (asset_url, asset_requests) = mock_server(vec![(200, Vec::new(), b"app".to_vec())]);
+        let client: ReleaseClient = ReleaseClient::new()
+            .base_url(url.as_str())
+            .token(Some("secret"));
+        assert!(client.releases("veeso", "brol").is_ok());
+        assert!(requests
+            .recv()
+            .unwrap()
+            .contains("Authorization: token secret"));
+        let mut data: Vec<u8> = Vec::new();
+        assert_eq!(
+            client
+                .download(
+                    format!("{}/app.tar.gz", asset_url).as_str(),
+                    &mut data,
+                    |_, _| {}
+                )
+                .unwrap(),
+            3
+        );
+        assert!(!asset_requests
+            .recv()
+            .unwrap()
+            .to_ascii_lowercase()
+            .contains("authorization"));
+    }
+}
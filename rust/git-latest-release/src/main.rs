@@ -15,44 +15,100 @@
  *
  *   0. You just DO WHAT THE FUCK YOU WANT TO.
 */
-extern crate ureq;
+extern crate git_latest_release;
 
-use serde::Deserialize;
-
-#[derive(Deserialize)]
-struct TagInfo {
-    tag_name: String,
-}
+use git_latest_release::client::{self, Release, ReleaseClient, ReleaseFilter};
 
 use std::env;
 use std::process::exit;
 
+fn usage(program: &str) {
+    eprintln!("Usage: {} [options] <author> <reponame>", program);
+    eprintln!();
+    eprintln!("    --constraint <req>    Pick the highest release matching `req` (e.g. `^1.2`)");
+    eprintln!("    --prerelease          Consider prereleases too");
+    eprintln!(
+        "    --base-url <url>      API base URL (default: {})",
+        client::GITHUB_API_URL
+    );
+    eprintln!(
+        "    --token <token>       Authenticate requests with `token` (default: $GITHUB_TOKEN for api.github.com)"
+    );
+    eprintln!("    --assets              Print release assets and their download URL");
+}
+
 fn main() {
     // Get arguments
     let args: Vec<String> = env::args().collect();
+    let program: &str = args
+        .first()
+        .map(|x| x.as_str())
+        .unwrap_or("git-latest-release");
+    let mut positionals: Vec<&str> = Vec::new();
+    let mut constraint: Option<&str> = None;
+    let mut prerelease: bool = false;
+    let mut base_url: Option<&str> = None;
+    let mut token: Option<&str> = None;
+    let mut show_assets: bool = false;
+    let mut iter = args.iter().skip(1);
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--constraint" => constraint = iter.next().map(|x| x.as_str()),
+            "--prerelease" => prerelease = true,
+            "--base-url" => base_url = iter.next().map(|x| x.as_str()),
+            "--token" => token = iter.next().map(|x| x.as_str()),
+            "--assets" => show_assets = true,
+            "-h" | "--help" => {
+                usage(program);
+                exit(255);
+            }
+            arg => positionals.push(arg),
+        }
+    }
     // Check args len
-    if args.len() < 3 {
-        eprintln!("Usage: {} <author> <reponame>", args.get(0).unwrap());
+    if positionals.len() < 2 {
+        usage(program);
         exit(255);
     }
-
-    let author: String = args.get(1).unwrap().to_string();
-    let repository: String = args.get(2).unwrap().to_string();
-    // make url
-    let url: String = format!(
-        "https://api.github.com/repos/{}/{}/releases/latest",
-        author, repository
-    );
-    println!("Sending request to \"{}\"", url);
-    // Send request
-    let body = match ureq::get(url.as_str()).call() {
-        Ok(response) => response,
-        Err(err) => panic!("Request failed API: {}", err),
+    let author: &str = positionals[0];
+    let repository: &str = positionals[1];
+    let mut filter: ReleaseFilter = match ReleaseFilter::new(constraint) {
+        Ok(f) => f,
+        Err(err) => {
+            eprintln!("{}", err);
+            exit(255);
+        }
     };
-    let body: TagInfo = match body.into_json() {
-        Ok(b) => b,
-        Err(err) => panic!("Could not parse response: {}", err),
+    filter.prerelease = prerelease;
+    let mut client: ReleaseClient = ReleaseClient::new();
+    if let Some(base_url) = base_url {
+        client = client.base_url(base_url);
+    }
+    if token.is_some() {
+        client = client.token(token);
+    }
+    // Send request
+    let release: Release = match client.latest(author, repository, &filter) {
+        Ok(release) => release,
+        Err(err) => {
+            eprintln!("Could not get latest release: {}", err);
+            exit(1);
+        }
     };
     // get latest tag
-    println!("Latest {} version: {}", repository, body.tag_name);
+    println!("Latest {} version: {}", repository, release.tag_name);
+    if show_assets {
+        for asset in release.assets.iter() {
+            println!(
+                "{}\t{}\t{}",
+                asset.name, asset.size, asset.browser_download_url
+            );
+        }
+    }
+    if let Some(limit) = client.rate_limit() {
+        eprintln!(
+            "Rate limit: {}/{} requests remaining",
+            limit.remaining, limit.limit
+        );
+    }
 }
@@ -0,0 +1,732 @@
+/**
+ *
+ *
+ *           DO WHAT THE FUCK YOU WANT TO PUBLIC LICENSE
+ *                   Version 2, December 2004
+ *
+ *  Copyright (C) 2021 Christian Visintin
+ *
+ *  Everyone is permitted to copy and distribute verbatim or modified
+ *  copies of this license document, and changing it is allowed as long
+ *  as the name is changed.
+ *
+ *             DO WHAT THE FUCK YOU WANT TO PUBLIC LICENSE
+ *    TERMS AND CONDITIONS FOR COPYING, DISTRIBUTION AND MODIFICATION
+ *
+ *   0. You just DO WHAT THE FUCK YOU WANT TO.
+*/
+use super::client::{Asset, Release, ReleaseClient, ReleaseError, ReleaseFilter};
+
+use semver::Version;
+use sha2::{Digest, Sha256};
+use std::env;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+
+/// Target triple this crate was built for
+pub const TARGET: &str = env!("TARGET");
+/// Names of release assets listing the checksums of all the other assets
+const CHECKSUM_LISTS: [&str; 5] = [
+    "sha256sums",
+    "sha256sums.txt",
+    "checksums.txt",
+    "checksums.sha256",
+    "checksums.sha256.txt",
+];
+/// Extensions of release assets carrying the checksum of a single asset
+const CHECKSUM_EXTENSIONS: [&str; 2] = [".sha256", ".sha256sum"];
+/// Extensions of release assets which are archives and can't replace the executable as they are
+const ARCHIVE_EXTENSIONS: [&str; 12] = [
+    ".tar", ".tar.gz", ".tgz", ".tar.xz", ".txz", ".tar.bz2", ".tbz2", ".tar.zst", ".zip", ".7z",
+    ".deb", ".rpm",
+];
+
+/// ## Update
+///
+/// An available update
+#[derive(Debug, Clone)]
+pub struct Update {
+    pub current: Version,
+    pub latest: Version,
+    pub release: Release,
+    /// Asset built for the update target
+    pub asset: Asset,
+}
+
+/// ## UpdateError
+///
+/// Self-update error
+#[derive(Debug)]
+pub enum UpdateError {
+    Release(ReleaseError),
+    InvalidVersion(String),
+    /// Latest release has no asset for the provided target
+    NoAsset(String),
+    /// Latest release publishes no checksum for the provided asset
+    NoChecksum(String),
+    ChecksumMismatch {
+        expected: String,
+        actual: String,
+    },
+    Io(io::Error),
+}
+
+impl fmt::Display for UpdateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Release(err) => write!(f, "{}", err),
+            Self::InvalidVersion(err) => write!(f, "Invalid version: {}", err),
+            Self::NoAsset(target) => write!(f, "No release asset found for {}", target),
+            Self::NoChecksum(asset) => write!(f, "No SHA-256 checksum published for {}", asset),
+            Self::ChecksumMismatch { expected, actual } => write!(
+                f,
+                "Checksum mismatch: expected {}, got {}",
+                expected, actual
+            ),
+            Self::Io(err) => write!(f, "IO error: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for UpdateError {}
+
+impl From<ReleaseError> for UpdateError {
+    fn from(err: ReleaseError) -> Self {
+        Self::Release(err)
+    }
+}
+
+impl From<io::Error> for UpdateError {
+    fn from(err: io::Error) -> Self {
+        Self::Io(err)
+    }
+}
+
+/// ## Updater
+///
+/// Updates a binary to the latest release of `owner/repository`.
+/// Release assets are expected to be bare executables, named after the target they're built for
+/// (e.g. `app-x86_64-unknown-linux-gnu` or `app-linux-amd64`), along with their SHA-256 checksum,
+/// published either as `<asset>.sha256` or in a `SHA256SUMS` / `checksums.txt` list.
+pub struct Updater {
+    owner: String,
+    repository: String,
+    client: ReleaseClient,
+    filter: ReleaseFilter,
+    target: String,
+}
+
+impl Updater {
+    /// ### new
+    ///
+    /// Instantiate an updater for stable releases of `owner/repository`, built for the current target
+    pub fn new(owner: &str, repository: &str) -> Self {
+        Self {
+            owner: owner.to_string(),
+            repository: repository.to_string(),
+            client: ReleaseClient::new(),
+            filter: ReleaseFilter::default(),
+            target: TARGET.to_string(),
+        }
+    }
+
+    /// ### client
+    ///
+    /// Set client used to query releases and download assets
+    pub fn client(mut self, client: ReleaseClient) -> Self {
+        self.client = client;
+        self
+    }
+
+    /// ### filter
+    ///
+    /// Set filter describing which releases are eligible
+    pub fn filter(mut self, filter: ReleaseFilter) -> Self {
+        self.filter = filter;
+        self
+    }
+
+    /// ### target
+    ///
+    /// Set target triple to pick the asset for
+    pub fn target(mut self, target: &str) -> Self {
+        self.target = target.to_string();
+        self
+    }
+
+    /// ### check_update
+    ///
+    /// Check whether a release newer than `current_version` is available.
+    /// Returns `None` if already up to date
+    pub fn check_update(&self, current_version: &str) -> Result<Option<Update>, UpdateError> {
+        let current: Version = Version::parse(current_version.trim_start_matches('v'))
+            .map_err(|e| UpdateError::InvalidVersion(e.to_string()))?;
+        let release: Release =
+            match self
+                .client
+                .latest(self.owner.as_str(), self.repository.as_str(), &self.filter)
+            {
+                Ok(release) => release,
+                Err(ReleaseError::NotFound) => return Ok(None),
+                Err(err) => return Err(err.into()),
+            };
+        let latest: Version = release
+            .version()
+            .ok_or_else(|| UpdateError::InvalidVersion(release.tag_name.clone()))?;
+        if latest <= current {
+            return Ok(None);
+        }
+        let asset: Asset = select_asset(release.assets.as_slice(), self.target.as_str())
+            .cloned()
+            .ok_or_else(|| UpdateError::NoAsset(self.target.clone()))?;
+        Ok(Some(Update {
+            current,
+            latest,
+            release,
+            asset,
+        }))
+    }
+
+    /// ### install
+    ///
+    /// Download `update` and replace the running executable with it.
+    /// Returns the path of the replaced executable
+    pub fn install<F>(&self, update: &Update, progress: F) -> Result<PathBuf, UpdateError>
+    where
+        F: FnMut(u64, Option<u64>),
+    {
+        let path: PathBuf = env::current_exe()?;
+        self.install_to(update, path.as_path(), progress)?;
+        Ok(path)
+    }
+
+    /// ### install_to
+    ///
+    /// Download `update` and atomically replace `path` with it.
+    /// The download is written next to `path` and moved in place only once its checksum has been verified;
+    /// `progress` is called with the amount of bytes downloaded so far and the total size, if known
+    pub fn install_to<F>(
+        &self,
+        update: &Update,
+        path: &Path,
+        progress: F,
+    ) -> Result<(), UpdateError>
+    where
+        F: FnMut(u64, Option<u64>),
+    {
+        let expected: String = self.checksum(&update.release, &update.asset)?;
+        let tmp: PathBuf = Self::temp_path(path);
+        let result: Result<(), UpdateError> = self
+            .download_verified(
+                &update.asset,
+                tmp.as_path(),
+                path,
+                expected.as_str(),
+                progress,
+            )
+            .and_then(|_| Self::replace(tmp.as_path(), path));
+        if result.is_err() {
+            let _ = fs::remove_file(tmp.as_path());
+        }
+        result
+    }
+
+    /// ### checksum
+    ///
+    /// Get the published SHA-256 checksum of `asset`, as lowercase hex
+    fn checksum(&self, release: &Release, asset: &Asset) -> Result<String, UpdateError> {
+        // Look for `<asset>.sha256` first
+        if let Some(file) = release.assets.iter().find(|x| {
+            CHECKSUM_EXTENSIONS
+                .iter()
+                .any(|ext| x.name == format!("{}{}", asset.name, ext))
+        }) {
+            if let Some(checksum) = self
+                .fetch_text(file)?
+                .split_whitespace()
+                .next()
+                .and_then(Self::parse_checksum)
+            {
+                return Ok(checksum);
+            }
+        }
+        // Then in checksum lists
+        for file in release
+            .assets
+            .iter()
+            .filter(|x| CHECKSUM_LISTS.contains(&x.name.to_lowercase().as_str()))
+        {
+            let text: String = self.fetch_text(file)?;
+            let checksum: Option<String> = text.lines().find_map(|line| {
+                let mut tokens = line.split_whitespace();
+                let checksum: &str = tokens.next()?;
+                match tokens.next().map(|x| x.trim_start_matches('*')) == Some(asset.name.as_str())
+                {
+                    true => Self::parse_checksum(checksum),
+                    false => None,
+                }
+            });
+            if let Some(checksum) = checksum {
+                return Ok(checksum);
+            }
+        }
+        Err(UpdateError::NoChecksum(asset.name.clone()))
+    }
+
+    /// ### parse_checksum
+    ///
+    /// Returns `checksum` lowercase if it's a valid SHA-256 hex digest
+    fn parse_checksum(checksum: &str) -> Option<String> {
+        match checksum.len() == 64 && checksum.chars().all(|x| x.is_ascii_hexdigit()) {
+            true => Some(checksum.to_lowercase()),
+            false => None,
+        }
+    }
+
+    /// ### fetch_text
+    ///
+    /// Download a textual asset
+    fn fetch_text(&self, asset: &Asset) -> Result<String, UpdateError> {
+        let mut buffer: Vec<u8> = Vec::new();
+        self.client
+            .download(asset.browser_download_url.as_str(), &mut buffer, |_, _| {})?;
+        String::from_utf8(buffer).map_err(|e| ReleaseError::Parse(e.to_string()).into())
+    }
+
+    /// ### download_verified
+    ///
+    /// Download `asset` to `tmp`, verifying its checksum against `expected`.
+    /// Permissions are copied from `path` if it exists
+    fn download_verified<F>(
+        &self,
+        asset: &Asset,
+        tmp: &Path,
+        path: &Path,
+        expected: &str,
+        progress: F,
+    ) -> Result<(), UpdateError>
+    where
+        F: FnMut(u64, Option<u64>),
+    {
+        let mut writer: HashWriter<File> = HashWriter::new(File::create(tmp)?);
+        self.client
+            .download(asset.browser_download_url.as_str(), &mut writer, progress)?;
+        let (file, actual) = writer.finalize();
+        if actual != expected {
+            return Err(UpdateError::ChecksumMismatch {
+                expected: expected.to_string(),
+                actual,
+            });
+        }
+        file.sync_all()?;
+        Self::set_permissions(&file, path)
+    }
+
+    #[cfg(unix)]
+    fn set_permissions(file: &File, path: &Path) -> Result<(), UpdateError> {
+        use std::os::unix::fs::PermissionsExt;
+        let permissions: fs::Permissions = fs::metadata(path)
+            .map(|x| x.permissions())
+            .unwrap_or_else(|_| fs::Permissions::from_mode(0o755));
+        file.set_permissions(permissions).map_err(UpdateError::from)
+    }
+
+    #[cfg(not(unix))]
+    fn set_permissions(_file: &File, _path: &Path) -> Result<(), UpdateError> {
+        Ok(())
+    }
+
+    /// ### replace
+    ///
+    /// Move `tmp` over `path`
+    #[cfg(not(windows))]
+    fn replace(tmp: &Path, path: &Path) -> Result<(), UpdateError> {
+        fs::rename(tmp, path).map_err(UpdateError::from)
+    }
+
+    /// ### replace
+    ///
+    /// Move `tmp` over `path`.
+    /// A running executable can't be overwritten on Windows, but it can be renamed: move it aside first
+    #[cfg(windows)]
+    fn replace(tmp: &Path, path: &Path) -> Result<(), UpdateError> {
+        let old: PathBuf = path.with_extension("old");
+        let _ = fs::remove_file(old.as_path());
+        if path.exists() {
+            fs::rename(path, old.as_path())?;
+        }
+        if let Err(err) = fs::rename(tmp, path) {
+            let _ = fs::rename(old.as_path(), path);
+            return Err(err.into());
+        }
+        Ok(())
+    }
+
+    /// ### temp_path
+    ///
+    /// Get path the update is downloaded to, in the same directory of `path` so that it can be renamed
+    fn temp_path(path: &Path) -> PathBuf {
+        let name: String = path
+            .file_name()
+            .map(|x| x.to_string_lossy().to_string())
+            .unwrap_or_default();
+        path.with_file_name(format!(".{}.{}.update", name, process::id()))
+    }
+}
+
+/// ### select_asset
+///
+/// Select the asset built for `target` among `assets`.
+/// Assets containing the full target triple are preferred; otherwise an asset naming both
+/// the architecture and the operating system (or a common alias, e.g. `amd64`, `macos`) is picked.
+/// Checksums and archives are never selected, since they can't replace the executable
+pub fn select_asset<'a>(assets: &'a [Asset], target: &str) -> Option<&'a Asset> {
+    let candidates: Vec<&Asset> = assets
+        .iter()
+        .filter(|x| !is_checksum(x) && !is_archive(x))
+        .collect();
+    if let Some(asset) = candidates.iter().find(|x| x.name.contains(target)) {
+        return Some(asset);
+    }
+    let arch: Vec<&str> = arch_aliases(target.split('-').next()?);
+    let os: Vec<&str> = os_aliases(target);
+    candidates.into_iter().find(|asset| {
+        let name: String = asset.name.to_lowercase();
+        let words: Vec<&str> = name.split(['-', '_', '.', ' ']).collect();
+        // Aliases containing an underscore (`x86_64`) would be split, so look them up in the whole name
+        let has = |alias: &&str| match alias.contains('_') {
+            true => name.contains(alias),
+            false => words.contains(alias),
+        };
+        arch.iter().any(has) && os.iter().any(has)
+    })
+}
+
+/// ### is_checksum
+///
+/// Returns whether `asset` is a checksum or a signature
+fn is_checksum(asset: &Asset) -> bool {
+    let name: String = asset.name.to_lowercase();
+    CHECKSUM_LISTS.contains(&name.as_str())
+        || CHECKSUM_EXTENSIONS
+            .iter()
+            .chain([".sig", ".asc"].iter())
+            .any(|ext| name.ends_with(ext))
+}
+
+/// ### is_archive
+///
+/// Returns whether `asset` is an archive or a package
+fn is_archive(asset: &Asset) -> bool {
+    let name: String = asset.name.to_lowercase();
+    ARCHIVE_EXTENSIONS.iter().any(|ext| name.ends_with(ext))
+}
+
+/// ### arch_aliases
+///
+/// Get names architecture `arch` is usually published as
+fn arch_aliases(arch: &str) -> Vec<&str> {
+    match arch {
+        "x86_64" => vec!["x86_64", "amd64", "x64"],
+        "aarch64" => vec!["aarch64", "arm64"],
+        "i386" | "i586" | "i686" => vec![arch, "i386", "i686", "x86", "386"],
+        arch if arch.starts_with("armv7") => vec![arch, "armv7", "armhf"],
+        arch => vec![arch],
+    }
+}
+
+/// ### os_aliases
+///
+/// Get names the operating system of `target` is usually published as
+fn os_aliases(target: &str) -> Vec<&str> {
+    let components: Vec<&str> = target.split('-').collect();
+    if components.contains(&"linux") {
+        vec!["linux"]
+    } else if components.contains(&"darwin") || components.contains(&"apple") {
+        vec!["darwin", "macos", "apple", "osx", "mac"]
+    } else if components.contains(&"windows") {
+        vec!["windows", "win64", "win32", "win"]
+    } else {
+        components
+            .into_iter()
+            .skip(1)
+            .filter(|x| *x != "unknown")
+            .collect()
+    }
+}
+
+/// ## HashWriter
+///
+/// Writer computing the SHA-256 digest of the data written through it
+struct HashWriter<W: Write> {
+    inner: W,
+    hasher: Sha256,
+}
+
+impl<W: Write> HashWriter<W> {
+    fn new(inner: W) -> Self {
+        Self {
+            inner,
+            hasher: Sha256::new(),
+        }
+    }
+
+    /// ### finalize
+    ///
+    /// Returns the inner writer and the digest of the written data, as lowercase hex
+    fn finalize(self) -> (W, String) {
+        (self.inner, format!("{:x}", self.hasher.finalize()))
+    }
+}
+
+impl<W: Write> Write for HashWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let bytes: usize = self.inner.write(buf)?;
+        self.hasher.update(&buf[..bytes]);
+        Ok(bytes)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::client::tests::{mock_server, MockResponse};
+
+    const LINUX: &str = "x86_64-unknown-linux-gnu";
+    const BINARY: &[u8] = b"#!/bin/sh\necho 1.2.0\n";
+
+    fn asset(name: &str) -> Asset {
+        Asset {
+            name: name.to_string(),
+            size: 0,
+            content_type: None,
+            browser_download_url: format!("{{url}}/download/{}", name),
+        }
+    }
+
+    fn assets(names: &[&str]) -> Vec<Asset> {
+        names.iter().map(|x| asset(x)).collect()
+    }
+
+    fn sha256(data: &[u8]) -> String {
+        let mut hasher: Sha256 = Sha256::new();
+        hasher.update(data);
+        format!("{:x}", hasher.finalize())
+    }
+
+    fn releases(assets: &[&str]) -> MockResponse {
+        let assets: Vec<String> = self::assets(assets)
+            .iter()
+            .map(|x| {
+                format!(
+                    r#"{{"name":"{}","browser_download_url":"{}"}}"#,
+                    x.name, x.browser_download_url
+                )
+            })
+            .collect();
+        (
+            200,
+            Vec::new(),
+            format!(
+                r#"[{{"tag_name":"v1.2.0","assets":[{}]}},{{"tag_name":"v1.1.0","assets":[]}}]"#,
+                assets.join(",")
+            )
+            .into_bytes(),
+        )
+    }
+
+    fn temp_file(name: &str) -> PathBuf {
+        let path: PathBuf = env::temp_dir().join(format!("glr-{}-{}", process::id(), name));
+        fs::write(path.as_path(), b"old").unwrap();
+        path
+    }
+
+    #[test]
+    fn should_select_asset_for_target() {
+        let published: Vec<Asset> = assets(&[
+            "app-x86_64-unknown-linux-gnu.sha256",
+            "app-x86_64-apple-darwin",
+            "app-x86_64-unknown-linux-musl",
+            "app-x86_64-unknown-linux-gnu",
+        ]);
+        assert_eq!(
+            select_asset(published.as_slice(), LINUX).unwrap().name,
+            "app-x86_64-unknown-linux-gnu"
+        );
+        let published: Vec<Asset> = assets(&[
+            "SHA256SUMS",
+            "app_darwin_amd64",
+            "app_linux_arm64",
+            "app_linux_amd64",
+            "app_windows_amd64.exe",
+        ]);
+        assert_eq!(
+            select_asset(published.as_slice(), LINUX).unwrap().name,
+            "app_linux_amd64"
+        );
+        assert_eq!(
+            select_asset(published.as_slice(), "aarch64-unknown-linux-gnu")
+                .unwrap()
+                .name,
+            "app_linux_arm64"
+        );
+        assert_eq!(
+            select_asset(published.as_slice(), "x86_64-pc-windows-msvc")
+                .unwrap()
+                .name,
+            "app_windows_amd64.exe"
+        );
+        assert_eq!(
+            select_asset(published.as_slice(), "x86_64-apple-darwin")
+                .unwrap()
+                .name,
+            "app_darwin_amd64"
+        );
+        // `win` must not match `darwin`
+        let published: Vec<Asset> = assets(&["app-darwin-x64"]);
+        assert!(select_asset(published.as_slice(), "x86_64-pc-windows-msvc").is_none());
+        assert!(select_asset(published.as_slice(), "i686-unknown-linux-gnu").is_none());
+    }
+
+    #[test]
+    fn should_not_select_archive_assets() {
+        let published: Vec<Asset> = assets(&[
+            "app-x86_64-unknown-linux-gnu.tar.gz",
+            "app-x86_64-unknown-linux-gnu.zip",
+            "app_linux_amd64.deb",
+            "app-x86_64-unknown-linux-gnu",
+        ]);
+        assert_eq!(
+            select_asset(published.as_slice(), LINUX).unwrap().name,
+            "app-x86_64-unknown-linux-gnu"
+        );
+        let published: Vec<Asset> = assets(&["app_linux_amd64.tgz", "app_linux_amd64.TAR.XZ"]);
+        assert!(select_asset(published.as_slice(), LINUX).is_none());
+    }
+
+    #[test]
+    fn should_check_update() {
+        let (url, _) = mock_server(vec![
+            releases(&["app-x86_64-unknown-linux-gnu"]),
+            releases(&["app-x86_64-unknown-linux-gnu"]),
+            releases(&["app-aarch64-unknown-linux-gnu"]),
+        ]);
+        let updater: Updater = Updater::new("veeso", "brol")
+            .client(ReleaseClient::new().base_url(url.as_str()).token(None))
+            .target(LINUX);
+        let update: Update = updater.check_update("v1.1.0").unwrap().unwrap();
+        assert_eq!(update.current, Version::new(1, 1, 0));
+        assert_eq!(update.latest, Version::new(1, 2, 0));
+        assert_eq!(update.release.tag_name, "v1.2.0");
+        assert_eq!(update.asset.name, "app-x86_64-unknown-linux-gnu");
+        assert_eq!(
+            update.asset.browser_download_url,
+            format!("{}/download/app-x86_64-unknown-linux-gnu", url)
+        );
+        assert!(updater.check_update("1.2.0").unwrap().is_none());
+        assert!(matches!(
+            updater.check_update("1.1.0"),
+            Err(UpdateError::NoAsset(_))
+        ));
+        assert!(matches!(
+            updater.check_update("latest"),
+            Err(UpdateError::InvalidVersion(_))
+        ));
+    }
+
+    #[test]
+    fn should_install_update() {
+        let name: &str = "app-x86_64-unknown-linux-gnu";
+        let (url, requests) = mock_server(vec![
+            releases(&[name, "SHA256SUMS"]),
+            (
+                200,
+                Vec::new(),
+                format!(
+                    "{}  app-x86_64-apple-darwin\n{} *{}\n",
+                    sha256(b"darwin"),
+                    sha256(BINARY).to_uppercase(),
+                    name
+                )
+                .into_bytes(),
+            ),
+            (200, Vec::new(), BINARY.to_vec()),
+        ]);
+        let updater: Updater = Updater::new("veeso", "brol")
+            .client(ReleaseClient::new().base_url(url.as_str()).token(None))
+            .target(LINUX);
+        let update: Update = updater.check_update("1.1.0").unwrap().unwrap();
+        let path: PathBuf = temp_file("install");
+        let mut progress: Vec<(u64, Option<u64>)> = Vec::new();
+        updater
+            .install_to(&update, path.as_path(), |done, total| {
+                progress.push((done, total))
+            })
+            .unwrap();
+        assert_eq!(fs::read(path.as_path()).unwrap(), BINARY);
+        assert_eq!(progress.first(), Some(&(0, Some(BINARY.len() as u64))));
+        assert_eq!(
+            progress.last(),
+            Some(&(BINARY.len() as u64, Some(BINARY.len() as u64)))
+        );
+        assert!(!Updater::temp_path(path.as_path()).exists());
+        assert!(requests
+            .recv()
+            .unwrap()
+            .starts_with("GET /repos/veeso/brol/releases"));
+        assert!(requests
+            .recv()
+            .unwrap()
+            .starts_with("GET /download/SHA256SUMS "));
+        assert!(requests
+            .recv()
+            .unwrap()
+            .starts_with(format!("GET /download/{} ", name).as_str()));
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn should_reject_checksum_mismatch() {
+        let name: &str = "app-x86_64-unknown-linux-gnu";
+        let (url, _) = mock_server(vec![
+            releases(&[name, "app-x86_64-unknown-linux-gnu.sha256"]),
+            (
+                200,
+                Vec::new(),
+                format!("{}  {}\n", sha256(b"something else"), name).into_bytes(),
+            ),
+            (200, Vec::new(), BINARY.to_vec()),
+            releases(&[name]),
+        ]);
+        let updater: Updater = Updater::new("veeso", "brol")
+            .client(ReleaseClient::new().base_url(url.as_str()).token(None))
+            .target(LINUX);
+        let update: Update = updater.check_update("1.1.0").unwrap().unwrap();
+        let path: PathBuf = temp_file("mismatch");
+        match updater.install_to(&update, path.as_path(), |_, _| {}) {
+            Err(UpdateError::ChecksumMismatch { expected, actual }) => {
+                assert_eq!(expected, sha256(b"something else"));
+                assert_eq!(actual, sha256(BINARY));
+            }
+            other => panic!("Expected checksum mismatch, got {:?}", other),
+        }
+        // Binary is left untouched
+        assert_eq!(fs::read(path.as_path()).unwrap(), b"old");
+        assert!(!Updater::temp_path(path.as_path()).exists());
+        // No checksum published
+        let update: Update = updater.check_update("1.1.0").unwrap().unwrap();
+        assert!(matches!(
+            updater.install_to(&update, path.as_path(), |_, _| {}),
+            Err(UpdateError::NoChecksum(_))
+        ));
+        fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,515 @@
+//! ## Changelog
+//!
+//! `Changelog` generates a changelog from the Conventional Commits made since the latest tag
+
+/*
+*
+*   Copyright (C) 2020 Christian Visintin - christian.visintin1997@gmail.com
+*
+* 	This file is part of "Pyc"
+*
+*   Pyc is free software: you can redistribute it and/or modify
+*   it under the terms of the GNU General Public License as published by
+*   the Free Software Foundation, either version 3 of the License, or
+*   (at your option) any later version.
+*
+*   Pyc is distributed in the hope that it will be useful,
+*   but WITHOUT ANY WARRANTY; without even the implied warranty of
+*   MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+*   GNU General Public License for more details.
+*
+*   You should have received a copy of the GNU General Public License
+*   along with Pyc.  If not, see <http://www.gnu.org/licenses/>.
+*
+*/
+
+extern crate git2;
+
+use super::git::{CommitInfo, Git};
+
+use git2::Repository;
+use std::fmt;
+
+/// Length of the commit hashes shown in the changelog
+const HASH_LEN: usize = 7;
+
+/// ## ConventionalCommit
+///
+/// A commit whose message follows the Conventional Commits specification
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ConventionalCommit {
+    pub hash: String,
+    /// Commit type (e.g. `feat`, `fix`), lowercase
+    pub kind: String,
+    pub scope: Option<String>,
+    pub description: String,
+    /// Whether the commit introduces a breaking change (`!` in header or `BREAKING CHANGE` footer)
+    pub breaking: bool,
+    /// Description of the breaking change from the `BREAKING CHANGE` footer
+    pub breaking_note: Option<String>,
+}
+
+impl ConventionalCommit {
+    /// ### parse
+    ///
+    /// Parse a commit message. Returns `None` if the header isn't in the form `type(scope)!: description`
+    pub fn parse(hash: &str, message: &str) -> Option<ConventionalCommit> {
+        let mut lines = message.lines();
+        let header: &str = lines.next()?.trim();
+        let (prefix, description) = header.split_once(':')?;
+        let description: &str = description.trim();
+        let (prefix, bang): (&str, bool) = match prefix.strip_suffix('!') {
+            Some(prefix) => (prefix, true),
+            None => (prefix, false),
+        };
+        let (kind, scope): (&str, Option<&str>) = match prefix.split_once('(') {
+            Some((kind, scope)) => (kind, Some(scope.strip_suffix(')')?)),
+            None => (prefix, None),
+        };
+        if kind.is_empty()
+            || description.is_empty()
+            || !kind.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+            || scope.map(|x| x.is_empty()).unwrap_or(false)
+        {
+            return None;
+        }
+        // Look for breaking change footer
+        let breaking_note: Option<String> = lines
+            .skip_while(|line| {
+                !line.starts_with("BREAKING CHANGE:") && !line.starts_with("BREAKING-CHANGE:")
+            })
+            .enumerate()
+            .map(|(i, line)| match i {
+                0 => line[line.find(':').unwrap() + 1..].trim(),
+                _ => line.trim(),
+            })
+            .take_while(|line| !line.is_empty())
+            .fold(None, |note: Option<String>, line| match note {
+                None => Some(line.to_string()),
+                Some(note) => Some(format!("{} {}", note, line)),
+            });
+        Some(ConventionalCommit {
+            hash: hash.to_string(),
+            kind: kind.to_ascii_lowercase(),
+            scope: scope.map(|x| x.to_string()),
+            description: description.to_string(),
+            breaking: bang || breaking_note.is_some(),
+            breaking_note,
+        })
+    }
+}
+
+/// ## Bump
+///
+/// Semantic version increment
+#[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd)]
+pub enum Bump {
+    None,
+    Patch,
+    Minor,
+    Major,
+}
+
+impl Bump {
+    /// ### apply
+    ///
+    /// Apply bump to `version` (e.g. `v1.2.3` or `1.2.3`), keeping its `v` prefix.
+    /// Breaking changes bump the minor version while major version is 0.
+    /// Returns `None` if `version` is not a semantic version
+    pub fn apply(&self, version: &str) -> Option<String> {
+        let (prefix, version): (&str, &str) = match version.strip_prefix('v') {
+            Some(version) => ("v", version),
+            None => ("", version),
+        };
+        // Ignore pre-release and build metadata
+        let core: &str = version.split(['-', '+']).next()?;
+        let numbers: Vec<u64> = core
+            .split('.')
+            .map(|x| x.parse::<u64>().ok())
+            .collect::<Option<Vec<u64>>>()?;
+        let (major, minor, patch) = match numbers.as_slice() {
+            [major, minor, patch] => (*major, *minor, *patch),
+            _ => return None,
+        };
+        let (major, minor, patch) = match (self, major) {
+            (Bump::None, _) => (major, minor, patch),
+            (Bump::Major, 0) | (Bump::Minor, _) => (major, minor + 1, 0),
+            (Bump::Major, _) => (major + 1, 0, 0),
+            (Bump::Patch, _) => (major, minor, patch + 1),
+        };
+        Some(format!("{}{}.{}.{}", prefix, major, minor, patch))
+    }
+}
+
+/// ## ChangelogFormat
+///
+/// Output format of the changelog
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ChangelogFormat {
+    /// Sections by commit type, as conventional-changelog does
+    Markdown,
+    /// Sections as described by <https://keepachangelog.com>
+    KeepAChangelog,
+}
+
+/// ## Changelog
+///
+/// Conventional commits made since a tag
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Changelog {
+    /// Tag the changelog starts from; `None` if the repository has no tags
+    pub previous: Option<String>,
+    /// Conventional commits, newest first. Other commits are ignored
+    pub commits: Vec<ConventionalCommit>,
+}
+
+impl Changelog {
+    /// ### since_latest_tag
+    ///
+    /// Collect conventional commits made since the latest tag reachable from HEAD (or all of them if there are no tags)
+    pub fn since_latest_tag(repository: &Repository) -> Result<Changelog, git2::Error> {
+        let previous: Option<String> = Git::get_latest_tag(repository);
+        let range: String = match previous.as_ref() {
+            Some(tag) => format!("refs/tags/{}..HEAD", tag),
+            None => String::from("HEAD"),
+        };
+        let commits: Vec<CommitInfo> = Git::log(repository, range.as_str(), None)?;
+        Ok(Changelog::from_commits(previous, commits.as_slice()))
+    }
+
+    /// ### from_commits
+    ///
+    /// Make changelog from commits made since `previous`
+    pub fn from_commits(previous: Option<String>, commits: &[CommitInfo]) -> Changelog {
+        Changelog {
+            previous,
+            commits: commits
+                .iter()
+                .filter_map(|x| ConventionalCommit::parse(x.hash.as_str(), x.message.as_str()))
+                .collect(),
+        }
+    }
+
+    /// ### bump
+    ///
+    /// Suggest version bump: major for breaking changes, minor for features, patch for fixes
+    pub fn bump(&self) -> Bump {
+        self.commits
+            .iter()
+            .map(|x| match (x.breaking, x.kind.as_str()) {
+                (true, _) => Bump::Major,
+                (false, "feat") => Bump::Minor,
+                (false, "fix") | (false, "perf") => Bump::Patch,
+                _ => Bump::None,
+            })
+            .max()
+            .unwrap_or(Bump::None)
+    }
+
+    /// ### next_version
+    ///
+    /// Suggest next version applying `bump` to the previous tag, keeping its prefix.
+    /// If there are no tags, the first version is `prefix` followed by `0.1.0` (e.g. `v0.1.0`).
+    /// Returns `None` if there is nothing to release or the previous tag is not a semantic version
+    pub fn next_version(&self, prefix: &str) -> Option<String> {
+        if self.bump() == Bump::None {
+            return None;
+        }
+        match self.previous.as_ref() {
+            Some(tag) => self.bump().apply(tag.as_str()),
+            None => Some(format!("{}0.1.0", prefix)),
+        }
+    }
+
+    /// ### render
+    ///
+    /// Render changelog for release `version` published on `date`
+    pub fn render(&self, format: ChangelogFormat, version: &str, date: &str) -> String {
+        match format {
+            ChangelogFormat::Markdown => self.render_markdown(version, date),
+            ChangelogFormat::KeepAChangelog => self.render_keepachangelog(version, date),
+        }
+    }
+
+    fn render_markdown(&self, version: &str, date: &str) -> String {
+        let mut out: String = format!("## {} ({})\n", version, date);
+        let breaking: Vec<String> = self
+            .commits
+            .iter()
+            .filter(|x| x.breaking)
+            .map(|x| {
+                Self::entry(
+                    x,
+                    x.breaking_note.as_deref().unwrap_or(x.description.as_str()),
+                )
+            })
+            .collect();
+        Self::section(&mut out, "⚠ BREAKING CHANGES", breaking.as_slice());
+        for (title, kinds) in [
+            ("Features", &["feat"][..]),
+            ("Bug Fixes", &["fix"][..]),
+            ("Performance Improvements", &["perf"][..]),
+            ("Reverts", &["revert"][..]),
+            ("Documentation", &["docs"][..]),
+            ("Code Refactoring", &["refactor"][..]),
+        ]
+        .iter()
+        {
+            let entries: Vec<String> = self.entries(|x| kinds.contains(&x.kind.as_str()));
+            Self::section(&mut out, title, entries.as_slice());
+        }
+        out
+    }
+
+    fn render_keepachangelog(&self, version: &str, date: &str) -> String {
+        let mut out: String = format!("## [{}] - {}\n", version, date);
+        let added: Vec<String> = self.entries(|x| !x.breaking && x.kind == "feat");
+        let changed: Vec<String> = self
+            .commits
+            .iter()
+            .filter(|x| x.breaking || x.kind == "perf" || x.kind == "refactor")
+            .map(|x| match x.breaking {
+                true => Self::entry(
+                    x,
+                    format!(
+                        "**BREAKING** {}",
+                        x.breaking_note.as_deref().unwrap_or(x.description.as_str())
+                    )
+                    .as_str(),
+                ),
+                false => Self::entry(x, x.description.as_str()),
+            })
+            .collect();
+        let fixed: Vec<String> =
+            self.entries(|x| !x.breaking && (x.kind == "fix" || x.kind == "revert"));
+        Self::section(&mut out, "Added", added.as_slice());
+        Self::section(&mut out, "Changed", changed.as_slice());
+        Self::section(&mut out, "Fixed", fixed.as_slice());
+        out
+    }
+
+    /// ### entries
+    ///
+    /// Render entries of the commits matching `filter`
+    fn entries<F>(&self, filter: F) -> Vec<String>
+    where
+        F: Fn(&ConventionalCommit) -> bool,
+    {
+        self.commits
+            .iter()
+            .filter(|x| filter(x))
+            .map(|x| Self::entry(x, x.description.as_str()))
+            .collect()
+    }
+
+    /// ### entry
+    ///
+    /// Render a changelog entry for `commit` with `text`
+    fn entry(commit: &ConventionalCommit, text: &str) -> String {
+        let hash: String = commit.hash.chars().take(HASH_LEN).collect();
+        match commit.scope.as_ref() {
+            Some(scope) => format!("- **{}:** {} ({})", scope, text, hash),
+            None => format!("- {} ({})", text, hash),
+        }
+    }
+
+    /// ### section
+    ///
+    /// Append section `title` to `out`, if it has any entry
+    fn section(out: &mut String, title: &str, entries: &[String]) {
+        if entries.is_empty() {
+            return;
+        }
+        out.push_str(format!("\n### {}\n\n", title).as_str());
+        entries.iter().for_each(|x| {
+            out.push_str(x.as_str());
+            out.push('\n');
+        });
+    }
+}
+
+impl fmt::Display for Bump {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name: &str = match self {
+            Bump::None => "none",
+            Bump::Patch => "patch",
+            Bump::Minor => "minor",
+            Bump::Major => "major",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+//@! Tests
+
+#[cfg(test)]
+mod tests {
+
+    use super::super::git::tests::commit_all;
+    use super::*;
+
+    use git2::Oid;
+
+    #[test]
+    fn test_changelog_parse_conventional_commit() {
+        let commit =
+            ConventionalCommit::parse("1a2b3c4d", "feat(prompt): show git status").unwrap();
+        assert_eq!(commit.kind.as_str(), "feat");
+        assert_eq!(commit.scope.as_deref(), Some("prompt"));
+        assert_eq!(commit.description.as_str(), "show git status");
+        assert!(!commit.breaking);
+        assert!(commit.breaking_note.is_none());
+        // Breaking
+        let commit = ConventionalCommit::parse("1a2b3c4d", "Fix!: drop config v1").unwrap();
+        assert_eq!(commit.kind.as_str(), "fix");
+        assert!(commit.scope.is_none());
+        assert!(commit.breaking);
+        let commit = ConventionalCommit::parse(
+            "1a2b3c4d",
+            "refactor: rename options\n\nSome details\n\nBREAKING CHANGE: `foo` is now\n`bar`\n\nSigned-off-by: me",
+        )
+        .unwrap();
+        assert!(commit.breaking);
+        assert_eq!(commit.breaking_note.as_deref(), Some("`foo` is now `bar`"));
+        // Not conventional
+        assert!(ConventionalCommit::parse("1a2b3c4d", "Update README").is_none());
+        assert!(ConventionalCommit::parse("1a2b3c4d", "Merge branch 'a': b").is_none());
+        assert!(ConventionalCommit::parse("1a2b3c4d", "feat(: foo").is_none());
+        assert!(ConventionalCommit::parse("1a2b3c4d", "feat: ").is_none());
+        assert!(ConventionalCommit::parse("1a2b3c4d", "").is_none());
+    }
+
+    #[test]
+    fn test_changelog_bump() {
+        assert_eq!(Bump::Patch.apply("v1.2.3").as_deref(), Some("v1.2.4"));
+        assert_eq!(Bump::Minor.apply("1.2.3").as_deref(), Some("1.3.0"));
+        assert_eq!(Bump::Major.apply("v1.2.3").as_deref(), Some("v2.0.0"));
+        assert_eq!(Bump::Major.apply("v0.2.3").as_deref(), Some("v0.3.0"));
+        assert_eq!(Bump::None.apply("v0.2.3").as_deref(), Some("v0.2.3"));
+        assert_eq!(Bump::Patch.apply("v1.2.3-rc1").as_deref(), Some("v1.2.4"));
+        assert!(Bump::Patch.apply("latest").is_none());
+        assert!(Bump::Patch.apply("v1.2").is_none());
+        let changelog: Changelog = make_changelog(&["fix: a", "docs: b"]);
+        assert_eq!(changelog.bump(), Bump::Patch);
+        assert_eq!(changelog.next_version("v").as_deref(), Some("v1.0.1"));
+        assert_eq!(make_changelog(&["fix: a", "feat: b"]).bump(), Bump::Minor);
+        assert_eq!(make_changelog(&["feat!: a", "fix: b"]).bump(), Bump::Major);
+        assert_eq!(make_changelog(&["chore: a"]).bump(), Bump::None);
+        assert!(make_changelog(&["chore: a"]).next_version("v").is_none());
+        assert_eq!(Bump::Major.to_string(), "major");
+    }
+
+    #[test]
+    fn test_changelog_render() {
+        let changelog: Changelog = make_changelog(&[
+            "feat(prompt): show git status",
+            "fix: handle detached HEAD",
+            "chore: bump deps",
+            "refactor!: drop legacy config",
+        ]);
+        assert_eq!(
+            changelog.render(ChangelogFormat::Markdown, "v2.0.0", "2021-06-01"),
+            "## v2.0.0 (2021-06-01)
+
+### ⚠ BREAKING CHANGES
+
+- drop legacy config (3000000)
+
+### Features
+
+- **prompt:** show git status (0000000)
+
+### Bug Fixes
+
+- handle detached HEAD (1000000)
+
+### Code Refactoring
+
+- drop legacy config (3000000)
+"
+        );
+        assert_eq!(
+            changelog.render(ChangelogFormat::KeepAChangelog, "2.0.0", "2021-06-01"),
+            "## [2.0.0] - 2021-06-01
+
+### Added
+
+- **prompt:** show git status (0000000)
+
+### Changed
+
+- **BREAKING** drop legacy config (3000000)
+
+### Fixed
+
+- handle detached HEAD (1000000)
+"
+        );
+    }
+
+    #[test]
+    fn test_changelog_render_keepachangelog_once() {
+        let changelog: Changelog = make_changelog(&[
+            "feat!: new config format",
+            "fix(git)!: reject shallow clones",
+            "revert: show git status",
+        ]);
+        assert_eq!(
+            changelog.render(ChangelogFormat::KeepAChangelog, "2.0.0", "2021-06-01"),
+            "## [2.0.0] - 2021-06-01
+
+### Changed
+
+- **BREAKING** new config format (0000000)
+- **git:** **BREAKING** reject shallow clones (1000000)
+
+### Fixed
+
+- show git status (2000000)
+"
+        );
+    }
+
+    #[test]
+    fn test_changelog_since_latest_tag() {
+        let tmpdir: tempfile::TempDir = tempfile::TempDir::new().unwrap();
+        let repo: Repository = Repository::init(tmpdir.path()).unwrap();
+        std::fs::write(tmpdir.path().join("README.md"), "").unwrap();
+        let first: Oid = commit_all(&repo, "feat: initial commit");
+        // No tags
+        let changelog: Changelog = Changelog::since_latest_tag(&repo).unwrap();
+        assert!(changelog.previous.is_none());
+        assert_eq!(changelog.commits.len(), 1);
+        assert_eq!(changelog.next_version("").as_deref(), Some("0.1.0"));
+        assert_eq!(changelog.next_version("v").as_deref(), Some("v0.1.0"));
+        let obj = repo.find_object(first, None).unwrap();
+        repo.tag_lightweight("v0.1.0", &obj, false).unwrap();
+        std::fs::write(tmpdir.path().join("LICENSE"), "").unwrap();
+        commit_all(&repo, "fix: add license");
+        std::fs::write(tmpdir.path().join("NOTES"), "").unwrap();
+        commit_all(&repo, "Update notes");
+        let changelog: Changelog = Changelog::since_latest_tag(&repo).unwrap();
+        assert_eq!(changelog.previous.as_deref(), Some("v0.1.0"));
+        assert_eq!(changelog.commits.len(), 1);
+        assert_eq!(changelog.commits[0].description.as_str(), "add license");
+        assert_eq!(changelog.next_version("").as_deref(), Some("v0.1.1"));
+    }
+
+    fn make_changelog(messages: &[&str]) -> Changelog {
+        let commits: Vec<CommitInfo> = messages
+            .iter()
+            .enumerate()
+            .map(|(i, message)| CommitInfo {
+                hash: format!("{:0<40}", i),
+                author: String::from("test"),
+                email: String::from("test@example.com"),
+                date: 0,
+                date_offset: 0,
+                summary: message.lines().next().unwrap().to_string(),
+                message: message.to_string(),
+                parents: Vec::new(),
+            })
+            .collect();
+        Changelog::from_commits(Some(String::from("v1.0.0")), commits.as_slice())
+    }
+}
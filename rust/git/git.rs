@@ -26,11 +26,158 @@
 
 extern crate git2;
 
-use git2::Repository;
+use git2::{
+    BranchType, Commit, DescribeFormatOptions, DescribeOptions, Diff, Oid, Patch, Repository,
+    RepositoryState, Sort, Status, StatusOptions, Tree,
+};
+use std::collections::HashMap;
+use std::fs;
 use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, TryRecvError};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+/// Default template used to render the prompt segment
+pub const DEFAULT_PROMPT_TEMPLATE: &str = "{branch}{dirty}{ahead}{behind}{operation}{stale}";
 
 pub struct Git {}
 
+/// ## CommitInfo
+///
+/// Describes a commit
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct CommitInfo {
+    pub hash: String,
+    pub author: String,
+    pub email: String,
+    /// Commit time as seconds since the unix epoch
+    pub date: i64,
+    /// Timezone offset of `date` in minutes
+    pub date_offset: i32,
+    /// First line of the commit message
+    pub summary: String,
+    /// Full commit message
+    pub message: String,
+    /// Hashes of the parent commits
+    pub parents: Vec<String>,
+}
+
+impl CommitInfo {
+    fn new(commit: &Commit) -> CommitInfo {
+        let author = commit.author();
+        CommitInfo {
+            hash: commit.id().to_string(),
+            author: author.name().unwrap_or_default().to_string(),
+            email: author.email().unwrap_or_default().to_string(),
+            date: commit.time().seconds(),
+            date_offset: commit.time().offset_minutes(),
+            summary: commit.summary().unwrap_or_default().to_string(),
+            message: commit.message().unwrap_or_default().to_string(),
+            parents: commit.parent_ids().map(|x| x.to_string()).collect(),
+        }
+    }
+}
+
+/// ## FileStat
+///
+/// Lines changed in a file
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct FileStat {
+    /// Path of the file; the new one if the file has been renamed
+    pub path: PathBuf,
+    pub insertions: usize,
+    pub deletions: usize,
+}
+
+/// ## RepoStatus
+///
+/// Everything needed to describe the state of a repository in the prompt
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct RepoStatus {
+    /// Current branch; set even if the branch has no commits yet. `None` if HEAD is detached
+    pub branch: Option<String>,
+    /// Current commit hash; `None` if the branch has no commits yet
+    pub commit: Option<String>,
+    /// Description of HEAD when detached (nearest tag, e.g. `v1.0.0-2-g1a2b3c4`, or commit hash)
+    pub detached: Option<String>,
+    /// Tag pointing at HEAD
+    pub tag: Option<String>,
+    /// Upstream of current branch (e.g. `origin/master`)
+    pub upstream: Option<String>,
+    /// Commits in current branch which are not in upstream
+    pub ahead: usize,
+    /// Commits in upstream which are not in current branch
+    pub behind: usize,
+    /// Files with changes in the index
+    pub staged: usize,
+    /// Tracked files with changes in the working tree
+    pub unstaged: usize,
+    /// Files not tracked
+    pub untracked: usize,
+    /// Files with merge conflicts
+    pub conflicted: usize,
+    /// Operation in progress
+    pub operation: Option<Operation>,
+}
+
+impl RepoStatus {
+    /// ### is_dirty
+    ///
+    /// Returns whether there are changes in the index or in the working tree
+    pub fn is_dirty(&self) -> bool {
+        self.staged + self.unstaged + self.untracked + self.conflicted > 0
+    }
+}
+
+/// ## Operation
+///
+/// Operation in progress in the repository
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Operation {
+    ApplyMailbox,
+    Bisect,
+    CherryPick,
+    Merge,
+    Rebase,
+    Revert,
+}
+
+impl Operation {
+    /// ### from_state
+    ///
+    /// Get operation in progress from repository state
+    fn from_state(state: RepositoryState) -> Option<Self> {
+        match state {
+            RepositoryState::Clean => None,
+            RepositoryState::Merge => Some(Self::Merge),
+            RepositoryState::Revert | RepositoryState::RevertSequence => Some(Self::Revert),
+            RepositoryState::CherryPick | RepositoryState::CherryPickSequence => {
+                Some(Self::CherryPick)
+            }
+            RepositoryState::Bisect => Some(Self::Bisect),
+            RepositoryState::Rebase
+            | RepositoryState::RebaseInteractive
+            | RepositoryState::RebaseMerge
+            | RepositoryState::ApplyMailboxOrRebase => Some(Self::Rebase),
+            RepositoryState::ApplyMailbox => Some(Self::ApplyMailbox),
+        }
+    }
+}
+
+impl std::fmt::Display for Operation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name: &str = match self {
+            Operation::ApplyMailbox => "AM",
+            Operation::Bisect => "BISECTING",
+            Operation::CherryPick => "CHERRY-PICKING",
+            Operation::Merge => "MERGING",
+            Operation::Rebase => "REBASING",
+            Operation::Revert => "REVERTING",
+        };
+        write!(f, "{}", name)
+    }
+}
+
 impl Git {
 
     /// ### find_repository
@@ -70,7 +217,233 @@ impl Git {
             Err(_) => return None,
         };
         let commit_oid = head_commit.id();
-        Some(bytes_to_hexstr(commit_oid.as_bytes(), hashlen))
+        Some(Self::bytes_to_hexstr(commit_oid.as_bytes(), hashlen))
+    }
+
+    /// ### get_status
+    ///
+    /// Get branch, working tree, upstream and in-progress operation status of repository.
+    /// Returns `None` if the status couldn't be read (e.g. bare repository)
+    pub fn get_status(repository: &Repository, hashlen: usize) -> Option<RepoStatus> {
+        let mut status: RepoStatus = RepoStatus {
+            commit: Self::get_commit(repository, hashlen),
+            operation: Operation::from_state(repository.state()),
+            ..RepoStatus::default()
+        };
+        let head_oid: Option<Oid> = repository
+            .head()
+            .ok()
+            .and_then(|head| head.peel_to_commit().ok())
+            .map(|commit| commit.id());
+        match repository.head_detached() {
+            Ok(true) => {
+                status.detached = head_oid.map(|oid| Self::describe(repository, oid, hashlen))
+            }
+            _ => status.branch = Self::get_head_branch(repository),
+        }
+        if let Some(oid) = head_oid {
+            status.tag = Self::get_tag(repository, oid);
+        }
+        // Ahead / behind
+        if let (Some(branch), Some(local)) = (status.branch.as_ref(), head_oid) {
+            if let Some((upstream, upstream_oid)) = Self::get_upstream(repository, branch.as_str())
+            {
+                if let Ok((ahead, behind)) = repository.graph_ahead_behind(local, upstream_oid) {
+                    status.ahead = ahead;
+                    status.behind = behind;
+                }
+                status.upstream = Some(upstream);
+            }
+        }
+        // Working tree
+        let mut options: StatusOptions = StatusOptions::new();
+        options
+            .include_untracked(true)
+            .recurse_untracked_dirs(true)
+            .exclude_submodules(true);
+        let statuses = repository.statuses(Some(&mut options)).ok()?;
+        for entry in statuses.iter() {
+            let flags: Status = entry.status();
+            if flags.is_conflicted() {
+                status.conflicted += 1;
+                continue;
+            }
+            if flags.is_wt_new() {
+                status.untracked += 1;
+            }
+            if flags.intersects(
+                Status::INDEX_NEW
+                    | Status::INDEX_MODIFIED
+                    | Status::INDEX_DELETED
+                    | Status::INDEX_RENAMED
+                    | Status::INDEX_TYPECHANGE,
+            ) {
+                status.staged += 1;
+            }
+            if flags.intersects(
+                Status::WT_MODIFIED
+                    | Status::WT_DELETED
+                    | Status::WT_RENAMED
+                    | Status::WT_TYPECHANGE,
+            ) {
+                status.unstaged += 1;
+            }
+        }
+        Some(status)
+    }
+
+    /// ### get_latest_tag
+    ///
+    /// Get the most recent tag reachable from HEAD
+    pub fn get_latest_tag(repository: &Repository) -> Option<String> {
+        let mut options: DescribeOptions = DescribeOptions::new();
+        options.describe_tags();
+        let mut format: DescribeFormatOptions = DescribeFormatOptions::new();
+        format.abbreviated_size(0);
+        repository
+            .describe(&options)
+            .and_then(|describe| describe.format(Some(&format)))
+            .ok()
+    }
+
+    /// ### log
+    ///
+    /// Get commits reachable from `range`, newest first. `range` is either a revision (e.g. `HEAD`)
+    /// or a range (e.g. `v0.1.0..HEAD`). At most `limit` commits are returned, if provided
+    pub fn log(
+        repository: &Repository,
+        range: &str,
+        limit: Option<usize>,
+    ) -> Result<Vec<CommitInfo>, git2::Error> {
+        let mut revwalk = repository.revwalk()?;
+        revwalk.set_sorting(Sort::TOPOLOGICAL | Sort::TIME)?;
+        match range.contains("..") {
+            true => revwalk.push_range(range)?,
+            false => revwalk.push(repository.revparse_single(range)?.peel_to_commit()?.id())?,
+        }
+        revwalk
+            .take(limit.unwrap_or(usize::MAX))
+            .map(|oid| {
+                let commit: Commit = repository.find_commit(oid?)?;
+                Ok(CommitInfo::new(&commit))
+            })
+            .collect()
+    }
+
+    /// ### diff_stat
+    ///
+    /// Get insertions and deletions for each file changed between revisions `from` and `to`
+    pub fn diff_stat(
+        repository: &Repository,
+        from: &str,
+        to: &str,
+    ) -> Result<Vec<FileStat>, git2::Error> {
+        let diff: Diff = Self::diff_trees(repository, from, to)?;
+        let mut stats: Vec<FileStat> = Vec::with_capacity(diff.deltas().len());
+        for (index, delta) in diff.deltas().enumerate() {
+            let path: PathBuf = match delta.new_file().path().or_else(|| delta.old_file().path()) {
+                Some(path) => path.to_path_buf(),
+                None => continue,
+            };
+            let (insertions, deletions) = match Patch::from_diff(&diff, index)? {
+                Some(patch) => {
+                    let (_, insertions, deletions) = patch.line_stats()?;
+                    (insertions, deletions)
+                }
+                None => (0, 0), // Binary file
+            };
+            stats.push(FileStat {
+                path,
+                insertions,
+                deletions,
+            });
+        }
+        Ok(stats)
+    }
+
+    /// ### changed_files_since
+    ///
+    /// Get files changed between `tag` and HEAD, sorted by path
+    pub fn changed_files_since(
+        repository: &Repository,
+        tag: &str,
+    ) -> Result<Vec<PathBuf>, git2::Error> {
+        let diff: Diff =
+            Self::diff_trees(repository, format!("refs/tags/{}", tag).as_str(), "HEAD")?;
+        let mut files: Vec<PathBuf> = diff
+            .deltas()
+            .filter_map(|delta| delta.new_file().path().or_else(|| delta.old_file().path()))
+            .map(|path| path.to_path_buf())
+            .collect();
+        files.sort();
+        files.dedup();
+        Ok(files)
+    }
+
+    /// ### diff_trees
+    ///
+    /// Diff trees of revisions `from` and `to`, detecting renames
+    fn diff_trees<'a>(
+        repository: &'a Repository,
+        from: &str,
+        to: &str,
+    ) -> Result<Diff<'a>, git2::Error> {
+        let from: Tree = repository.revparse_single(from)?.peel_to_tree()?;
+        let to: Tree = repository.revparse_single(to)?.peel_to_tree()?;
+        let mut diff: Diff = repository.diff_tree_to_tree(Some(&from), Some(&to), None)?;
+        diff.find_similar(None)?;
+        Ok(diff)
+    }
+
+    /// ### get_head_branch
+    ///
+    /// Get the branch HEAD points to, even if it has no commits yet
+    fn get_head_branch(repository: &Repository) -> Option<String> {
+        let head = repository.find_reference("HEAD").ok()?;
+        head.symbolic_target()
+            .map(|target| target.trim_start_matches("refs/heads/").to_string())
+    }
+
+    /// ### get_upstream
+    ///
+    /// Get name and commit of the upstream of local `branch`
+    fn get_upstream(repository: &Repository, branch: &str) -> Option<(String, Oid)> {
+        let branch = repository.find_branch(branch, BranchType::Local).ok()?;
+        let upstream = branch.upstream().ok()?;
+        let name: String = upstream.name().ok()??.to_string();
+        let oid: Oid = upstream.get().peel_to_commit().ok()?.id();
+        Some((name, oid))
+    }
+
+    /// ### get_tag
+    ///
+    /// Get the first tag, in alphabetical order, pointing at commit `oid`
+    fn get_tag(repository: &Repository, oid: Oid) -> Option<String> {
+        let tags = repository.tag_names(None).ok()?;
+        tags.iter()
+            .flatten()
+            .find(|name| {
+                repository
+                    .revparse_single(format!("refs/tags/{}", name).as_str())
+                    .and_then(|obj| obj.peel_to_commit())
+                    .map(|commit| commit.id() == oid)
+                    .unwrap_or(false)
+            })
+            .map(|name| name.to_string())
+    }
+
+    /// ### describe
+    ///
+    /// Describe detached HEAD at `oid` relative to the nearest tag; falls back to the commit hash
+    fn describe(repository: &Repository, oid: Oid, hashlen: usize) -> String {
+        let mut options: DescribeOptions = DescribeOptions::new();
+        options.describe_tags();
+        let mut format: DescribeFormatOptions = DescribeFormatOptions::new();
+        format.abbreviated_size(std::cmp::max(hashlen, 4) as u32);
+        repository
+            .describe(&options)
+            .and_then(|describe| describe.format(Some(&format)))
+            .unwrap_or_else(|_| Self::bytes_to_hexstr(oid.as_bytes(), hashlen))
     }
 
     /// ### bytes_to_hexstr
@@ -89,10 +462,280 @@ impl Git {
 
 }
 
+/// ## GitPromptCache
+///
+/// Caches repository status for the prompt, so that it is recomputed only when HEAD or the index change.
+/// Status is computed on a background thread; if it takes longer than the time budget,
+/// the last known status (or just branch and commit) is returned marked as stale
+pub struct GitPromptCache {
+    template: String,
+    hashlen: usize,
+    budget: Duration,
+    repositories: HashMap<PathBuf, Option<PathBuf>>,
+    entries: HashMap<PathBuf, CacheEntry>,
+    pending: HashMap<PathBuf, (CacheKey, Receiver<Option<RepoStatus>>)>,
+}
+
+/// ## PromptStatus
+///
+/// Repository status returned by `GitPromptCache`
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct PromptStatus {
+    pub status: RepoStatus,
+    /// Whether status may be outdated, because it couldn't be computed within the time budget
+    pub stale: bool,
+}
+
+/// ## CacheKey
+///
+/// Modification times of the files which change along with the repository status
+#[derive(Clone, Debug, Eq, PartialEq)]
+struct CacheKey {
+    head: Option<SystemTime>,
+    index: Option<SystemTime>,
+}
+
+impl CacheKey {
+    fn new(gitdir: &Path) -> Self {
+        let mtime = |name: &str| {
+            fs::metadata(gitdir.join(name))
+                .and_then(|m| m.modified())
+                .ok()
+        };
+        CacheKey {
+            head: mtime("HEAD"),
+            index: mtime("index"),
+        }
+    }
+}
+
+struct CacheEntry {
+    key: CacheKey,
+    status: RepoStatus,
+}
+
+impl GitPromptCache {
+    /// ### new
+    ///
+    /// Instantiate a new cache, rendering segments with `template`, commit hashes of `hashlen` characters
+    /// and waiting at most `budget` for the status to be computed
+    pub fn new(template: &str, hashlen: usize, budget: Duration) -> GitPromptCache {
+        GitPromptCache {
+            template: template.to_string(),
+            hashlen,
+            budget,
+            repositories: HashMap::new(),
+            entries: HashMap::new(),
+            pending: HashMap::new(),
+        }
+    }
+
+    /// ### render
+    ///
+    /// Render prompt segment for the repository containing `wrkdir`.
+    /// Returns `None` if `wrkdir` is not inside a repository
+    pub fn render(&mut self, wrkdir: &Path) -> Option<String> {
+        let status: PromptStatus = self.get_status(wrkdir)?;
+        Some(Self::format(self.template.as_str(), &status))
+    }
+
+    /// ### get_status
+    ///
+    /// Get status of the repository containing `wrkdir`; recomputed only if HEAD or the index changed.
+    /// Returns `None` if `wrkdir` is not inside a repository
+    pub fn get_status(&mut self, wrkdir: &Path) -> Option<PromptStatus> {
+        let gitdir: PathBuf = self.find_gitdir(wrkdir)?;
+        let key: CacheKey = CacheKey::new(gitdir.as_path());
+        self.collect_pending(gitdir.as_path());
+        if let Some(entry) = self.entries.get(&gitdir) {
+            if entry.key == key {
+                return Some(PromptStatus {
+                    status: entry.status.clone(),
+                    stale: false,
+                });
+            }
+        }
+        // Compute status, unless a computation for the same state is still running
+        let still_pending: bool =
+            matches!(self.pending.get(&gitdir), Some((pending_key, _)) if *pending_key == key);
+        if !still_pending {
+            let (tx, rx) = mpsc::channel();
+            let repo_path: PathBuf = gitdir.clone();
+            let hashlen: usize = self.hashlen;
+            thread::spawn(move || {
+                let status: Option<RepoStatus> = Repository::open(repo_path)
+                    .ok()
+                    .and_then(|repo| Git::get_status(&repo, hashlen));
+                let _ = tx.send(status);
+            });
+            self.pending.insert(gitdir.clone(), (key.clone(), rx));
+        }
+        let result = match self.pending.get(&gitdir) {
+            Some((_, rx)) => rx.recv_timeout(self.budget),
+            None => Err(RecvTimeoutError::Disconnected),
+        };
+        match result {
+            Ok(status) => {
+                self.pending.remove(&gitdir);
+                let status: RepoStatus = status?;
+                self.entries.insert(
+                    gitdir,
+                    CacheEntry {
+                        key,
+                        status: status.clone(),
+                    },
+                );
+                Some(PromptStatus {
+                    status,
+                    stale: false,
+                })
+            }
+            Err(RecvTimeoutError::Timeout) => Some(PromptStatus {
+                status: self.partial_status(gitdir.as_path()),
+                stale: true,
+            }),
+            Err(RecvTimeoutError::Disconnected) => {
+                self.pending.remove(&gitdir);
+                None
+            }
+        }
+    }
+
+    /// ### invalidate
+    ///
+    /// Drop cached status of every repository (e.g. after files in the working tree have been edited)
+    pub fn invalidate(&mut self) {
+        self.entries.clear();
+    }
+
+    /// ### format
+    ///
+    /// Render `status` with `template`. Supported placeholders are:
+    ///
+    /// - `{branch}`: branch name, or HEAD description in parentheses if detached
+    /// - `{commit}`: commit hash
+    /// - `{tag}`: tag pointing at HEAD
+    /// - `{upstream}`: upstream branch
+    /// - `{dirty}`: `*` if there are changes
+    /// - `{ahead}`, `{behind}`: `↑n`, `↓n` commits ahead/behind upstream
+    /// - `{staged}`, `{unstaged}`, `{untracked}`, `{conflicted}`: `+n`, `!n`, `?n`, `=n` changed files
+    /// - `{operation}`: `|MERGING`, `|REBASING`, ...
+    /// - `{stale}`: `…` if status may be outdated
+    ///
+    /// Placeholders with nothing to show render as empty strings; unknown ones are kept as they are
+    pub fn format(template: &str, status: &PromptStatus) -> String {
+        let counter = |prefix: &str, n: usize| match n {
+            0 => String::new(),
+            n => format!("{}{}", prefix, n),
+        };
+        let repo: &RepoStatus = &status.status;
+        let mut output: String = String::with_capacity(template.len());
+        let mut rest: &str = template;
+        while let Some(start) = rest.find('{') {
+            let end: usize = match rest[start..].find('}') {
+                Some(end) => start + end,
+                None => break,
+            };
+            output.push_str(&rest[..start]);
+            let value: Option<String> = match &rest[start + 1..end] {
+                "branch" => Some(match (repo.branch.as_ref(), repo.detached.as_ref()) {
+                    (Some(branch), _) => branch.clone(),
+                    (None, Some(detached)) => format!("({})", detached),
+                    (None, None) => String::new(),
+                }),
+                "commit" => Some(repo.commit.clone().unwrap_or_default()),
+                "tag" => Some(repo.tag.clone().unwrap_or_default()),
+                "upstream" => Some(repo.upstream.clone().unwrap_or_default()),
+                "dirty" => Some(match repo.is_dirty() {
+                    true => String::from("*"),
+                    false => String::new(),
+                }),
+                "ahead" => Some(counter("↑", repo.ahead)),
+                "behind" => Some(counter("↓", repo.behind)),
+                "staged" => Some(counter("+", repo.staged)),
+                "unstaged" => Some(counter("!", repo.unstaged)),
+                "untracked" => Some(counter("?", repo.untracked)),
+                "conflicted" => Some(counter("=", repo.conflicted)),
+                "operation" => Some(
+                    repo.operation
+                        .map(|op| format!("|{}", op))
+                        .unwrap_or_default(),
+                ),
+                "stale" => Some(match status.stale {
+                    true => String::from("…"),
+                    false => String::new(),
+                }),
+                _ => None,
+            };
+            match value {
+                Some(value) => output.push_str(value.as_str()),
+                None => output.push_str(&rest[start..=end]),
+            }
+            rest = &rest[end + 1..];
+        }
+        output.push_str(rest);
+        output
+    }
+
+    /// ### find_gitdir
+    ///
+    /// Find the git directory of the repository containing `wrkdir`; lookups are cached
+    fn find_gitdir(&mut self, wrkdir: &Path) -> Option<PathBuf> {
+        if let Some(gitdir) = self.repositories.get(wrkdir) {
+            return gitdir.clone();
+        }
+        let gitdir: Option<PathBuf> =
+            Git::find_repository(&wrkdir.to_path_buf()).map(|repo| repo.path().to_path_buf());
+        self.repositories
+            .insert(wrkdir.to_path_buf(), gitdir.clone());
+        gitdir
+    }
+
+    /// ### collect_pending
+    ///
+    /// Store the result of the background computation for `gitdir`, if it has completed
+    fn collect_pending(&mut self, gitdir: &Path) {
+        let result = match self.pending.get(gitdir) {
+            Some((_, rx)) => rx.try_recv(),
+            None => return,
+        };
+        match result {
+            Ok(status) => {
+                let (key, _) = self.pending.remove(gitdir).unwrap();
+                if let Some(status) = status {
+                    self.entries
+                        .insert(gitdir.to_path_buf(), CacheEntry { key, status });
+                }
+            }
+            Err(TryRecvError::Disconnected) => {
+                self.pending.remove(gitdir);
+            }
+            Err(TryRecvError::Empty) => {}
+        }
+    }
+
+    /// ### partial_status
+    ///
+    /// Get the last known status of `gitdir`, or just branch and commit if it has never been computed
+    fn partial_status(&self, gitdir: &Path) -> RepoStatus {
+        if let Some(entry) = self.entries.get(gitdir) {
+            return entry.status.clone();
+        }
+        match Repository::open(gitdir) {
+            Ok(repo) => RepoStatus {
+                branch: Git::get_branch(&repo),
+                commit: Git::get_commit(&repo, self.hashlen),
+                ..RepoStatus::default()
+            },
+            Err(_) => RepoStatus::default(),
+        }
+    }
+}
+
 //@! Tests
 
 #[cfg(test)]
-mod tests {
+pub(crate) mod tests {
 
     use super::*;
 
@@ -137,8 +780,277 @@ mod tests {
         assert_eq!(commit.unwrap().len(), 8);
     }
 
+    #[test]
+    fn test_prompt_git_status_empty() {
+        let tmpdir: tempfile::TempDir = tempfile::TempDir::new().unwrap();
+        let repo: Repository = Repository::init(tmpdir.path()).unwrap();
+        std::fs::write(tmpdir.path().join("README.md"), "# Test\n").unwrap();
+        let status: RepoStatus = Git::get_status(&repo, 8).unwrap();
+        // Branch is known even without commits
+        assert!(status.branch.is_some());
+        assert!(status.commit.is_none());
+        assert!(status.detached.is_none());
+        assert_eq!(status.untracked, 1);
+        assert!(status.is_dirty());
+        assert!(status.operation.is_none());
+    }
+
+    #[test]
+    fn test_prompt_git_status_with_commits() {
+        let tmpdir: tempfile::TempDir = tempfile::TempDir::new().unwrap();
+        let repo: Repository = Repository::init(tmpdir.path()).unwrap();
+        std::fs::write(tmpdir.path().join("README.md"), "# Test\n").unwrap();
+        let first: Oid = commit_all(&repo, "first");
+        let status: RepoStatus = Git::get_status(&repo, 8).unwrap();
+        assert!(!status.is_dirty());
+        assert_eq!(status.commit.as_ref().unwrap().len(), 8);
+        // Tag
+        let obj = repo.find_object(first, None).unwrap();
+        repo.tag_lightweight("v0.1.0", &obj, false).unwrap();
+        assert_eq!(
+            Git::get_status(&repo, 8).unwrap().tag.as_deref(),
+            Some("v0.1.0")
+        );
+        // Staged, unstaged and untracked
+        std::fs::write(tmpdir.path().join("README.md"), "# Test\n\nChanged\n").unwrap();
+        std::fs::write(tmpdir.path().join("LICENSE"), "WTFPL\n").unwrap();
+        let mut index = repo.index().unwrap();
+        index.add_path(Path::new("LICENSE")).unwrap();
+        index.write().unwrap();
+        std::fs::write(tmpdir.path().join("new.txt"), "new\n").unwrap();
+        let status: RepoStatus = Git::get_status(&repo, 8).unwrap();
+        assert_eq!(status.staged, 1);
+        assert_eq!(status.unstaged, 1);
+        assert_eq!(status.untracked, 1);
+        // Ahead / behind a local upstream
+        let branch: String = status.branch.unwrap();
+        repo.branch("upstream", &repo.find_commit(first).unwrap(), false)
+            .unwrap();
+        let second: Oid = commit_all(&repo, "second");
+        repo.find_branch(branch.as_str(), BranchType::Local)
+            .unwrap()
+            .set_upstream(Some("upstream"))
+            .unwrap();
+        let status: RepoStatus = Git::get_status(&repo, 8).unwrap();
+        assert_eq!(status.upstream.as_deref(), Some("upstream"));
+        assert_eq!(status.ahead, 1);
+        assert_eq!(status.behind, 0);
+        assert!(status.tag.is_none());
+        // Detached HEAD
+        repo.set_head_detached(second).unwrap();
+        let status: RepoStatus = Git::get_status(&repo, 8).unwrap();
+        assert!(status.branch.is_none());
+        assert!(status.detached.unwrap().starts_with("v0.1.0-1-g"));
+        // Operation in progress
+        std::fs::write(repo.path().join("MERGE_HEAD"), format!("{}\n", first)).unwrap();
+        let status: RepoStatus = Git::get_status(&repo, 8).unwrap();
+        assert_eq!(status.operation, Some(Operation::Merge));
+        assert_eq!(status.operation.unwrap().to_string(), "MERGING");
+    }
+
+    #[test]
+    fn test_git_log() {
+        let tmpdir: tempfile::TempDir = tempfile::TempDir::new().unwrap();
+        let repo: Repository = Repository::init(tmpdir.path()).unwrap();
+        std::fs::write(tmpdir.path().join("README.md"), "# Test\n").unwrap();
+        let first: Oid = commit_all(&repo, "first\n\nInitial commit");
+        let obj = repo.find_object(first, None).unwrap();
+        repo.tag_lightweight("v0.1.0", &obj, false).unwrap();
+        std::fs::write(tmpdir.path().join("LICENSE"), "WTFPL\n").unwrap();
+        let second: Oid = commit_all(&repo, "second");
+        std::fs::write(tmpdir.path().join("main.rs"), "fn main() {}\n").unwrap();
+        commit_all(&repo, "third");
+        //Whole history
+        let log: Vec<CommitInfo> = Git::log(&repo, "HEAD", None).unwrap();
+        assert_eq!(log.len(), 3);
+        assert_eq!(log[0].summary.as_str(), "third");
+        assert_eq!(log[1].hash, second.to_string());
+        assert_eq!(log[1].parents, vec![first.to_string()]);
+        assert_eq!(log[2].summary.as_str(), "first");
+        assert_eq!(log[2].message.as_str(), "first\n\nInitial commit");
+        assert!(log[2].parents.is_empty());
+        assert_eq!(log[2].author.as_str(), "test");
+        assert_eq!(log[2].email.as_str(), "test@example.com");
+        assert!(log[2].date > 0);
+        //Limit
+        assert_eq!(Git::log(&repo, "HEAD", Some(1)).unwrap().len(), 1);
+        //Range
+        let log: Vec<CommitInfo> = Git::log(&repo, "v0.1.0..HEAD", None).unwrap();
+        assert_eq!(
+            log.iter()
+                .map(|x| x.summary.as_str())
+                .collect::<Vec<&str>>(),
+            vec!["third", "second"]
+        );
+        //Bad revision
+        assert!(Git::log(&repo, "v9.9.9", None).is_err());
+    }
+
+    #[test]
+    fn test_git_diff_stat() {
+        let tmpdir: tempfile::TempDir = tempfile::TempDir::new().unwrap();
+        let repo: Repository = Repository::init(tmpdir.path()).unwrap();
+        std::fs::write(tmpdir.path().join("README.md"), "# Test\n\nfoo\nbar\n").unwrap();
+        std::fs::write(tmpdir.path().join("LICENSE"), "WTFPL\n").unwrap();
+        let first: Oid = commit_all(&repo, "first");
+        let obj = repo.find_object(first, None).unwrap();
+        repo.tag_lightweight("v0.1.0", &obj, false).unwrap();
+        std::fs::write(tmpdir.path().join("README.md"), "# Test\n\nfoo\nbaz\nqux\n").unwrap();
+        std::fs::write(tmpdir.path().join("main.rs"), "fn main() {}\n").unwrap();
+        commit_all(&repo, "second");
+        let mut stats: Vec<FileStat> = Git::diff_stat(&repo, "v0.1.0", "HEAD").unwrap();
+        stats.sort_by(|a, b| a.path.cmp(&b.path));
+        assert_eq!(
+            stats,
+            vec![
+                FileStat {
+                    path: PathBuf::from("README.md"),
+                    insertions: 2,
+                    deletions: 1,
+                },
+                FileStat {
+                    path: PathBuf::from("main.rs"),
+                    insertions: 1,
+                    deletions: 0,
+                },
+            ]
+        );
+        assert_eq!(
+            Git::changed_files_since(&repo, "v0.1.0").unwrap(),
+            vec![PathBuf::from("README.md"), PathBuf::from("main.rs")]
+        );
+        assert!(Git::changed_files_since(&repo, "v9.9.9").is_err());
+        assert_eq!(Git::get_latest_tag(&repo).as_deref(), Some("v0.1.0"));
+    }
+
+    #[test]
+    fn test_prompt_git_cache_format() {
+        let mut status: PromptStatus = PromptStatus {
+            status: RepoStatus {
+                branch: Some(String::from("master")),
+                commit: Some(String::from("1a2b3c4d")),
+                ahead: 2,
+                unstaged: 1,
+                operation: Some(Operation::Rebase),
+                ..RepoStatus::default()
+            },
+            stale: false,
+        };
+        assert_eq!(
+            GitPromptCache::format(DEFAULT_PROMPT_TEMPLATE, &status),
+            "master*↑2|REBASING"
+        );
+        assert_eq!(
+            GitPromptCache::format("[{commit}{behind}{unknown}] {untracked}", &status),
+            "[1a2b3c4d{unknown}] "
+        );
+        status.stale = true;
+        status.status.branch = None;
+        status.status.detached = Some(String::from("v1.0.0-1-g1a2b3c4d"));
+        assert_eq!(
+            GitPromptCache::format("{branch}{stale} {", &status),
+            "(v1.0.0-1-g1a2b3c4d)… {"
+        );
+    }
+
+    #[test]
+    fn test_prompt_git_cache() {
+        let tmpdir: tempfile::TempDir = tempfile::TempDir::new().unwrap();
+        let repo: Repository = Repository::init(tmpdir.path()).unwrap();
+        std::fs::write(tmpdir.path().join("README.md"), "# Test\n").unwrap();
+        commit_all(&repo, "first");
+        let mut cache: GitPromptCache =
+            GitPromptCache::new(DEFAULT_PROMPT_TEMPLATE, 8, Duration::from_secs(10));
+        assert!(cache.render(Path::new("/")).is_none());
+        let status: PromptStatus = cache.get_status(tmpdir.path()).unwrap();
+        assert!(!status.stale);
+        assert!(!status.status.is_dirty());
+        // Working tree changes don't invalidate cache...
+        std::fs::write(tmpdir.path().join("new.txt"), "new\n").unwrap();
+        assert_eq!(cache.get_status(tmpdir.path()).unwrap(), status);
+        cache.invalidate();
+        assert_eq!(cache.get_status(tmpdir.path()).unwrap().status.untracked, 1);
+        // ...while index changes do; mtime is moved forward, as it may not change on filesystems
+        // with a coarse resolution
+        let index_path: PathBuf = repo.path().join("index");
+        let modified: SystemTime = std::fs::metadata(index_path.as_path())
+            .unwrap()
+            .modified()
+            .unwrap();
+        let mut index = repo.index().unwrap();
+        index.add_path(Path::new("new.txt")).unwrap();
+        index.write().unwrap();
+        std::fs::File::options()
+            .write(true)
+            .open(index_path.as_path())
+            .unwrap()
+            .set_modified(modified + Duration::from_secs(10))
+            .unwrap();
+        let status: PromptStatus = cache.get_status(tmpdir.path()).unwrap();
+        assert_eq!(status.status.staged, 1);
+        assert_eq!(status.status.untracked, 0);
+    }
+
+    #[test]
+    fn test_prompt_git_cache_budget() {
+        let tmpdir: tempfile::TempDir = tempfile::TempDir::new().unwrap();
+        let repo: Repository = Repository::init(tmpdir.path()).unwrap();
+        std::fs::write(tmpdir.path().join("README.md"), "# Test\n").unwrap();
+        commit_all(&repo, "first");
+        std::fs::write(tmpdir.path().join("notes.txt"), "wip\n").unwrap();
+        let mut cache: GitPromptCache =
+            GitPromptCache::new(DEFAULT_PROMPT_TEMPLATE, 8, Duration::from_secs(0));
+        let gitdir: PathBuf = cache.find_gitdir(tmpdir.path()).unwrap();
+        // Before status has ever been computed, only branch and commit are known
+        let partial: PromptStatus = PromptStatus {
+            status: cache.partial_status(gitdir.as_path()),
+            stale: true,
+        };
+        let branch: String = partial.status.branch.clone().unwrap();
+        assert_eq!(partial.status.commit.as_ref().map(|x| x.len()), Some(8));
+        assert!(!partial.status.is_dirty());
+        assert_eq!(
+            GitPromptCache::format(DEFAULT_PROMPT_TEMPLATE, &partial),
+            format!("{}…", branch)
+        );
+        // Status is eventually available; a larger budget waits for the computation which is running
+        assert!(cache.get_status(tmpdir.path()).is_some());
+        cache.budget = Duration::from_secs(10);
+        assert!(!cache.get_status(tmpdir.path()).unwrap().stale);
+        assert_eq!(cache.render(tmpdir.path()).unwrap(), format!("{}*", branch));
+        // Then the last known status is returned while it is being recomputed
+        assert_eq!(cache.partial_status(gitdir.as_path()).untracked, 1);
+    }
+
     #[test]
     fn test_prompt_git_repo_not_found() {
         assert!(Git::find_repository(&PathBuf::from("/")).is_none());
     }
+
+    /// Stage every file in the workdir and commit it on HEAD
+    pub(crate) fn commit_all(repo: &Repository, message: &str) -> Oid {
+        let mut index = repo.index().unwrap();
+        index
+            .add_all(["*"].iter(), git2::IndexAddOption::DEFAULT, None)
+            .unwrap();
+        index.write().unwrap();
+        let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+        let signature = git2::Signature::now("test", "test@example.com").unwrap();
+        let parents: Vec<git2::Commit> = repo
+            .head()
+            .ok()
+            .and_then(|head| head.peel_to_commit().ok())
+            .into_iter()
+            .collect();
+        let parents: Vec<&git2::Commit> = parents.iter().collect();
+        repo.commit(
+            Some("HEAD"),
+            &signature,
+            &signature,
+            message,
+            &tree,
+            parents.as_slice(),
+        )
+        .unwrap()
+    }
 }
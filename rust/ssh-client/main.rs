@@ -16,17 +16,20 @@
  *   0. You just DO WHAT THE FUCK YOU WANT TO.
 */
 // Dependencies
+extern crate libc;
 extern crate rpassword;
 extern crate ssh2;
 
 // Includes
-use ssh2::Session;
+use ssh2::{Channel, Session};
 use std::env;
+use std::fs::File;
 use std::io;
 use std::io::*;
 use std::net::TcpStream;
 use std::process::exit;
 // Threading
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::mpsc;
 use std::sync::mpsc::Receiver;
 use std::sync::mpsc::TryRecvError;
@@ -35,32 +38,51 @@ use std::time::Duration;
 
 fn main() {
     let args: Vec<String> = env::args().collect();
+    // Split remote command from connection arguments
+    let (args, command): (&[String], Option<String>) =
+        match args.iter().position(|x| x.as_str() == "--") {
+            Some(pos) => (&args[..pos], Some(args[pos + 1..].join(" "))),
+            None => (&args[..], None),
+        };
     // Check args len
     if args.len() < 2 {
-        eprintln!("Usage: {} <address> [port]", args.get(0).unwrap());
+        eprintln!(
+            "Usage: {} [username@]<address> [port] [-- command [args...]]",
+            args.get(0).unwrap()
+        );
         exit(255);
     }
-    let address: String = args.get(1).unwrap().clone();
+    let (username, address): (Option<String>, String) = match args.get(1).unwrap().split_once('@') {
+        Some((username, address)) => (Some(username.to_string()), address.to_string()),
+        None => (None, args.get(1).unwrap().clone()),
+    };
     let port: u16 = match args.get(2) {
         Some(p) => p.parse::<u16>().unwrap(),
         None => 22,
     };
     // Create session
-    println!("Connecting to {}:{}", address, port);
+    eprintln!("Connecting to {}:{}", address, port);
     let tcp = TcpStream::connect(format!("{}:{}", address, port)).unwrap();
     // Create session
     let mut session = Session::new().unwrap();
     session.set_tcp_stream(tcp);
     session.handshake().unwrap();
-    println!("Connection established");
+    eprintln!("Connection established");
     // Ask for username
-    println!("Type username");
-    let mut username = String::new();
-    let _ = io::stdin().read_line(&mut username);
+    let mut username: String = match username {
+        Some(username) => username,
+        None => match read_username() {
+            Ok(username) => username,
+            Err(err) => {
+                eprintln!("Could not read username: {}", err);
+                exit(255);
+            }
+        },
+    };
     let password: String = rpassword::read_password_from_tty(Some("Password: ")).unwrap();
     // Trim
     trim_newline(&mut username);
-    println!("Authenticating with '{}'", username);
+    eprintln!("Authenticating with '{}'", username);
     // Try to authenticate
     session
         .userauth_password(username.as_str(), password.as_str())
@@ -69,6 +91,33 @@ fn main() {
         eprintln!("Authentication failed...");
         exit(1);
     }
+    // Run either remote command or shell
+    let rc: i32 = match command {
+        Some(command) => exec(&session, command.as_str()),
+        None => shell(&session),
+    };
+    // Close session
+    let _ = session.disconnect(None, "mandi", None);
+    exit(rc);
+}
+
+/// ### read_username
+///
+/// Read username from the terminal, as the password is, so that piped stdin is left to the remote command
+fn read_username() -> io::Result<String> {
+    let mut tty = BufReader::new(File::open("/dev/tty")?);
+    eprintln!("Type username");
+    let mut username = String::new();
+    tty.read_line(&mut username)?;
+    Ok(username)
+}
+
+/// ### shell
+///
+/// Open an interactive shell on the remote host.
+/// The local terminal is put into raw mode, so every byte is forwarded unchanged.
+/// Type `~.` at the beginning of a line to disconnect
+fn shell(session: &Session) -> i32 {
     // Print banner
     if let Some(banner) = session.banner() {
         println!("{}", banner);
@@ -76,71 +125,357 @@ fn main() {
     // Request pty
     let mut mode = ssh2::PtyModes::new();
     mode.set_character(ssh2::PtyModeOpcode::VINTR, Some(3 as char));
+    let term: String = env::var("TERM").unwrap_or_else(|_| String::from("vt100"));
+    let size: Option<(u32, u32, u32, u32)> = terminal_size().map(|(w, h)| (w, h, 0, 0));
     let mut channel = session.channel_session().unwrap();
-    if let Err(err) = channel.request_pty("vt100", Some(mode), None) {
+    if let Err(err) = channel.request_pty(term.as_str(), Some(mode), size) {
         eprintln!("Could not get pty: {}", err);
-        exit(1);
+        return 1;
     }
     if let Err(err) = channel.shell() {
         eprintln!("Failed to start shell: {}", err);
-        exit(1);
+        return 1;
+    }
+    // Put terminal into raw mode; restored once dropped
+    let _raw_terminal: Option<RawTerminal> = RawTerminal::enable().ok();
+    // Listen for window size changes
+    unsafe {
+        libc::signal(
+            libc::SIGWINCH,
+            on_window_change as extern "C" fn(libc::c_int) as libc::sighandler_t,
+        );
     }
     // Set blocking to false
     session.set_blocking(false);
     // Prepare stdin listener
     let stdin_channel = spawn_stdin_channel();
+    let mut stdin_open: bool = true;
+    let mut escape: EscapeReader = EscapeReader::default();
+    let mut pending: Vec<u8> = Vec::new();
+    let mut buffer: [u8; 8192] = [0; 8192];
     // Until ssh session has terminated
     while !channel.eof() {
-        match stdin_channel.try_recv() {
-            Ok(user_input) => {
-                // Write
-                if let Err(err) = channel.write(user_input.as_str().as_bytes()) {
-                    eprintln!("Write failed: {}", err);
-                    exit(1);
+        let mut idle: bool = true;
+        // Propagate window size
+        if WINDOW_CHANGED.swap(false, Ordering::Relaxed) {
+            if let Some((width, height)) = terminal_size() {
+                if channel.request_pty_size(width, height, None, None).is_err() {
+                    // Retry later
+                    WINDOW_CHANGED.store(true, Ordering::Relaxed);
                 }
             }
-            Err(TryRecvError::Empty) => {}
-            Err(TryRecvError::Disconnected) => panic!("Channel disconnected"),
+        }
+        // Take more input once the previous one has been written
+        if stdin_open && pending.is_empty() {
+            match stdin_channel.try_recv() {
+                Ok(user_input) => {
+                    idle = false;
+                    let (user_input, disconnect) = escape.process(user_input.as_slice());
+                    if disconnect {
+                        break;
+                    }
+                    pending = user_input;
+                }
+                Err(TryRecvError::Empty) => {}
+                Err(TryRecvError::Disconnected) => {
+                    match channel.send_eof().map_err(io::Error::from) {
+                        Ok(_) => stdin_open = false,
+                        Err(err) if err.kind() == io::ErrorKind::WouldBlock => {} // Retry later
+                        Err(err) => {
+                            eprintln!("Could not send EOF: {}", err);
+                            return 1;
+                        }
+                    }
+                }
+            }
+        }
+        // Write
+        match write_pending(&mut channel, &mut pending) {
+            Ok(true) => idle = false,
+            Ok(false) => {}
+            Err(err) => {
+                eprintln!("Write failed: {}", err);
+                return 1;
+            }
         }
         // Read output
-        let mut output: String = String::new();
-        let mut buffer: [u8; 8192] = [0; 8192];
         match channel.read(&mut buffer) {
+            Ok(0) => {}
             Ok(bytes_read) => {
-                output.push_str(std::str::from_utf8(&buffer[0..bytes_read]).unwrap());
+                idle = false;
+                let mut stdout = io::stdout();
+                stdout.write_all(&buffer[0..bytes_read]).unwrap();
+                // Flush
+                stdout.flush().unwrap();
             }
+            Err(err) if err.kind() == io::ErrorKind::WouldBlock => {}
             Err(err) => {
-                if err.kind() == io::ErrorKind::WouldBlock {
-                    continue; // Ignore
-                } else {
-                    eprintln!("Could not read output: {}", err);
-                    exit(1);
+                eprintln!("Could not read output: {}", err);
+                return 1;
+            }
+        }
+        if idle {
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+    unsafe {
+        libc::signal(libc::SIGWINCH, libc::SIG_DFL);
+    }
+    if !channel.eof() {
+        // Disconnected by the user
+        return 0;
+    }
+    session.set_blocking(true);
+    let _ = channel.wait_close();
+    channel.exit_status().unwrap_or(0)
+}
+
+/// ### exec
+///
+/// Execute `command` on the remote host, streaming stdin, stdout and stderr.
+/// Returns the remote exit status
+fn exec(session: &Session, command: &str) -> i32 {
+    let mut channel = session.channel_session().unwrap();
+    if let Err(err) = channel.exec(command) {
+        eprintln!("Failed to execute command: {}", err);
+        return 255;
+    }
+    // Set blocking to false
+    session.set_blocking(false);
+    // Prepare stdin listener
+    let stdin_channel = spawn_stdin_channel();
+    let mut stdin_open: bool = true;
+    let mut pending: Vec<u8> = Vec::new();
+    let mut buffer: [u8; 8192] = [0; 8192];
+    // Until remote command has terminated
+    while !channel.eof() {
+        let mut idle: bool = true;
+        // Forward stdin; take more input once the previous one has been written
+        if stdin_open && pending.is_empty() {
+            match stdin_channel.try_recv() {
+                Ok(input) => {
+                    idle = false;
+                    pending = input;
+                }
+                Err(TryRecvError::Empty) => {}
+                Err(TryRecvError::Disconnected) => {
+                    // Stdin has been closed; tell remote there's nothing else to read
+                    match channel.send_eof().map_err(io::Error::from) {
+                        Ok(_) => stdin_open = false,
+                        Err(err) if err.kind() == io::ErrorKind::WouldBlock => {} // Retry later
+                        Err(err) => {
+                            eprintln!("Could not send EOF: {}", err);
+                            return 255;
+                        }
+                    }
                 }
             }
         }
-        let _ = channel.wait_close();
-        if output.len() > 0 {
-            print!("{}", output);
-            // Flush
-            io::stdout().flush().unwrap();
+        // Write only what the channel accepts, so that output keeps being read while the remote
+        // command is busy writing it rather than reading its input
+        match write_pending(&mut channel, &mut pending) {
+            Ok(true) => idle = false,
+            Ok(false) => {}
+            Err(err) => {
+                eprintln!("Write failed: {}", err);
+                return 255;
+            }
+        }
+        // Read stdout
+        match channel.read(&mut buffer) {
+            Ok(0) => {}
+            Ok(bytes_read) => {
+                idle = false;
+                let mut stdout = io::stdout();
+                stdout.write_all(&buffer[0..bytes_read]).unwrap();
+                stdout.flush().unwrap();
+            }
+            Err(err) if err.kind() == io::ErrorKind::WouldBlock => {}
+            Err(err) => {
+                eprintln!("Could not read stdout: {}", err);
+                return 255;
+            }
+        }
+        // Read stderr
+        match channel.stderr().read(&mut buffer) {
+            Ok(0) => {}
+            Ok(bytes_read) => {
+                idle = false;
+                let mut stderr = io::stderr();
+                stderr.write_all(&buffer[0..bytes_read]).unwrap();
+                stderr.flush().unwrap();
+            }
+            Err(err) if err.kind() == io::ErrorKind::WouldBlock => {}
+            Err(err) => {
+                eprintln!("Could not read stderr: {}", err);
+                return 255;
+            }
+        }
+        if idle {
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+    // Drain what's left and wait for exit status
+    session.set_blocking(true);
+    let mut output: Vec<u8> = Vec::new();
+    if channel.read_to_end(&mut output).is_ok() {
+        let _ = io::stdout().write_all(output.as_slice());
+    }
+    output.clear();
+    if channel.stderr().read_to_end(&mut output).is_ok() {
+        let _ = io::stderr().write_all(output.as_slice());
+    }
+    let _ = io::stdout().flush();
+    if let Err(err) = channel.wait_close() {
+        eprintln!("Could not close channel: {}", err);
+        return 255;
+    }
+    match channel.exit_status() {
+        Ok(rc) => rc,
+        Err(err) => {
+            eprintln!("Could not get exit status: {}", err);
+            255
         }
-        thread::sleep(Duration::from_millis(10));
     }
-    // Close session
-    let _ = session.disconnect(None, "mandi", None);
-    exit(0);
 }
 
-fn spawn_stdin_channel() -> Receiver<String> {
-    let (tx, rx) = mpsc::channel::<String>();
-    thread::spawn(move || loop {
-        let mut buffer = String::new();
-        io::stdin().read_line(&mut buffer).unwrap();
-        tx.send(buffer).unwrap();
+/// ### write_pending
+///
+/// Write as much of `pending` as the non-blocking channel accepts right now, removing written bytes.
+/// Returns whether anything has been written
+fn write_pending(channel: &mut Channel, pending: &mut Vec<u8>) -> io::Result<bool> {
+    if pending.is_empty() {
+        return Ok(false);
+    }
+    match channel.write(pending.as_slice()) {
+        Ok(0) => Err(io::Error::from(io::ErrorKind::WriteZero)),
+        Ok(bytes_written) => {
+            pending.drain(..bytes_written);
+            Ok(true)
+        }
+        Err(err) if err.kind() == io::ErrorKind::WouldBlock => Ok(false),
+        Err(err) => Err(err),
+    }
+}
+
+/// ### spawn_stdin_channel
+///
+/// Read stdin in raw byte mode on a separate thread.
+/// The channel gets disconnected once stdin reaches EOF
+fn spawn_stdin_channel() -> Receiver<Vec<u8>> {
+    let (tx, rx) = mpsc::channel::<Vec<u8>>();
+    thread::spawn(move || {
+        let mut stdin = io::stdin();
+        let mut buffer: [u8; 8192] = [0; 8192];
+        loop {
+            match stdin.read(&mut buffer) {
+                Ok(0) | Err(_) => break,
+                Ok(bytes_read) => {
+                    if tx.send(buffer[0..bytes_read].to_vec()).is_err() {
+                        break;
+                    }
+                }
+            }
+        }
     });
     rx
 }
 
+/// ## RawTerminal
+///
+/// Keeps the local terminal in raw mode; the original attributes are restored on drop
+struct RawTerminal {
+    original: libc::termios,
+}
+
+impl RawTerminal {
+    /// ### enable
+    ///
+    /// Put stdin terminal into raw mode. Fails if stdin is not a terminal
+    fn enable() -> io::Result<Self> {
+        unsafe {
+            let mut original: libc::termios = std::mem::zeroed();
+            if libc::tcgetattr(libc::STDIN_FILENO, &mut original) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            let mut raw: libc::termios = original;
+            libc::cfmakeraw(&mut raw);
+            if libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &raw) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(Self { original })
+        }
+    }
+}
+
+impl Drop for RawTerminal {
+    fn drop(&mut self) {
+        unsafe {
+            libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &self.original);
+        }
+    }
+}
+
+/// ## EscapeReader
+///
+/// Intercepts `~` escape sequences typed at the beginning of a line:
+///
+/// - `~.`: disconnect
+/// - `~~`: send a single `~`
+#[derive(Default)]
+struct EscapeReader {
+    mid_line: bool,
+    pending_tilde: bool,
+}
+
+impl EscapeReader {
+    /// ### process
+    ///
+    /// Filter user input; returns the bytes to forward and whether the user asked to disconnect
+    fn process(&mut self, input: &[u8]) -> (Vec<u8>, bool) {
+        let mut output: Vec<u8> = Vec::with_capacity(input.len());
+        for byte in input.iter().copied() {
+            if self.pending_tilde {
+                self.pending_tilde = false;
+                match byte {
+                    b'.' => return (output, true),
+                    b'~' => {
+                        output.push(b'~');
+                        self.mid_line = true;
+                        continue;
+                    }
+                    _ => output.push(b'~'),
+                }
+            } else if !self.mid_line && byte == b'~' {
+                self.pending_tilde = true;
+                continue;
+            }
+            output.push(byte);
+            self.mid_line = byte != b'\r' && byte != b'\n';
+        }
+        (output, false)
+    }
+}
+
+static WINDOW_CHANGED: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn on_window_change(_: libc::c_int) {
+    WINDOW_CHANGED.store(true, Ordering::Relaxed);
+}
+
+/// ### terminal_size
+///
+/// Get local terminal size as (columns, rows)
+fn terminal_size() -> Option<(u32, u32)> {
+    unsafe {
+        let mut size: libc::winsize = std::mem::zeroed();
+        match libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &mut size) {
+            0 => Some((size.ws_col as u32, size.ws_row as u32)),
+            _ => None,
+        }
+    }
+}
+
 fn trim_newline(s: &mut String) {
     if s.ends_with('\n') {
         s.pop();
@@ -149,3 +484,45 @@ fn trim_newline(s: &mut String) {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn escape_reader_should_disconnect_at_line_start() {
+        let mut escape = EscapeReader::default();
+        assert_eq!(escape.process(b"~."), (vec![], true));
+        let mut escape = EscapeReader::default();
+        assert_eq!(escape.process(b"ls\r~.rest"), (b"ls\r".to_vec(), true));
+    }
+
+    #[test]
+    fn escape_reader_should_handle_tilde_split_across_reads() {
+        let mut escape = EscapeReader::default();
+        assert_eq!(escape.process(b"echo\n~"), (b"echo\n".to_vec(), false));
+        assert_eq!(escape.process(b"."), (vec![], true));
+        let mut escape = EscapeReader::default();
+        assert_eq!(escape.process(b"~"), (vec![], false));
+        assert_eq!(escape.process(b"a"), (b"~a".to_vec(), false));
+        // the forwarded tilde started the line: a following tilde is plain text
+        assert_eq!(escape.process(b"~."), (b"~.".to_vec(), false));
+    }
+
+    #[test]
+    fn escape_reader_should_send_single_tilde_on_double_tilde() {
+        let mut escape = EscapeReader::default();
+        assert_eq!(escape.process(b"~~."), (b"~.".to_vec(), false));
+        assert_eq!(escape.process(b"\n~~"), (b"\n~".to_vec(), false));
+    }
+
+    #[test]
+    fn escape_reader_should_ignore_tilde_mid_line() {
+        let mut escape = EscapeReader::default();
+        assert_eq!(escape.process(b"cd ~."), (b"cd ~.".to_vec(), false));
+        assert_eq!(escape.process(b"~."), (b"~.".to_vec(), false));
+        assert_eq!(escape.process(b"\r"), (b"\r".to_vec(), false));
+        assert_eq!(escape.process(b"~."), (vec![], true));
+    }
+}